@@ -1,9 +1,11 @@
 //! Example showing migration from deprecated OpenRTB 2.5 Video Placement Types
 //! to the current AdCom 1.0 Video Plcmt Subtypes
 
+use rtb::BidRequest;
+use rtb::openrtb::utils::migrate::{downgrade_to_2_5, upgrade_to_2_6};
 use rtb::spec::{adcom, openrtb};
 
-fn main() {
+fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("=== Video Placement Type Migration Example ===\n");
 
     println!("DEPRECATED OpenRTB 2.5 Video Placement Types:");
@@ -52,4 +54,39 @@ fn main() {
     println!("  - OpenRTB 2.5 'placement' field → AdCom 1.0 'plcmt' field");
     println!("  - The AdCom values provide more precise categorization");
     println!("  - Use rtb::spec::adcom::video_plcmt_subtypes for new code");
+    println!();
+
+    println!("=== Migrating a 2.5-shaped request ===\n");
+
+    let mut request: BidRequest = serde_json::from_str(
+        r#"{
+            "id": "legacy-request",
+            "imp": [{"id": "1", "video": {"mimes": ["video/mp4"], "placement": 1}}],
+            "regs": {"ext": {"gdpr": 1, "us_privacy": "1YNN"}},
+            "user": {"ext": {"consent": "CONSENT-STRING"}}
+        }"#,
+    )?;
+
+    let report = upgrade_to_2_6(&mut request)?;
+    for change in &report.changes {
+        println!("  {} <- {:?}", change.path, change.kind);
+    }
+
+    if let Some(video) = request.imp[0].video.as_ref() {
+        println!(
+            "  plcmt is now {} ({})",
+            video.plcmt,
+            adcom::video_plcmt_subtypes::description(video.plcmt as u32).unwrap_or("unknown")
+        );
+    }
+    println!();
+
+    println!("=== Downgrading for a 2.5-only bidder ===\n");
+
+    let report = downgrade_to_2_5(&mut request)?;
+    for change in &report.changes {
+        println!("  {} <- {:?}", change.path, change.kind);
+    }
+
+    Ok(())
 }
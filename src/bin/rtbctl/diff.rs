@@ -0,0 +1,149 @@
+//! Differences between the JSON forms of a payload before and after a
+//! round trip
+
+use serde_json::{Map, Value};
+
+/// Paths where `after` lost or changed a value of `before`, e.g.
+/// `imp[0].ext.channel: 546 -> (missing)`.
+///
+/// Default values count as absent and flags as their 0/1 integers, since
+/// the JSON codec omits defaults and writes booleans as integers. Numbers
+/// encoded as strings, as 64 bit integers are, equal their numeric value.
+pub fn diff(before: &Value, after: &Value) -> Vec<String> {
+    let mut differences = Vec::new();
+    walk("", before, after, &mut differences);
+    differences
+}
+
+fn walk(path: &str, before: &Value, after: &Value, differences: &mut Vec<String>) {
+    if before.is_object() || after.is_object() {
+        let empty = Map::new();
+        let (Some(before_object), Some(after_object)) =
+            (as_object(before, &empty), as_object(after, &empty))
+        else {
+            differences.push(format!("{path}: {} -> {}", show(before), show(after)));
+            return;
+        };
+
+        let keys = before_object.keys().chain(
+            after_object
+                .keys()
+                .filter(|k| !before_object.contains_key(*k)),
+        );
+        for key in keys {
+            let path = if path.is_empty() {
+                key.clone()
+            } else {
+                format!("{path}.{key}")
+            };
+            walk(
+                &path,
+                before_object.get(key).unwrap_or(&Value::Null),
+                after_object.get(key).unwrap_or(&Value::Null),
+                differences,
+            );
+        }
+        return;
+    }
+
+    if let (Value::Array(a), Value::Array(b)) = (before, after)
+        && a.len() == b.len()
+    {
+        for (i, (a, b)) in a.iter().zip(b).enumerate() {
+            walk(&format!("{path}[{i}]"), a, b, differences);
+        }
+        return;
+    }
+
+    if !equivalent(before, after) {
+        differences.push(format!("{path}: {} -> {}", show(before), show(after)));
+    }
+}
+
+/// Objects, with absent ones as empty
+fn as_object<'a>(
+    value: &'a Value,
+    empty: &'a Map<String, Value>,
+) -> Option<&'a Map<String, Value>> {
+    match value {
+        Value::Object(object) => Some(object),
+        _ if is_default(value) => Some(empty),
+        _ => None,
+    }
+}
+
+fn equivalent(a: &Value, b: &Value) -> bool {
+    if is_default(a) || is_default(b) {
+        return is_default(a) && is_default(b);
+    }
+    match (number(a), number(b)) {
+        (Some(a), Some(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Values the JSON codec leaves out
+fn is_default(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::Bool(b) => !b,
+        Value::String(s) => s.is_empty(),
+        Value::Array(items) => items.is_empty(),
+        Value::Object(object) => object.values().all(is_default),
+        Value::Number(_) => number(value) == Some(0.0),
+    }
+}
+
+fn number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::Bool(b) => Some(f64::from(u8::from(*b))),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    }
+}
+
+fn show(value: &Value) -> String {
+    match value {
+        Value::Null => "(missing)".to_string(),
+        _ => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_equivalent_forms() {
+        let before =
+            json!({"id": "1", "test": 0, "tmax": 100, "dt": 5, "imp": [{"secure": 1, "ext": {}}]});
+        let after = json!({"id": "1", "tmax": 100, "dt": "5", "imp": [{"secure": true}]});
+        assert!(diff(&before, &after).is_empty());
+    }
+
+    #[test]
+    fn test_reports_changes() {
+        let before = json!({
+            "id": "1",
+            "imp": [{"id": "a", "ext": {"channel": 546, "gpid": "g"}}],
+            "cur": ["USD"]
+        });
+        let after = json!({
+            "id": "2",
+            "imp": [{"id": "a", "ext": {"gpid": "g"}}],
+            "bcat": ["IAB1"]
+        });
+
+        assert_eq!(
+            diff(&before, &after),
+            [
+                r#"cur: ["USD"] -> (missing)"#,
+                r#"id: "1" -> "2""#,
+                "imp[0].ext.channel: 546 -> (missing)",
+                r#"bcat: (missing) -> ["IAB1"]"#,
+            ]
+        );
+    }
+}
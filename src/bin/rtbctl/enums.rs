@@ -0,0 +1,201 @@
+//! Enumerated fields of a payload with their spec list descriptions
+
+use rtb::spec::{adcom, openrtb};
+use serde_json::Value;
+
+type Describe = fn(i64) -> Option<&'static str>;
+
+/// An enumerated field, one per element of list fields such as `api`
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnumField {
+    pub path: String,
+    pub value: i64,
+    /// Spec list the value is from, e.g. `adcom::devicetype`
+    pub list: &'static str,
+    /// `None` when the value is not in the list, e.g. exchange specific
+    pub description: Option<&'static str>,
+}
+
+macro_rules! list {
+    ($spec:ident :: $list:ident) => {
+        list!($spec::$list, u32)
+    };
+    ($spec:ident :: $list:ident, $ty:ty) => {
+        Some((
+            concat!(stringify!($spec), "::", stringify!($list)),
+            (|v: i64| <$ty>::try_from(v).ok().and_then($spec::$list::description)) as Describe,
+        ))
+    };
+}
+
+/// The spec list of a field, by its name and the name of the object or
+/// array holding it
+fn list(parent: Option<&str>, key: &str) -> Option<(&'static str, Describe)> {
+    match (parent, key) {
+        (_, "devicetype") => list!(adcom::devicetype),
+        (_, "connectiontype") => list!(adcom::connection_types),
+        (_, "pos") => list!(adcom::placement_positions),
+        (_, "battr" | "attr") => list!(adcom::creative_attributes),
+        (_, "api") => list!(adcom::api_frameworks),
+        (_, "playbackmethod") => list!(adcom::playback_methods),
+        (_, "playbackend") => list!(adcom::playback_cessation_modes),
+        (_, "linearity") => list!(adcom::linearity_modes),
+        (_, "startdelay") => list!(adcom::start_delay_modes, i32),
+        (_, "slotinpod") => list!(adcom::slot_position_in_pod, i32),
+        (_, "podseq") => list!(adcom::pod_sequence, i32),
+        (_, "poddedupe") => list!(adcom::pod_deduplication_settings),
+        (_, "delivery") => list!(adcom::delivery_methods),
+        (_, "plcmt") => list!(adcom::video_plcmt_subtypes),
+        (_, "protocols" | "protocol") => list!(adcom::creative_subtypes_audio_video),
+        (_, "companiontype") => list!(adcom::companion_types),
+        (_, "expdir") => list!(adcom::expandable_directions),
+        (_, "feed") => list!(adcom::feed_types),
+        (_, "nvol") => list!(adcom::volume_normalization_modes),
+        (_, "prodq") => list!(adcom::production_qualities),
+        (_, "qagmediarating") => list!(adcom::media_ratings),
+        (_, "cattax") => list!(adcom::category_taxonomies),
+        (Some("content"), "context") => list!(adcom::content_contexts),
+        (Some("geo"), "type") => list!(adcom::location_types),
+        (Some("geo"), "ipservice") => list!(adcom::ip_location_services),
+        (Some("eids"), "mm") => list!(adcom::id_match_methods),
+        (Some("uids"), "atype") => list!(adcom::agent_types),
+        (Some("sua"), "source") => list!(adcom::user_agent_source),
+        (Some("video"), "placement") => list!(openrtb::video_placement_types),
+        (Some("bid"), "mtype") => list!(openrtb::creative_markup_types),
+        (None, "nbr") => list!(openrtb::nobidreason),
+        _ => None,
+    }
+}
+
+/// Every enumerated field of a payload in its JSON form
+pub fn enum_fields(payload: &Value) -> Vec<EnumField> {
+    let mut fields = Vec::new();
+    walk(payload, None, "", &mut fields);
+    fields
+}
+
+fn walk(value: &Value, parent: Option<&str>, path: &str, fields: &mut Vec<EnumField>) {
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object {
+                let path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{path}.{key}")
+                };
+
+                match list(parent, key) {
+                    Some(list) => push(value, &path, list, fields),
+                    None => walk(value, Some(key), &path, fields),
+                }
+            }
+        }
+        Value::Array(items) => {
+            for (i, item) in items.iter().enumerate() {
+                walk(item, parent, &format!("{path}[{i}]"), fields);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn push(
+    value: &Value,
+    path: &str,
+    (list, describe): (&'static str, Describe),
+    fields: &mut Vec<EnumField>,
+) {
+    match value {
+        Value::Array(items) => {
+            for (i, item) in items.iter().enumerate() {
+                push(item, &format!("{path}[{i}]"), (list, describe), fields);
+            }
+        }
+        _ => {
+            if let Some(value) = value.as_i64() {
+                fields.push(EnumField {
+                    path: path.to_string(),
+                    value,
+                    list,
+                    description: describe(value),
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_enum_fields() {
+        let request = json!({
+            "id": "r1",
+            "imp": [{"id": "1", "video": {"plcmt": 1, "api": [7, 500], "startdelay": -1}}],
+            "device": {"devicetype": 4, "geo": {"type": 2}},
+            "site": {"content": {"context": 1}}
+        });
+
+        let mut fields: Vec<(String, &str, Option<&str>)> = enum_fields(&request)
+            .into_iter()
+            .map(|f| (f.path, f.list, f.description))
+            .collect();
+        fields.sort();
+
+        let expected = [
+            ("device.devicetype", "adcom::devicetype", Some("Phone")),
+            (
+                "device.geo.type",
+                "adcom::location_types",
+                adcom::location_types::description(2),
+            ),
+            (
+                "imp[0].video.api[0]",
+                "adcom::api_frameworks",
+                adcom::api_frameworks::description(7),
+            ),
+            ("imp[0].video.api[1]", "adcom::api_frameworks", None),
+            (
+                "imp[0].video.plcmt",
+                "adcom::video_plcmt_subtypes",
+                adcom::video_plcmt_subtypes::description(1),
+            ),
+            (
+                "imp[0].video.startdelay",
+                "adcom::start_delay_modes",
+                adcom::start_delay_modes::description(-1),
+            ),
+            (
+                "site.content.context",
+                "adcom::content_contexts",
+                adcom::content_contexts::description(1),
+            ),
+        ];
+        assert_eq!(fields.len(), expected.len());
+        for (field, (path, list, description)) in fields.iter().zip(expected) {
+            assert_eq!(
+                (field.0.as_str(), field.1, field.2),
+                (path, list, description)
+            );
+            assert!(field.2.is_some() || path.ends_with("api[1]"));
+        }
+    }
+
+    #[test]
+    fn test_response_fields() {
+        let response =
+            json!({"id": "r1", "nbr": 2, "seatbid": [{"bid": [{"mtype": 2, "attr": [1]}]}]});
+        let mut lists: Vec<&str> = enum_fields(&response).iter().map(|f| f.list).collect();
+        lists.sort();
+        assert_eq!(
+            lists,
+            [
+                "adcom::creative_attributes",
+                "openrtb::creative_markup_types",
+                "openrtb::nobidreason"
+            ]
+        );
+    }
+}
@@ -0,0 +1,274 @@
+//! `rtbctl`, a tool to inspect and convert OpenRTB payloads.
+//!
+//! ```text
+//! rtbctl print request.json
+//! rtbctl validate --kind response --stream responses.jsonl.gz
+//! rtbctl convert --to protobuf --stream capture.jsonl capture.pb
+//! rtbctl roundtrip --protobuf request.json
+//! rtbctl enums request.pb
+//! rtbctl consent CPXxRfAPXxRfAAfKABENB-CgAAAAAAAAAAYgAAAAAAAA
+//! ```
+
+mod diff;
+mod enums;
+mod payload;
+
+use anyhow::{Result, bail};
+use clap::{Args, Parser, Subcommand};
+use payload::{Format, Kind, Payload};
+use rtb::openrtb::utils::consent::{TcfConsent, UsPrivacy};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+#[derive(Parser)]
+#[command(
+    name = "rtbctl",
+    version,
+    about = "Inspect, validate and convert OpenRTB payloads"
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Pretty-prints payloads as JSON
+    Print(Input),
+    /// Checks that payloads decode and have their required fields
+    Validate {
+        #[command(flatten)]
+        input: Input,
+        /// Also fail on enum values missing from the spec lists
+        #[arg(long)]
+        strict: bool,
+    },
+    /// Converts payloads between JSON and protobuf
+    Convert {
+        #[command(flatten)]
+        input: Input,
+        /// File to write, `-` for stdout
+        output: PathBuf,
+        #[arg(long, value_enum)]
+        to: Format,
+        /// Compresses the output with gzip
+        #[arg(long)]
+        gzip: bool,
+    },
+    /// Checks that decoding and re-encoding keeps every field, including
+    /// custom `ext` fields
+    Roundtrip {
+        #[command(flatten)]
+        input: Input,
+        /// Round trips through protobuf, which drops custom `ext` fields
+        #[arg(long)]
+        protobuf: bool,
+    },
+    /// Lists the enumerated fields of payloads with their spec descriptions
+    Enums(Input),
+    /// Decodes a TCF v2 or US Privacy consent string
+    Consent { value: String },
+}
+
+#[derive(Args)]
+struct Input {
+    /// File to read, `-` for stdin. Gzip and zstd input is detected.
+    path: PathBuf,
+    #[arg(long, value_enum, default_value_t = Kind::Request)]
+    kind: Kind,
+    /// Encoding of the input, detected from its content when omitted
+    #[arg(long, value_enum)]
+    format: Option<Format>,
+    /// The input holds many payloads, as JSON lines or length-delimited
+    /// protobuf messages
+    #[arg(long)]
+    stream: bool,
+}
+
+impl Input {
+    /// Reads the input and splits it into its payloads
+    fn read(&self) -> Result<(Format, Vec<Vec<u8>>)> {
+        let bytes = payload::read_input(&self.path)?;
+        let format = self.format.unwrap_or_else(|| Format::detect(&bytes));
+
+        let messages = if self.stream {
+            payload::split_stream(&bytes, format)?
+        } else {
+            vec![bytes]
+        };
+        Ok((format, messages))
+    }
+
+    /// Prefix of the lines reported for a payload
+    fn label(&self, index: usize) -> String {
+        if self.stream {
+            format!("{}#{index}", self.path.display())
+        } else {
+            self.path.display().to_string()
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    match run(Cli::parse().command) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("error: {e:#}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+/// Runs a command, returning false if it found problems
+fn run(command: Command) -> Result<bool> {
+    match command {
+        Command::Print(input) => print(&input),
+        Command::Validate { input, strict } => validate(&input, strict),
+        Command::Convert {
+            input,
+            output,
+            to,
+            gzip,
+        } => convert(&input, &output, to, gzip),
+        Command::Roundtrip { input, protobuf } => roundtrip(&input, protobuf),
+        Command::Enums(input) => print_enums(&input),
+        Command::Consent { value } => consent(&value),
+    }
+}
+
+fn print(input: &Input) -> Result<bool> {
+    let (format, messages) = input.read()?;
+    for (i, message) in messages.iter().enumerate() {
+        let json = Payload::decode(input.kind, format, message).and_then(|p| p.to_json());
+        match json {
+            Ok(json) => println!("{}", serde_json::to_string_pretty(&json)?),
+            Err(e) => bail!("{}: {e:#}", input.label(i)),
+        }
+    }
+    Ok(true)
+}
+
+fn validate(input: &Input, strict: bool) -> Result<bool> {
+    let (format, messages) = input.read()?;
+    let mut valid = true;
+
+    for (i, message) in messages.iter().enumerate() {
+        let label = input.label(i);
+        let payload = match Payload::decode(input.kind, format, message) {
+            Ok(payload) => payload,
+            Err(e) => {
+                println!("{label}: invalid: {e:#}");
+                valid = false;
+                continue;
+            }
+        };
+
+        let mut problems = payload.problems();
+        let unknown: Vec<String> = enums::enum_fields(&payload.to_json()?)
+            .into_iter()
+            .filter(|field| field.description.is_none())
+            .map(|field| format!("{} = {} is not in {}", field.path, field.value, field.list))
+            .collect();
+
+        for warning in &unknown {
+            println!("{label}: warning: {warning}");
+        }
+        if strict {
+            problems.extend(unknown);
+        }
+
+        if problems.is_empty() {
+            println!("{label}: ok");
+        } else {
+            for problem in problems {
+                println!("{label}: invalid: {problem}");
+            }
+            valid = false;
+        }
+    }
+
+    Ok(valid)
+}
+
+fn convert(input: &Input, output: &Path, to: Format, gzip: bool) -> Result<bool> {
+    let (format, messages) = input.read()?;
+    let mut out = Vec::new();
+
+    for (i, message) in messages.iter().enumerate() {
+        match Payload::decode(input.kind, format, message) {
+            Ok(payload) => payload.encode(to, input.stream, &mut out)?,
+            Err(e) => bail!("{}: {e:#}", input.label(i)),
+        }
+    }
+
+    payload::write_output(output, &out, gzip)?;
+    Ok(true)
+}
+
+fn roundtrip(input: &Input, protobuf: bool) -> Result<bool> {
+    let (format, messages) = input.read()?;
+    if format != Format::Json {
+        bail!("the round trip check needs JSON input");
+    }
+
+    let mut lossless = true;
+    for (i, message) in messages.iter().enumerate() {
+        let label = input.label(i);
+        let payload = match Payload::decode(input.kind, format, message) {
+            Ok(payload) if protobuf => payload.via_protobuf()?,
+            Ok(payload) => payload,
+            Err(e) => bail!("{label}: {e:#}"),
+        };
+
+        let differences = diff::diff(&serde_json::from_slice(message)?, &payload.to_json()?);
+        if differences.is_empty() {
+            println!("{label}: lossless");
+        }
+        for difference in differences {
+            println!("{label}: {difference}");
+            lossless = false;
+        }
+    }
+
+    Ok(lossless)
+}
+
+fn print_enums(input: &Input) -> Result<bool> {
+    let (format, messages) = input.read()?;
+
+    for (i, message) in messages.iter().enumerate() {
+        let label = input.label(i);
+        let payload = match Payload::decode(input.kind, format, message) {
+            Ok(payload) => payload,
+            Err(e) => bail!("{label}: {e:#}"),
+        };
+
+        for field in enums::enum_fields(&payload.to_json()?) {
+            let description = field.description.unwrap_or("(not in list)");
+            let prefix = if input.stream {
+                format!("{label} ")
+            } else {
+                String::new()
+            };
+            println!(
+                "{prefix}{} = {} {description} [{}]",
+                field.path, field.value, field.list
+            );
+        }
+    }
+
+    Ok(true)
+}
+
+/// US Privacy strings are four characters starting with their version 1,
+/// anything else is taken as TCF
+fn consent(value: &str) -> Result<bool> {
+    let json = if value.len() == 4 && value.starts_with('1') {
+        serde_json::to_string_pretty(&UsPrivacy::parse(value)?)?
+    } else {
+        serde_json::to_string_pretty(&TcfConsent::parse(value)?)?
+    };
+    println!("{json}");
+    Ok(true)
+}
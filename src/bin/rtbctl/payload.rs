@@ -0,0 +1,242 @@
+//! Reading, decoding and encoding of the payloads rtbctl works on
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use prost::Message;
+use rtb::stream::{self, Compression, Encoder, RecordFormat, RecordReader};
+use rtb::{BidRequest, BidResponse};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Kind {
+    Request,
+    Response,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    Json,
+    Protobuf,
+}
+
+impl Format {
+    /// JSON when the first non-whitespace byte opens an object
+    pub fn detect(bytes: &[u8]) -> Self {
+        match bytes.iter().find(|b| !b.is_ascii_whitespace()) {
+            Some(b'{') => Format::Json,
+            _ => Format::Protobuf,
+        }
+    }
+}
+
+pub enum Payload {
+    Request(BidRequest),
+    Response(BidResponse),
+}
+
+impl Payload {
+    pub fn decode(kind: Kind, format: Format, bytes: &[u8]) -> Result<Self> {
+        Ok(match (kind, format) {
+            (Kind::Request, Format::Json) => Payload::Request(serde_json::from_slice(bytes)?),
+            (Kind::Request, Format::Protobuf) => Payload::Request(BidRequest::decode(bytes)?),
+            (Kind::Response, Format::Json) => Payload::Response(serde_json::from_slice(bytes)?),
+            (Kind::Response, Format::Protobuf) => Payload::Response(BidResponse::decode(bytes)?),
+        })
+    }
+
+    /// Appends the payload to `out`, as a JSON line or a length-delimited
+    /// message when `stream` is set
+    pub fn encode(&self, format: Format, stream: bool, out: &mut Vec<u8>) -> Result<()> {
+        match format {
+            Format::Json => {
+                match self {
+                    Payload::Request(request) => serde_json::to_writer(&mut *out, request)?,
+                    Payload::Response(response) => serde_json::to_writer(&mut *out, response)?,
+                }
+                out.push(b'\n');
+            }
+            Format::Protobuf if stream => match self {
+                Payload::Request(request) => request.encode_length_delimited(out)?,
+                Payload::Response(response) => response.encode_length_delimited(out)?,
+            },
+            Format::Protobuf => match self {
+                Payload::Request(request) => request.encode(out)?,
+                Payload::Response(response) => response.encode(out)?,
+            },
+        }
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> Result<Value> {
+        Ok(match self {
+            Payload::Request(request) => serde_json::to_value(request)?,
+            Payload::Response(response) => serde_json::to_value(response)?,
+        })
+    }
+
+    /// Through protobuf and back, which drops what protobuf cannot carry
+    pub fn via_protobuf(&self) -> Result<Self> {
+        let mut bytes = Vec::new();
+        self.encode(Format::Protobuf, false, &mut bytes)?;
+        Payload::decode(self.kind(), Format::Protobuf, &bytes)
+    }
+
+    fn kind(&self) -> Kind {
+        match self {
+            Payload::Request(_) => Kind::Request,
+            Payload::Response(_) => Kind::Response,
+        }
+    }
+
+    /// Required fields which are missing or inconsistent
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        match self {
+            Payload::Request(request) => {
+                if request.id.is_empty() {
+                    problems.push("id is empty".to_string());
+                }
+                if request.imp.is_empty() {
+                    problems.push("imp is empty".to_string());
+                }
+                let mut ids = HashSet::new();
+                for (i, imp) in request.imp.iter().enumerate() {
+                    if imp.id.is_empty() {
+                        problems.push(format!("imp[{i}].id is empty"));
+                    } else if !ids.insert(imp.id.as_str()) {
+                        problems.push(format!("imp[{i}].id {:?} is not unique", imp.id));
+                    }
+                }
+            }
+            Payload::Response(response) => {
+                if response.id.is_empty() {
+                    problems.push("id is empty".to_string());
+                }
+                for (i, seatbid) in response.seatbid.iter().enumerate() {
+                    for (j, bid) in seatbid.bid.iter().enumerate() {
+                        let path = format!("seatbid[{i}].bid[{j}]");
+                        if bid.id.is_empty() {
+                            problems.push(format!("{path}.id is empty"));
+                        }
+                        if bid.impid.is_empty() {
+                            problems.push(format!("{path}.impid is empty"));
+                        }
+                        if bid.price < 0.0 || !bid.price.is_finite() {
+                            problems.push(format!("{path}.price {} is invalid", bid.price));
+                        }
+                    }
+                }
+            }
+        }
+
+        problems
+    }
+}
+
+/// Reads a file, or stdin for `-`, decompressing gzip and zstd
+pub fn read_input(path: &Path) -> Result<Vec<u8>> {
+    let mut reader = if path == Path::new("-") {
+        stream::decompress(BufReader::new(io::stdin()))?
+    } else {
+        stream::open(path).with_context(|| format!("reading {}", path.display()))?
+    };
+
+    let mut bytes = Vec::new();
+    reader
+        .read_to_end(&mut bytes)
+        .with_context(|| format!("reading {}", path.display()))?;
+    Ok(bytes)
+}
+
+/// Writes to a file, or stdout for `-`
+pub fn write_output(path: &Path, bytes: &[u8], gzip: bool) -> Result<()> {
+    let writer: Box<dyn Write> = if path == Path::new("-") {
+        Box::new(io::stdout().lock())
+    } else {
+        Box::new(File::create(path).with_context(|| format!("creating {}", path.display()))?)
+    };
+
+    let compression = if gzip {
+        Compression::Gzip
+    } else {
+        Compression::None
+    };
+    let mut encoder = Encoder::new(writer, compression)?;
+    encoder.write_all(bytes)?;
+    encoder.finish()?;
+    Ok(())
+}
+
+/// Splits a stream into its messages: non-blank JSON lines or
+/// length-delimited protobuf messages
+pub fn split_stream(bytes: &[u8], format: Format) -> Result<Vec<Vec<u8>>> {
+    let format = match format {
+        Format::Json => RecordFormat::Jsonl,
+        Format::Protobuf => RecordFormat::Protobuf,
+    };
+
+    let messages = RecordReader::new(bytes, format)
+        .map(|record| record.map(|record| record.bytes))
+        .collect::<Result<_, _>>()?;
+    Ok(messages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REQUEST: &str = r#"{"id":"r1","imp":[{"id":"1"},{"id":"1"}]}"#;
+
+    #[test]
+    fn test_detect_format() {
+        assert_eq!(Format::detect(b"  \n{\"id\":1}"), Format::Json);
+        assert_eq!(Format::detect(&[0x0a, 0x02, b'r', b'1']), Format::Protobuf);
+    }
+
+    #[test]
+    fn test_split_streams() {
+        let request = Payload::decode(Kind::Request, Format::Json, REQUEST.as_bytes()).unwrap();
+
+        for format in [Format::Json, Format::Protobuf] {
+            let mut stream = Vec::new();
+            request.encode(format, true, &mut stream).unwrap();
+            request.encode(format, true, &mut stream).unwrap();
+
+            let messages = split_stream(&stream, format).unwrap();
+            assert_eq!(messages.len(), 2);
+            let Payload::Request(decoded) =
+                Payload::decode(Kind::Request, format, &messages[1]).unwrap()
+            else {
+                panic!("expected a request");
+            };
+            assert_eq!(decoded.id, "r1");
+        }
+
+        assert!(split_stream(&[0x05, 0x0a], Format::Protobuf).is_err());
+    }
+
+    #[test]
+    fn test_problems() {
+        let request = Payload::decode(Kind::Request, Format::Json, REQUEST.as_bytes()).unwrap();
+        assert_eq!(request.problems(), [r#"imp[1].id "1" is not unique"#]);
+
+        let response = Payload::decode(
+            Kind::Response,
+            Format::Json,
+            br#"{"id":"r1","seatbid":[{"bid":[{"id":"b1","price":-1}]}]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            response.problems(),
+            [
+                "seatbid[0].bid[0].impid is empty",
+                "seatbid[0].bid[0].price -1 is invalid"
+            ]
+        );
+    }
+}
@@ -1,6 +1,97 @@
+#[cfg(feature = "crypto")]
+use super::keyring::{Keyring, NonceSet, SignatureError, new_nonce};
+use super::packed;
+#[cfg(feature = "crypto")]
+use super::utils::epoch_timestamp;
+use crate::openrtb::spec::auction_macros::AuctionMacro;
+#[cfg(feature = "crypto")]
+use crate::openrtb::utils::price_crypto::PriceCrypter;
 use anyhow::{Error, Result, anyhow, bail};
+#[cfg(feature = "crypto")]
+use derive_builder::Builder;
+use serde::de::{DeserializeOwned, Deserializer, MapAccess, Visitor};
 use serde::{Deserialize, Serialize};
-use url::Url;
+use std::borrow::Cow;
+use std::sync::Arc;
+#[cfg(feature = "crypto")]
+use std::time::Duration;
+use url::{Url, form_urlencoded};
+
+/// Query parameters added by [`DataUrl::finalize_signed`], reserved on
+/// signed URLs
+const KEY_ID_PARAM: &str = "kid";
+const EXPIRY_PARAM: &str = "exp";
+const NONCE_PARAM: &str = "nonce";
+const SIGNATURE_PARAM: &str = "sig";
+/// Keys of the parameters added with [`DataUrl::add_macro`], whose values
+/// are substituted after signing
+const MACRO_KEYS_PARAM: &str = "mk";
+const SIGNING_PARAMS: [&str; 5] = [
+    KEY_ID_PARAM,
+    EXPIRY_PARAM,
+    NONCE_PARAM,
+    SIGNATURE_PARAM,
+    MACRO_KEYS_PARAM,
+];
+
+/// How URLs are signed by [`DataUrl::finalize_signed`] and verified by
+/// [`DataUrl::from_verified`]
+#[cfg(feature = "crypto")]
+#[derive(Clone, Builder)]
+pub struct UrlSigning {
+    /// Signs URLs with its active key and verifies them with the key they
+    /// name
+    pub keyring: Keyring,
+
+    /// Lifetime of signed URLs
+    #[builder(default)]
+    pub ttl: Option<Duration>,
+
+    /// Seen nonces, so that each URL is accepted once
+    pub nonces: Arc<dyn NonceSet>,
+
+    /// Decrypts the values substituted for macro parameters, which the
+    /// signature cannot cover. URLs with macro parameters are only signed
+    /// with one, so that each of them must carry an encrypted price.
+    #[builder(default)]
+    pub price_crypter: Option<Arc<dyn PriceCrypter>>,
+}
+
+/// Parameters packed by [`DataUrl::set_encoding`]
+const PACKED_PARAM: &str = "_p";
+
+/// How the parameters of a [`DataUrl`] are written into its query string
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DataUrlEncoding {
+    /// One `key=value` pair per parameter
+    #[default]
+    Query,
+    /// Every parameter but macros packed into a single base64url blob, with
+    /// varints for integers and each key written once, optionally deflated
+    /// with the `gzip` feature. Shorter for many or repeated parameters, at
+    /// the cost of readability.
+    Packed { deflate: bool },
+}
+
+/// A parameter of a parsed URL still holds the macro it was built with, e.g.
+/// `${AUCTION_PRICE}`, because it was fired without substitution
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnsubstitutedMacro {
+    pub key: String,
+    pub value: String,
+}
+
+impl std::fmt::Display for UnsubstitutedMacro {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Parameter '{}' holds unsubstituted macro {}",
+            self.key, self.value
+        )
+    }
+}
+
+impl std::error::Error for UnsubstitutedMacro {}
 
 /// A URL builder that supports typed key-value pairs with finalization semantics.
 ///
@@ -20,12 +111,27 @@ use url::Url;
 /// # Ok(())
 /// # }
 /// ```
+///
+/// Beacons which carry values worth forging, such as a win price, should be
+/// signed with [`DataUrl::finalize_signed`] and parsed with
+/// [`DataUrl::from_verified`], which needs the `crypto` feature.
+///
+/// Long URLs can be shortened by packing the parameters into a single `_p`
+/// parameter with [`DataUrl::set_encoding`], which [`DataUrl::from`] and the
+/// getters read transparently.
 #[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
 pub struct DataUrl {
     domain: String,
     path: String,
     url: Url,
     finalized: bool,
+    /// The parameters held by the packed parameter, if any
+    #[serde(default)]
+    packed: Vec<(Arc<str>, String)>,
+    /// Keys added with [`DataUrl::add_macro`], signed without their value
+    #[cfg(feature = "crypto")]
+    #[serde(default)]
+    macro_keys: Vec<String>,
 }
 
 #[allow(dead_code)]
@@ -51,16 +157,34 @@ impl DataUrl {
             path: path.to_string(),
             url,
             finalized: false,
+            packed: Vec::new(),
+            #[cfg(feature = "crypto")]
+            macro_keys: Vec::new(),
         })
     }
 
     /// Clones this DataUrl and returns it in an unfinalized state
-    /// for further parameter additions
+    /// for further parameter additions. The signature of a signed URL is
+    /// dropped, it must be signed again once finalized.
     pub fn clone_unfinalized(&self) -> Self {
         let mut clone = self.clone();
 
         clone.finalized = false;
 
+        if self.get_param(SIGNATURE_PARAM).is_some() {
+            let pairs: Vec<(String, String)> = self
+                .url
+                .query_pairs()
+                .filter(|(k, _)| !SIGNING_PARAMS.contains(&&**k))
+                .map(|(k, v)| (k.into_owned(), v.into_owned()))
+                .collect();
+
+            clone.url.set_query(None);
+            if !pairs.is_empty() {
+                clone.url.query_pairs_mut().extend_pairs(pairs);
+            }
+        }
+
         clone
     }
 
@@ -68,7 +192,22 @@ impl DataUrl {
     ///
     /// This is useful for parsing beacon URLs that have already been constructed.
     /// The returned DataUrl is immediately finalized and cannot be mutated.
+    /// Packed parameters are unpacked, so the getters work with either
+    /// encoding. Deflated packed URLs are rejected without the `gzip`
+    /// feature.
+    ///
+    /// The signature of a signed URL is not checked, see
+    /// [`DataUrl::from_verified`].
+    ///
+    /// # Errors
+    /// Returns an error if the URL cannot be parsed.
     pub fn from(url_str: &str) -> Result<Self> {
+        Self::parse(url_str)?.unpacked()
+    }
+
+    /// Parses a URL, leaving the packed parameter for [`DataUrl::unpacked`]
+    /// so that a signature is verified before the blob is decoded
+    fn parse(url_str: &str) -> Result<Self> {
         let url = Url::parse(url_str)?;
 
         let domain = url
@@ -83,9 +222,90 @@ impl DataUrl {
             path,
             url,
             finalized: true,
+            packed: Vec::new(),
+            #[cfg(feature = "crypto")]
+            macro_keys: Vec::new(),
         })
     }
 
+    /// Reads the parameters held by the packed parameter, if any
+    fn unpacked(mut self) -> Result<Self> {
+        if let Some((_, blob)) = self.url.query_pairs().find(|(k, _)| k == PACKED_PARAM) {
+            self.packed = packed::unpack(&blob)?;
+        }
+        Ok(self)
+    }
+
+    /// Parses a URL signed by [`DataUrl::finalize_signed`], rejecting it
+    /// unless the signature matches, the URL has not expired and its nonce
+    /// has not been seen before.
+    ///
+    /// The values substituted for macro parameters are decrypted with the
+    /// price crypter of `signing`, so that the getters read the clear price.
+    ///
+    /// # Errors
+    /// Returns an error if the URL cannot be parsed, or a [`SignatureError`]
+    /// if verification fails or a substituted value does not decrypt.
+    #[cfg(feature = "crypto")]
+    pub fn from_verified(url_str: &str, signing: &UrlSigning) -> Result<Self> {
+        let url = Self::parse(url_str)?;
+        url.verify(&signing.keyring, epoch_timestamp())?;
+        let url = url.decrypt_macros(signing.price_crypter.as_deref())?;
+        url.record_nonce(signing.nonces.as_ref())?;
+        url.unpacked()
+    }
+
+    /// Verifies the signature of the URL with the key its key id names, and
+    /// that it has not expired at `now_ms` (epoch millis). The values of
+    /// macro parameters are not covered, see [`DataUrl::from_verified`].
+    #[cfg(feature = "crypto")]
+    pub fn verify(&self, keyring: &Keyring, now_ms: u64) -> Result<(), SignatureError> {
+        let (Some(key_id), Some(signature)) = (
+            self.get_param(KEY_ID_PARAM),
+            self.get_param(SIGNATURE_PARAM),
+        ) else {
+            return Err(SignatureError::Missing);
+        };
+
+        keyring.verify(&key_id, self.signing_payload().as_bytes(), &signature)?;
+
+        if let Some(expiry) = self.get_param(EXPIRY_PARAM) {
+            let expired_at = expiry.parse::<u64>().map_err(|_| SignatureError::Invalid)?;
+            if now_ms / 1000 > expired_at {
+                return Err(SignatureError::Expired { expired_at });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Like [`DataUrl::verify`], also rejecting the URL if `nonces` has seen
+    /// its nonce before, and recording it otherwise.
+    #[cfg(feature = "crypto")]
+    pub fn verify_once(
+        &self,
+        keyring: &Keyring,
+        nonces: &dyn NonceSet,
+        now_ms: u64,
+    ) -> Result<(), SignatureError> {
+        self.verify(keyring, now_ms)?;
+        self.record_nonce(nonces)
+    }
+
+    /// Records the nonce of a verified URL, rejecting it if seen before
+    #[cfg(feature = "crypto")]
+    fn record_nonce(&self, nonces: &dyn NonceSet) -> Result<(), SignatureError> {
+        let nonce = self.get_param(NONCE_PARAM).ok_or(SignatureError::Missing)?;
+        let expires_at = self
+            .get_param(EXPIRY_PARAM)
+            .and_then(|expiry| expiry.parse().ok());
+        if !nonces.insert(&nonce, expires_at) {
+            return Err(SignatureError::Replayed);
+        }
+
+        Ok(())
+    }
+
     /// Adds a string parameter to the URL.
     ///
     /// # Errors
@@ -140,16 +360,60 @@ impl DataUrl {
         Ok(self)
     }
 
+    /// Adds a parameter whose value is a macro the exchange or player
+    /// substitutes, e.g. `${AUCTION_PRICE}` or the VAST `[TIMESTAMP]`.
+    ///
+    /// Unlike [`DataUrl::add_string`] the macro is kept literal in the output
+    /// of [`DataUrl::url`] rather than percent-encoded, which would keep it
+    /// from being substituted.
+    ///
+    /// # Errors
+    /// Returns an error if the URL has already been finalized or `value` is
+    /// not a `${NAME}` or `[NAME]` macro.
+    pub fn add_macro(&mut self, key: &str, value: &str) -> Result<&mut Self> {
+        if self.finalized {
+            bail!("Cannot add parameters to a finalized DataUrl");
+        }
+        if !is_macro(value) {
+            bail!("'{}' is not a ${{NAME}} or [NAME] macro", value);
+        }
+
+        #[cfg(feature = "crypto")]
+        self.macro_keys.push(key.to_string());
+        self.append_literal(key, value);
+        Ok(self)
+    }
+
+    /// Appends a parameter whose value is written as is
+    fn append_literal(&mut self, key: &str, value: &str) {
+        let key: String = form_urlencoded::byte_serialize(key.as_bytes()).collect();
+        let query = match self.url.query() {
+            Some(query) if !query.is_empty() => format!("{query}&{key}={value}"),
+            _ => format!("{key}={value}"),
+        };
+        self.url.set_query(Some(&query));
+    }
+
+    /// Adds one of the OpenRTB auction macros, see [`DataUrl::add_macro`].
+    ///
+    /// # Errors
+    /// Returns an error if the URL has already been finalized.
+    pub fn add_auction_macro(&mut self, key: &str, value: AuctionMacro) -> Result<&mut Self> {
+        self.add_macro(key, value.as_str())
+    }
+
     /// Gets a string parameter from the URL.
     ///
     /// Returns `Ok(Some(value))` if the parameter exists,
     /// `Ok(None)` if the parameter is missing.
+    ///
+    /// All getters return an [`UnsubstitutedMacro`] error if the parameter
+    /// still holds a macro, i.e. the URL was fired without substitution. Only
+    /// macros written literally, as by [`DataUrl::add_macro`], count: a value
+    /// such as `[42]` added with [`DataUrl::add_string`] is percent-encoded
+    /// and read back as is.
     pub fn get_string(&self, key: &str) -> Result<Option<String>> {
-        Ok(self
-            .url
-            .query_pairs()
-            .find(|(k, _)| k == key)
-            .map(|(_, v)| v.to_string()))
+        self.lookup(key)
     }
 
     /// Gets a boolean parameter from the URL.
@@ -158,8 +422,8 @@ impl DataUrl {
     /// `Ok(None)` if the parameter is missing,
     /// `Err` if the parameter exists but cannot be parsed as a boolean.
     pub fn get_bool(&self, key: &str) -> Result<Option<bool>> {
-        match self.url.query_pairs().find(|(k, _)| k == key) {
-            Some((_, v)) => {
+        match self.lookup(key)? {
+            Some(v) => {
                 let parsed = v.parse::<bool>().map_err(|e| {
                     anyhow!(
                         "Failed to parse '{}' as boolean for key '{}': {}",
@@ -180,8 +444,8 @@ impl DataUrl {
     /// `Ok(None)` if the parameter is missing,
     /// `Err` if the parameter exists but cannot be parsed as an integer.
     pub fn get_int(&self, key: &str) -> Result<Option<i64>> {
-        match self.url.query_pairs().find(|(k, _)| k == key) {
-            Some((_, v)) => {
+        match self.lookup(key)? {
+            Some(v) => {
                 let parsed = v.parse::<i64>().map_err(|e| {
                     anyhow!(
                         "Failed to parse '{}' as integer for key '{}': {}",
@@ -202,8 +466,8 @@ impl DataUrl {
     /// `Ok(None)` if the parameter is missing,
     /// `Err` if the parameter exists but cannot be parsed as a float.
     pub fn get_float(&self, key: &str) -> Result<Option<f64>> {
-        match self.url.query_pairs().find(|(k, _)| k == key) {
-            Some((_, v)) => {
+        match self.lookup(key)? {
+            Some(v) => {
                 let parsed = v.parse::<f64>().map_err(|e| {
                     anyhow!("Failed to parse '{}' as float for key '{}': {}", v, key, e)
                 })?;
@@ -249,6 +513,183 @@ impl DataUrl {
             .ok_or_else(|| anyhow!("Missing required parameter: {}", key))
     }
 
+    /// Adds every field of `value` as a parameter, so a beacon schema can be
+    /// declared once as a struct. Short keys are chosen with
+    /// `#[serde(rename = "...")]`, `None` fields are skipped and string
+    /// fields holding a macro are added with [`DataUrl::add_macro`].
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() -> anyhow::Result<()> {
+    /// use serde::{Deserialize, Serialize};
+    ///
+    /// #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    /// struct Win {
+    ///     #[serde(rename = "a")]
+    ///     auction_id: String,
+    ///     #[serde(rename = "p")]
+    ///     price: String,
+    ///     #[serde(rename = "b")]
+    ///     bid_floor: Option<f64>,
+    /// }
+    ///
+    /// let mut url = rtb::common::DataUrl::new("example.com", "win")?;
+    /// url.encode(&Win {
+    ///     auction_id: "abc123".to_string(),
+    ///     price: "${AUCTION_PRICE}".to_string(),
+    ///     bid_floor: None,
+    /// })?;
+    /// url.finalize();
+    /// assert_eq!(
+    ///     url.url(true)?,
+    ///     "https://example.com/win?a=abc123&p=${AUCTION_PRICE}"
+    /// );
+    ///
+    /// let fired = rtb::common::DataUrl::from("https://example.com/win?a=abc123&p=1.25")?;
+    /// let win: Win = fired.decode()?;
+    /// assert_eq!(win.price, "1.25");
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    /// Returns an error if the URL has already been finalized, or `value`
+    /// does not serialize to a struct or map of scalar values.
+    pub fn encode<T: Serialize>(&mut self, value: &T) -> Result<&mut Self> {
+        if self.finalized {
+            bail!("Cannot add parameters to a finalized DataUrl");
+        }
+
+        let json = serde_json::to_string(value)?;
+        let Ok(Fields(fields)) = serde_json::from_str(&json) else {
+            bail!("Only structs and maps can be encoded into a DataUrl");
+        };
+
+        for (key, value) in fields {
+            match value {
+                serde_json::Value::Null => {}
+                serde_json::Value::String(s) if is_macro(&s) => {
+                    self.add_macro(&key, &s)?;
+                }
+                serde_json::Value::String(s) => {
+                    self.add_string(&key, &s)?;
+                }
+                serde_json::Value::Bool(b) => {
+                    self.add_bool(&key, b)?;
+                }
+                serde_json::Value::Number(n) => {
+                    self.add_string(&key, &n.to_string())?;
+                }
+                _ => bail!("Field '{}' is not a scalar and cannot be encoded", key),
+            }
+        }
+
+        Ok(self)
+    }
+
+    /// Reads the parameters back into `T`, the inverse of
+    /// [`DataUrl::encode`]. Parameters without a field are ignored, as are
+    /// the signature parameters of a signed URL.
+    ///
+    /// # Errors
+    /// Returns an [`UnsubstitutedMacro`] error if a parameter still holds a
+    /// macro, or an error if a field is missing or cannot be parsed.
+    pub fn decode<T: DeserializeOwned>(&self) -> Result<T> {
+        let mut query = form_urlencoded::Serializer::new(String::new());
+        for (k, v, is_macro) in self.data_entries() {
+            if is_macro {
+                return Err(UnsubstitutedMacro {
+                    key: k.into_owned(),
+                    value: v.into_owned(),
+                }
+                .into());
+            }
+            query.append_pair(&k, &v);
+        }
+
+        serde_urlencoded::from_str(&query.finish())
+            .map_err(|e| anyhow!("Failed to decode DataUrl parameters: {}", e))
+    }
+
+    /// Rewrites the parameters added so far with `encoding`, e.g. to pack
+    /// them before signing. Parameters added afterwards are appended as
+    /// plain pairs. Macros are never packed, as they must stay literal to be
+    /// substituted, and move after the other parameters.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() -> anyhow::Result<()> {
+    /// use rtb::common::{DataUrl, DataUrlEncoding};
+    ///
+    /// let mut url = DataUrl::new("example.com", "beacon")?;
+    /// url.add_string("auction_id", "abc123")?
+    ///    .add_int("bid_ts", 1_700_000_000_000)?
+    ///    .add_macro("price", "${AUCTION_PRICE}")?;
+    ///
+    /// let packed = DataUrlEncoding::Packed { deflate: false };
+    /// if url.url_len(true, packed)? < url.url_len(true, DataUrlEncoding::Query)? {
+    ///     url.set_encoding(packed)?;
+    /// }
+    /// url.finalize();
+    ///
+    /// let parsed = DataUrl::from(&url.url(true)?)?;
+    /// assert_eq!(parsed.get_int("bid_ts")?, Some(1_700_000_000_000));
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    /// Returns an error if the URL has already been finalized, or if
+    /// `deflate` is set without the `gzip` feature.
+    pub fn set_encoding(&mut self, encoding: DataUrlEncoding) -> Result<&mut Self> {
+        if self.finalized {
+            bail!("Cannot change the encoding of a finalized DataUrl");
+        }
+
+        let (macros, params): (Vec<_>, Vec<_>) = self
+            .entries()
+            .map(|(k, v, is_macro)| ((k.into_owned(), v.into_owned()), is_macro))
+            .partition(|(_, is_macro)| *is_macro);
+        let macros = macros.into_iter().map(|(param, _)| param);
+        let params: Vec<_> = params.into_iter().map(|(param, _)| param).collect();
+        let blob = match encoding {
+            DataUrlEncoding::Packed { deflate } if !params.is_empty() => {
+                Some(packed::pack(&params, deflate)?)
+            }
+            _ => None,
+        };
+
+        self.url.set_query(None);
+        self.packed.clear();
+        match blob {
+            Some(blob) => {
+                self.url.query_pairs_mut().append_pair(PACKED_PARAM, &blob);
+                self.packed = params.into_iter().map(|(k, v)| (k.into(), v)).collect();
+            }
+            None if !params.is_empty() => {
+                self.url.query_pairs_mut().extend_pairs(&params);
+            }
+            None => {}
+        }
+
+        for (key, value) in macros {
+            self.append_literal(&key, &value);
+        }
+
+        Ok(self)
+    }
+
+    /// Length of [`DataUrl::url`] if the parameters were written with
+    /// `encoding`, to choose the shortest. A signature made by
+    /// [`DataUrl::finalize_signed`] is not counted, it adds the same length
+    /// to either.
+    pub fn url_len(&self, secure: bool, encoding: DataUrlEncoding) -> Result<usize> {
+        let mut url = self.clone_unfinalized();
+        url.set_encoding(encoding)?;
+        url.finalized = true;
+        Ok(url.url(secure)?.len())
+    }
+
     /// Finalizes the URL, preventing any further mutations.
     ///
     /// After calling this method, add_* methods will panic if called.
@@ -257,6 +698,76 @@ impl DataUrl {
         self.finalized = true;
     }
 
+    /// Signs and finalizes the URL.
+    ///
+    /// Adds the id of the active key, an expiry from now in epoch seconds if
+    /// `signing` has a ttl, a nonce for [`DataUrl::verify_once`], and an
+    /// HMAC-SHA256 signature over the path and every parameter. The domain
+    /// is not signed, so the URL may be served through another host name.
+    /// The parameter names `kid`, `exp`, `nonce`, `mk` and `sig` are reserved
+    /// on signed URLs.
+    ///
+    /// Macro parameters are signed by key only, as their values are only
+    /// known once substituted. They must be substituted with a price
+    /// encrypted for the price crypter of `signing`, whose integrity check
+    /// [`DataUrl::from_verified`] relies on.
+    ///
+    /// # Errors
+    /// Returns an error if the URL has already been finalized, or has macro
+    /// parameters but `signing` no price crypter, or repeats the key of a
+    /// macro parameter.
+    #[cfg(feature = "crypto")]
+    pub fn finalize_signed(&mut self, signing: &UrlSigning) -> Result<()> {
+        if self.finalized {
+            bail!("Cannot sign a finalized DataUrl");
+        }
+        if !self.macro_keys.is_empty() && signing.price_crypter.is_none() {
+            bail!("Signing macro parameters needs a price crypter to verify their values");
+        }
+        if let Some(key) = self
+            .macro_keys
+            .iter()
+            .find(|key| self.params().filter(|(k, _)| k == key.as_str()).count() > 1)
+        {
+            bail!(
+                "Macro parameter '{}' must be the only one with its key",
+                key
+            );
+        }
+
+        self.sign(&signing.keyring, signing.ttl);
+        Ok(())
+    }
+
+    #[cfg(feature = "crypto")]
+    fn sign(&mut self, keyring: &Keyring, ttl: Option<Duration>) {
+        self.url
+            .query_pairs_mut()
+            .append_pair(KEY_ID_PARAM, keyring.active_key_id());
+        if let Some(ttl) = ttl {
+            let expires_at = epoch_timestamp() / 1000 + ttl.as_secs();
+            self.url
+                .query_pairs_mut()
+                .append_pair(EXPIRY_PARAM, &expires_at.to_string());
+        }
+        self.url
+            .query_pairs_mut()
+            .append_pair(NONCE_PARAM, &new_nonce());
+
+        if !self.macro_keys.is_empty() {
+            self.url
+                .query_pairs_mut()
+                .append_pair(MACRO_KEYS_PARAM, &self.macro_keys.join(","));
+        }
+
+        let signature = keyring.sign(self.signing_payload().as_bytes());
+        self.url
+            .query_pairs_mut()
+            .append_pair(SIGNATURE_PARAM, &signature);
+
+        self.finalized = true;
+    }
+
     /// Returns the final URL string with the specified protocol.
     ///
     /// # Arguments
@@ -277,11 +788,164 @@ impl DataUrl {
 
         Ok(url.to_string())
     }
+
+    /// Value of a parameter, unless it is an unsubstituted macro
+    fn lookup(&self, key: &str) -> Result<Option<String>> {
+        match self.entries().find(|(k, _, _)| k == key) {
+            Some((_, value, true)) => Err(UnsubstitutedMacro {
+                key: key.to_string(),
+                value: value.into_owned(),
+            }
+            .into()),
+            entry => Ok(entry.map(|(_, value, _)| value.into_owned())),
+        }
+    }
+
+    fn get_param(&self, key: &str) -> Option<String> {
+        self.params()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.into_owned())
+    }
+
+    /// Every parameter but those added by [`DataUrl::finalize_signed`] to a
+    /// signed URL
+    pub(crate) fn data_params(&self) -> impl Iterator<Item = (Cow<'_, str>, Cow<'_, str>)> {
+        self.data_entries().map(|(k, v, _)| (k, v))
+    }
+
+    /// [`DataUrl::data_params`], telling which are macros as
+    /// [`DataUrl::entries`] does
+    fn data_entries(&self) -> impl Iterator<Item = (Cow<'_, str>, Cow<'_, str>, bool)> {
+        let signed = self.get_param(SIGNATURE_PARAM).is_some();
+        self.entries()
+            .filter(move |(k, _, _)| !(signed && SIGNING_PARAMS.contains(&&**k)))
+    }
+
+    /// The path, without a leading slash
+    pub(crate) fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Every parameter in order, the packed ones first as they were added
+    /// before any left in the query string
+    fn params(&self) -> impl Iterator<Item = (Cow<'_, str>, Cow<'_, str>)> {
+        self.entries().map(|(k, v, _)| (k, v))
+    }
+
+    /// [`DataUrl::params`] and whether each still holds a macro. Only
+    /// [`DataUrl::add_macro`] writes a value unencoded, so a macro is told
+    /// apart from a value that merely looks like one by its raw form in the
+    /// query string. Packed values are never macros.
+    fn entries(&self) -> impl Iterator<Item = (Cow<'_, str>, Cow<'_, str>, bool)> {
+        let query = self.url.query().unwrap_or_default();
+        let pairs = query.split('&').filter_map(|pair| {
+            let (key, value) = form_urlencoded::parse(pair.as_bytes()).next()?;
+            let raw = pair.split_once('=').map_or("", |(_, raw)| raw);
+            Some((key, value, is_macro(raw)))
+        });
+
+        self.packed
+            .iter()
+            .map(|(k, v)| (Cow::Borrowed(&**k), Cow::Borrowed(v.as_str()), false))
+            .chain(pairs.filter(|(k, _, _)| k != PACKED_PARAM))
+    }
+
+    /// Replaces the values substituted for the macro parameters of a signed
+    /// URL with the prices they decrypt to. Values still holding their macro
+    /// are kept, for the getters to report as unsubstituted.
+    #[cfg(feature = "crypto")]
+    fn decrypt_macros(mut self, crypter: Option<&dyn PriceCrypter>) -> Result<Self> {
+        let Some(macro_keys) = self.get_param(MACRO_KEYS_PARAM) else {
+            return Ok(self);
+        };
+        let crypter = crypter.ok_or(SignatureError::Invalid)?;
+
+        let mut query = Vec::new();
+        for pair in self.url.query().unwrap_or_default().split('&') {
+            let (k, raw) = pair.split_once('=').unwrap_or((pair, ""));
+            match form_urlencoded::parse(pair.as_bytes()).next() {
+                Some((key, value)) if !is_macro(raw) && macro_keys.split(',').any(|m| m == key) => {
+                    let price = crypter
+                        .decrypt_price(&value)
+                        .map_err(|_| SignatureError::Invalid)?;
+                    query.push(format!("{k}={price}"));
+                }
+                _ => query.push(pair.to_string()),
+            }
+        }
+
+        self.url.set_query(Some(&query.join("&")));
+        Ok(self)
+    }
+
+    /// The path and every parameter but the signature, re-encoded so that
+    /// equivalent encodings of a value sign the same. Macro parameters are
+    /// signed without their value.
+    #[cfg(feature = "crypto")]
+    fn signing_payload(&self) -> String {
+        let macro_keys = self.get_param(MACRO_KEYS_PARAM).unwrap_or_default();
+        let macro_keys: Vec<&str> = macro_keys.split(',').collect();
+
+        let mut payload = form_urlencoded::Serializer::new(format!("{}?", self.url.path()));
+        for (k, v) in self.url.query_pairs().filter(|(k, _)| k != SIGNATURE_PARAM) {
+            let value = if macro_keys.contains(&&*k) { "" } else { &v };
+            payload.append_pair(&k, value);
+        }
+        payload.finish()
+    }
+}
+
+/// The fields of a serialized struct or map in their order, which a
+/// `serde_json::Value` does not keep
+struct Fields(Vec<(String, serde_json::Value)>);
+
+impl<'de> Deserialize<'de> for Fields {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct FieldsVisitor;
+
+        impl<'de> Visitor<'de> for FieldsVisitor {
+            type Value = Fields;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.write_str("a struct or map")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Fields, A::Error> {
+                let mut fields = Vec::new();
+                while let Some(field) = map.next_entry()? {
+                    fields.push(field);
+                }
+                Ok(Fields(fields))
+            }
+        }
+
+        deserializer.deserialize_map(FieldsVisitor)
+    }
+}
+
+/// Whether a value is a `${NAME}` or `[NAME]` macro, e.g. `${AUCTION_PRICE}`,
+/// `${AUCTION_PRICE:B64}` or the VAST `[CACHEBUSTING]`
+fn is_macro(value: &str) -> bool {
+    let name = value
+        .strip_prefix("${")
+        .and_then(|v| v.strip_suffix('}'))
+        .or_else(|| value.strip_prefix('[').and_then(|v| v.strip_suffix(']')));
+
+    name.is_some_and(|name| {
+        !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | ':' | '.'))
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    #[cfg(feature = "crypto")]
+    use crate::common::MemoryNonceSet;
+    #[cfg(feature = "crypto")]
+    use crate::openrtb::utils::price_crypto::HmacPriceCrypter;
 
     #[test]
     fn test_new_basic() {
@@ -555,4 +1219,601 @@ mod tests {
         let result = url.get_required_float("invalid");
         assert!(result.is_err());
     }
+
+    #[cfg(feature = "crypto")]
+    fn signing(keyring: Keyring) -> UrlSigning {
+        UrlSigningBuilder::default()
+            .keyring(keyring)
+            .nonces(Arc::new(MemoryNonceSet::new(16)))
+            .build()
+            .unwrap()
+    }
+
+    #[cfg(feature = "crypto")]
+    fn signed_url(signing: &UrlSigning) -> String {
+        let mut url = DataUrl::new("example.com", "win").unwrap();
+        url.add_string("auction_id", "abc 123")
+            .unwrap()
+            .add_float("price", 1.25)
+            .unwrap();
+        url.finalize_signed(signing).unwrap();
+        url.url(true).unwrap()
+    }
+
+    #[cfg(feature = "crypto")]
+    fn rejection(url: &str, signing: &UrlSigning) -> SignatureError {
+        DataUrl::from_verified(url, signing)
+            .unwrap_err()
+            .downcast::<SignatureError>()
+            .unwrap()
+    }
+
+    #[cfg(feature = "crypto")]
+    #[test]
+    fn test_signed_round_trip() {
+        let signing = signing(Keyring::new("k1", b"secret".to_vec()));
+        let signed = signed_url(&signing);
+        assert!(signed.contains("kid=k1"));
+        assert!(!signed.contains("exp="));
+
+        let url = DataUrl::from_verified(&signed, &signing).unwrap();
+        assert_eq!(url.get_float("price").unwrap(), Some(1.25));
+
+        // the protocol and host are not signed
+        let http = signed_url(&signing).replace("https://example.com", "http://beacon.example.net");
+        assert!(DataUrl::from_verified(&http, &signing).is_ok());
+
+        // parsing without verifying is explicit
+        assert!(DataUrl::from("https://example.com/win?price=9.25").is_ok());
+    }
+
+    #[cfg(feature = "crypto")]
+    #[test]
+    fn test_signed_rejects_tampering() {
+        let signing = signing(Keyring::new("k1", b"secret".to_vec()));
+        let signed = signed_url(&signing);
+
+        assert_eq!(
+            rejection(&signed.replace("price=1.25", "price=9.25"), &signing),
+            SignatureError::Invalid
+        );
+        assert_eq!(
+            rejection(&signed.replace("/win?", "/imp?"), &signing),
+            SignatureError::Invalid
+        );
+        assert_eq!(
+            rejection(&format!("{signed}&price=0.01"), &signing),
+            SignatureError::Invalid
+        );
+        assert_eq!(
+            rejection("https://example.com/win?price=1.25", &signing),
+            SignatureError::Missing
+        );
+
+        let other = self::signing(Keyring::new("k1", b"guessed".to_vec()));
+        assert!(DataUrl::from_verified(&signed, &other).is_err());
+    }
+
+    #[cfg(feature = "crypto")]
+    #[test]
+    fn test_signed_expiry() {
+        let mut signing = signing(Keyring::new("k1", b"secret".to_vec()));
+        signing.ttl = Some(Duration::from_secs(60));
+        let url = DataUrl::from(&signed_url(&signing)).unwrap();
+
+        let now = epoch_timestamp();
+        assert_eq!(url.verify(&signing.keyring, now), Ok(()));
+        assert!(matches!(
+            url.verify(&signing.keyring, now + 120_000),
+            Err(SignatureError::Expired { .. })
+        ));
+    }
+
+    #[cfg(feature = "crypto")]
+    #[test]
+    fn test_signed_key_rotation() {
+        let mut signing = signing(Keyring::new("k1", b"first".to_vec()));
+        let old = signed_url(&signing);
+
+        signing.keyring.rotate("k2", b"second".to_vec());
+        let new = signed_url(&signing);
+        assert!(new.contains("kid=k2"));
+        assert!(DataUrl::from_verified(&old, &signing).is_ok());
+        assert!(DataUrl::from_verified(&new, &signing).is_ok());
+
+        signing.keyring.remove("k1");
+        assert_eq!(
+            rejection(&old, &signing),
+            SignatureError::UnknownKey("k1".to_string())
+        );
+    }
+
+    #[cfg(feature = "crypto")]
+    #[test]
+    fn test_signed_replay() {
+        let mut signing = signing(Keyring::new("k1", b"secret".to_vec()));
+        signing.ttl = Some(Duration::from_secs(60));
+
+        let signed = signed_url(&signing);
+        assert!(DataUrl::from_verified(&signed, &signing).is_ok());
+        assert_eq!(rejection(&signed, &signing), SignatureError::Replayed);
+
+        // each signing draws a new nonce
+        let again = signed_url(&signing);
+        assert_ne!(signed, again);
+        assert!(DataUrl::from_verified(&again, &signing).is_ok());
+
+        // the nonce is signed
+        let forged = signed.replace("nonce=", "nonce=x");
+        assert_eq!(rejection(&forged, &signing), SignatureError::Invalid);
+
+        let nonces = MemoryNonceSet::new(16);
+        let now = epoch_timestamp();
+        let url = DataUrl::from(&signed).unwrap();
+        assert_eq!(url.verify_once(&signing.keyring, &nonces, now), Ok(()));
+        assert_eq!(
+            url.verify_once(&signing.keyring, &nonces, now),
+            Err(SignatureError::Replayed)
+        );
+    }
+
+    #[cfg(feature = "crypto")]
+    #[test]
+    fn test_clone_unfinalized_drops_signature() {
+        let mut signing = signing(Keyring::new("k1", b"secret".to_vec()));
+        signing.ttl = Some(Duration::from_secs(60));
+        let url = DataUrl::from(&signed_url(&signing)).unwrap();
+
+        let mut clone = url.clone_unfinalized();
+        assert_eq!(clone.get_string("sig").unwrap(), None);
+        assert_eq!(clone.get_string("exp").unwrap(), None);
+        assert_eq!(clone.get_string("nonce").unwrap(), None);
+        assert_eq!(clone.get_float("price").unwrap(), Some(1.25));
+
+        clone.add_string("event", "click").unwrap();
+        clone.finalize_signed(&signing).unwrap();
+        assert!(DataUrl::from_verified(&clone.url(true).unwrap(), &signing).is_ok());
+    }
+
+    #[cfg(feature = "crypto")]
+    #[test]
+    fn test_cannot_sign_after_finalize() {
+        let signing = signing(Keyring::new("k1", b"secret".to_vec()));
+        let mut url = DataUrl::new("example.com", "beacon").unwrap();
+        url.finalize();
+        assert!(url.finalize_signed(&signing).is_err());
+    }
+
+    #[test]
+    fn test_macros_stay_literal() {
+        let mut url = DataUrl::new("example.com", "win").unwrap();
+        url.add_string("id", "a b")
+            .unwrap()
+            .add_auction_macro("price", AuctionMacro::Price)
+            .unwrap()
+            .add_macro("cb", "[CACHEBUSTING]")
+            .unwrap()
+            .add_int("n", 1)
+            .unwrap();
+        url.finalize();
+
+        assert_eq!(
+            url.url(true).unwrap(),
+            "https://example.com/win?id=a+b&price=${AUCTION_PRICE}&cb=[CACHEBUSTING]&n=1"
+        );
+
+        // add_string still encodes, so it is never mistaken for a macro
+        let mut url = DataUrl::new("example.com", "win").unwrap();
+        url.add_string("price", "${AUCTION_PRICE}").unwrap();
+        url.finalize();
+        assert!(url.url(true).unwrap().contains("%24%7BAUCTION_PRICE%7D"));
+    }
+
+    #[test]
+    fn test_add_macro_rejects_non_macros() {
+        let mut url = DataUrl::new("example.com", "win").unwrap();
+        assert!(url.add_macro("price", "1.5").is_err());
+        assert!(url.add_macro("price", "${}").is_err());
+        assert!(url.add_macro("price", "${A&b=1}").is_err());
+        assert!(url.add_macro("price", "${AUCTION_PRICE:B64}").is_ok());
+    }
+
+    #[test]
+    fn test_unsubstituted_macro_error() {
+        let url = DataUrl::from("https://example.com/win?price=${AUCTION_PRICE}&id=1").unwrap();
+
+        let err = url.get_float("price").unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<UnsubstitutedMacro>(),
+            Some(&UnsubstitutedMacro {
+                key: "price".to_string(),
+                value: "${AUCTION_PRICE}".to_string(),
+            })
+        );
+        assert!(url.get_string("price").is_err());
+        assert!(url.get_required_float("price").is_err());
+        assert_eq!(url.get_int("id").unwrap(), Some(1));
+
+        let substituted = DataUrl::from("https://example.com/win?price=1.25").unwrap();
+        assert_eq!(substituted.get_float("price").unwrap(), Some(1.25));
+
+        // a bad value is still a parse failure, not a macro
+        let bad = DataUrl::from("https://example.com/win?price=abc").unwrap();
+        let err = bad.get_float("price").unwrap_err();
+        assert!(err.downcast_ref::<UnsubstitutedMacro>().is_none());
+    }
+
+    #[test]
+    fn test_macro_shaped_strings_are_values() {
+        let mut url = DataUrl::new("example.com", "win").unwrap();
+        url.add_string("seg", "[42]")
+            .unwrap()
+            .add_string("tpl", "${ABC}")
+            .unwrap()
+            .add_macro("cb", "[CACHEBUSTING]")
+            .unwrap();
+        assert_eq!(url.get_string("seg").unwrap().as_deref(), Some("[42]"));
+        assert_eq!(url.get_string("tpl").unwrap().as_deref(), Some("${ABC}"));
+        assert!(url.get_string("cb").is_err());
+        url.finalize();
+
+        let fired = url.url(true).unwrap().replace("[CACHEBUSTING]", "7");
+        let parsed = DataUrl::from(&fired).unwrap();
+        assert_eq!(parsed.get_string("seg").unwrap().as_deref(), Some("[42]"));
+        assert_eq!(parsed.get_int("cb").unwrap(), Some(7));
+
+        #[derive(Deserialize)]
+        struct Fired {
+            seg: String,
+        }
+        assert_eq!(parsed.decode::<Fired>().unwrap().seg, "[42]");
+    }
+
+    #[cfg(feature = "crypto")]
+    fn price_signing() -> (UrlSigning, Arc<HmacPriceCrypter>) {
+        let crypter = Arc::new(HmacPriceCrypter::new(b"enc".to_vec(), b"int".to_vec()));
+        let mut signing = signing(Keyring::new("k1", b"secret".to_vec()));
+        signing.price_crypter = Some(crypter.clone());
+        (signing, crypter)
+    }
+
+    #[cfg(feature = "crypto")]
+    #[test]
+    fn test_signed_macros_survive_substitution() {
+        let (signing, crypter) = price_signing();
+        let mut url = DataUrl::new("example.com", "win").unwrap();
+        url.add_string("id", "abc")
+            .unwrap()
+            .add_string("seg", "[abc]")
+            .unwrap()
+            .add_auction_macro("price", AuctionMacro::Price)
+            .unwrap();
+        let mut unfired = url.clone();
+        url.finalize_signed(&signing).unwrap();
+
+        let signed = url.url(true).unwrap();
+        assert!(signed.contains("price=${AUCTION_PRICE}"));
+        assert!(signed.contains("mk=price&"));
+
+        let fire = |price: &str| signed.replace("${AUCTION_PRICE}", price);
+        let url = DataUrl::from_verified(&fire(&crypter.encrypt_price(1.25)), &signing).unwrap();
+        assert_eq!(url.get_float("price").unwrap(), Some(1.25));
+        assert_eq!(url.get_string("seg").unwrap().as_deref(), Some("[abc]"));
+
+        // the substituted price must decrypt, other values are signed
+        assert_eq!(rejection(&fire("9.99"), &signing), SignatureError::Invalid);
+        let encrypted = fire(&crypter.encrypt_price(1.25));
+        for tampered in [
+            encrypted.replace("id=abc", "id=xyz"),
+            encrypted.replace("seg=%5Babc%5D", "seg=[xyz]"),
+        ] {
+            assert_eq!(rejection(&tampered, &signing), SignatureError::Invalid);
+        }
+
+        // a beacon fired without substitution verifies, but has no price
+        unfired.finalize_signed(&signing).unwrap();
+        let url = DataUrl::from_verified(&unfired.url(true).unwrap(), &signing).unwrap();
+        assert!(url.get_float("price").is_err());
+    }
+
+    #[cfg(feature = "crypto")]
+    #[test]
+    fn test_signed_macros_need_price_crypter() {
+        let (signing, _) = price_signing();
+        let mut url = DataUrl::new("example.com", "win").unwrap();
+        url.add_auction_macro("price", AuctionMacro::Price).unwrap();
+        let mut without = signing.clone();
+        without.price_crypter = None;
+        assert!(url.clone().finalize_signed(&without).is_err());
+
+        url.finalize_signed(&signing).unwrap();
+        let fired = url.url(true).unwrap().replace("${AUCTION_PRICE}", "1");
+        assert_eq!(rejection(&fired, &without), SignatureError::Invalid);
+
+        // a clear value under the key of a macro would not be signed
+        let mut url = DataUrl::new("example.com", "win").unwrap();
+        url.add_float("price", 1.5)
+            .unwrap()
+            .add_auction_macro("price", AuctionMacro::Price)
+            .unwrap();
+        assert!(url.finalize_signed(&signing).is_err());
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Beacon {
+        #[serde(rename = "a")]
+        auction_id: String,
+        #[serde(rename = "w")]
+        won: bool,
+        #[serde(rename = "n")]
+        count: i64,
+        #[serde(rename = "p")]
+        price: Option<f64>,
+    }
+
+    #[test]
+    fn test_encode_decode_round_trip() {
+        let beacon = Beacon {
+            auction_id: "a b&c".to_string(),
+            won: true,
+            count: -3,
+            price: Some(1.25),
+        };
+
+        let mut url = DataUrl::new("example.com", "beacon").unwrap();
+        url.encode(&beacon).unwrap();
+        url.finalize();
+        assert_eq!(
+            url.url(true).unwrap(),
+            "https://example.com/beacon?a=a+b%26c&w=true&n=-3&p=1.25"
+        );
+        assert_eq!(url.decode::<Beacon>().unwrap(), beacon);
+
+        // the typed getters read the same parameters
+        assert!(url.get_required_bool("w").unwrap());
+        assert_eq!(url.get_required_int("n").unwrap(), -3);
+
+        // None is skipped and decodes back to None
+        let mut url = DataUrl::new("example.com", "beacon").unwrap();
+        url.encode(&Beacon {
+            price: None,
+            ..beacon
+        })
+        .unwrap();
+        assert_eq!(url.get_string("p").unwrap(), None);
+        assert_eq!(url.decode::<Beacon>().unwrap().price, None);
+    }
+
+    #[test]
+    fn test_encode_rejects_non_scalars() {
+        let mut url = DataUrl::new("example.com", "beacon").unwrap();
+        assert!(url.encode(&"not a struct").is_err());
+        assert!(url.encode(&serde_json::json!({"a": [1, 2]})).is_err());
+
+        url.finalize();
+        assert!(url.encode(&serde_json::json!({"a": 1})).is_err());
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        let missing = DataUrl::from("https://example.com/beacon?a=x&w=true").unwrap();
+        assert!(missing.decode::<Beacon>().is_err());
+
+        let bad = DataUrl::from("https://example.com/beacon?a=x&w=yes&n=1").unwrap();
+        assert!(bad.decode::<Beacon>().is_err());
+
+        let unsubstituted =
+            DataUrl::from("https://example.com/beacon?a=x&w=true&n=1&p=${AUCTION_PRICE}").unwrap();
+        let err = unsubstituted.decode::<Beacon>().unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<UnsubstitutedMacro>()
+                .map(|e| e.key.as_str()),
+            Some("p")
+        );
+    }
+
+    #[cfg(feature = "crypto")]
+    #[test]
+    fn test_decode_signed_ignores_reserved_params() {
+        #[derive(Deserialize, Debug)]
+        #[serde(deny_unknown_fields)]
+        struct Strict {
+            #[serde(rename = "a")]
+            _auction_id: String,
+            #[serde(rename = "p")]
+            price: f64,
+        }
+
+        let (mut signing, crypter) = price_signing();
+        signing.ttl = Some(Duration::from_secs(60));
+        let mut url = DataUrl::new("example.com", "win").unwrap();
+        url.add_string("a", "abc")
+            .unwrap()
+            .add_auction_macro("p", AuctionMacro::Price)
+            .unwrap();
+        url.finalize_signed(&signing).unwrap();
+
+        let fired = url
+            .url(true)
+            .unwrap()
+            .replace("${AUCTION_PRICE}", &crypter.encrypt_price(2.5));
+        let url = DataUrl::from_verified(&fired, &signing).unwrap();
+        assert_eq!(url.decode::<Strict>().unwrap().price, 2.5);
+    }
+
+    #[cfg(feature = "crypto")]
+    impl DataUrl {
+        fn get_raw(&self, key: &str) -> String {
+            self.url
+                .query_pairs()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v.into_owned())
+                .unwrap()
+        }
+    }
+
+    fn many_params() -> DataUrl {
+        let mut url = DataUrl::new("example.com", "beacon").unwrap();
+        url.add_string("auction_id", "0f1e2d3c-4b5a-6978-8796-a5b4c3d2e1f0")
+            .unwrap()
+            .add_int("bid_ts", 1_700_000_000_000)
+            .unwrap()
+            .add_float("floor", 0.35)
+            .unwrap()
+            .add_bool("test", false)
+            .unwrap()
+            .add_auction_macro("price", AuctionMacro::Price)
+            .unwrap();
+        for i in 0..10 {
+            url.add_int("segment", 1000 + i).unwrap();
+        }
+        url
+    }
+
+    #[test]
+    fn test_packed_round_trip() {
+        for deflate in [false, cfg!(feature = "gzip")] {
+            let mut url = many_params();
+            url.set_encoding(DataUrlEncoding::Packed { deflate })
+                .unwrap();
+            url.finalize();
+
+            let fired = url.url(true).unwrap();
+            assert!(fired.starts_with("https://example.com/beacon?_p="));
+            assert!(fired.ends_with("&price=${AUCTION_PRICE}"));
+            assert!(!fired.contains("auction_id"));
+
+            let parsed = DataUrl::from(&fired.replace("${AUCTION_PRICE}", "1.5")).unwrap();
+            assert_eq!(
+                parsed.get_required_string("auction_id").unwrap(),
+                "0f1e2d3c-4b5a-6978-8796-a5b4c3d2e1f0"
+            );
+            assert_eq!(parsed.get_int("bid_ts").unwrap(), Some(1_700_000_000_000));
+            assert_eq!(parsed.get_float("floor").unwrap(), Some(0.35));
+            assert_eq!(parsed.get_bool("test").unwrap(), Some(false));
+            assert_eq!(parsed.get_int("segment").unwrap(), Some(1000));
+            assert_eq!(parsed.get_float("price").unwrap(), Some(1.5));
+            assert_eq!(parsed.get_string("_p").unwrap(), None);
+        }
+    }
+
+    #[test]
+    fn test_packed_url_len() {
+        let url = many_params();
+        let query = url.url_len(true, DataUrlEncoding::Query).unwrap();
+        let packed = url
+            .url_len(true, DataUrlEncoding::Packed { deflate: false })
+            .unwrap();
+        assert!(packed < query, "{packed} < {query}");
+        #[cfg(feature = "gzip")]
+        {
+            let deflated = url
+                .url_len(true, DataUrlEncoding::Packed { deflate: true })
+                .unwrap();
+            assert!(deflated < query, "{deflated} < {query}");
+        }
+
+        let mut packed_url = url.clone();
+        packed_url
+            .set_encoding(DataUrlEncoding::Packed { deflate: false })
+            .unwrap();
+        packed_url.finalize();
+        assert_eq!(packed_url.url(true).unwrap().len(), packed);
+
+        // measuring leaves the url as is
+        let mut url = url;
+        url.finalize();
+        assert_eq!(url.url(true).unwrap().len(), query);
+    }
+
+    #[test]
+    fn test_packed_back_to_query() {
+        let mut url = many_params();
+        url.set_encoding(DataUrlEncoding::Packed { deflate: false })
+            .unwrap();
+        url.add_string("late", "x").unwrap();
+        url.set_encoding(DataUrlEncoding::Query).unwrap();
+        url.finalize();
+
+        let fired = url.url(true).unwrap();
+        assert!(!fired.contains("_p="));
+        assert!(fired.contains("bid_ts=1700000000000"));
+        assert!(fired.contains("late=x"));
+        assert!(fired.ends_with("&price=${AUCTION_PRICE}"));
+    }
+
+    #[cfg(feature = "crypto")]
+    #[test]
+    fn test_packed_signed() {
+        let (mut signing, crypter) = price_signing();
+        signing.ttl = Some(Duration::from_secs(60));
+        let mut url = many_params();
+        url.set_encoding(DataUrlEncoding::Packed { deflate: false })
+            .unwrap();
+        url.finalize_signed(&signing).unwrap();
+
+        let fired = url
+            .url(true)
+            .unwrap()
+            .replace("${AUCTION_PRICE}", &crypter.encrypt_price(2.0));
+        let parsed = DataUrl::from_verified(&fired, &signing).unwrap();
+        assert_eq!(parsed.get_float("floor").unwrap(), Some(0.35));
+        assert_eq!(parsed.get_float("price").unwrap(), Some(2.0));
+
+        // the blob is signed like any other parameter
+        let mut forged = many_params();
+        forged.add_int("bid_ts", 1).unwrap();
+        forged
+            .set_encoding(DataUrlEncoding::Packed { deflate: false })
+            .unwrap();
+        forged.finalize();
+        let blob = |url: &DataUrl| url.get_raw(PACKED_PARAM);
+        let tampered = fired.replace(&blob(&url), &blob(&forged));
+        assert_eq!(rejection(&tampered, &signing), SignatureError::Invalid);
+
+        // and verified before it is decoded
+        let garbage = fired.replace(&blob(&url), "AAAA");
+        assert_eq!(rejection(&garbage, &signing), SignatureError::Invalid);
+    }
+
+    #[test]
+    fn test_packed_decode_and_errors() {
+        #[derive(Deserialize)]
+        struct Segment {
+            bid_ts: i64,
+            floor: f64,
+        }
+
+        let mut url = many_params();
+        url.set_encoding(DataUrlEncoding::Packed { deflate: false })
+            .unwrap();
+        url.finalize();
+        let parsed =
+            DataUrl::from(&url.url(true).unwrap().replace("${AUCTION_PRICE}", "1")).unwrap();
+        let segment: Segment = parsed.decode().unwrap();
+        assert_eq!(segment.bid_ts, 1_700_000_000_000);
+        assert_eq!(segment.floor, 0.35);
+
+        assert!(DataUrl::from("https://example.com/beacon?_p=garbage!").is_err());
+        assert!(DataUrl::from("https://example.com/beacon?_p=AA").is_err());
+
+        let mut finalized = many_params();
+        finalized.finalize();
+        assert!(finalized.set_encoding(DataUrlEncoding::Query).is_err());
+    }
+
+    #[cfg(not(feature = "gzip"))]
+    #[test]
+    fn test_deflate_needs_gzip() {
+        let mut url = many_params();
+        let before = url.clone();
+        assert!(
+            url.set_encoding(DataUrlEncoding::Packed { deflate: true })
+                .is_err()
+        );
+        assert_eq!(url, before);
+
+        // a deflated blob, header 0x03
+        assert!(DataUrl::from("https://example.com/beacon?_p=AwAA").is_err());
+    }
 }
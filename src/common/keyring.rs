@@ -0,0 +1,293 @@
+use super::utils::epoch_timestamp;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Why a signed [`crate::common::DataUrl`] was rejected
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignatureError {
+    /// The URL carries no signature or key id
+    Missing,
+    /// The key id is not in the keyring, e.g. a key retired after rotation
+    UnknownKey(String),
+    /// The signature does not match, the URL was tampered with
+    Invalid,
+    /// The expiry has passed
+    Expired { expired_at: u64 },
+    /// The nonce was seen before, the URL was replayed
+    Replayed,
+}
+
+impl std::fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SignatureError::Missing => write!(f, "URL is not signed"),
+            SignatureError::UnknownKey(id) => write!(f, "unknown signing key '{id}'"),
+            SignatureError::Invalid => write!(f, "URL signature is invalid"),
+            SignatureError::Expired { expired_at } => {
+                write!(f, "URL expired at {expired_at}")
+            }
+            SignatureError::Replayed => write!(f, "URL was already used"),
+        }
+    }
+}
+
+impl std::error::Error for SignatureError {}
+
+/// HMAC-SHA256 keys for signing beacon URLs, with rotation.
+///
+/// URLs are signed with the active key and verified with whichever key
+/// their key id names. To rotate, make a new key active and keep the old
+/// one until every URL signed with it has expired, then remove it.
+///
+/// # Example
+/// ```
+/// use rtb::common::Keyring;
+///
+/// let mut keyring = Keyring::new("2024-01", b"first secret".to_vec());
+/// keyring.rotate("2024-02", b"second secret".to_vec());
+///
+/// assert_eq!(keyring.active_key_id(), "2024-02");
+/// assert!(keyring.contains("2024-01"));
+///
+/// keyring.remove("2024-01");
+/// assert!(!keyring.contains("2024-01"));
+/// ```
+#[derive(Clone)]
+pub struct Keyring {
+    active: String,
+    keys: HashMap<String, Vec<u8>>,
+}
+
+impl Keyring {
+    /// Creates a keyring whose active key is `secret`, identified by `id`
+    pub fn new(id: impl Into<String>, secret: impl Into<Vec<u8>>) -> Self {
+        let id = id.into();
+        let mut keys = HashMap::new();
+        keys.insert(id.clone(), secret.into());
+
+        Self { active: id, keys }
+    }
+
+    /// Adds a key used for verification only, e.g. the previous key
+    /// after a restart
+    pub fn add(&mut self, id: impl Into<String>, secret: impl Into<Vec<u8>>) -> &mut Self {
+        self.keys.insert(id.into(), secret.into());
+        self
+    }
+
+    /// Makes a new key active, keeping the current one for verification
+    pub fn rotate(&mut self, id: impl Into<String>, secret: impl Into<Vec<u8>>) -> &mut Self {
+        let id = id.into();
+        self.keys.insert(id.clone(), secret.into());
+        self.active = id;
+        self
+    }
+
+    /// Retires a key so URLs signed with it are rejected. The active key
+    /// cannot be removed, returns whether the key was removed.
+    pub fn remove(&mut self, id: &str) -> bool {
+        id != self.active && self.keys.remove(id).is_some()
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.keys.contains_key(id)
+    }
+
+    pub fn active_key_id(&self) -> &str {
+        &self.active
+    }
+
+    /// Signs `payload` with the active key, returning the signature encoded
+    /// as URL-safe base64
+    pub(crate) fn sign(&self, payload: &[u8]) -> String {
+        let mut mac = self
+            .mac(&self.active)
+            .expect("active key is in the keyring");
+        mac.update(payload);
+        URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes())
+    }
+
+    /// Checks a signature made by [`Keyring::sign`] with the key `id`, in
+    /// constant time
+    pub(crate) fn verify(
+        &self,
+        id: &str,
+        payload: &[u8],
+        signature: &str,
+    ) -> Result<(), SignatureError> {
+        let mut mac = self
+            .mac(id)
+            .ok_or_else(|| SignatureError::UnknownKey(id.to_string()))?;
+        let signature = URL_SAFE_NO_PAD
+            .decode(signature)
+            .map_err(|_| SignatureError::Invalid)?;
+
+        mac.update(payload);
+        mac.verify_slice(&signature)
+            .map_err(|_| SignatureError::Invalid)
+    }
+
+    fn mac(&self, id: &str) -> Option<HmacSha256> {
+        let secret = self.keys.get(id)?;
+        Some(HmacSha256::new_from_slice(secret).expect("HMAC accepts keys of any length"))
+    }
+}
+
+impl std::fmt::Debug for Keyring {
+    // never print the secrets
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut ids: Vec<_> = self.keys.keys().collect();
+        ids.sort();
+
+        f.debug_struct("Keyring")
+            .field("active", &self.active)
+            .field("keys", &ids)
+            .finish()
+    }
+}
+
+/// Remembers the nonces of verified URLs, so that each signed URL is
+/// accepted once.
+///
+/// [`MemoryNonceSet`] serves a single host, beacons received by several
+/// hosts need an implementation over a shared store.
+pub trait NonceSet: Send + Sync {
+    /// Records `nonce`, returning `false` if it was already recorded. The
+    /// nonce need not be kept past `expires_at` (epoch seconds), when the URL
+    /// carrying it expires.
+    fn insert(&self, nonce: &str, expires_at: Option<u64>) -> bool;
+}
+
+/// In-process [`NonceSet`] holding up to `capacity` nonces.
+///
+/// Nonces are kept until their URL expires, so URLs should be signed with a
+/// ttl. Once full the nonces expiring soonest are dropped first, which lets
+/// those URLs be replayed.
+pub struct MemoryNonceSet {
+    capacity: usize,
+    seen: Mutex<HashMap<String, u64>>,
+}
+
+impl MemoryNonceSet {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            seen: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl NonceSet for MemoryNonceSet {
+    fn insert(&self, nonce: &str, expires_at: Option<u64>) -> bool {
+        let mut seen = self.seen.lock().unwrap();
+        if seen.contains_key(nonce) {
+            return false;
+        }
+
+        if seen.len() >= self.capacity {
+            let now = epoch_timestamp() / 1000;
+            seen.retain(|_, expires_at| *expires_at >= now);
+        }
+        if seen.len() >= self.capacity {
+            let soonest = seen
+                .iter()
+                .min_by_key(|(_, expires_at)| **expires_at)
+                .map(|(nonce, _)| nonce.clone());
+            if let Some(soonest) = soonest {
+                seen.remove(&soonest);
+            }
+        }
+
+        seen.insert(nonce.to_string(), expires_at.unwrap_or(u64::MAX));
+        true
+    }
+}
+
+/// A nonce unique within the process and, with overwhelming likelihood,
+/// across processes: a random per-process prefix and a counter
+pub(crate) fn new_nonce() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    static PREFIX: OnceLock<u64> = OnceLock::new();
+
+    let prefix = PREFIX.get_or_init(|| RandomState::new().hash_one(epoch_timestamp()));
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+
+    let mut bytes = [0u8; 16];
+    bytes[..8].copy_from_slice(&prefix.to_be_bytes());
+    bytes[8..].copy_from_slice(&count.to_be_bytes());
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign_and_verify() {
+        let keyring = Keyring::new("k1", b"secret".to_vec());
+        let signature = keyring.sign(b"payload");
+
+        assert_eq!(keyring.verify("k1", b"payload", &signature), Ok(()));
+        assert_eq!(
+            keyring.verify("k1", b"tampered", &signature),
+            Err(SignatureError::Invalid)
+        );
+        assert_eq!(
+            keyring.verify("k1", b"payload", "not base64!"),
+            Err(SignatureError::Invalid)
+        );
+        assert_eq!(
+            keyring.verify("k2", b"payload", &signature),
+            Err(SignatureError::UnknownKey("k2".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_rotation() {
+        let mut keyring = Keyring::new("k1", b"first".to_vec());
+        let old = keyring.sign(b"payload");
+
+        keyring.rotate("k2", b"second".to_vec());
+        let new = keyring.sign(b"payload");
+        assert_ne!(old, new);
+        assert_eq!(keyring.verify("k1", b"payload", &old), Ok(()));
+        assert_eq!(keyring.verify("k2", b"payload", &new), Ok(()));
+
+        assert!(!keyring.remove("k2"));
+        assert!(keyring.remove("k1"));
+        assert!(keyring.verify("k1", b"payload", &old).is_err());
+    }
+
+    #[test]
+    fn test_memory_nonce_set() {
+        let nonces = MemoryNonceSet::new(2);
+        assert!(nonces.insert("a", None));
+        assert!(!nonces.insert("a", None));
+
+        // full, the nonce expiring soonest makes room
+        assert!(nonces.insert("b", Some(u64::MAX - 1)));
+        assert!(nonces.insert("c", None));
+        assert!(!nonces.insert("a", None));
+        assert!(!nonces.insert("c", None));
+        assert!(nonces.insert("b", None));
+
+        assert_ne!(new_nonce(), new_nonce());
+    }
+
+    #[test]
+    fn test_debug_hides_secrets() {
+        let keyring = Keyring::new("k1", b"hunter2".to_vec());
+        let debug = format!("{keyring:?}");
+        assert!(debug.contains("k1"));
+        assert!(!debug.contains("hunter2"));
+    }
+}
@@ -1,7 +1,15 @@
 pub mod bidresponsestate;
 pub mod clock;
 mod dataurl;
+#[cfg(feature = "crypto")]
+mod keyring;
+mod packed;
 pub mod utils;
 
 pub use clock::{Clock, MonotonicClock, SystemClock};
-pub use dataurl::DataUrl;
+pub use dataurl::DataUrlEncoding;
+pub use dataurl::{DataUrl, UnsubstitutedMacro};
+#[cfg(feature = "crypto")]
+pub use dataurl::{UrlSigning, UrlSigningBuilder};
+#[cfg(feature = "crypto")]
+pub use keyring::{Keyring, MemoryNonceSet, NonceSet, SignatureError};
@@ -0,0 +1,398 @@
+//! Binary encoding of the parameters of a packed [`super::DataUrl`].
+//!
+//! The blob is URL-safe base64 without padding. Its first byte holds the
+//! format version shifted left by one and whether the rest is raw deflate.
+//! The rest is a dictionary of the distinct keys followed by the entries,
+//! each a dictionary index and a typed value:
+//!
+//! ```text
+//! header   u8         version << 1 | deflate
+//! keys     varint n   then n times: varint len, utf-8 bytes
+//! entries  varint n   then n times: varint key index, u8 type, value
+//! ```
+//!
+//! Values are typed by how they read back: `true`/`false`, integers as
+//! zigzag varints, floats as 8 little-endian bytes, anything else as a
+//! string. A value is only typed when it renders back to the same string,
+//! so unpacking always restores the original parameters.
+//!
+//! Deflate needs the `gzip` feature.
+
+use anyhow::{Result, anyhow, bail, ensure};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+#[cfg(feature = "gzip")]
+use flate2::Compression;
+#[cfg(feature = "gzip")]
+use flate2::read::DeflateDecoder;
+#[cfg(feature = "gzip")]
+use flate2::write::DeflateEncoder;
+#[cfg(feature = "gzip")]
+use std::io::{Read, Write};
+use std::sync::Arc;
+
+const VERSION: u8 = 1;
+const DEFLATE_FLAG: u8 = 1;
+
+/// Upper bound of an unpacked payload and of the parameters it decodes to,
+/// URLs are rarely longer than 8KB
+const MAX_UNPACKED_SIZE: u64 = 64 * 1024;
+/// Upper bound of the number of keys, and of entries
+const MAX_PARAMS: u64 = 1024;
+
+const TYPE_STRING: u8 = 0;
+const TYPE_FALSE: u8 = 1;
+const TYPE_TRUE: u8 = 2;
+const TYPE_INT: u8 = 3;
+const TYPE_FLOAT: u8 = 4;
+
+/// Packs parameters into a blob, see the module docs for the format
+pub(crate) fn pack(pairs: &[(String, String)], deflate: bool) -> Result<String> {
+    let mut keys: Vec<&str> = Vec::new();
+    let mut entries = Vec::new();
+    for (key, value) in pairs {
+        let index = match keys.iter().position(|k| *k == key.as_str()) {
+            Some(index) => index,
+            None => {
+                keys.push(key);
+                keys.len() - 1
+            }
+        };
+        write_varint(&mut entries, index as u64);
+        write_value(&mut entries, value);
+    }
+
+    let mut body = Vec::with_capacity(entries.len() + 16);
+    write_varint(&mut body, keys.len() as u64);
+    for key in &keys {
+        write_bytes(&mut body, key.as_bytes());
+    }
+    write_varint(&mut body, pairs.len() as u64);
+    body.extend_from_slice(&entries);
+
+    let mut blob = vec![VERSION << 1];
+    if deflate {
+        blob[0] |= DEFLATE_FLAG;
+        blob = deflate_into(blob, &body)?;
+    } else {
+        blob.extend_from_slice(&body);
+    }
+
+    Ok(URL_SAFE_NO_PAD.encode(blob))
+}
+
+/// Unpacks a blob made by [`pack`], the entries sharing their key
+pub(crate) fn unpack(blob: &str) -> Result<Vec<(Arc<str>, String)>> {
+    let blob = URL_SAFE_NO_PAD
+        .decode(blob)
+        .map_err(|e| anyhow!("Packed parameters are not base64: {}", e))?;
+    let (&header, rest) = blob
+        .split_first()
+        .ok_or_else(|| anyhow!("Packed parameters are empty"))?;
+
+    let version = header >> 1;
+    if version != VERSION {
+        bail!("Unsupported packed parameters version {}", version);
+    }
+
+    let inflated;
+    let mut body = if header & DEFLATE_FLAG != 0 {
+        inflated = inflate(rest)?;
+        &inflated[..]
+    } else {
+        rest
+    };
+
+    let key_count = read_count(&mut body)?;
+    let mut keys: Vec<Arc<str>> = Vec::with_capacity(key_count);
+    for _ in 0..key_count {
+        keys.push(read_string(&mut body)?.into());
+    }
+
+    // short entries can repeat a long key or expand to long numbers
+    let mut size = 0;
+    let entry_count = read_count(&mut body)?;
+    let mut pairs = Vec::with_capacity(entry_count);
+    for _ in 0..entry_count {
+        let index = read_varint(&mut body)?;
+        let key = keys
+            .get(index as usize)
+            .ok_or_else(|| anyhow!("Packed key index {} out of range", index))?;
+        let value = read_value(&mut body)?;
+        size += key.len() + value.len();
+        ensure!(
+            size as u64 <= MAX_UNPACKED_SIZE,
+            "Packed parameters are too large"
+        );
+        pairs.push((Arc::clone(key), value));
+    }
+
+    ensure!(body.is_empty(), "Trailing bytes after packed parameters");
+    Ok(pairs)
+}
+
+/// Appends `body` to `blob`, deflated
+#[cfg(feature = "gzip")]
+fn deflate_into(blob: Vec<u8>, body: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = DeflateEncoder::new(blob, Compression::best());
+    encoder.write_all(body)?;
+    Ok(encoder.finish()?)
+}
+
+#[cfg(not(feature = "gzip"))]
+fn deflate_into(_blob: Vec<u8>, _body: &[u8]) -> Result<Vec<u8>> {
+    bail!("Deflating packed parameters needs the `gzip` feature")
+}
+
+/// Inflates a deflated body of at most [`MAX_UNPACKED_SIZE`] bytes
+#[cfg(feature = "gzip")]
+fn inflate(deflated: &[u8]) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    DeflateDecoder::new(deflated)
+        .take(MAX_UNPACKED_SIZE + 1)
+        .read_to_end(&mut out)
+        .map_err(|e| anyhow!("Packed parameters fail to inflate: {}", e))?;
+    ensure!(
+        out.len() as u64 <= MAX_UNPACKED_SIZE,
+        "Packed parameters are too large"
+    );
+    Ok(out)
+}
+
+#[cfg(not(feature = "gzip"))]
+fn inflate(_deflated: &[u8]) -> Result<Vec<u8>> {
+    bail!("Deflated packed parameters need the `gzip` feature")
+}
+
+fn write_value(out: &mut Vec<u8>, value: &str) {
+    match value {
+        "false" => out.push(TYPE_FALSE),
+        "true" => out.push(TYPE_TRUE),
+        _ => {
+            if let Some(int) = value.parse::<i64>().ok().filter(|i| i.to_string() == value) {
+                out.push(TYPE_INT);
+                write_varint(out, ((int << 1) ^ (int >> 63)) as u64);
+            } else if let Some(float) = value.parse::<f64>().ok().filter(|f| f.to_string() == value)
+            {
+                out.push(TYPE_FLOAT);
+                out.extend_from_slice(&float.to_le_bytes());
+            } else {
+                out.push(TYPE_STRING);
+                write_bytes(out, value.as_bytes());
+            }
+        }
+    }
+}
+
+fn read_value(input: &mut &[u8]) -> Result<String> {
+    let kind = read_u8(input)?;
+    let value = match kind {
+        TYPE_STRING => read_string(input)?,
+        TYPE_FALSE => "false".to_string(),
+        TYPE_TRUE => "true".to_string(),
+        TYPE_INT => {
+            let zigzag = read_varint(input)?;
+            (((zigzag >> 1) as i64) ^ -((zigzag & 1) as i64)).to_string()
+        }
+        TYPE_FLOAT => {
+            ensure!(input.len() >= 8, "Truncated packed float");
+            let (bytes, rest) = input.split_at(8);
+            *input = rest;
+            f64::from_le_bytes(bytes.try_into().expect("8 bytes")).to_string()
+        }
+        _ => bail!("Unknown packed value type {}", kind),
+    };
+    Ok(value)
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(input: &mut &[u8]) -> Result<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = read_u8(input)?;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    bail!("Packed varint is too long")
+}
+
+fn read_count(input: &mut &[u8]) -> Result<usize> {
+    let count = read_varint(input)?;
+    ensure!(count <= MAX_PARAMS, "Too many packed parameters: {}", count);
+    Ok(count as usize)
+}
+
+fn write_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    write_varint(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+fn read_string(input: &mut &[u8]) -> Result<String> {
+    let len = read_varint(input)? as usize;
+    ensure!(input.len() >= len, "Truncated packed string");
+    let (bytes, rest) = input.split_at(len);
+    *input = rest;
+    Ok(std::str::from_utf8(bytes)?.to_string())
+}
+
+fn read_u8(input: &mut &[u8]) -> Result<u8> {
+    let (&byte, rest) = input
+        .split_first()
+        .ok_or_else(|| anyhow!("Truncated packed parameters"))?;
+    *input = rest;
+    Ok(byte)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pairs(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    fn unpacked(blob: &str) -> Result<Vec<(String, String)>> {
+        Ok(unpack(blob)?
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v))
+            .collect())
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let params = pairs(&[
+            ("id", "abc 123"),
+            ("won", "true"),
+            ("lost", "false"),
+            ("n", "-42"),
+            ("big", "9223372036854775807"),
+            ("p", "1.25"),
+            // typed lookalikes must come back verbatim
+            ("zero", "007"),
+            ("plus", "+1"),
+            ("exp", "1e3"),
+            ("neg0", "-0"),
+            ("t", "True"),
+            ("empty", ""),
+            ("utf8", "héllo"),
+            ("id", "repeated"),
+        ]);
+
+        for deflate in [false, cfg!(feature = "gzip")] {
+            let blob = pack(&params, deflate).unwrap();
+            assert!(
+                blob.chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            );
+            assert_eq!(unpacked(&blob).unwrap(), params);
+        }
+    }
+
+    #[test]
+    fn test_dictionary_and_varints() {
+        let params = pairs(&[("k", "1"), ("k", "2"), ("k", "300")]);
+        let blob = URL_SAFE_NO_PAD
+            .decode(pack(&params, false).unwrap())
+            .unwrap();
+
+        // header, 1 key "k", 3 entries of index, type and one or two bytes
+        assert_eq!(
+            blob,
+            [
+                2, 1, 1, b'k', 3, 0, TYPE_INT, 2, 0, TYPE_INT, 4, 0, TYPE_INT, 0xd8, 0x04
+            ]
+        );
+    }
+
+    #[test]
+    fn test_header() {
+        let params = pairs(&[("k", "v")]);
+        let plain = URL_SAFE_NO_PAD
+            .decode(pack(&params, false).unwrap())
+            .unwrap();
+        assert_eq!(plain[0], 2);
+        #[cfg(feature = "gzip")]
+        {
+            let deflated = URL_SAFE_NO_PAD
+                .decode(pack(&params, true).unwrap())
+                .unwrap();
+            assert_eq!(deflated[0], 3);
+        }
+        #[cfg(not(feature = "gzip"))]
+        {
+            assert!(pack(&params, true).is_err());
+            let mut deflated = plain.clone();
+            deflated[0] = 3;
+            assert!(unpack(&URL_SAFE_NO_PAD.encode(deflated)).is_err());
+        }
+
+        let mut future = plain.clone();
+        future[0] = 2 << 1;
+        let err = unpack(&URL_SAFE_NO_PAD.encode(future)).unwrap_err();
+        assert!(err.to_string().contains("version 2"));
+    }
+
+    #[test]
+    fn test_malformed() {
+        assert!(unpack("").is_err());
+        assert!(unpack("not base64!").is_err());
+
+        let blob = URL_SAFE_NO_PAD
+            .decode(pack(&pairs(&[("key", "value")]), false).unwrap())
+            .unwrap();
+        for len in 1..blob.len() {
+            assert!(unpack(&URL_SAFE_NO_PAD.encode(&blob[..len])).is_err());
+        }
+
+        let mut trailing = blob.clone();
+        trailing.push(0);
+        assert!(unpack(&URL_SAFE_NO_PAD.encode(trailing)).is_err());
+
+        // entry pointing past the dictionary
+        let bad_index = [2, 1, 1, b'k', 1, 5, TYPE_TRUE];
+        assert!(unpack(&URL_SAFE_NO_PAD.encode(bad_index)).is_err());
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_inflate_limit() {
+        let huge = "a".repeat(MAX_UNPACKED_SIZE as usize);
+        let blob = pack(&pairs(&[("k", &huge)]), true).unwrap();
+        let err = unpack(&blob).unwrap_err();
+        assert!(err.to_string().contains("too large"));
+    }
+
+    #[test]
+    fn test_decoded_limits() {
+        // a count far beyond the bytes is rejected before allocating
+        let mut blob = vec![VERSION << 1];
+        write_varint(&mut blob, u64::MAX >> 1);
+        let err = unpack(&URL_SAFE_NO_PAD.encode(blob)).unwrap_err();
+        assert!(err.to_string().contains("Too many"));
+
+        // a few KB of entries repeating one long key
+        let key = "k".repeat(4096);
+        let params: Vec<_> = (0..MAX_PARAMS)
+            .map(|_| (key.clone(), "true".to_string()))
+            .collect();
+        let blob = pack(&params, false).unwrap();
+        assert!(blob.len() < 16 * 1024);
+        let err = unpack(&blob).unwrap_err();
+        assert!(err.to_string().contains("too large"));
+
+        let shared = unpack(&pack(&pairs(&[("k", "1"), ("k", "2")]), false).unwrap()).unwrap();
+        assert!(Arc::ptr_eq(&shared[0].0, &shared[1].0));
+    }
+}
@@ -0,0 +1,315 @@
+use crate::bid_request::DistributionchannelOneof;
+use crate::common::DataUrl;
+use crate::common::utils::epoch_timestamp;
+use crate::{BidRequest, BidResponse};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// An auction: a bid request and the response it got
+#[derive(Clone, PartialEq, prost::Message, Serialize, Deserialize)]
+pub struct AuctionEvent {
+    /// Epoch millis
+    #[prost(uint64, tag = "1")]
+    pub ts_ms: u64,
+    #[prost(string, tag = "2")]
+    pub request_id: String,
+    #[prost(string, repeated, tag = "3")]
+    pub imp_ids: Vec<String>,
+    /// Site domain or app bundle
+    #[prost(string, tag = "4")]
+    pub inventory_id: String,
+    #[prost(string, tag = "5")]
+    pub publisher_id: String,
+    /// Number of bids in the response
+    #[prost(uint32, tag = "6")]
+    pub bids: u32,
+    /// No-bid reason of the response, see [`crate::openrtb::spec::nobidreason`]
+    #[prost(int32, tag = "7")]
+    pub nbr: i32,
+    /// Time taken to respond
+    #[prost(uint64, tag = "8")]
+    pub latency_ms: u64,
+}
+
+impl AuctionEvent {
+    /// Summarises an auction, `response` is `None` when no bid was made
+    pub fn new(request: &BidRequest, response: Option<&BidResponse>, latency_ms: u64) -> Self {
+        let (inventory_id, publisher_id) = match &request.distributionchannel_oneof {
+            Some(DistributionchannelOneof::Site(site)) => (
+                site.domain.clone(),
+                site.publisher.as_ref().map(|p| p.id.clone()),
+            ),
+            Some(DistributionchannelOneof::App(app)) => (
+                app.bundle.clone(),
+                app.publisher.as_ref().map(|p| p.id.clone()),
+            ),
+            _ => (String::new(), None),
+        };
+
+        Self {
+            ts_ms: epoch_timestamp(),
+            request_id: request.id.clone(),
+            imp_ids: request.imp.iter().map(|imp| imp.id.clone()).collect(),
+            inventory_id,
+            publisher_id: publisher_id.unwrap_or_default(),
+            bids: response.map_or(0, |r| r.seatbid.iter().map(|s| s.bid.len() as u32).sum()),
+            nbr: response.map_or(0, |r| r.nbr),
+            latency_ms,
+        }
+    }
+}
+
+/// A bid made in an auction
+#[derive(Clone, PartialEq, prost::Message, Serialize, Deserialize)]
+pub struct BidEvent {
+    /// Epoch millis
+    #[prost(uint64, tag = "1")]
+    pub ts_ms: u64,
+    #[prost(string, tag = "2")]
+    pub request_id: String,
+    #[prost(string, tag = "3")]
+    pub imp_id: String,
+    #[prost(string, tag = "4")]
+    pub bid_id: String,
+    #[prost(string, tag = "5")]
+    pub seat: String,
+    #[prost(double, tag = "6")]
+    pub price: f64,
+    #[prost(string, tag = "7")]
+    pub currency: String,
+    #[prost(string, tag = "8")]
+    pub crid: String,
+    #[prost(string, repeated, tag = "9")]
+    pub adomain: Vec<String>,
+    #[prost(string, tag = "10")]
+    pub deal_id: String,
+}
+
+impl BidEvent {
+    /// One event for every bid of the response
+    pub fn from_response(response: &BidResponse) -> Vec<Self> {
+        let ts_ms = epoch_timestamp();
+
+        response
+            .seatbid
+            .iter()
+            .flat_map(|seatbid| seatbid.bid.iter().map(move |bid| (seatbid, bid)))
+            .map(|(seatbid, bid)| Self {
+                ts_ms,
+                request_id: response.id.clone(),
+                imp_id: bid.impid.clone(),
+                bid_id: bid.id.clone(),
+                seat: seatbid.seat.clone(),
+                price: bid.price,
+                currency: response.cur.clone(),
+                crid: bid.crid.clone(),
+                adomain: bid.adomain.clone(),
+                deal_id: bid.dealid.clone(),
+            })
+            .collect()
+    }
+}
+
+/// A tracking request, e.g. a win notice or an impression pixel
+#[derive(Clone, PartialEq, prost::Message, Serialize, Deserialize)]
+pub struct BeaconEvent {
+    /// Epoch millis
+    #[prost(uint64, tag = "1")]
+    pub ts_ms: u64,
+    /// What was tracked, by default the path of the beacon URL
+    #[prost(string, tag = "2")]
+    pub kind: String,
+    /// Parameters of the beacon URL, without those of its signature
+    #[prost(btree_map = "string, string", tag = "3")]
+    pub params: BTreeMap<String, String>,
+}
+
+impl BeaconEvent {
+    /// Records a beacon URL, which is named after its path. Repeated
+    /// parameters keep their first value.
+    pub fn new(url: &DataUrl) -> Self {
+        let mut params = BTreeMap::new();
+        for (k, v) in url.data_params() {
+            params
+                .entry(k.into_owned())
+                .or_insert_with(|| v.into_owned());
+        }
+
+        Self {
+            ts_ms: epoch_timestamp(),
+            kind: url.path().to_string(),
+            params,
+        }
+    }
+}
+
+/// An entry of the event log, written as JSON with a `type` tag or as an
+/// [`EventRecord`]
+#[derive(Clone, PartialEq, prost::Oneof, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    #[prost(message, tag = "1")]
+    Auction(AuctionEvent),
+    #[prost(message, tag = "2")]
+    Bid(BidEvent),
+    #[prost(message, tag = "3")]
+    Beacon(BeaconEvent),
+}
+
+impl From<AuctionEvent> for Event {
+    fn from(event: AuctionEvent) -> Self {
+        Event::Auction(event)
+    }
+}
+
+impl From<BidEvent> for Event {
+    fn from(event: BidEvent) -> Self {
+        Event::Bid(event)
+    }
+}
+
+impl From<BeaconEvent> for Event {
+    fn from(event: BeaconEvent) -> Self {
+        Event::Beacon(event)
+    }
+}
+
+/// Protobuf envelope of an [`Event`], as written length-delimited by
+/// [`super::EventFormat::Protobuf`]
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct EventRecord {
+    #[prost(oneof = "Event", tags = "1, 2, 3")]
+    pub event: Option<Event>,
+}
+
+impl From<Event> for EventRecord {
+    fn from(event: Event) -> Self {
+        Self { event: Some(event) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bid_request::{Imp, Publisher, Site};
+    use crate::bid_response::{Bid, SeatBid};
+    use prost::Message;
+
+    fn request() -> BidRequest {
+        BidRequest {
+            id: "req-1".to_string(),
+            imp: vec![
+                Imp {
+                    id: "1".to_string(),
+                    ..Default::default()
+                },
+                Imp {
+                    id: "2".to_string(),
+                    ..Default::default()
+                },
+            ],
+            distributionchannel_oneof: Some(DistributionchannelOneof::Site(Site {
+                domain: "news.example.com".to_string(),
+                publisher: Some(Publisher {
+                    id: "pub-1".to_string(),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+    }
+
+    fn response() -> BidResponse {
+        BidResponse {
+            id: "req-1".to_string(),
+            cur: "USD".to_string(),
+            seatbid: vec![SeatBid {
+                seat: "seat-1".to_string(),
+                bid: vec![
+                    Bid {
+                        id: "b1".to_string(),
+                        impid: "1".to_string(),
+                        price: 1.5,
+                        crid: "cr-1".to_string(),
+                        adomain: vec!["brand.com".to_string()],
+                        ..Default::default()
+                    },
+                    Bid {
+                        id: "b2".to_string(),
+                        impid: "2".to_string(),
+                        price: 0.5,
+                        ..Default::default()
+                    },
+                ],
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_auction_event() {
+        let event = AuctionEvent::new(&request(), Some(&response()), 12);
+        assert_eq!(event.request_id, "req-1");
+        assert_eq!(event.imp_ids, ["1", "2"]);
+        assert_eq!(event.inventory_id, "news.example.com");
+        assert_eq!(event.publisher_id, "pub-1");
+        assert_eq!(event.bids, 2);
+        assert_eq!(event.latency_ms, 12);
+        assert!(event.ts_ms > 0);
+
+        let no_bid = AuctionEvent::new(&request(), None, 3);
+        assert_eq!(no_bid.bids, 0);
+    }
+
+    #[test]
+    fn test_bid_events() {
+        let events = BidEvent::from_response(&response());
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].bid_id, "b1");
+        assert_eq!(events[0].seat, "seat-1");
+        assert_eq!(events[0].currency, "USD");
+        assert_eq!(events[0].adomain, ["brand.com"]);
+        assert_eq!(events[1].imp_id, "2");
+        assert_eq!(events[1].price, 0.5);
+    }
+
+    #[cfg(feature = "crypto")]
+    #[test]
+    fn test_beacon_event_skips_signature() {
+        let signing = crate::common::UrlSigningBuilder::default()
+            .keyring(crate::common::Keyring::new("k1", b"secret".to_vec()))
+            .nonces(std::sync::Arc::new(crate::common::MemoryNonceSet::new(16)))
+            .build()
+            .unwrap();
+        let mut url = DataUrl::new("t.example.com", "win").unwrap();
+        url.add_string("a", "abc").unwrap().add_int("p", 2).unwrap();
+        url.finalize_signed(&signing).unwrap();
+
+        let url = DataUrl::from(&url.url(true).unwrap()).unwrap();
+        let event = BeaconEvent::new(&url);
+        assert_eq!(event.kind, "win");
+        assert_eq!(
+            event.params,
+            BTreeMap::from([
+                ("a".to_string(), "abc".to_string()),
+                ("p".to_string(), "2".to_string())
+            ])
+        );
+    }
+
+    #[test]
+    fn test_event_encodings() {
+        let event = Event::from(BidEvent::from_response(&response()).remove(0));
+
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["type"], "bid");
+        assert_eq!(json["bid_id"], "b1");
+        assert_eq!(serde_json::from_value::<Event>(json).unwrap(), event);
+
+        let bytes = EventRecord::from(event.clone()).encode_to_vec();
+        let record = EventRecord::decode(bytes.as_slice()).unwrap();
+        assert_eq!(record.event, Some(event));
+    }
+}
@@ -0,0 +1,379 @@
+use super::event::Event;
+use super::sink::EventSink;
+use derive_builder::Builder;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TrySendError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Queue and flush settings of an [`EventLog`]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Builder)]
+#[builder(build_fn(validate = "Self::validate"))]
+pub struct EventLogConfig {
+    /// Events held while the sinks catch up, further events are dropped.
+    /// Must be greater than 0.
+    #[builder(default = "65_536")]
+    pub capacity: usize,
+
+    /// Longest time an event waits in a sink's buffer. Must be greater
+    /// than 0.
+    #[builder(default = "Duration::from_secs(1)")]
+    pub flush_interval: Duration,
+}
+
+const ZERO_CAPACITY: &str = "EventLog capacity must be greater than 0";
+const ZERO_FLUSH_INTERVAL: &str = "EventLog flush interval must be greater than 0";
+
+impl Default for EventLogConfig {
+    fn default() -> Self {
+        EventLogConfigBuilder::default().build().unwrap()
+    }
+}
+
+impl EventLogConfigBuilder {
+    fn validate(&self) -> Result<(), String> {
+        match (self.capacity, self.flush_interval) {
+            (Some(0), _) => Err(ZERO_CAPACITY.to_string()),
+            (_, Some(Duration::ZERO)) => Err(ZERO_FLUSH_INTERVAL.to_string()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Counters of an [`EventLog`] since it started
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct EventLogStats {
+    /// Events accepted into the queue
+    pub enqueued: u64,
+    /// Events dropped because the queue was full
+    pub dropped: u64,
+    /// Events written to every sink
+    pub written: u64,
+    /// Sink writes and flushes which failed
+    pub failed: u64,
+}
+
+#[derive(Default)]
+struct Counters {
+    enqueued: AtomicU64,
+    dropped: AtomicU64,
+    written: AtomicU64,
+    failed: AtomicU64,
+}
+
+/// Hands events from hot paths to a background thread writing them to
+/// sinks.
+///
+/// [`EventLog::log`] never blocks: events go into a bounded queue and are
+/// dropped and counted when it is full, so a slow disk costs events rather
+/// than bid latency. The queue is drained and the sinks flushed on
+/// [`EventLog::shutdown`] or drop.
+///
+/// # Example
+/// ```
+/// use rtb::eventlog::{BeaconEvent, EventFormat, EventLog, EventLogConfig, WriterSink};
+///
+/// let log = EventLog::start(
+///     EventLogConfig::default(),
+///     vec![Box::new(WriterSink::new(std::io::sink(), EventFormat::Jsonl))],
+/// )
+/// .unwrap();
+///
+/// assert!(log.log(BeaconEvent::default()));
+/// log.shutdown();
+/// ```
+pub struct EventLog {
+    sender: Option<SyncSender<Event>>,
+    counters: Arc<Counters>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl EventLog {
+    /// Spawns the worker thread writing to `sinks`. A zero capacity, e.g.
+    /// from a deserialized config, is rejected as it would drop every event,
+    /// and so is a zero flush interval, which would keep the worker spinning.
+    pub fn start(config: EventLogConfig, sinks: Vec<Box<dyn EventSink>>) -> io::Result<Self> {
+        if config.capacity == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, ZERO_CAPACITY));
+        }
+        if config.flush_interval.is_zero() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                ZERO_FLUSH_INTERVAL,
+            ));
+        }
+
+        let (sender, receiver) = mpsc::sync_channel(config.capacity);
+        let counters = Arc::new(Counters::default());
+
+        let worker = {
+            let counters = counters.clone();
+            thread::Builder::new()
+                .name("rtb-eventlog".to_string())
+                .spawn(move || run(receiver, sinks, config.flush_interval, &counters))?
+        };
+
+        Ok(Self {
+            sender: Some(sender),
+            counters,
+            worker: Some(worker),
+        })
+    }
+
+    /// Queues an event, returns false if it was dropped
+    pub fn log(&self, event: impl Into<Event>) -> bool {
+        let Some(sender) = &self.sender else {
+            return false;
+        };
+
+        match sender.try_send(event.into()) {
+            Ok(()) => {
+                self.counters.enqueued.fetch_add(1, Ordering::Relaxed);
+                true
+            }
+            Err(TrySendError::Full(_) | TrySendError::Disconnected(_)) => {
+                self.counters.dropped.fetch_add(1, Ordering::Relaxed);
+                false
+            }
+        }
+    }
+
+    pub fn stats(&self) -> EventLogStats {
+        EventLogStats {
+            enqueued: self.counters.enqueued.load(Ordering::Relaxed),
+            dropped: self.counters.dropped.load(Ordering::Relaxed),
+            written: self.counters.written.load(Ordering::Relaxed),
+            failed: self.counters.failed.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Writes the queued events, flushes the sinks and stops the worker
+    pub fn shutdown(mut self) -> EventLogStats {
+        self.stop();
+        self.stats()
+    }
+
+    fn stop(&mut self) {
+        // the worker drains the queue once every sender is gone
+        self.sender.take();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+impl Drop for EventLog {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn run(
+    receiver: Receiver<Event>,
+    mut sinks: Vec<Box<dyn EventSink>>,
+    flush_interval: Duration,
+    counters: &Counters,
+) {
+    let mut last_flush = Instant::now();
+
+    loop {
+        let timeout = flush_interval.saturating_sub(last_flush.elapsed());
+        match receiver.recv_timeout(timeout) {
+            Ok(event) => {
+                let mut ok = true;
+                for sink in sinks.iter_mut() {
+                    if sink.write(&event).is_err() {
+                        counters.failed.fetch_add(1, Ordering::Relaxed);
+                        ok = false;
+                    }
+                }
+                if ok {
+                    counters.written.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+
+        if last_flush.elapsed() >= flush_interval {
+            flush(&mut sinks, counters);
+            last_flush = Instant::now();
+        }
+    }
+
+    flush(&mut sinks, counters);
+}
+
+fn flush(sinks: &mut [Box<dyn EventSink>], counters: &Counters) {
+    for sink in sinks.iter_mut() {
+        if sink.flush().is_err() {
+            counters.failed.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eventlog::{BeaconEvent, EventFormat, WriterSink};
+    use std::sync::Mutex;
+    use std::sync::mpsc::Sender;
+
+    /// Shares what it is given with the test
+    struct SharedSink(Arc<Mutex<Vec<Event>>>);
+
+    impl EventSink for SharedSink {
+        fn write(&mut self, event: &Event) -> io::Result<()> {
+            self.0.lock().unwrap().push(event.clone());
+            Ok(())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Blocks every write until the test lets it through
+    struct GatedSink(Receiver<()>, Sender<()>);
+
+    impl EventSink for GatedSink {
+        fn write(&mut self, _event: &Event) -> io::Result<()> {
+            self.1.send(()).unwrap();
+            self.0.recv().map_err(io::Error::other)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    struct FailingSink;
+
+    impl EventSink for FailingSink {
+        fn write(&mut self, _event: &Event) -> io::Result<()> {
+            Err(io::Error::other("disk full"))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn beacon(kind: &str) -> BeaconEvent {
+        BeaconEvent {
+            kind: kind.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_shutdown_drains_queue() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let log = EventLog::start(
+            EventLogConfig::default(),
+            vec![
+                Box::new(SharedSink(events.clone())),
+                Box::new(WriterSink::new(io::sink(), EventFormat::Protobuf)),
+            ],
+        )
+        .unwrap();
+
+        for i in 0..100 {
+            assert!(log.log(beacon(&i.to_string())));
+        }
+        let stats = log.shutdown();
+
+        assert_eq!(
+            stats,
+            EventLogStats {
+                enqueued: 100,
+                written: 100,
+                ..Default::default()
+            }
+        );
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 100);
+        assert_eq!(events[99], Event::Beacon(beacon("99")));
+    }
+
+    #[test]
+    fn test_full_queue_drops() {
+        let (release, gate) = mpsc::channel();
+        let (entered, writing) = mpsc::channel();
+        let config = EventLogConfigBuilder::default()
+            .capacity(2)
+            .build()
+            .unwrap();
+        let log = EventLog::start(config, vec![Box::new(GatedSink(gate, entered))]).unwrap();
+
+        // the worker holds the first event, the queue the next two
+        assert!(log.log(beacon("a")));
+        writing.recv().unwrap();
+        assert!(log.log(beacon("b")));
+        assert!(log.log(beacon("c")));
+        assert!(!log.log(beacon("d")));
+        assert_eq!(log.stats().dropped, 1);
+
+        for _ in 0..3 {
+            release.send(()).unwrap();
+        }
+        let stats = log.shutdown();
+        assert_eq!(stats.enqueued, 3);
+        assert_eq!(stats.written, 3);
+        assert_eq!(stats.dropped, 1);
+    }
+
+    #[test]
+    fn test_zero_capacity_rejected() {
+        assert!(
+            EventLogConfigBuilder::default()
+                .capacity(0)
+                .build()
+                .is_err()
+        );
+
+        let config = EventLogConfig {
+            capacity: 0,
+            ..Default::default()
+        };
+        let err = EventLog::start(config, Vec::new()).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_zero_flush_interval_rejected() {
+        assert!(
+            EventLogConfigBuilder::default()
+                .flush_interval(Duration::ZERO)
+                .build()
+                .is_err()
+        );
+
+        let config = EventLogConfig {
+            flush_interval: Duration::ZERO,
+            ..Default::default()
+        };
+        let err = EventLog::start(config, Vec::new()).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_sink_failures_are_counted() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let log = EventLog::start(
+            EventLogConfig::default(),
+            vec![Box::new(FailingSink), Box::new(SharedSink(events.clone()))],
+        )
+        .unwrap();
+
+        log.log(beacon("a"));
+        let stats = log.shutdown();
+        assert_eq!(stats.failed, 1);
+        assert_eq!(stats.written, 0);
+        // the other sinks still get the event
+        assert_eq!(events.lock().unwrap().len(), 1);
+    }
+}
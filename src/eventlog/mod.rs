@@ -0,0 +1,37 @@
+//! Structured log of auctions, bids and beacons.
+//!
+//! Events are queued by [`EventLog::log`] without blocking and written by a
+//! background thread to one or more [`EventSink`]s, e.g. rotating JSONL or
+//! length-delimited protobuf files and stdout.
+//!
+//! # Example
+//! ```no_run
+//! use rtb::eventlog::{
+//!     AuctionEvent, BidEvent, EventFormat, EventLog, EventLogConfig, RotatingFileSink,
+//!     WriterSink,
+//! };
+//! # let request = rtb::BidRequest::default();
+//! # let response = rtb::BidResponse::default();
+//!
+//! let log = EventLog::start(
+//!     EventLogConfig::default(),
+//!     vec![
+//!         Box::new(RotatingFileSink::new("/var/log/bidder", "events", EventFormat::Jsonl).unwrap()),
+//!         Box::new(WriterSink::stdout()),
+//!     ],
+//! )
+//! .unwrap();
+//!
+//! log.log(AuctionEvent::new(&request, Some(&response), 4));
+//! for bid in BidEvent::from_response(&response) {
+//!     log.log(bid);
+//! }
+//! ```
+
+mod event;
+mod log;
+mod sink;
+
+pub use event::{AuctionEvent, BeaconEvent, BidEvent, Event, EventRecord};
+pub use log::{EventLog, EventLogConfig, EventLogConfigBuilder, EventLogStats};
+pub use sink::{EventFormat, EventSink, RotatingFileSink, RotationPolicy, WriterSink};
@@ -0,0 +1,422 @@
+use super::event::{Event, EventRecord};
+use crate::common::{Clock, SystemClock};
+use prost::Message;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Stdout, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Destination of the events of an [`super::EventLog`]. Sinks are driven by
+/// the log's worker thread, so they may block.
+pub trait EventSink: Send {
+    fn write(&mut self, event: &Event) -> io::Result<()>;
+
+    /// Called periodically and before the log shuts down
+    fn flush(&mut self) -> io::Result<()>;
+}
+
+/// How events are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventFormat {
+    /// One JSON object per line, tagged with its `type`
+    Jsonl,
+    /// Length-delimited [`EventRecord`] messages
+    Protobuf,
+}
+
+impl EventFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            EventFormat::Jsonl => "jsonl",
+            EventFormat::Protobuf => "pb",
+        }
+    }
+
+    /// Appends one encoded event to `buf`
+    pub fn encode(self, event: &Event, buf: &mut Vec<u8>) -> io::Result<()> {
+        match self {
+            EventFormat::Jsonl => {
+                serde_json::to_writer(&mut *buf, event)?;
+                buf.push(b'\n');
+            }
+            EventFormat::Protobuf => {
+                EventRecord::from(event.clone())
+                    .encode_length_delimited(buf)
+                    .map_err(io::Error::other)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes events to any writer, e.g. stdout
+pub struct WriterSink<W> {
+    writer: W,
+    format: EventFormat,
+    buf: Vec<u8>,
+}
+
+impl<W: Write + Send> WriterSink<W> {
+    pub fn new(writer: W, format: EventFormat) -> Self {
+        Self {
+            writer,
+            format,
+            buf: Vec::new(),
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl WriterSink<BufWriter<Stdout>> {
+    /// JSON lines on stdout
+    pub fn stdout() -> Self {
+        Self::new(BufWriter::new(io::stdout()), EventFormat::Jsonl)
+    }
+}
+
+impl<W: Write + Send> EventSink for WriterSink<W> {
+    fn write(&mut self, event: &Event) -> io::Result<()> {
+        self.buf.clear();
+        self.format.encode(event, &mut self.buf)?;
+        self.writer.write_all(&self.buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// When a [`RotatingFileSink`] starts a new file, whichever limit is
+/// reached first
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RotationPolicy {
+    pub max_bytes: Option<u64>,
+    pub max_age: Option<Duration>,
+}
+
+impl Default for RotationPolicy {
+    fn default() -> Self {
+        Self {
+            max_bytes: Some(128 * 1024 * 1024),
+            max_age: Some(Duration::from_secs(3600)),
+        }
+    }
+}
+
+/// Writes events to files in a directory, starting a new file according to
+/// its [`RotationPolicy`].
+///
+/// Files are named `{prefix}-{epoch millis}-{sequence}.{jsonl|pb}` and are
+/// written to with a `.part` suffix, which is removed once the file is
+/// rotated or the sink dropped, so a shipper only picks up complete files.
+///
+/// # Example
+/// ```no_run
+/// use rtb::eventlog::{EventFormat, RotatingFileSink, RotationPolicy};
+///
+/// let sink = RotatingFileSink::new("/var/log/bidder", "bids", EventFormat::Jsonl)
+///     .unwrap()
+///     .with_policy(RotationPolicy {
+///         max_bytes: Some(64 * 1024 * 1024),
+///         max_age: None,
+///     });
+/// ```
+pub struct RotatingFileSink<C = SystemClock> {
+    dir: PathBuf,
+    prefix: String,
+    format: EventFormat,
+    policy: RotationPolicy,
+    clock: C,
+    current: Option<OpenFile>,
+    sequence: u64,
+    buf: Vec<u8>,
+}
+
+struct OpenFile {
+    writer: BufWriter<File>,
+    path: PathBuf,
+    bytes: u64,
+    opened_ms: u64,
+}
+
+impl OpenFile {
+    /// Flushes the file and drops its `.part` suffix
+    fn complete(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        fs::rename(&self.path, self.path.with_extension(""))
+    }
+}
+
+impl RotatingFileSink {
+    /// Creates `dir` if needed. The first file is opened on the first event.
+    pub fn new(dir: impl AsRef<Path>, prefix: &str, format: EventFormat) -> io::Result<Self> {
+        fs::create_dir_all(dir.as_ref())?;
+
+        Ok(Self {
+            dir: dir.as_ref().to_path_buf(),
+            prefix: prefix.to_string(),
+            format,
+            policy: RotationPolicy::default(),
+            clock: SystemClock,
+            current: None,
+            sequence: 0,
+            buf: Vec::new(),
+        })
+    }
+}
+
+impl<C: Clock> RotatingFileSink<C> {
+    pub fn with_policy(mut self, policy: RotationPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Uses the given clock for file names and their age
+    pub fn with_clock<C2: Clock>(mut self, clock: C2) -> RotatingFileSink<C2> {
+        // fields are taken rather than moved as the sink implements Drop
+        RotatingFileSink {
+            dir: mem::take(&mut self.dir),
+            prefix: mem::take(&mut self.prefix),
+            format: self.format,
+            policy: self.policy.clone(),
+            clock,
+            current: self.current.take(),
+            sequence: self.sequence,
+            buf: mem::take(&mut self.buf),
+        }
+    }
+
+    /// Completes the current file, the next event starts a new one
+    pub fn rotate(&mut self) -> io::Result<()> {
+        let Some(mut file) = self.current.take() else {
+            return Ok(());
+        };
+
+        file.complete()
+    }
+
+    fn should_rotate(&self, file: &OpenFile, now_ms: u64) -> bool {
+        let too_big = self.policy.max_bytes.is_some_and(|max| file.bytes >= max);
+        let too_old = self
+            .policy
+            .max_age
+            .is_some_and(|max| now_ms.saturating_sub(file.opened_ms) >= max.as_millis() as u64);
+        too_big || too_old
+    }
+
+    fn open(&mut self, now_ms: u64) -> io::Result<OpenFile> {
+        self.sequence += 1;
+        let path = self.dir.join(format!(
+            "{}-{}-{:06}.{}.part",
+            self.prefix,
+            now_ms,
+            self.sequence,
+            self.format.extension()
+        ));
+
+        Ok(OpenFile {
+            writer: BufWriter::new(File::create_new(&path)?),
+            path,
+            bytes: 0,
+            opened_ms: now_ms,
+        })
+    }
+}
+
+impl<C: Clock> EventSink for RotatingFileSink<C> {
+    fn write(&mut self, event: &Event) -> io::Result<()> {
+        let now_ms = self.clock.now_ms();
+        if self
+            .current
+            .as_ref()
+            .is_some_and(|file| self.should_rotate(file, now_ms))
+        {
+            self.rotate()?;
+        }
+
+        if self.current.is_none() {
+            self.current = Some(self.open(now_ms)?);
+        }
+        let file = self.current.as_mut().expect("file opened above");
+
+        self.buf.clear();
+        self.format.encode(event, &mut self.buf)?;
+        file.writer.write_all(&self.buf)?;
+        file.bytes += self.buf.len() as u64;
+        Ok(())
+    }
+
+    /// Also completes a file which is due, so an idle log still rotates
+    /// by age
+    fn flush(&mut self) -> io::Result<()> {
+        let now_ms = self.clock.now_ms();
+        if self
+            .current
+            .as_ref()
+            .is_some_and(|file| self.should_rotate(file, now_ms))
+        {
+            return self.rotate();
+        }
+
+        match self.current.as_mut() {
+            Some(file) => file.writer.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<C> Drop for RotatingFileSink<C> {
+    fn drop(&mut self) {
+        if let Some(mut file) = self.current.take() {
+            let _ = file.complete();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eventlog::BeaconEvent;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    struct ManualClock(AtomicU64);
+
+    impl Clock for ManualClock {
+        fn now_ms(&self) -> u64 {
+            self.0.load(Ordering::SeqCst)
+        }
+    }
+
+    fn event(kind: &str) -> Event {
+        Event::Beacon(BeaconEvent {
+            ts_ms: 1,
+            kind: kind.to_string(),
+            ..Default::default()
+        })
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("rtb-eventlog-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn files(dir: &Path) -> Vec<String> {
+        let mut files: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn test_writer_sink_formats() {
+        let mut sink = WriterSink::new(Vec::new(), EventFormat::Jsonl);
+        sink.write(&event("win")).unwrap();
+        sink.write(&event("imp")).unwrap();
+        let out = String::from_utf8(sink.into_inner()).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            serde_json::from_str::<Event>(lines[1]).unwrap(),
+            event("imp")
+        );
+
+        let mut sink = WriterSink::new(Vec::new(), EventFormat::Protobuf);
+        sink.write(&event("win")).unwrap();
+        sink.write(&event("imp")).unwrap();
+        let out = sink.into_inner();
+        let mut buf = out.as_slice();
+        let first = EventRecord::decode_length_delimited(&mut buf).unwrap();
+        let second = EventRecord::decode_length_delimited(&mut buf).unwrap();
+        assert!(buf.is_empty());
+        assert_eq!(first.event, Some(event("win")));
+        assert_eq!(second.event, Some(event("imp")));
+    }
+
+    #[test]
+    fn test_rotation_by_size() {
+        let dir = temp_dir("size");
+        let mut sink = RotatingFileSink::new(&dir, "bids", EventFormat::Jsonl)
+            .unwrap()
+            .with_policy(RotationPolicy {
+                max_bytes: Some(1),
+                max_age: None,
+            })
+            .with_clock(ManualClock(AtomicU64::new(1000)));
+
+        sink.write(&event("a")).unwrap();
+        sink.write(&event("b")).unwrap();
+        // the first file is complete, the second still being written
+        assert_eq!(
+            files(&dir),
+            ["bids-1000-000001.jsonl", "bids-1000-000002.jsonl.part"]
+        );
+
+        // and due as well, so flushing completes it
+        sink.flush().unwrap();
+        let files = files(&dir);
+        assert_eq!(files, ["bids-1000-000001.jsonl", "bids-1000-000002.jsonl"]);
+        let second = fs::read_to_string(dir.join(&files[1])).unwrap();
+        assert_eq!(
+            serde_json::from_str::<Event>(second.trim()).unwrap(),
+            event("b")
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_rotation_by_age() {
+        let dir = temp_dir("age");
+        let clock = ManualClock(AtomicU64::new(0));
+        let mut sink = RotatingFileSink::new(&dir, "beacons", EventFormat::Protobuf)
+            .unwrap()
+            .with_policy(RotationPolicy {
+                max_bytes: None,
+                max_age: Some(Duration::from_secs(60)),
+            })
+            .with_clock(clock);
+
+        sink.write(&event("a")).unwrap();
+        sink.clock.0.store(59_999, Ordering::SeqCst);
+        sink.write(&event("b")).unwrap();
+        sink.clock.0.store(60_000, Ordering::SeqCst);
+        sink.write(&event("c")).unwrap();
+        sink.rotate().unwrap();
+
+        assert_eq!(
+            files(&dir),
+            ["beacons-0-000001.pb", "beacons-60000-000002.pb"]
+        );
+        let first = fs::read(dir.join("beacons-0-000001.pb")).unwrap();
+        let mut buf = first.as_slice();
+        let mut kinds = Vec::new();
+        while !buf.is_empty() {
+            match EventRecord::decode_length_delimited(&mut buf)
+                .unwrap()
+                .event
+            {
+                Some(Event::Beacon(beacon)) => kinds.push(beacon.kind),
+                other => panic!("unexpected {other:?}"),
+            }
+        }
+        assert_eq!(kinds, ["a", "b"]);
+
+        // an idle sink completes its file when flushed
+        sink.write(&event("d")).unwrap();
+        sink.flush().unwrap();
+        assert!(files(&dir).contains(&"beacons-60000-000003.pb.part".to_string()));
+        sink.clock.0.store(120_000, Ordering::SeqCst);
+        sink.flush().unwrap();
+        assert!(files(&dir).contains(&"beacons-60000-000003.pb".to_string()));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
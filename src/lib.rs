@@ -113,4 +113,10 @@ mod macros;
 pub mod server;
 
 pub mod common;
+
+/// Structured event log of auctions, bids and beacons with pluggable sinks.
+pub mod eventlog;
+
 mod observability;
+/// Readers and writers of JSONL and length-delimited protobuf message streams.
+pub mod stream;
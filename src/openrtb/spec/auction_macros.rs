@@ -39,3 +39,40 @@ pub const AUCTION_MULTIPLIER: &str = "${AUCTION_MULTIPLIER}";
 /// notification as soon as the impression takes place. If omitted, it is assumed the impression
 /// took place a few seconds before the notification is fired.
 pub const AUCTION_IMP_TS: &str = "${AUCTION_IMP_TS}";
+
+/// The auction macros as a type, e.g. for [`crate::common::DataUrl::add_auction_macro`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AuctionMacro {
+    Id,
+    BidId,
+    ImpId,
+    SeatId,
+    AdId,
+    Price,
+    Currency,
+    Mbr,
+    Loss,
+    MinToWin,
+    Multiplier,
+    ImpTs,
+}
+
+impl AuctionMacro {
+    /// The macro text, e.g. `${AUCTION_PRICE}`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuctionMacro::Id => AUCTION_ID,
+            AuctionMacro::BidId => AUCTION_BID_ID,
+            AuctionMacro::ImpId => AUCTION_IMP_ID,
+            AuctionMacro::SeatId => AUCTION_SEAT_ID,
+            AuctionMacro::AdId => AUCTION_AD_ID,
+            AuctionMacro::Price => AUCTION_PRICE,
+            AuctionMacro::Currency => AUCTION_CURRENCY,
+            AuctionMacro::Mbr => AUCTION_MBR,
+            AuctionMacro::Loss => AUCTION_LOSS,
+            AuctionMacro::MinToWin => AUCTION_MIN_TO_WIN,
+            AuctionMacro::Multiplier => AUCTION_MULTIPLIER,
+            AuctionMacro::ImpTs => AUCTION_IMP_TS,
+        }
+    }
+}
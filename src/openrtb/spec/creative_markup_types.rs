@@ -0,0 +1,19 @@
+//! Creative Markup Types
+//!
+//! The type of creative markup in a bid, as signalled by `bid.mtype` (OpenRTB 2.6).
+
+use crate::spec_list;
+
+spec_list! {
+    /// Banner
+    BANNER = 1 => "Banner",
+
+    /// Video
+    VIDEO = 2 => "Video",
+
+    /// Audio
+    AUDIO = 3 => "Audio",
+
+    /// Native
+    NATIVE = 4 => "Native",
+}
@@ -1,4 +1,5 @@
 pub mod auction_macros;
+pub mod creative_markup_types;
 pub mod lossreason;
 pub mod nobidreason;
 pub mod video_placement_types;
@@ -1,7 +1,15 @@
+use super::sniff::{mtype_format, root_element, sniff_bid};
+use super::trackers::{html_pixel, html_script};
+use crate::BidRequest;
 use crate::bid_response::Bid;
 use crate::bid_response::bid::AdmOneof;
+use crate::common::DataUrl;
 use anyhow::{Error, bail};
+use derive_builder::Builder;
+use quick_xml::Reader;
+use quick_xml::events::Event;
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use strum::{Display, EnumString};
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, EnumString, Display)]
@@ -27,7 +35,7 @@ impl AdFormat {
 /// Convenience method for extracting textual adm from a bid response.
 /// The only time this fails is if the adm is empty, or if the
 /// bid response is a native bid via protobuf, in which case
-/// the adm_oneof must be extracted manually
+/// use [`get_adm_text`] or [`super::native::get_native_response`]
 pub fn get_adm(bid: &Bid) -> Result<&str, Error> {
     if bid.adm_oneof.is_none() {
         bail!("No adm present on bid");
@@ -35,37 +43,154 @@ pub fn get_adm(bid: &Bid) -> Result<&str, Error> {
 
     match bid.adm_oneof.as_ref().unwrap() {
         AdmOneof::Adm(s) => Ok(s),
-        _ => bail!("NativeResponse object detected (protobuf?) must handle manually"),
+        _ => bail!("NativeResponse object detected (protobuf?) use get_adm_text instead"),
+    }
+}
+
+/// Like [`get_adm`], but serialises a protobuf [`AdmOneof::AdmNative`]
+/// response to its textual Native 1.2 JSON form instead of failing
+pub fn get_adm_text(bid: &Bid) -> Result<Cow<'_, str>, Error> {
+    match bid.adm_oneof.as_ref() {
+        Some(AdmOneof::Adm(s)) => Ok(Cow::Borrowed(s)),
+        Some(AdmOneof::AdmNative(native)) => Ok(Cow::Owned(
+            super::native::native_response_to_string(native, false)?,
+        )),
+        None => bail!("No adm present on bid"),
     }
 }
 
 pub fn is_vast(adm: &str) -> bool {
-    use memchr::memchr;
+    root_tag_is(adm, "vast")
+}
+
+/// True if the markup is a DAAST (Digital Audio Ad Serving Template) document
+pub fn is_daast(adm: &str) -> bool {
+    root_tag_is(adm, "daast")
+}
+
+fn root_tag_is(adm: &str, name: &str) -> bool {
+    root_element(adm).is_some_and(|(tag, _)| tag.eq_ignore_ascii_case(name))
+}
+
+/// Tells audio from video VAST by the declared `adType` (VAST 4.1+) or by
+/// the `MediaFile` mime types. Only VAST whose media files are all `audio/*`
+/// is audio, wrappers and documents without media files count as video.
+pub(crate) fn vast_format(adm: &str) -> AdFormat {
+    stream_vast_format(adm).unwrap_or_else(|| scan_vast_format(adm))
+}
 
-    let mut s = adm;
+/// Streams the elements of well formed VAST, reading the `adType` of each
+/// `Ad` and the `type` of each `MediaFile` within `MediaFiles`, so that
+/// lookalikes in comments or extensions are ignored. `None` if the markup is
+/// not well formed or its root is not `VAST`.
+fn stream_vast_format(adm: &str) -> Option<AdFormat> {
+    let mut reader = Reader::from_str(adm);
+    let mut path: Vec<String> = Vec::new();
+    let mut root = false;
+    let mut audio = false;
+    let mut video = false;
 
-    // jump to first tag start to ignore leading comments/garbage if present
-    if let Some(i) = memchr(b'<', s.as_bytes()) {
-        s = &s[i..];
+    loop {
+        let (e, empty) = match reader.read_event().ok()? {
+            Event::Start(e) => (e, false),
+            Event::Empty(e) => (e, true),
+            Event::End(_) => {
+                path.pop();
+                continue;
+            }
+            Event::Eof => break,
+            _ => continue,
+        };
+
+        let name = String::from_utf8_lossy(e.name().as_ref()).into_owned();
+        let attr = |key: &str| {
+            e.try_get_attribute(key)
+                .ok()
+                .flatten()
+                .map(|a| String::from_utf8_lossy(&a.value).into_owned())
+        };
+        match (path.len(), path.last().map(String::as_str), name.as_str()) {
+            (0, _, tag) if !tag.eq_ignore_ascii_case("vast") => return None,
+            (0, _, _) => root = true,
+            (1, _, "Ad") if attr("adType").as_deref() == Some("audio") => {
+                return Some(AdFormat::Audio);
+            }
+            (_, Some("MediaFiles"), "MediaFile") => match attr("type") {
+                Some(mime) if mime.trim().to_ascii_lowercase().starts_with("audio/") => {
+                    audio = true
+                }
+                _ => video = true,
+            },
+            _ => {}
+        }
+
+        if !empty {
+            path.push(name);
+        }
     }
 
-    if s.starts_with("<?xml") {
-        if let Some(end) = s.find("?>") {
-            s = s[end + 2..].trim_start();
+    match (root, audio && !video) {
+        (false, _) => None,
+        (true, true) => Some(AdFormat::Audio),
+        (true, false) => Some(AdFormat::Video),
+    }
+}
+
+/// Fallback of [`vast_format`] for markup which is not well formed, e.g.
+/// when truncated or wrapped in CDATA, scanning the raw tags instead
+fn scan_vast_format(adm: &str) -> AdFormat {
+    if find_ignore_case(adm, r#"adtype="audio""#, false).is_some() {
+        return AdFormat::Audio;
+    }
+
+    let mut audio = false;
+    let mut video = false;
+    let mut rest = adm;
+    while let Some(start) = rest.find("<MediaFile") {
+        rest = &rest[start + "<MediaFile".len()..];
+        // skip the MediaFiles container
+        if !rest.starts_with(|c: char| c.is_ascii_whitespace()) {
+            continue;
+        }
+
+        let tag = &rest[..rest.find('>').unwrap_or(rest.len())];
+        match attr_value(tag, "type") {
+            Some(mime) if mime.trim().to_ascii_lowercase().starts_with("audio/") => audio = true,
+            _ => video = true,
         }
     }
 
-    if let Some(rest) = s.strip_prefix('<') {
-        let tag_end = rest
-            .find(|c: char| c.is_ascii_whitespace() || c == '>' || c == '/')
-            .unwrap_or(rest.len());
-        let tag = &rest[..tag_end];
-        return tag.eq_ignore_ascii_case("vast");
+    if audio && !video {
+        AdFormat::Audio
+    } else {
+        AdFormat::Video
+    }
+}
+
+/// Value of an attribute within the inside of a start tag
+fn attr_value<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let mut offset = 0;
+    while let Some(i) = tag[offset..].find(name) {
+        let start = offset + i;
+        offset = start + name.len();
+
+        let preceded_by_space = tag[..start].ends_with(|c: char| c.is_ascii_whitespace());
+        let value = tag[offset..].trim_start().strip_prefix('=');
+        if let (true, Some(value)) = (preceded_by_space, value) {
+            let value = value.trim_start();
+            let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+            let value = &value[1..];
+            return value.find(quote).map(|end| &value[..end]);
+        }
     }
 
-    false
+    None
 }
 
+/// Classifies markup by its first element, or as native when it is a JSON
+/// object mentioning `native`. Cheap enough for every bid, see
+/// [`super::sniff::sniff_adm`] for a thorough classification with
+/// confidence and signals.
 pub fn classify_adm(adm: &AdmOneof) -> Option<AdFormat> {
     match adm {
         AdmOneof::Adm(s) => {
@@ -76,8 +201,10 @@ pub fn classify_adm(adm: &AdmOneof) -> Option<AdFormat> {
             }
 
             if is_vast(trim_adm) {
-                Some(AdFormat::Video)
-            } else if trim_adm.starts_with("{") && trim_adm.contains("native") {
+                Some(vast_format(trim_adm))
+            } else if is_daast(trim_adm) {
+                Some(AdFormat::Audio)
+            } else if trim_adm.starts_with('{') && trim_adm.contains("native") {
                 Some(AdFormat::Native)
             } else {
                 Some(AdFormat::Banner)
@@ -105,14 +232,327 @@ where
     Ok(())
 }
 
-/// Detect the type of ['AdFormat'] the bid markup is
+/// Click macros which expand to a click-through prefix, with the
+/// unescaped landing page URL written directly after them
+pub const CLICK_MACROS: &[&str] = &["%%CLICK_URL_UNESC%%", "${CLICK_URL}"];
+
+/// Tracking to inject into HTML banner markup
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Builder)]
+pub struct BannerTrackers {
+    /// Image pixel fired when the ad renders
+    #[builder(default)]
+    pub impression: Option<String>,
+
+    /// JavaScript tracker loaded when the ad renders
+    #[builder(default)]
+    pub impression_js: Option<String>,
+
+    /// Click redirect endpoint. When present, anchor `href`s and click macros
+    /// are routed through it with the landing page passed as [`Self::click_param`]
+    #[builder(default)]
+    pub click_redirect: Option<DataUrl>,
+
+    /// Query parameter carrying the landing page on the click redirect
+    #[builder(default = r#""url".to_string()"#)]
+    pub click_param: String,
+
+    /// Emit the click redirect as https
+    #[builder(default = "true")]
+    pub secure: bool,
+}
+
+/// Injects tracking into HTML banner markup.
+///
+/// Impression pixels and JS trackers are placed before the closing `</body>`
+/// tag when present, otherwise appended to the markup.
+///
+/// When a click redirect is configured, known [`CLICK_MACROS`] followed by a
+/// literal `http(s)` landing page are chained through it, and plain `http(s)`
+/// anchor `href`s are rewritten to it, in both cases with the landing page
+/// url-encoded. Macros without a literal landing page are left alone, since
+/// whatever is appended to them at serve time could not be encoded. MRAID
+/// ads handle clicks via `mraid.open()` and are never rewritten, nor are
+/// anchors in script-driven ads since their markup may be generated or read
+/// back by the script.
+///
+/// # Errors
+/// Returns an error if a tracker URL is invalid, e.g. a JS tracker holding a
+/// quote or angle bracket, or if the click redirect cannot be built.
+///
+/// # Example
+/// ```
+/// use rtb::openrtb::utils::adm::{BannerTrackersBuilder, inject_banner_trackers};
+///
+/// let trackers = BannerTrackersBuilder::default()
+///     .impression(Some("https://t.example.com/imp".to_string()))
+///     .build()
+///     .unwrap();
+///
+/// let adm = inject_banner_trackers("<html><body>ad</body></html>", &trackers).unwrap();
+/// assert!(adm.ends_with(r#"alt="" /></body></html>"#));
+/// ```
+pub fn inject_banner_trackers(adm: &str, trackers: &BannerTrackers) -> Result<String, Error> {
+    let mut out = match &trackers.click_redirect {
+        Some(redirect) if !is_mraid(adm) => wrap_clicks(adm, redirect, trackers)?,
+        _ => adm.to_string(),
+    };
+
+    let mut tags = String::new();
+    if let Some(url) = trackers.impression.as_deref() {
+        tags.push_str(&html_pixel(url)?);
+    }
+    if let Some(url) = trackers.impression_js.as_deref() {
+        tags.push_str(&html_script(url)?);
+    }
+
+    if !tags.is_empty() {
+        match find_ignore_case(&out, "</body>", true) {
+            Some(i) => out.insert_str(i, &tags),
+            None => out.push_str(&tags),
+        }
+    }
+
+    Ok(out)
+}
+
+/// Applies [`inject_banner_trackers`] to the textual adm of a bid
+pub fn inject_banner_bid_trackers(bid: &mut Bid, trackers: &BannerTrackers) -> Result<(), Error> {
+    let new_adm = inject_banner_trackers(get_adm(bid)?, trackers)?;
+    bid.adm_oneof = Some(AdmOneof::Adm(new_adm));
+
+    Ok(())
+}
+
+pub(crate) fn is_mraid(adm: &str) -> bool {
+    find_ignore_case(adm, "mraid.", false).is_some()
+}
+
+fn is_script_driven(adm: &str) -> bool {
+    find_ignore_case(adm, "<script", false).is_some()
+}
+
+fn wrap_clicks(adm: &str, redirect: &DataUrl, trackers: &BannerTrackers) -> Result<String, Error> {
+    let redirect_url = |target: &str| -> Result<String, Error> {
+        let mut url = redirect.clone_unfinalized();
+        url.add_string(&trackers.click_param, target)?;
+        url.finalize();
+        url.url(trackers.secure)
+    };
+
+    let mut out = adm.to_string();
+    for mac in CLICK_MACROS {
+        out = chain_click_macro(&out, mac, &redirect_url)?;
+    }
+
+    if is_script_driven(&out) {
+        return Ok(out);
+    }
+
+    rewrite_anchor_hrefs(&out, |href| {
+        let target = href.replace("&amp;", "&");
+        let lower = target.to_ascii_lowercase();
+        if !(lower.starts_with("http://") || lower.starts_with("https://")) {
+            return Ok(None);
+        }
+
+        Ok(Some(redirect_url(&target)?.replace('&', "&amp;")))
+    })
+}
+
+/// Routes the landing page written directly after each `mac` through the
+/// redirect, keeping the macro in front of it
+fn chain_click_macro<F>(html: &str, mac: &str, redirect_url: F) -> Result<String, Error>
+where
+    F: Fn(&str) -> Result<String, Error>,
+{
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while let Some(i) = rest.find(mac) {
+        let after = i + mac.len();
+        out.push_str(&rest[..after]);
+        rest = &rest[after..];
+
+        let lower = rest.get(..8).unwrap_or(rest).to_ascii_lowercase();
+        if !(lower.starts_with("http://") || lower.starts_with("https://")) {
+            continue;
+        }
+
+        let end = rest
+            .find(|c: char| matches!(c, '"' | '\'' | '<' | '>' | '`') || c.is_whitespace())
+            .unwrap_or(rest.len());
+        let landing = &rest[..end];
+
+        // keep entity-encoded ampersands entity-encoded
+        let url = redirect_url(&landing.replace("&amp;", "&"))?;
+        if landing.contains("&amp;") {
+            out.push_str(&url.replace('&', "&amp;"));
+        } else {
+            out.push_str(&url);
+        }
+        rest = &rest[end..];
+    }
+
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// Calls `f` with the `href` value of every `<a>` tag, replacing the value if
+/// `f` returns a new one. Macro-driven hrefs are left alone.
+fn rewrite_anchor_hrefs<F>(html: &str, mut f: F) -> Result<String, Error>
+where
+    F: FnMut(&str) -> Result<Option<String>, Error>,
+{
+    let lower = html.to_ascii_lowercase();
+    let mut out = String::with_capacity(html.len());
+    let mut pos = 0;
+
+    while let Some(rel) = lower[pos..].find("<a") {
+        let tag_start = pos + rel;
+        let after = tag_start + 2;
+
+        if !lower[after..].starts_with(|c: char| c.is_ascii_whitespace()) {
+            out.push_str(&html[pos..after]);
+            pos = after;
+            continue;
+        }
+
+        let tag_end = lower[after..].find('>').map_or(html.len(), |i| after + i);
+        out.push_str(&html[pos..after]);
+        pos = after;
+
+        if let Some((start, end)) = href_value_range(&lower[after..tag_end]) {
+            let (start, end) = (after + start, after + end);
+            let value = &html[start..end];
+
+            let replacement = if CLICK_MACROS.iter().any(|m| value.contains(m)) {
+                None
+            } else {
+                f(value)?
+            };
+
+            if let Some(new_value) = replacement {
+                out.push_str(&html[pos..start]);
+                out.push_str(&new_value);
+                pos = end;
+            }
+        }
+
+        out.push_str(&html[pos..tag_end]);
+        pos = tag_end;
+    }
+
+    out.push_str(&html[pos..]);
+    Ok(out)
+}
+
+/// Byte range of the `href` attribute value within the lowercased inside of a tag
+fn href_value_range(tag: &str) -> Option<(usize, usize)> {
+    let bytes = tag.as_bytes();
+    let mut search = 0;
+
+    while let Some(rel) = tag[search..].find("href") {
+        let name_start = search + rel;
+        search = name_start + 4;
+
+        // must be a whole attribute name
+        if name_start > 0 && !bytes[name_start - 1].is_ascii_whitespace() {
+            continue;
+        }
+
+        let mut i = search;
+        while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        if i >= bytes.len() || bytes[i] != b'=' {
+            continue;
+        }
+        i += 1;
+        while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        if i >= bytes.len() {
+            return None;
+        }
+
+        return match bytes[i] {
+            quote @ (b'"' | b'\'') => {
+                let start = i + 1;
+                let end = tag[start..]
+                    .find(quote as char)
+                    .map_or(tag.len(), |e| start + e);
+                Some((start, end))
+            }
+            _ => {
+                let end = tag[i..]
+                    .find(|c: char| c.is_ascii_whitespace())
+                    .map_or(tag.len(), |e| i + e);
+                Some((i, end))
+            }
+        };
+    }
+
+    None
+}
+
+fn find_ignore_case(haystack: &str, needle: &str, last: bool) -> Option<usize> {
+    let haystack = haystack.to_ascii_lowercase();
+    if last {
+        haystack.rfind(needle)
+    } else {
+        haystack.find(needle)
+    }
+}
+
+/// Detect the type of ['AdFormat'] the bid markup is, honouring `bid.mtype`
+/// when set. See [`sniff_bid`] for the confidence and signals behind it.
 pub fn detect_ad_format(bid: &'_ Bid) -> Option<AdFormat> {
-    let adm = match &bid.adm_oneof {
-        Some(adm) => adm,
-        None => return None,
+    sniff_bid(bid).map(|sniff| sniff.format)
+}
+
+/// Like [`detect_ad_format`], but uses the bid request to settle what the
+/// markup alone cannot.
+///
+/// `bid.mtype` is trusted when set. Otherwise VAST markup answering an
+/// impression which only offers `audio` (or only `video`) takes that format,
+/// since audio VAST is often served with media files a sniffer cannot tell
+/// apart. Bids without markup, e.g. those relying on `nurl`, take the format
+/// of their impression when it offers a single one.
+pub fn detect_ad_format_for(bid: &Bid, request: &BidRequest) -> Option<AdFormat> {
+    if let Some(format) = mtype_format(bid) {
+        return Some(format);
+    }
+
+    let markup = detect_ad_format(bid);
+    let Some(imp) = request.imp.iter().find(|imp| imp.id == bid.impid) else {
+        return markup;
     };
 
-    classify_adm(adm)
+    let offered = [
+        (imp.banner.is_some(), AdFormat::Banner),
+        (imp.video.is_some(), AdFormat::Video),
+        (imp.audio.is_some(), AdFormat::Audio),
+        (imp.native.is_some(), AdFormat::Native),
+    ];
+    let only_offered = |format: AdFormat| {
+        offered
+            .iter()
+            .all(|(present, f)| *present == (*f == format))
+    };
+
+    match markup {
+        Some(AdFormat::Video | AdFormat::Audio) if only_offered(AdFormat::Audio) => {
+            Some(AdFormat::Audio)
+        }
+        Some(AdFormat::Video | AdFormat::Audio) if only_offered(AdFormat::Video) => {
+            Some(AdFormat::Video)
+        }
+        Some(format) => Some(format),
+        None => offered
+            .iter()
+            .map(|(_, format)| *format)
+            .find(|format| only_offered(*format)),
+    }
 }
 
 #[cfg(test)]
@@ -120,6 +560,7 @@ mod tests {
     use super::*;
     use crate::NativeResponse;
     use crate::native_response::Link;
+    use crate::openrtb::spec::creative_markup_types;
 
     #[test]
     fn test_detect_banner_html() {
@@ -305,6 +746,128 @@ mod tests {
         assert!(format.is_none());
     }
 
+    fn click_trackers() -> BannerTrackers {
+        BannerTrackersBuilder::default()
+            .click_redirect(Some(DataUrl::new("t.example.com", "click").unwrap()))
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_banner_trackers_before_body() {
+        let trackers = BannerTrackersBuilder::default()
+            .impression(Some("https://t.example.com/imp".to_string()))
+            .impression_js(Some("https://t.example.com/imp.js".to_string()))
+            .build()
+            .unwrap();
+
+        let adm =
+            inject_banner_trackers("<html><BODY><div>ad</div></BODY></html>", &trackers).unwrap();
+
+        let body_end = adm.find("</BODY>").unwrap();
+        assert!(adm.find(r#"src="https://t.example.com/imp""#).unwrap() < body_end);
+        assert!(
+            adm[..body_end].ends_with(r#"<script src="https://t.example.com/imp.js"></script>"#)
+        );
+    }
+
+    #[test]
+    fn test_banner_trackers_appended_without_body() {
+        let trackers = BannerTrackersBuilder::default()
+            .impression(Some("https://t.example.com/imp".to_string()))
+            .build()
+            .unwrap();
+
+        let adm = inject_banner_trackers("<div>ad</div>", &trackers).unwrap();
+        assert!(adm.starts_with("<div>ad</div><img"));
+    }
+
+    #[test]
+    fn test_banner_invalid_tracker_fails() {
+        let trackers = BannerTrackersBuilder::default()
+            .impression_js(Some("javascript:void(0)".to_string()))
+            .build()
+            .unwrap();
+
+        assert!(inject_banner_trackers("<div>ad</div>", &trackers).is_err());
+
+        let trackers = BannerTrackersBuilder::default()
+            .impression_js(Some(r#"https://t.example.com/x.js"><b>"#.to_string()))
+            .build()
+            .unwrap();
+        assert!(inject_banner_trackers("<div>ad</div>", &trackers).is_err());
+    }
+
+    #[test]
+    fn test_banner_click_href_wrapping() {
+        let adm = r##"<a class="x" href="https://landing.com/?a=1&amp;b=2"><img src="b.jpg"></a><a href="#top">top</a>"##;
+        let out = inject_banner_trackers(adm, &click_trackers()).unwrap();
+
+        assert!(out.contains(
+            r#"href="https://t.example.com/click?url=https%3A%2F%2Flanding.com%2F%3Fa%3D1%26b%3D2""#
+        ));
+        assert!(out.contains(r##"<a href="#top">"##));
+        assert!(out.contains(r#"<img src="b.jpg">"#));
+    }
+
+    #[test]
+    fn test_banner_click_macro_chaining() {
+        let adm = r#"<a href="%%CLICK_URL_UNESC%%https://landing.com">go</a>"#;
+        let out = inject_banner_trackers(adm, &click_trackers()).unwrap();
+
+        assert_eq!(
+            out,
+            r#"<a href="%%CLICK_URL_UNESC%%https://t.example.com/click?url=https%3A%2F%2Flanding.com">go</a>"#
+        );
+
+        let out = inject_banner_trackers("${CLICK_URL}", &click_trackers()).unwrap();
+        assert_eq!(out, "${CLICK_URL}");
+    }
+
+    #[test]
+    fn test_banner_click_macro_encodes_landing_query() {
+        let adm = r#"<a href="${CLICK_URL}https://landing.com/?a=1&b=2#x">go</a>"#;
+        let out = inject_banner_trackers(adm, &click_trackers()).unwrap();
+
+        assert_eq!(
+            out,
+            r#"<a href="${CLICK_URL}https://t.example.com/click?url=https%3A%2F%2Flanding.com%2F%3Fa%3D1%26b%3D2%23x">go</a>"#
+        );
+    }
+
+    #[test]
+    fn test_banner_mraid_and_script_ads_intact() {
+        let mraid = r#"<script src="mraid.js"></script><a href="https://landing.com">go</a>"#;
+        assert_eq!(
+            inject_banner_trackers(mraid, &click_trackers()).unwrap(),
+            mraid
+        );
+
+        let scripted = r#"<script>document.write('<a href="https://landing.com">go</a>')</script>"#;
+        assert_eq!(
+            inject_banner_trackers(scripted, &click_trackers()).unwrap(),
+            scripted
+        );
+    }
+
+    #[test]
+    fn test_banner_bid_trackers() {
+        let mut bid = Bid {
+            id: "banner-bid".to_string(),
+            impid: "imp-1".to_string(),
+            adm_oneof: Some(AdmOneof::Adm("<div>ad</div>".to_string())),
+            ..Default::default()
+        };
+
+        let trackers = BannerTrackersBuilder::default()
+            .impression(Some("https://t.example.com/imp".to_string()))
+            .build()
+            .unwrap();
+
+        inject_banner_bid_trackers(&mut bid, &trackers).unwrap();
+        assert!(get_adm(&bid).unwrap().contains("https://t.example.com/imp"));
+    }
+
     #[test]
     fn test_bom_handling() {
         let html_with_bom = "\u{feff}<div>Banner</div>";
@@ -320,4 +883,147 @@ mod tests {
         let format = detect_ad_format(&bid);
         assert!(matches!(format, Some(AdFormat::Banner)));
     }
+
+    #[test]
+    fn test_classify_adm() {
+        let classify = |adm: &str| classify_adm(&AdmOneof::Adm(adm.to_string()));
+
+        assert_eq!(classify("  \n"), None);
+        assert_eq!(classify("<div>ad</div>"), Some(AdFormat::Banner));
+        assert_eq!(
+            classify(r#"<!-- ad --><VAST version="4.0"><Ad/></VAST>"#),
+            Some(AdFormat::Video)
+        );
+        assert_eq!(
+            classify(r#"<VAST version="4.1"><Ad adType="audio"><Wrapper/></Ad></VAST>"#),
+            Some(AdFormat::Audio)
+        );
+        assert_eq!(
+            classify(r#"<?xml version="1.0"?><DAAST version="1.0"><Ad/></DAAST>"#),
+            Some(AdFormat::Audio)
+        );
+        assert_eq!(
+            classify(r#"{"native":{"ver":"1.2"}}"#),
+            Some(AdFormat::Native)
+        );
+        assert_eq!(
+            classify_adm(&AdmOneof::AdmNative(Default::default())),
+            Some(AdFormat::Native)
+        );
+    }
+
+    fn vast_bid(media_type: &str) -> Bid {
+        let vast_xml = format!(
+            r#"<VAST version="4.0"><Ad><InLine><Creatives><Creative><Linear>
+  <MediaFiles>
+    <MediaFile delivery="progressive" type="{media_type}"><![CDATA[https://cdn.example.com/ad]]></MediaFile>
+  </MediaFiles>
+</Linear></Creative></Creatives></InLine></Ad></VAST>"#
+        );
+
+        Bid {
+            id: "1".to_string(),
+            impid: "imp-1".to_string(),
+            price: 1.0,
+            adm_oneof: Some(AdmOneof::Adm(vast_xml)),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_detect_audio_from_media_files() {
+        assert_eq!(
+            detect_ad_format(&vast_bid("audio/mpeg")),
+            Some(AdFormat::Audio)
+        );
+        assert_eq!(
+            detect_ad_format(&vast_bid("video/mp4")),
+            Some(AdFormat::Video)
+        );
+        assert_eq!(
+            detect_ad_format(&vast_bid("application/x-mpegURL")),
+            Some(AdFormat::Video)
+        );
+    }
+
+    #[test]
+    fn test_vast_format_ignores_markup_outside_the_model() {
+        let audio = r#"<VAST version="4.0"><Ad><InLine>
+  <Creatives><Creative><Linear><MediaFiles>
+    <MediaFile type="audio/mpeg"><![CDATA[https://cdn.example.com/ad.mp3]]></MediaFile>
+  </MediaFiles></Linear></Creative></Creatives>
+  <Extensions><Extension><MediaFile type="video/mp4">https://cdn.example.com/x</MediaFile></Extension></Extensions>
+</InLine></Ad></VAST>"#;
+        assert_eq!(vast_format(audio), AdFormat::Audio);
+
+        let video = r#"<VAST version="4.1"><!-- adType="audio" --><Ad><InLine>
+  <Creatives><Creative><Linear><MediaFiles>
+    <MediaFile type="video/mp4"><![CDATA[https://cdn.example.com/ad.mp4]]></MediaFile>
+  </MediaFiles></Linear></Creative></Creatives>
+</InLine></Ad></VAST>"#;
+        assert_eq!(vast_format(video), AdFormat::Video);
+
+        // not well formed, so classified by scanning
+        let broken = r#"<![CDATA[<VAST version="4.0"><Ad><InLine><MediaFile type="audio/mpeg">"#;
+        assert_eq!(vast_format(broken), AdFormat::Audio);
+    }
+
+    #[test]
+    fn test_detect_audio_from_ad_type_and_daast() {
+        let bid = Bid {
+            adm_oneof: Some(AdmOneof::Adm(
+                r#"<VAST version="4.1"><Ad adType="audio"><Wrapper/></Ad></VAST>"#.to_string(),
+            )),
+            ..Default::default()
+        };
+        assert_eq!(detect_ad_format(&bid), Some(AdFormat::Audio));
+
+        let bid = Bid {
+            adm_oneof: Some(AdmOneof::Adm(
+                r#"<?xml version="1.0"?><DAAST version="1.0"><Ad/></DAAST>"#.to_string(),
+            )),
+            ..Default::default()
+        };
+        assert_eq!(detect_ad_format(&bid), Some(AdFormat::Audio));
+    }
+
+    #[test]
+    fn test_detect_ad_format_for_request() {
+        let request: BidRequest = serde_json::from_str(
+            r#"{"id":"1","imp":[
+                {"id":"imp-1","audio":{"mimes":["audio/mp4"]}},
+                {"id":"imp-2","video":{"mimes":["video/mp4"]},"audio":{"mimes":["audio/mp4"]}},
+                {"id":"imp-3","banner":{"w":300,"h":250}}
+            ]}"#,
+        )
+        .unwrap();
+
+        // audio only imp, markup which looks like video
+        let bid = vast_bid("application/x-mpegURL");
+        assert_eq!(detect_ad_format_for(&bid, &request), Some(AdFormat::Audio));
+
+        // both offered, markup decides
+        let mut bid = vast_bid("video/mp4");
+        bid.impid = "imp-2".to_string();
+        assert_eq!(detect_ad_format_for(&bid, &request), Some(AdFormat::Video));
+
+        // mtype wins
+        bid.mtype = creative_markup_types::AUDIO as i32;
+        assert_eq!(detect_ad_format_for(&bid, &request), Some(AdFormat::Audio));
+
+        // no markup, single format imp
+        let bid = Bid {
+            impid: "imp-3".to_string(),
+            nurl: "https://bidder.example.com/win".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(detect_ad_format_for(&bid, &request), Some(AdFormat::Banner));
+
+        // no markup, several formats offered
+        let bid = Bid {
+            impid: "imp-2".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(detect_ad_format_for(&bid, &request), None);
+    }
 }
@@ -0,0 +1,353 @@
+//! Decoding of the consent strings carried in `user.consent` and
+//! `regs.us_privacy`.
+//!
+//! [`TcfConsent`] reads the core segment of an IAB TCF v2 string, which is
+//! enough to answer purpose and vendor consent questions. Publisher
+//! restrictions and the optional segments after the first `.` are skipped.
+//! [`UsPrivacy`] reads the four character CCPA string.
+
+use anyhow::{Result, anyhow, bail};
+use serde::{Deserialize, Serialize};
+
+/// Core segment of a TCF v2 consent string
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TcfConsent {
+    pub version: u8,
+    /// Epoch millis, stored with decisecond precision
+    pub created_ms: u64,
+    /// Epoch millis, stored with decisecond precision
+    pub last_updated_ms: u64,
+    pub cmp_id: u16,
+    pub cmp_version: u16,
+    pub consent_screen: u8,
+    /// Two letter ISO 639-1 code, e.g. `EN`
+    pub consent_language: String,
+    pub vendor_list_version: u16,
+    pub policy_version: u8,
+    pub is_service_specific: bool,
+    pub use_non_standard_texts: bool,
+    /// Special features opted in to, numbered from 1
+    pub special_feature_opt_ins: Vec<u8>,
+    /// Purposes consented to, numbered from 1
+    pub purpose_consents: Vec<u8>,
+    /// Purposes whose legitimate interest was disclosed, numbered from 1
+    pub purpose_legitimate_interests: Vec<u8>,
+    pub purpose_one_treatment: bool,
+    /// Two letter ISO 3166-1 code of the publisher
+    pub publisher_cc: String,
+    /// Vendors consented to, ascending
+    pub vendor_consents: Vec<u16>,
+    /// Vendors whose legitimate interest was disclosed, ascending
+    pub vendor_legitimate_interests: Vec<u16>,
+}
+
+impl TcfConsent {
+    /// Decodes the core segment of `consent`, the part before the first `.`
+    ///
+    /// # Example
+    /// ```
+    /// use rtb::openrtb::utils::consent::TcfConsent;
+    ///
+    /// let consent = TcfConsent::parse("CPXxRfAPXxRfAAfKABENB-CgAAAAAAAAAAYgAAAAAAAA").unwrap();
+    /// assert_eq!(consent.cmp_id, 31);
+    /// assert_eq!(consent.consent_language, "EN");
+    /// ```
+    pub fn parse(consent: &str) -> Result<Self> {
+        let core = consent.split('.').next().unwrap_or_default();
+        let bytes = decode_base64url(core.trim_end_matches('='))?;
+        let mut bits = BitReader::new(&bytes);
+
+        let version = bits.read(6)? as u8;
+        if version != 2 {
+            bail!("unsupported TCF version {version}");
+        }
+
+        Ok(Self {
+            version,
+            created_ms: bits.read(36)? * 100,
+            last_updated_ms: bits.read(36)? * 100,
+            cmp_id: bits.read(12)? as u16,
+            cmp_version: bits.read(12)? as u16,
+            consent_screen: bits.read(6)? as u8,
+            consent_language: bits.read_letters()?,
+            vendor_list_version: bits.read(12)? as u16,
+            policy_version: bits.read(6)? as u8,
+            is_service_specific: bits.read_bool()?,
+            use_non_standard_texts: bits.read_bool()?,
+            special_feature_opt_ins: bits.read_flags(12)?,
+            purpose_consents: bits.read_flags(24)?,
+            purpose_legitimate_interests: bits.read_flags(24)?,
+            purpose_one_treatment: bits.read_bool()?,
+            publisher_cc: bits.read_letters()?,
+            vendor_consents: bits.read_vendors()?,
+            vendor_legitimate_interests: bits.read_vendors()?,
+        })
+    }
+
+    pub fn has_purpose_consent(&self, purpose: u8) -> bool {
+        self.purpose_consents.contains(&purpose)
+    }
+
+    pub fn has_vendor_consent(&self, vendor: u16) -> bool {
+        self.vendor_consents.binary_search(&vendor).is_ok()
+    }
+}
+
+/// Decodes unpadded base64url, the bits of a trailing partial byte are
+/// dropped
+fn decode_base64url(text: &str) -> Result<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(text.len() * 3 / 4);
+    let (mut acc, mut bits) = (0u32, 0);
+
+    for c in text.bytes() {
+        let sextet = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'-' => 62,
+            b'_' => 63,
+            _ => bail!("consent string is not base64url: {:?}", c as char),
+        };
+        acc = (acc << 6) | u32::from(sextet);
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((acc >> bits) as u8);
+            acc &= (1 << bits) - 1;
+        }
+    }
+    Ok(bytes)
+}
+
+/// Reads big endian bit fields
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, pos: 0 }
+    }
+
+    fn read(&mut self, bits: usize) -> Result<u64> {
+        if self.pos + bits > self.bytes.len() * 8 {
+            bail!("consent string truncated at bit {}", self.pos);
+        }
+
+        let mut value = 0;
+        for _ in 0..bits {
+            let bit = (self.bytes[self.pos / 8] >> (7 - self.pos % 8)) & 1;
+            value = (value << 1) | u64::from(bit);
+            self.pos += 1;
+        }
+        Ok(value)
+    }
+
+    fn read_bool(&mut self) -> Result<bool> {
+        Ok(self.read(1)? == 1)
+    }
+
+    /// Two letters of six bits each, `A` being 0
+    fn read_letters(&mut self) -> Result<String> {
+        (0..2)
+            .map(|_| {
+                let letter = self.read(6)? as u8;
+                if letter > 25 {
+                    bail!("invalid letter {letter} in consent string");
+                }
+                Ok(char::from(b'A' + letter))
+            })
+            .collect()
+    }
+
+    /// The numbers, from 1, of the set bits of a fixed size field
+    fn read_flags(&mut self, bits: usize) -> Result<Vec<u8>> {
+        let mut set = Vec::new();
+        for n in 1..=bits {
+            if self.read_bool()? {
+                set.push(n as u8);
+            }
+        }
+        Ok(set)
+    }
+
+    /// A vendor section, either a bit field or a list of ranges. Ranges are
+    /// collected in a bitset, as 4095 of them may each span every vendor.
+    fn read_vendors(&mut self) -> Result<Vec<u16>> {
+        let max_vendor_id = self.read(16)? as u16;
+        let mut vendors = Vec::new();
+
+        if !self.read_bool()? {
+            for vendor in 1..=max_vendor_id {
+                if self.read_bool()? {
+                    vendors.push(vendor);
+                }
+            }
+            return Ok(vendors);
+        }
+
+        let mut bitset = vec![0u64; usize::from(max_vendor_id) / 64 + 1];
+        for _ in 0..self.read(12)? {
+            let is_range = self.read_bool()?;
+            let start = self.read(16)? as u16;
+            let end = if is_range {
+                self.read(16)? as u16
+            } else {
+                start
+            };
+            if start == 0 || end < start || end > max_vendor_id {
+                bail!("invalid vendor range {start}-{end}");
+            }
+            set_bits(&mut bitset, usize::from(start), usize::from(end));
+        }
+
+        vendors.extend((1..=max_vendor_id).filter(|&vendor| {
+            let vendor = usize::from(vendor);
+            bitset[vendor / 64] & (1 << (vendor % 64)) != 0
+        }));
+        Ok(vendors)
+    }
+}
+
+/// Sets the bits `start..=end`, a word at a time
+fn set_bits(bitset: &mut [u64], start: usize, end: usize) {
+    let (first, last) = (start / 64, end / 64);
+    for (bits, word) in bitset[first..=last].iter_mut().zip(first..) {
+        let low = if word == first { start % 64 } else { 0 };
+        let high = if word == last { end % 64 } else { 63 };
+        *bits |= (u64::MAX << low) & (u64::MAX >> (63 - high));
+    }
+}
+
+/// A US Privacy (CCPA) string such as `1YNN`. Flags are `None` when the
+/// string has `-`, i.e. the signal does not apply.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct UsPrivacy {
+    pub version: u8,
+    /// The user was given notice and the opportunity to opt out
+    pub notice: Option<bool>,
+    pub opt_out: Option<bool>,
+    /// The publisher is a signatory of the LSPA
+    pub lspa: Option<bool>,
+}
+
+impl UsPrivacy {
+    pub fn parse(value: &str) -> Result<Self> {
+        let bytes = value.as_bytes();
+        if bytes.len() != 4 || bytes[0] != b'1' {
+            bail!("invalid US privacy string {value:?}");
+        }
+
+        let flag = |b: u8| match b.to_ascii_uppercase() {
+            b'Y' => Ok(Some(true)),
+            b'N' => Ok(Some(false)),
+            b'-' => Ok(None),
+            _ => Err(anyhow!("invalid US privacy string {value:?}")),
+        };
+
+        Ok(Self {
+            version: 1,
+            notice: flag(bytes[1])?,
+            opt_out: flag(bytes[2])?,
+            lspa: flag(bytes[3])?,
+        })
+    }
+
+    pub fn is_opted_out(&self) -> bool {
+        self.opt_out == Some(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_tcf() {
+        let consent = TcfConsent::parse("CPXxRfAPXxRfAAfKABENB-CgAAAAAAAAAAYgAAAAAAAA").unwrap();
+        assert_eq!(consent.version, 2);
+        assert_eq!(consent.created_ms, 1_650_492_000_000);
+        assert_eq!(consent.cmp_id, 31);
+        assert_eq!(consent.cmp_version, 640);
+        assert_eq!(consent.consent_screen, 1);
+        assert_eq!(consent.vendor_list_version, 126);
+        assert_eq!(consent.policy_version, 2);
+        assert!(consent.is_service_specific);
+        assert_eq!(consent.publisher_cc, "DE");
+        assert!(consent.purpose_consents.is_empty());
+        assert!(consent.vendor_consents.is_empty());
+    }
+
+    #[test]
+    fn test_parse_tcf_vendor_encodings() {
+        // bit field consents, range encoded legitimate interests
+        let consent = TcfConsent::parse(
+            "CPXxRfAPXxT7QAKADCFRCWEIAOJAAEIAAAAAAGEQQLzgBQK8Ar4BeYAA.YAAAAAAAAAAA",
+        )
+        .unwrap();
+        assert_eq!(consent.last_updated_ms, 1_650_493_000_000);
+        assert_eq!(consent.consent_language, "FR");
+        assert_eq!(consent.special_feature_opt_ins, [1]);
+        assert_eq!(consent.purpose_consents, [1, 2, 3, 7, 10]);
+        assert_eq!(consent.purpose_legitimate_interests, [2, 7]);
+        assert_eq!(consent.vendor_consents, [2, 6, 12]);
+        assert_eq!(consent.vendor_legitimate_interests, [700, 701, 702, 755]);
+        assert!(consent.has_purpose_consent(7));
+        assert!(!consent.has_purpose_consent(4));
+        assert!(consent.has_vendor_consent(6));
+        assert!(!consent.has_vendor_consent(7));
+    }
+
+    #[test]
+    fn test_read_vendors_overlapping_ranges() {
+        // max vendor id, range encoding, 4095 ranges of every vendor
+        let mut fields = vec![(65_535, 16), (1, 1), (4095, 12)];
+        for _ in 0..4095 {
+            fields.extend([(1, 1), (1, 16), (65_535, 16)]);
+        }
+        // then 3 ranges over a word boundary and a single vendor
+        fields.extend([(200, 16), (1, 1), (3, 12), (1, 1), (62, 16), (66, 16)]);
+        fields.extend([(1, 1), (64, 16), (65, 16), (0, 1), (128, 16)]);
+
+        let mut bytes = vec![0u8; fields.iter().map(|(_, bits)| bits).sum::<usize>() / 8 + 1];
+        let mut pos = 0;
+        for (value, bits) in fields {
+            for i in (0..bits).rev() {
+                if value >> i & 1 == 1 {
+                    bytes[pos / 8] |= 0x80 >> (pos % 8);
+                }
+                pos += 1;
+            }
+        }
+
+        let mut bits = BitReader::new(&bytes);
+        let vendors = bits.read_vendors().unwrap();
+        assert_eq!(vendors.len(), 65_535);
+        assert_eq!(vendors.last(), Some(&65_535));
+        assert_eq!(bits.read_vendors().unwrap(), [62, 63, 64, 65, 66, 128]);
+    }
+
+    #[test]
+    fn test_parse_tcf_errors() {
+        assert!(TcfConsent::parse("not base64!").is_err());
+        // TCF v1
+        assert!(TcfConsent::parse("BOEFEAyOEFEAyAHABDENAI4AAAB9vABAASA").is_err());
+        assert!(TcfConsent::parse("CPXxRfAPXxRfAAfKABEN").is_err());
+    }
+
+    #[test]
+    fn test_parse_us_privacy() {
+        let privacy = UsPrivacy::parse("1YYN").unwrap();
+        assert_eq!(privacy.notice, Some(true));
+        assert!(privacy.is_opted_out());
+        assert_eq!(privacy.lspa, Some(false));
+
+        let privacy = UsPrivacy::parse("1---").unwrap();
+        assert_eq!(privacy.opt_out, None);
+        assert!(!privacy.is_opted_out());
+
+        assert!(UsPrivacy::parse("2YNN").is_err());
+        assert!(UsPrivacy::parse("1YX").is_err());
+    }
+}
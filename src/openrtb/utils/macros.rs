@@ -0,0 +1,262 @@
+//! Substitution of the OpenRTB auction macros in `nurl`, `burl`, `lurl` and
+//! markup, see [`crate::openrtb::spec::auction_macros`].
+
+#[cfg(feature = "crypto")]
+use super::price_crypto::PriceCrypter;
+use crate::bid_response::Bid;
+use crate::bid_response::bid::AdmOneof;
+use crate::openrtb::spec::auction_macros::*;
+use derive_builder::Builder;
+#[cfg(feature = "crypto")]
+use std::sync::Arc;
+use url::form_urlencoded;
+
+/// Values substituted for the auction macros, `None` leaves a macro as is
+#[derive(Debug, Clone, PartialEq, Default, Builder)]
+#[builder(default, setter(into, strip_option))]
+pub struct AuctionMacroValues {
+    pub auction_id: Option<String>,
+    pub bid_id: Option<String>,
+    pub imp_id: Option<String>,
+    pub seat_id: Option<String>,
+    pub ad_id: Option<String>,
+    /// Clearing price, encrypted when the macros have a price crypter
+    pub price: Option<f64>,
+    pub currency: Option<String>,
+    pub mbr: Option<f64>,
+    /// See [`crate::openrtb::spec::lossreason`]
+    pub loss: Option<u32>,
+    pub min_to_win: Option<f64>,
+    pub multiplier: Option<f64>,
+    /// Epoch millis of the impression
+    pub imp_ts: Option<u64>,
+}
+
+/// Expands auction macros. With the `crypto` feature, `${AUCTION_PRICE}`
+/// can be emitted encrypted so clearing prices never appear in clear text in
+/// URLs or logs.
+///
+/// # Example
+/// ```
+/// # #[cfg(feature = "crypto")] {
+/// use rtb::openrtb::utils::macros::{AuctionMacroValuesBuilder, AuctionMacros};
+/// use rtb::openrtb::utils::price_crypto::{AesGcmPriceCrypter, PriceCrypter};
+/// use std::sync::Arc;
+///
+/// let crypter = Arc::new(AesGcmPriceCrypter::new(&[7u8; 32]).unwrap());
+/// let values = AuctionMacroValuesBuilder::default()
+///     .auction_id("req-1")
+///     .price(1.25)
+///     .build()
+///     .unwrap();
+/// let macros = AuctionMacros::new(values).with_price_crypter(crypter.clone());
+///
+/// let url = macros.expand("https://t.example.com/win?id=${AUCTION_ID}&p=${AUCTION_PRICE}");
+/// let encrypted = url.split("&p=").nth(1).unwrap();
+/// assert!(url.starts_with("https://t.example.com/win?id=req-1&p="));
+/// assert_eq!(crypter.decrypt_price(encrypted).unwrap(), 1.25);
+/// # }
+/// ```
+#[derive(Clone, Default)]
+pub struct AuctionMacros {
+    values: AuctionMacroValues,
+    #[cfg(feature = "crypto")]
+    price_crypter: Option<Arc<dyn PriceCrypter>>,
+}
+
+impl AuctionMacros {
+    pub fn new(values: AuctionMacroValues) -> Self {
+        Self {
+            values,
+            #[cfg(feature = "crypto")]
+            price_crypter: None,
+        }
+    }
+
+    /// Encrypts the clearing price substituted for `${AUCTION_PRICE}`
+    #[cfg(feature = "crypto")]
+    pub fn with_price_crypter(mut self, crypter: Arc<dyn PriceCrypter>) -> Self {
+        self.price_crypter = Some(crypter);
+        self
+    }
+
+    pub fn values(&self) -> &AuctionMacroValues {
+        &self.values
+    }
+
+    /// Substitutes every macro with a value, in a single pass so a value
+    /// holding a macro is not expanded in turn. Values are percent-encoded,
+    /// as macros stand in URLs. Macros without a value and unknown macros
+    /// are left untouched.
+    pub fn expand(&self, template: &str) -> String {
+        if !template.contains("${") {
+            return template.to_string();
+        }
+
+        // only encrypt when needed, each encryption draws a random IV
+        let mut price = None;
+        let mut out = String::with_capacity(template.len());
+        let mut rest = template;
+
+        while let Some(start) = rest.find("${") {
+            out.push_str(&rest[..start]);
+            rest = &rest[start..];
+
+            let value = rest
+                .find('}')
+                .and_then(|end| Some((end, self.value(&rest[..=end], &mut price)?)));
+            match value {
+                Some((end, value)) => {
+                    out.extend(form_urlencoded::byte_serialize(value.as_bytes()));
+                    rest = &rest[end + 1..];
+                }
+                None => {
+                    out.push_str("${");
+                    rest = &rest[2..];
+                }
+            }
+        }
+
+        out.push_str(rest);
+        out
+    }
+
+    /// Value of the macro `name`, the price encrypted once per expansion
+    fn value(&self, name: &str, price: &mut Option<Option<String>>) -> Option<String> {
+        let values = &self.values;
+        match name {
+            AUCTION_ID => values.auction_id.clone(),
+            AUCTION_BID_ID => values.bid_id.clone(),
+            AUCTION_IMP_ID => values.imp_id.clone(),
+            AUCTION_SEAT_ID => values.seat_id.clone(),
+            AUCTION_AD_ID => values.ad_id.clone(),
+            AUCTION_PRICE => price
+                .get_or_insert_with(|| values.price.map(|price| self.price(price)))
+                .clone(),
+            AUCTION_CURRENCY => values.currency.clone(),
+            AUCTION_MBR => values.mbr.map(|v| v.to_string()),
+            AUCTION_LOSS => values.loss.map(|v| v.to_string()),
+            AUCTION_MIN_TO_WIN => values.min_to_win.map(|v| v.to_string()),
+            AUCTION_MULTIPLIER => values.multiplier.map(|v| v.to_string()),
+            AUCTION_IMP_TS => values.imp_ts.map(|v| v.to_string()),
+            _ => None,
+        }
+    }
+
+    /// The clearing price, encrypted if the macros have a price crypter
+    fn price(&self, price: f64) -> String {
+        #[cfg(feature = "crypto")]
+        if let Some(crypter) = &self.price_crypter {
+            return crypter.encrypt_price(price);
+        }
+
+        price.to_string()
+    }
+
+    /// Expands the macros of the bid's `nurl`, `burl`, `lurl` and textual
+    /// markup in place
+    pub fn expand_bid(&self, bid: &mut Bid) {
+        bid.nurl = self.expand(&bid.nurl);
+        bid.burl = self.expand(&bid.burl);
+        bid.lurl = self.expand(&bid.lurl);
+
+        if let Some(AdmOneof::Adm(adm)) = bid.adm_oneof.as_mut() {
+            *adm = self.expand(adm);
+        }
+    }
+}
+
+impl std::fmt::Debug for AuctionMacros {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut f = f.debug_struct("AuctionMacros");
+        f.field("values", &self.values);
+        #[cfg(feature = "crypto")]
+        f.field("encrypted_price", &self.price_crypter.is_some());
+        f.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "crypto")]
+    use crate::openrtb::utils::price_crypto::HmacPriceCrypter;
+
+    fn values() -> AuctionMacroValues {
+        AuctionMacroValuesBuilder::default()
+            .auction_id("req-1")
+            .imp_id("imp-1")
+            .price(1.5)
+            .currency("USD")
+            .loss(102u32)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_expand_clear() {
+        let macros = AuctionMacros::new(values());
+        assert_eq!(
+            macros.expand("id=${AUCTION_ID}&imp=${AUCTION_IMP_ID}&p=${AUCTION_PRICE}&cur=${AUCTION_CURRENCY}&l=${AUCTION_LOSS}"),
+            "id=req-1&imp=imp-1&p=1.5&cur=USD&l=102"
+        );
+
+        // missing values and unknown macros are left for someone else
+        assert_eq!(
+            macros.expand("s=${AUCTION_SEAT_ID}&x=${OTHER}"),
+            "s=${AUCTION_SEAT_ID}&x=${OTHER}"
+        );
+    }
+
+    #[test]
+    fn test_expand_encodes_values_once() {
+        let values = AuctionMacroValuesBuilder::default()
+            .auction_id("a&b=${AUCTION_PRICE}")
+            .price(1.5)
+            .build()
+            .unwrap();
+        let macros = AuctionMacros::new(values);
+
+        assert_eq!(
+            macros.expand("id=${AUCTION_ID}&p=${AUCTION_PRICE}"),
+            "id=a%26b%3D%24%7BAUCTION_PRICE%7D&p=1.5"
+        );
+        assert_eq!(macros.expand("${${AUCTION_PRICE}}${"), "${1.5}${");
+    }
+
+    #[cfg(feature = "crypto")]
+    #[test]
+    fn test_expand_encrypted_price() {
+        let crypter = Arc::new(HmacPriceCrypter::new(b"enc".to_vec(), b"int".to_vec()));
+        let macros = AuctionMacros::new(values()).with_price_crypter(crypter.clone());
+
+        let expanded = macros.expand("p=${AUCTION_PRICE}");
+        let encrypted = expanded.strip_prefix("p=").unwrap();
+        assert!(!expanded.contains("1.5"));
+        assert_eq!(crypter.decrypt_price(encrypted), Ok(1.5));
+    }
+
+    #[test]
+    fn test_expand_bid() {
+        let macros = AuctionMacros::new(values());
+        let mut bid = Bid {
+            nurl: "https://t.example.com/win?p=${AUCTION_PRICE}".to_string(),
+            lurl: "https://t.example.com/loss?r=${AUCTION_LOSS}".to_string(),
+            adm_oneof: Some(AdmOneof::Adm(
+                r#"<img src="https://t.example.com/imp?id=${AUCTION_ID}">"#.to_string(),
+            )),
+            ..Default::default()
+        };
+
+        macros.expand_bid(&mut bid);
+        assert_eq!(bid.nurl, "https://t.example.com/win?p=1.5");
+        assert_eq!(bid.lurl, "https://t.example.com/loss?r=102");
+        assert_eq!(bid.burl, "");
+        assert_eq!(
+            bid.adm_oneof,
+            Some(AdmOneof::Adm(
+                r#"<img src="https://t.example.com/imp?id=req-1">"#.to_string()
+            ))
+        );
+    }
+}
@@ -0,0 +1,686 @@
+//! OpenRTB 2.5 <-> 2.6 request normalisation
+//!
+//! Several signals which OpenRTB 2.5 partners send inside `ext` objects were
+//! promoted to first-class fields in 2.6. [`upgrade_to_2_6`] moves them into
+//! their 2.6 location and [`downgrade_to_2_5`] moves them back for legacy
+//! bidders. Neither pass discards data: when both locations hold different
+//! values the first-class value wins and the ext copy is left untouched,
+//! which is recorded as a [`ChangeKind::Conflict`].
+
+use crate::BidRequest;
+use crate::extensions::ExtWithCustom;
+use crate::spec::adcom::video_plcmt_subtypes;
+use anyhow::{Context, Result};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+/// What happened to a single field during migration
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChangeKind {
+    /// Value was moved from `from` into the reported path
+    Moved { from: String },
+    /// Both locations held differing values, the first-class value was kept
+    /// and the ext value was left in place
+    Conflict { ext: String },
+    /// Both locations held the same value, the redundant ext copy was dropped
+    Deduplicated { ext: String },
+    /// A deprecated `video.placement` value was mapped to `video.plcmt` or back
+    Mapped { from: i32, to: i32 },
+    /// A video placement value has no single equivalent in the target field
+    Unmapped { value: i32 },
+}
+
+/// A single change applied to a request, addressed by its JSON-style path
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    pub path: String,
+    pub kind: ChangeKind,
+}
+
+/// Summary of all changes applied by a migration pass
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MigrationReport {
+    pub changes: Vec<Change>,
+}
+
+impl MigrationReport {
+    /// True if the pass did not modify the request. Conflicts and unmapped
+    /// values may still have been reported.
+    pub fn is_unchanged(&self) -> bool {
+        self.changes.iter().all(|c| {
+            matches!(
+                c.kind,
+                ChangeKind::Conflict { .. } | ChangeKind::Unmapped { .. }
+            )
+        })
+    }
+
+    fn push(&mut self, path: impl Into<String>, kind: ChangeKind) {
+        self.changes.push(Change {
+            path: path.into(),
+            kind,
+        });
+    }
+}
+
+/// Moves OpenRTB 2.5 ext signals into their OpenRTB 2.6 first-class fields.
+///
+/// Handles `regs.ext.gdpr`, `regs.ext.us_privacy`, `user.ext.consent`,
+/// `user.ext.eids` and `source.ext.schain`, and derives `imp.video.plcmt`
+/// from the deprecated `imp.video.placement` where the mapping is unambiguous.
+///
+/// # Errors
+/// Returns an error if an ext value has an unexpected shape, such as
+/// `user.ext.eids` not being an array of eid objects.
+///
+/// # Example
+/// ```
+/// use rtb::BidRequest;
+/// use rtb::openrtb::utils::migrate::upgrade_to_2_6;
+///
+/// let mut request: BidRequest = serde_json::from_str(
+///     r#"{"id":"1","user":{"ext":{"consent":"CPXxRfAPXxRfAAfKABENB-CgAAAAAAAAAAYgAAAAAAAA"}}}"#,
+/// ).unwrap();
+///
+/// let report = upgrade_to_2_6(&mut request).unwrap();
+/// assert!(!report.is_unchanged());
+/// assert!(!request.user.unwrap().consent.is_empty());
+/// ```
+#[allow(deprecated)]
+pub fn upgrade_to_2_6(request: &mut BidRequest) -> Result<MigrationReport> {
+    let mut report = MigrationReport::default();
+
+    if let Some(regs) = request.regs.as_mut() {
+        if let Some(value) = ext_value(&regs.ext, "gdpr")? {
+            let gdpr = json_flag(&value);
+            match regs.gdpr {
+                None => {
+                    regs.gdpr = gdpr;
+                    remove_ext_value(&mut regs.ext, "gdpr")?;
+                    report.push("regs.gdpr", moved("regs.ext.gdpr"));
+                }
+                Some(current) if Some(current) == gdpr => {
+                    remove_ext_value(&mut regs.ext, "gdpr")?;
+                    report.push("regs.gdpr", deduplicated("regs.ext.gdpr"));
+                }
+                Some(_) => report.push("regs.gdpr", conflict("regs.ext.gdpr")),
+            }
+        }
+
+        if let Some(value) = ext_value(&regs.ext, "us_privacy")? {
+            let drop_ext = upgrade_string(
+                &mut regs.us_privacy,
+                value,
+                "regs.us_privacy",
+                "regs.ext.us_privacy",
+                &mut report,
+            );
+            if drop_ext {
+                remove_ext_value(&mut regs.ext, "us_privacy")?;
+            }
+        }
+    }
+
+    if let Some(user) = request.user.as_mut() {
+        if let Some(value) = ext_value(&user.ext, "consent")? {
+            let drop_ext = upgrade_string(
+                &mut user.consent,
+                value,
+                "user.consent",
+                "user.ext.consent",
+                &mut report,
+            );
+            if drop_ext {
+                remove_ext_value(&mut user.ext, "consent")?;
+            }
+        }
+
+        if let Some(value) = ext_value(&user.ext, "eids")? {
+            if user.eids.is_empty() {
+                user.eids = serde_json::from_value(value).context("Invalid user.ext.eids value")?;
+                remove_ext_value(&mut user.ext, "eids")?;
+                report.push("user.eids", moved("user.ext.eids"));
+            } else if serde_json::to_value(&user.eids)? == value {
+                remove_ext_value(&mut user.ext, "eids")?;
+                report.push("user.eids", deduplicated("user.ext.eids"));
+            } else {
+                report.push("user.eids", conflict("user.ext.eids"));
+            }
+        }
+    }
+
+    if let Some(source) = request.source.as_mut() {
+        if let Some(value) = ext_value(&source.ext, "schain")? {
+            match &source.schain {
+                None => {
+                    source.schain = Some(
+                        serde_json::from_value(value).context("Invalid source.ext.schain value")?,
+                    );
+                    remove_ext_value(&mut source.ext, "schain")?;
+                    report.push("source.schain", moved("source.ext.schain"));
+                }
+                Some(current) if serde_json::to_value(current)? == value => {
+                    remove_ext_value(&mut source.ext, "schain")?;
+                    report.push("source.schain", deduplicated("source.ext.schain"));
+                }
+                Some(_) => report.push("source.schain", conflict("source.ext.schain")),
+            }
+        }
+    }
+
+    for (idx, imp) in request.imp.iter_mut().enumerate() {
+        let instl = imp.instl;
+        let Some(video) = imp.video.as_mut() else {
+            continue;
+        };
+
+        if video.placement == 0 || video.plcmt != 0 {
+            continue;
+        }
+
+        let path = format!("imp[{idx}].video.plcmt");
+        match placement_to_plcmt(video.placement, instl) {
+            Some(plcmt) => {
+                report.push(
+                    path,
+                    ChangeKind::Mapped {
+                        from: video.placement,
+                        to: plcmt,
+                    },
+                );
+                video.plcmt = plcmt;
+            }
+            None => report.push(
+                path,
+                ChangeKind::Unmapped {
+                    value: video.placement,
+                },
+            ),
+        }
+    }
+
+    Ok(report)
+}
+
+/// Moves OpenRTB 2.6 first-class signals back into their OpenRTB 2.5 ext
+/// locations for bidders which only understand 2.5.
+///
+/// This is the inverse of [`upgrade_to_2_6`]. `imp.video.placement` is only
+/// derived from `imp.video.plcmt` when it is unset and the mapping is
+/// unambiguous; `plcmt` itself is left in place since 2.5 bidders ignore it.
+#[allow(deprecated)]
+pub fn downgrade_to_2_5(request: &mut BidRequest) -> Result<MigrationReport> {
+    let mut report = MigrationReport::default();
+
+    if let Some(regs) = request.regs.as_mut() {
+        if let Some(gdpr) = regs.gdpr {
+            let ext_gdpr = ext_value(&regs.ext, "gdpr")?;
+            match ext_gdpr.as_ref().map(json_flag) {
+                None => {
+                    set_ext_value(&mut regs.ext, "gdpr", Value::from(gdpr as u8))?;
+                    regs.gdpr = None;
+                    report.push("regs.ext.gdpr", moved("regs.gdpr"));
+                }
+                Some(existing) if existing == Some(gdpr) => {
+                    regs.gdpr = None;
+                    report.push("regs.ext.gdpr", deduplicated("regs.gdpr"));
+                }
+                Some(_) => report.push("regs.ext.gdpr", conflict("regs.ext.gdpr")),
+            }
+        }
+
+        downgrade_string(
+            &mut regs.us_privacy,
+            &mut regs.ext,
+            "us_privacy",
+            "regs",
+            &mut report,
+        )?;
+    }
+
+    if let Some(user) = request.user.as_mut() {
+        downgrade_string(
+            &mut user.consent,
+            &mut user.ext,
+            "consent",
+            "user",
+            &mut report,
+        )?;
+
+        if !user.eids.is_empty() {
+            let eids = serde_json::to_value(&user.eids)?;
+            match ext_value(&user.ext, "eids")? {
+                None => {
+                    set_ext_value(&mut user.ext, "eids", eids)?;
+                    user.eids.clear();
+                    report.push("user.ext.eids", moved("user.eids"));
+                }
+                Some(existing) if existing == eids => {
+                    user.eids.clear();
+                    report.push("user.ext.eids", deduplicated("user.eids"));
+                }
+                Some(_) => report.push("user.ext.eids", conflict("user.ext.eids")),
+            }
+        }
+    }
+
+    if let Some(source) = request.source.as_mut() {
+        if let Some(schain) = &source.schain {
+            let schain = serde_json::to_value(schain)?;
+            match ext_value(&source.ext, "schain")? {
+                None => {
+                    set_ext_value(&mut source.ext, "schain", schain)?;
+                    source.schain = None;
+                    report.push("source.ext.schain", moved("source.schain"));
+                }
+                Some(existing) if existing == schain => {
+                    source.schain = None;
+                    report.push("source.ext.schain", deduplicated("source.schain"));
+                }
+                Some(_) => report.push("source.ext.schain", conflict("source.ext.schain")),
+            }
+        }
+    }
+
+    for (idx, imp) in request.imp.iter_mut().enumerate() {
+        let Some(video) = imp.video.as_mut() else {
+            continue;
+        };
+
+        if video.plcmt == 0 || video.placement != 0 {
+            continue;
+        }
+
+        let path = format!("imp[{idx}].video.placement");
+        match plcmt_to_placement(video.plcmt) {
+            Some(placement) => {
+                report.push(
+                    path,
+                    ChangeKind::Mapped {
+                        from: video.plcmt,
+                        to: placement,
+                    },
+                );
+                video.placement = placement;
+            }
+            None => report.push(path, ChangeKind::Unmapped { value: video.plcmt }),
+        }
+    }
+
+    Ok(report)
+}
+
+/// Maps a deprecated OpenRTB 2.5 `video.placement` value to an AdCom `plcmt`
+/// subtype where a single equivalent exists. In-article and in-feed players
+/// may be accompanying content or standalone, and floating players and
+/// sliders have no single equivalent, so value 5 only maps when the
+/// impression is a full-screen interstitial.
+#[allow(deprecated)]
+pub fn placement_to_plcmt(placement: i32, interstitial: bool) -> Option<i32> {
+    use crate::spec::openrtb::video_placement_types as legacy;
+
+    let placement = u32::try_from(placement).ok()?;
+    let plcmt = match placement {
+        legacy::IN_STREAM => video_plcmt_subtypes::INSTREAM,
+        legacy::IN_BANNER => video_plcmt_subtypes::NO_CONTENT_STANDALONE,
+        legacy::INTERSTITIAL_SLIDER_FLOATING if interstitial => video_plcmt_subtypes::INTERSTITIAL,
+        _ => return None,
+    };
+
+    Some(plcmt as i32)
+}
+
+/// Maps an AdCom `plcmt` subtype back to a deprecated OpenRTB 2.5
+/// `video.placement` value where a single equivalent exists.
+#[allow(deprecated)]
+pub fn plcmt_to_placement(plcmt: i32) -> Option<i32> {
+    use crate::spec::openrtb::video_placement_types as legacy;
+
+    let plcmt = u32::try_from(plcmt).ok()?;
+    let placement = match plcmt {
+        video_plcmt_subtypes::INSTREAM => legacy::IN_STREAM,
+        video_plcmt_subtypes::INTERSTITIAL => legacy::INTERSTITIAL_SLIDER_FLOATING,
+        _ => return None,
+    };
+
+    Some(placement as i32)
+}
+
+fn moved(from: &str) -> ChangeKind {
+    ChangeKind::Moved {
+        from: from.to_string(),
+    }
+}
+
+fn conflict(ext: &str) -> ChangeKind {
+    ChangeKind::Conflict {
+        ext: ext.to_string(),
+    }
+}
+
+fn deduplicated(ext: &str) -> ChangeKind {
+    ChangeKind::Deduplicated {
+        ext: ext.to_string(),
+    }
+}
+
+/// Interprets an OpenRTB flag which may be sent as 0/1, a bool or a string
+fn json_flag(value: &Value) -> Option<bool> {
+    match value {
+        Value::Bool(b) => Some(*b),
+        Value::Number(n) => n.as_f64().map(|n| n != 0.0),
+        Value::String(s) => match s.trim() {
+            "1" | "true" => Some(true),
+            "0" | "false" => Some(false),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Moves a string ext value into `field`. Returns false when the ext value
+/// must be kept, because it conflicts or is not a string.
+fn upgrade_string(
+    field: &mut String,
+    ext: Value,
+    path: &str,
+    ext_path: &str,
+    report: &mut MigrationReport,
+) -> bool {
+    let Value::String(ext) = ext else {
+        report.push(path, conflict(ext_path));
+        return false;
+    };
+
+    if field.is_empty() {
+        *field = ext;
+        report.push(path, moved(ext_path));
+    } else if *field == ext {
+        report.push(path, deduplicated(ext_path));
+    } else {
+        report.push(path, conflict(ext_path));
+        return false;
+    }
+    true
+}
+
+fn downgrade_string<T>(
+    field: &mut String,
+    ext: &mut Option<ExtWithCustom<T>>,
+    key: &str,
+    parent: &str,
+    report: &mut MigrationReport,
+) -> Result<()>
+where
+    T: Serialize + DeserializeOwned + Default,
+{
+    if field.is_empty() {
+        return Ok(());
+    }
+
+    let path = format!("{parent}.{key}");
+    let ext_path = format!("{parent}.ext.{key}");
+
+    match ext_value(ext, key)? {
+        None => {
+            set_ext_value(ext, key, Value::String(field.clone()))?;
+            field.clear();
+            report.push(ext_path, moved(&path));
+        }
+        Some(Value::String(existing)) if existing == *field => {
+            field.clear();
+            report.push(ext_path, deduplicated(&path));
+        }
+        Some(_) => report.push(&ext_path, conflict(&ext_path)),
+    }
+
+    Ok(())
+}
+
+/// Reads a key from an ext object regardless of whether it is proto-defined
+/// or only present in the custom fields
+fn ext_value<T>(ext: &Option<ExtWithCustom<T>>, key: &str) -> Result<Option<Value>>
+where
+    T: Serialize,
+{
+    let Some(ext) = ext else {
+        return Ok(None);
+    };
+
+    let mut value = serde_json::to_value(ext)?;
+    Ok(value
+        .as_object_mut()
+        .and_then(|obj| obj.remove(key))
+        .filter(|v| !v.is_null()))
+}
+
+fn remove_ext_value<T>(ext: &mut Option<ExtWithCustom<T>>, key: &str) -> Result<()>
+where
+    T: Serialize + DeserializeOwned,
+{
+    let Some(current) = ext.as_ref() else {
+        return Ok(());
+    };
+
+    let mut value = serde_json::to_value(current)?;
+    if let Some(obj) = value.as_object_mut() {
+        if obj.remove(key).is_some() {
+            *ext = Some(serde_json::from_value(value)?);
+        }
+    }
+
+    Ok(())
+}
+
+fn set_ext_value<T>(ext: &mut Option<ExtWithCustom<T>>, key: &str, new: Value) -> Result<()>
+where
+    T: Serialize + DeserializeOwned + Default,
+{
+    let current = ext.get_or_insert_with(Default::default);
+
+    let mut value = serde_json::to_value(&*current)?;
+    if let Some(obj) = value.as_object_mut() {
+        obj.insert(key.to_string(), new);
+    }
+    *current = serde_json::from_value(value).with_context(|| format!("Invalid ext.{key} value"))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEGACY: &str = r#"{
+        "id": "legacy",
+        "imp": [
+            {"id": "1", "video": {"mimes": ["video/mp4"], "placement": 1}},
+            {"id": "2", "video": {"mimes": ["video/mp4"], "placement": 5}}
+        ],
+        "regs": {"ext": {"gdpr": 1, "us_privacy": "1YNN"}},
+        "user": {"ext": {
+            "consent": "CONSENT",
+            "eids": [{"source": "id5-sync.com", "uids": [{"id": "abc", "atype": 1}]}],
+            "custom": 7
+        }},
+        "source": {"ext": {"schain": {
+            "complete": 1, "ver": "1.0",
+            "nodes": [{"asi": "exchange.com", "sid": "pub-1", "hp": 1}]
+        }}}
+    }"#;
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_upgrade_moves_ext_fields() {
+        let mut request: BidRequest = serde_json::from_str(LEGACY).unwrap();
+        let report = upgrade_to_2_6(&mut request).unwrap();
+
+        let regs = request.regs.as_ref().unwrap();
+        assert_eq!(regs.gdpr, Some(true));
+        assert_eq!(regs.us_privacy, "1YNN");
+        assert_eq!(regs.ext.as_ref().unwrap().gdpr, None);
+
+        let user = request.user.as_ref().unwrap();
+        assert_eq!(user.consent, "CONSENT");
+        assert_eq!(user.eids.len(), 1);
+        assert_eq!(user.eids[0].source, "id5-sync.com");
+
+        assert_eq!(ext_value(&user.ext, "consent").unwrap(), None);
+        assert_eq!(ext_value(&user.ext, "eids").unwrap(), None);
+        assert_eq!(
+            user.ext.as_ref().unwrap().custom().get_i64("custom"),
+            Some(7)
+        );
+
+        let schain = request.source.as_ref().unwrap().schain.as_ref().unwrap();
+        assert_eq!(schain.nodes[0].asi, "exchange.com");
+
+        assert_eq!(request.imp[0].video.as_ref().unwrap().plcmt, 1);
+        assert_eq!(request.imp[1].video.as_ref().unwrap().plcmt, 0);
+
+        assert!(report.changes.contains(&Change {
+            path: "imp[1].video.plcmt".to_string(),
+            kind: ChangeKind::Unmapped { value: 5 },
+        }));
+        assert!(report.changes.contains(&Change {
+            path: "user.consent".to_string(),
+            kind: ChangeKind::Moved {
+                from: "user.ext.consent".to_string()
+            },
+        }));
+    }
+
+    #[test]
+    fn test_roundtrip_is_lossless() {
+        let original: BidRequest = serde_json::from_str(LEGACY).unwrap();
+        let mut request = original.clone();
+
+        upgrade_to_2_6(&mut request).unwrap();
+        downgrade_to_2_5(&mut request).unwrap();
+
+        let before = serde_json::to_value(&original).unwrap();
+        let mut after = serde_json::to_value(&request).unwrap();
+
+        // The upgrade derives plcmt, which the downgrade intentionally keeps
+        after["imp"][0]["video"]
+            .as_object_mut()
+            .unwrap()
+            .remove("plcmt");
+
+        assert_eq!(before["regs"], after["regs"]);
+        assert_eq!(before["user"], after["user"]);
+        assert_eq!(before["source"], after["source"]);
+        assert_eq!(before["imp"], after["imp"]);
+    }
+
+    #[test]
+    fn test_conflict_keeps_both_values() {
+        let mut request: BidRequest =
+            serde_json::from_str(r#"{"id":"1","user":{"consent":"NEW","ext":{"consent":"OLD"}}}"#)
+                .unwrap();
+
+        let report = upgrade_to_2_6(&mut request).unwrap();
+        let user = request.user.as_ref().unwrap();
+
+        assert_eq!(user.consent, "NEW");
+        assert_eq!(
+            ext_value(&user.ext, "consent").unwrap(),
+            Some(Value::from("OLD"))
+        );
+        assert!(report.is_unchanged());
+        assert!(matches!(
+            report.changes[0].kind,
+            ChangeKind::Conflict { .. }
+        ));
+    }
+
+    #[test]
+    fn test_non_string_ext_value_is_kept() {
+        let mut report = MigrationReport::default();
+        let mut field = String::new();
+
+        let drop_ext = upgrade_string(
+            &mut field,
+            Value::from(1),
+            "regs.us_privacy",
+            "regs.ext.us_privacy",
+            &mut report,
+        );
+
+        assert!(!drop_ext);
+        assert!(field.is_empty());
+        assert!(report.is_unchanged());
+        assert_eq!(
+            report.changes,
+            [Change {
+                path: "regs.us_privacy".to_string(),
+                kind: conflict("regs.ext.us_privacy"),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_downgrade_conflict_is_reported_at_ext_path() {
+        let mut request: BidRequest =
+            serde_json::from_str(r#"{"id":"1","regs":{"gdpr":1,"ext":{"gdpr":0}}}"#).unwrap();
+
+        let report = downgrade_to_2_5(&mut request).unwrap();
+
+        assert_eq!(request.regs.as_ref().unwrap().gdpr, Some(true));
+        assert_eq!(
+            report.changes,
+            [Change {
+                path: "regs.ext.gdpr".to_string(),
+                kind: conflict("regs.ext.gdpr"),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_upgrade_is_idempotent() {
+        let mut request: BidRequest = serde_json::from_str(LEGACY).unwrap();
+        upgrade_to_2_6(&mut request).unwrap();
+
+        let report = upgrade_to_2_6(&mut request).unwrap();
+        assert!(report.is_unchanged());
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_downgrade_moves_first_class_fields() {
+        let mut request: BidRequest = serde_json::from_str(
+            r#"{"id":"1","imp":[{"id":"1","instl":1,"video":{"plcmt":3}}],
+            "regs":{"gdpr":0,"us_privacy":"1---"},"user":{"consent":"C"}}"#,
+        )
+        .unwrap();
+
+        downgrade_to_2_5(&mut request).unwrap();
+
+        let regs = request.regs.as_ref().unwrap();
+        assert_eq!(regs.gdpr, None);
+        assert!(regs.us_privacy.is_empty());
+        assert_eq!(regs.ext.as_ref().unwrap().gdpr, Some(false));
+
+        let user = request.user.as_ref().unwrap();
+        assert!(user.consent.is_empty());
+        assert_eq!(
+            ext_value(&user.ext, "consent").unwrap(),
+            Some(Value::from("C"))
+        );
+
+        assert_eq!(request.imp[0].video.as_ref().unwrap().placement, 5);
+    }
+
+    #[test]
+    fn test_placement_mapping() {
+        assert_eq!(placement_to_plcmt(1, false), Some(1));
+        assert_eq!(placement_to_plcmt(2, false), Some(4));
+        assert_eq!(placement_to_plcmt(3, false), None);
+        assert_eq!(placement_to_plcmt(4, false), None);
+        assert_eq!(placement_to_plcmt(5, true), Some(3));
+        assert_eq!(placement_to_plcmt(5, false), None);
+        assert_eq!(placement_to_plcmt(-1, false), None);
+        assert_eq!(plcmt_to_placement(2), None);
+    }
+}
@@ -1,3 +1,15 @@
 pub mod adm;
 pub use adm::detect_ad_format;
+pub mod consent;
+pub mod macros;
+pub mod migrate;
+pub mod native;
+pub mod pod;
+#[cfg(feature = "crypto")]
+pub mod price_crypto;
+pub mod secure;
+pub mod sniff;
+pub mod supply;
 pub mod trackers;
+pub mod vast;
+pub mod view;
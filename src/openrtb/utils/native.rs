@@ -0,0 +1,378 @@
+//! OpenRTB Native 1.2 request/response codec
+//!
+//! `imp.native.request` and native bid markup are JSON documents embedded as
+//! strings inside the OpenRTB payload. Over JSON they arrive as text, while
+//! protobuf integrations carry them as [`NativeRequest`]/[`NativeResponse`]
+//! messages. These helpers convert between both forms, accepting the legacy
+//! `{"native":{...}}` wrapper as well as the bare Native 1.2 object.
+
+use crate::bid_request::imp::Native;
+use crate::bid_request::imp::native::RequestOneof;
+use crate::bid_response::Bid;
+use crate::bid_response::bid::AdmOneof;
+use crate::{NativeRequest, NativeResponse, native_request, native_response};
+use anyhow::{Context, Error, Result, bail};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::borrow::Cow;
+
+/// Kind of a native asset, independent of request or response
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NativeAssetKind {
+    Title,
+    Image,
+    Video,
+    Data,
+}
+
+/// A problem found when validating a native response against its request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NativeIssue {
+    /// A required request asset has no response asset with the same id
+    MissingRequiredAsset { id: i32 },
+    /// A response asset id does not exist in the request
+    UnknownAsset { id: i32 },
+    /// The response asset kind differs from the requested kind
+    AssetKindMismatch {
+        id: i32,
+        expected: NativeAssetKind,
+        actual: Option<NativeAssetKind>,
+    },
+    /// The response has no top-level link, which Native 1.2 requires
+    MissingLink,
+}
+
+impl native_request::Asset {
+    /// Returns the kind of asset requested, if the asset oneof is populated
+    pub fn kind(&self) -> Option<NativeAssetKind> {
+        use native_request::asset::AssetOneof;
+
+        Some(match self.asset_oneof.as_ref()? {
+            AssetOneof::Title(_) => NativeAssetKind::Title,
+            AssetOneof::Img(_) => NativeAssetKind::Image,
+            AssetOneof::Video(_) => NativeAssetKind::Video,
+            AssetOneof::Data(_) => NativeAssetKind::Data,
+        })
+    }
+}
+
+impl native_response::Asset {
+    /// Returns the kind of asset supplied, if the asset oneof is populated
+    pub fn kind(&self) -> Option<NativeAssetKind> {
+        use native_response::asset::AssetOneof;
+
+        Some(match self.asset_oneof.as_ref()? {
+            AssetOneof::Title(_) => NativeAssetKind::Title,
+            AssetOneof::Img(_) => NativeAssetKind::Image,
+            AssetOneof::Video(_) => NativeAssetKind::Video,
+            AssetOneof::Data(_) => NativeAssetKind::Data,
+        })
+    }
+}
+
+impl NativeRequest {
+    /// Looks up a requested asset by its id
+    pub fn asset(&self, id: i32) -> Option<&native_request::Asset> {
+        self.assets.iter().find(|a| a.id == id)
+    }
+
+    /// Iterates over the assets the bidder must supply
+    pub fn required_assets(&self) -> impl Iterator<Item = &native_request::Asset> {
+        self.assets.iter().filter(|a| a.required)
+    }
+}
+
+impl NativeResponse {
+    /// Looks up a response asset by its id
+    pub fn asset(&self, id: i32) -> Option<&native_response::Asset> {
+        self.assets.iter().find(|a| a.id == id)
+    }
+
+    /// Mutable lookup of a response asset by its id
+    pub fn asset_mut(&mut self, id: i32) -> Option<&mut native_response::Asset> {
+        self.assets.iter_mut().find(|a| a.id == id)
+    }
+}
+
+/// Parses a textual native request, as found in `imp.native.request`.
+///
+/// Accepts both the `{"native":{...}}` wrapper used by Native 1.0 and the bare
+/// object used by Native 1.1+.
+///
+/// # Example
+/// ```
+/// use rtb::openrtb::utils::native::parse_native_request;
+///
+/// let wrapped = parse_native_request(r#"{"native":{"ver":"1.2","assets":[{"id":1,"required":1,"title":{"len":90}}]}}"#).unwrap();
+/// let bare = parse_native_request(r#"{"ver":"1.2","assets":[{"id":1,"required":1,"title":{"len":90}}]}"#).unwrap();
+/// assert_eq!(wrapped, bare);
+/// assert!(bare.asset(1).unwrap().required);
+/// ```
+pub fn parse_native_request(json: &str) -> Result<NativeRequest> {
+    parse_unwrapped(json)
+        .map(|(request, _)| request)
+        .context("Failed to parse native request")
+}
+
+/// Serialises a native request back to text for `imp.native.request`.
+///
+/// When `wrapped` is true the object is nested under a `native` key for
+/// consumers still expecting the Native 1.0 layout.
+pub fn native_request_to_string(request: &NativeRequest, wrapped: bool) -> Result<String> {
+    to_string_wrapped(request, wrapped)
+}
+
+/// Parses textual native bid markup into a [`NativeResponse`].
+///
+/// Accepts both the `{"native":{...}}` wrapper and the bare object.
+pub fn parse_native_response(adm: &str) -> Result<NativeResponse> {
+    parse_native_response_wrapped(adm).map(|(response, _)| response)
+}
+
+/// Parses textual native markup, also reporting whether it used the
+/// `{"native":{...}}` wrapper so it can be re-serialised in the same layout
+pub(crate) fn parse_native_response_wrapped(adm: &str) -> Result<(NativeResponse, bool)> {
+    parse_unwrapped(adm).context("Failed to parse native response")
+}
+
+/// Serialises a native response to text suitable for `bid.adm`.
+///
+/// When `wrapped` is true the object is nested under a `native` key.
+pub fn native_response_to_string(response: &NativeResponse, wrapped: bool) -> Result<String> {
+    to_string_wrapped(response, wrapped)
+}
+
+/// Extracts the native request of an impression, whether it was received as
+/// text (JSON) or as a [`NativeRequest`] message (protobuf).
+pub fn get_native_request(native: &Native) -> Result<Cow<'_, NativeRequest>> {
+    match native.request_oneof.as_ref() {
+        Some(RequestOneof::Request(text)) => parse_native_request(text).map(Cow::Owned),
+        Some(RequestOneof::RequestNative(request)) => Ok(Cow::Borrowed(request)),
+        None => bail!("No native request present on imp"),
+    }
+}
+
+/// Extracts the native response of a bid, whether it was received as textual
+/// adm (JSON) or as [`AdmOneof::AdmNative`] (protobuf).
+pub fn get_native_response(bid: &Bid) -> Result<Cow<'_, NativeResponse>> {
+    match bid.adm_oneof.as_ref() {
+        Some(AdmOneof::Adm(adm)) => parse_native_response(adm).map(Cow::Owned),
+        Some(AdmOneof::AdmNative(response)) => Ok(Cow::Borrowed(response)),
+        None => bail!("No adm present on bid"),
+    }
+}
+
+/// Converts a bid's textual native adm into [`AdmOneof::AdmNative`] in place,
+/// e.g. before re-encoding the response as protobuf. No-op if already native.
+pub fn adm_to_native(bid: &mut Bid) -> Result<(), Error> {
+    if let Some(AdmOneof::Adm(adm)) = bid.adm_oneof.as_ref() {
+        let response = parse_native_response(adm)?;
+        bid.adm_oneof = Some(AdmOneof::AdmNative(response));
+    }
+
+    Ok(())
+}
+
+/// Converts a bid's [`AdmOneof::AdmNative`] into textual adm in place, e.g.
+/// before responding over JSON. No-op if the adm is already textual.
+pub fn native_to_adm(bid: &mut Bid) -> Result<(), Error> {
+    if let Some(AdmOneof::AdmNative(response)) = bid.adm_oneof.as_ref() {
+        let adm = native_response_to_string(response, false)?;
+        bid.adm_oneof = Some(AdmOneof::Adm(adm));
+    }
+
+    Ok(())
+}
+
+/// Validates a native response against the request it answers.
+///
+/// Returns every issue found, so an empty vector means the response supplies
+/// all required assets with matching kinds and references no unknown assets.
+pub fn validate_native_response(
+    request: &NativeRequest,
+    response: &NativeResponse,
+) -> Vec<NativeIssue> {
+    let mut issues = Vec::new();
+
+    if response.link.is_none() {
+        issues.push(NativeIssue::MissingLink);
+    }
+
+    for requested in request.required_assets() {
+        if response.asset(requested.id).is_none() {
+            issues.push(NativeIssue::MissingRequiredAsset { id: requested.id });
+        }
+    }
+
+    for supplied in &response.assets {
+        let Some(requested) = request.asset(supplied.id) else {
+            issues.push(NativeIssue::UnknownAsset { id: supplied.id });
+            continue;
+        };
+
+        // Native 1.2 allows bare responses (e.g. asset with only a link)
+        // so only flag a mismatch when both sides declare a kind
+        if let Some(expected) = requested.kind() {
+            let actual = supplied.kind();
+            if actual.is_some_and(|actual| actual != expected) {
+                issues.push(NativeIssue::AssetKindMismatch {
+                    id: supplied.id,
+                    expected,
+                    actual,
+                });
+            }
+        }
+    }
+
+    issues
+}
+
+fn parse_unwrapped<T: DeserializeOwned>(json: &str) -> Result<(T, bool)> {
+    let mut value: Value = serde_json::from_str(json.trim_start_matches('\u{feff}'))?;
+    let mut wrapped = false;
+
+    if let Value::Object(obj) = &mut value {
+        if obj.len() == 1 && obj.get("native").is_some_and(Value::is_object) {
+            value = obj.remove("native").unwrap_or_default();
+            wrapped = true;
+        }
+    }
+
+    Ok((serde_json::from_value(value)?, wrapped))
+}
+
+fn to_string_wrapped<T: Serialize>(value: &T, wrapped: bool) -> Result<String> {
+    if wrapped {
+        let mut obj = serde_json::Map::new();
+        obj.insert("native".to_string(), serde_json::to_value(value)?);
+        Ok(serde_json::to_string(&obj)?)
+    } else {
+        Ok(serde_json::to_string(value)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REQUEST: &str = r#"{"native":{"ver":"1.2","assets":[
+        {"id":1,"required":1,"title":{"len":90}},
+        {"id":2,"required":1,"img":{"type":3,"wmin":300,"hmin":250}},
+        {"id":3,"data":{"type":2,"len":140}}
+    ],"eventtrackers":[{"event":1,"methods":[1,2]}]}}"#;
+
+    const RESPONSE: &str = r#"{"ver":"1.2","assets":[
+        {"id":1,"title":{"text":"Buy now"}},
+        {"id":2,"img":{"url":"https://cdn.example.com/a.png","w":300,"h":250}}
+    ],"link":{"url":"https://example.com/click"}}"#;
+
+    #[test]
+    fn test_parse_request_wrapped_and_bare() {
+        let wrapped = parse_native_request(REQUEST).unwrap();
+        let bare_json = native_request_to_string(&wrapped, false).unwrap();
+        let bare = parse_native_request(&bare_json).unwrap();
+
+        assert_eq!(wrapped, bare);
+        assert_eq!(wrapped.assets.len(), 3);
+        assert_eq!(wrapped.required_assets().count(), 2);
+        assert_eq!(
+            wrapped.asset(2).unwrap().kind(),
+            Some(NativeAssetKind::Image)
+        );
+        assert!(wrapped.asset(9).is_none());
+    }
+
+    #[test]
+    fn test_request_to_string_wrapped() {
+        let request = parse_native_request(REQUEST).unwrap();
+        let json = native_request_to_string(&request, true).unwrap();
+
+        assert!(json.starts_with(r#"{"native":"#));
+        assert_eq!(parse_native_request(&json).unwrap(), request);
+    }
+
+    #[test]
+    fn test_get_native_request_from_imp() {
+        let imp: crate::bid_request::Imp = serde_json::from_value(serde_json::json!({
+            "id": "1",
+            "native": {"request": REQUEST, "ver": "1.2"}
+        }))
+        .unwrap();
+
+        let request = get_native_request(imp.native.as_ref().unwrap()).unwrap();
+        assert_eq!(request.assets.len(), 3);
+    }
+
+    #[test]
+    fn test_response_roundtrip_between_adm_forms() {
+        let mut bid = Bid {
+            id: "1".to_string(),
+            impid: "1".to_string(),
+            adm_oneof: Some(AdmOneof::Adm(RESPONSE.to_string())),
+            ..Default::default()
+        };
+
+        adm_to_native(&mut bid).unwrap();
+        assert!(matches!(bid.adm_oneof, Some(AdmOneof::AdmNative(_))));
+        assert_eq!(get_native_response(&bid).unwrap().assets.len(), 2);
+
+        native_to_adm(&mut bid).unwrap();
+        let Some(AdmOneof::Adm(adm)) = &bid.adm_oneof else {
+            panic!("expected textual adm");
+        };
+        assert_eq!(
+            parse_native_response(adm).unwrap(),
+            parse_native_response(RESPONSE).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_wrapped_response() {
+        let wrapped = format!(r#"{{"native":{}}}"#, RESPONSE);
+        let response = parse_native_response(&wrapped).unwrap();
+
+        assert_eq!(
+            response.link.as_ref().unwrap().url,
+            "https://example.com/click"
+        );
+        assert_eq!(
+            response.asset(1).unwrap().kind(),
+            Some(NativeAssetKind::Title)
+        );
+    }
+
+    #[test]
+    fn test_validate_valid_response() {
+        let request = parse_native_request(REQUEST).unwrap();
+        let response = parse_native_response(RESPONSE).unwrap();
+
+        assert!(validate_native_response(&request, &response).is_empty());
+    }
+
+    #[test]
+    fn test_validate_reports_issues() {
+        let request = parse_native_request(REQUEST).unwrap();
+        let response = parse_native_response(
+            r#"{"assets":[{"id":1,"img":{"url":"https://x"}},{"id":7,"title":{"text":"t"}}]}"#,
+        )
+        .unwrap();
+
+        let issues = validate_native_response(&request, &response);
+
+        assert!(issues.contains(&NativeIssue::MissingLink));
+        assert!(issues.contains(&NativeIssue::MissingRequiredAsset { id: 2 }));
+        assert!(issues.contains(&NativeIssue::UnknownAsset { id: 7 }));
+        assert!(issues.contains(&NativeIssue::AssetKindMismatch {
+            id: 1,
+            expected: NativeAssetKind::Title,
+            actual: Some(NativeAssetKind::Image),
+        }));
+    }
+
+    #[test]
+    fn test_invalid_json_fails() {
+        assert!(parse_native_request("not json").is_err());
+        assert!(parse_native_response("<div/>").is_err());
+    }
+}
@@ -0,0 +1,749 @@
+//! Ad pod assembly for CTV and other long form video.
+//!
+//! A [`PodAssembler`] picks the bids which fill an ad pod under the OpenRTB
+//! 2.6 pod rules (`imp.video.poddur`, `maxseq`, `mincpmpersec`, ...), the
+//! `slotinpod` each bid asks for and competitive separation, then emits the
+//! result as a multi-Ad VAST pod or a VMAP ad break.
+
+use super::vast::{Ad, AdContent, AdSystem, Vast, Wrapper, XmlElement};
+use crate::bid_request::imp::Video;
+use crate::bid_response::Bid;
+use crate::bid_response::bid::AdmOneof;
+use crate::openrtb::spec::lossreason;
+use crate::spec::adcom::{pod_deduplication_settings, slot_position_in_pod};
+use anyhow::Result;
+use derive_builder::Builder;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// Rules a pod is filled under, usually read from the impression with
+/// [`PodRules::from_video`]. Durations are in seconds.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default, Builder)]
+pub struct PodRules {
+    /// `imp.video.podid`
+    #[builder(default)]
+    pub pod_id: Option<String>,
+
+    /// Total duration of the pod, `imp.video.poddur`
+    #[builder(default)]
+    pub pod_duration: Option<u32>,
+
+    /// Maximum number of ads in the pod, `imp.video.maxseq`
+    #[builder(default)]
+    pub max_ads: Option<u32>,
+
+    #[builder(default)]
+    pub min_ad_duration: Option<u32>,
+
+    #[builder(default)]
+    pub max_ad_duration: Option<u32>,
+
+    /// Exact ad durations allowed, `imp.video.rqddurs`
+    #[builder(default)]
+    pub required_durations: Vec<u32>,
+
+    /// Floor per second of ad time, `imp.video.mincpmpersec`
+    #[builder(default)]
+    pub min_cpm_per_second: Option<f64>,
+
+    /// Competitive separation, see
+    /// [`crate::spec::adcom::pod_deduplication_settings`]
+    #[builder(default)]
+    pub deduplication: Vec<u32>,
+}
+
+impl PodRules {
+    /// Reads the pod fields of a video impression. Deduplication is not part
+    /// of the request and has to be set separately.
+    pub fn from_video(video: &Video) -> Self {
+        let positive = |v: i32| (v > 0).then_some(v as u32);
+
+        Self {
+            pod_id: (!video.podid.is_empty()).then(|| video.podid.clone()),
+            pod_duration: positive(video.poddur),
+            max_ads: positive(video.maxseq),
+            min_ad_duration: positive(video.minduration),
+            max_ad_duration: positive(video.maxduration),
+            required_durations: video.rqddurs.iter().filter_map(|&d| positive(d)).collect(),
+            min_cpm_per_second: (video.mincpmpersec > 0.0).then_some(video.mincpmpersec),
+            deduplication: Vec::new(),
+        }
+    }
+
+    fn duration_allowed(&self, duration: u32) -> bool {
+        if !self.required_durations.is_empty() {
+            return self.required_durations.contains(&duration);
+        }
+
+        self.min_ad_duration.is_none_or(|min| duration >= min)
+            && self.max_ad_duration.is_none_or(|max| duration <= max)
+    }
+}
+
+/// Why a bid was left out of a pod
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum PodExclusion {
+    /// Neither VAST adm nor `nurl` was present
+    MissingMarkup,
+    /// The adm was not a VAST document with at least one ad
+    InvalidMarkup { reason: String },
+    /// Neither `bid.dur` nor the VAST `Duration` gave the ad length
+    MissingDuration,
+    /// The ad length is outside the allowed durations
+    DurationNotAllowed { duration: u32 },
+    /// The price per second of ad time is below `mincpmpersec`
+    BelowMinCpmPerSecond { cpm_per_second: f64 },
+    /// The pod already holds `maxseq` ads
+    PodFull,
+    /// The ad does not fit into the time left in the pod
+    PodDurationExceeded { duration: u32, remaining: u32 },
+    /// The requested `slotinpod` was taken by a higher bid
+    SlotTaken { slot: i32 },
+    /// A higher bid in the pod shares a deduplication key, see
+    /// [`crate::spec::adcom::pod_deduplication_settings`]
+    Duplicate { setting: u32, value: String },
+}
+
+impl PodExclusion {
+    /// Suggested loss reason to report to the bidder, see
+    /// [`crate::openrtb::spec::lossreason`]
+    pub fn loss_reason(&self) -> u32 {
+        match self {
+            PodExclusion::MissingMarkup => lossreason::MISSING_MARKUP,
+            PodExclusion::InvalidMarkup { .. } => lossreason::INVALID_BID_RESPONSE,
+            PodExclusion::MissingDuration | PodExclusion::DurationNotAllowed { .. } => {
+                lossreason::CREATIVE_FILTERED_INCORRECT_FORMAT
+            }
+            PodExclusion::BelowMinCpmPerSecond { .. } => lossreason::BID_BELOW_AUCTION_FLOOR,
+            PodExclusion::Duplicate { setting, .. } => match *setting {
+                pod_deduplication_settings::ADOMAIN => {
+                    lossreason::CREATIVE_FILTERED_ADVERTISER_EXCLUSIONS
+                }
+                pod_deduplication_settings::IAB_CONTENT_TAXONOMY => {
+                    lossreason::CREATIVE_FILTERED_CATEGORY_EXCLUSIONS
+                }
+                _ => lossreason::LOST_TO_HIGHER_BID,
+            },
+            PodExclusion::PodFull
+            | PodExclusion::PodDurationExceeded { .. }
+            | PodExclusion::SlotTaken { .. } => lossreason::LOST_TO_HIGHER_BID,
+        }
+    }
+}
+
+/// A bid placed in the pod
+#[derive(Debug, Clone, PartialEq)]
+pub struct PodAd<'a> {
+    pub bid: &'a Bid,
+    /// 1-based position within the pod
+    pub sequence: u32,
+    /// Length of the ad in seconds
+    pub duration: u32,
+    /// The ad as it is emitted, the bid's VAST ad or a Wrapper around its `nurl`
+    pub ad: Ad,
+}
+
+/// A bid left out of the pod
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExcludedBid<'a> {
+    pub bid: &'a Bid,
+    pub reason: PodExclusion,
+}
+
+/// Outcome of [`PodAssembler::assemble`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pod<'a> {
+    pub pod_id: Option<String>,
+    /// Selected ads in play order
+    pub ads: Vec<PodAd<'a>>,
+    pub excluded: Vec<ExcludedBid<'a>>,
+}
+
+impl Pod<'_> {
+    /// Combined length of the selected ads in seconds
+    pub fn total_duration(&self) -> u32 {
+        self.ads.iter().map(|ad| ad.duration).sum()
+    }
+
+    /// Builds a VAST pod, one `Ad` per selected bid carrying its `sequence`.
+    /// Pods require VAST 3.0 or later.
+    pub fn to_vast(&self, version: &str) -> Vast {
+        let ads = self
+            .ads
+            .iter()
+            .map(|pod_ad| Ad {
+                sequence: Some(pod_ad.sequence),
+                ..pod_ad.ad.clone()
+            })
+            .collect();
+
+        Vast {
+            version: version.to_string(),
+            ads,
+            ..Default::default()
+        }
+    }
+
+    /// Builds a VMAP 1.0 document holding the pod as a single linear ad
+    /// break, with the VAST pod inlined as `VASTAdData`
+    ///
+    /// # Arguments
+    /// * `version` - VAST version of the inlined pod
+    /// * `time_offset` - When the break plays, e.g. `start`, `end`,
+    ///   `00:10:00.000` or `50%`
+    pub fn to_vmap(&self, version: &str, time_offset: &str) -> Result<String> {
+        let mut data = XmlElement::new("vmap:VASTAdData");
+        data.push(self.to_vast(version).to_element());
+
+        let mut source = XmlElement::new("vmap:AdSource");
+        source.set_attr("id", self.pod_id.as_deref().unwrap_or("pod"));
+        source.set_attr("allowMultipleAds", "true");
+        source.set_attr("followRedirects", "true");
+        source.push(data);
+
+        let mut ad_break = XmlElement::new("vmap:AdBreak");
+        ad_break.set_attr("timeOffset", time_offset);
+        ad_break.set_attr("breakType", "linear");
+        if let Some(pod_id) = &self.pod_id {
+            ad_break.set_attr("breakId", pod_id);
+        }
+        ad_break.push(source);
+
+        let mut vmap = XmlElement::new("vmap:VMAP");
+        vmap.set_attr("xmlns:vmap", "http://www.iab.net/videosuite/vmap");
+        vmap.set_attr("version", "1.0");
+        vmap.push(ad_break);
+
+        vmap.to_xml_string()
+    }
+}
+
+/// Fills ad pods from a set of bids.
+///
+/// Bids are considered highest price first. Each is placed if it passes the
+/// per-ad rules, fits the remaining pod duration and ad count, can take the
+/// `slotinpod` it asked for, and shares no deduplication key with a bid
+/// already placed. Every other bid is reported with the reason it was left
+/// out. Ads asking for the first or last slot are played there, the rest in
+/// price order.
+///
+/// # Example
+/// ```
+/// use rtb::bid_response::Bid;
+/// use rtb::bid_response::bid::AdmOneof;
+/// use rtb::openrtb::utils::pod::{PodAssembler, PodRulesBuilder};
+///
+/// let bid = |id: &str, price: f64| Bid {
+///     id: id.to_string(),
+///     price,
+///     dur: 15,
+///     nurl: format!("https://bidder.example.com/vast/{id}"),
+///     ..Default::default()
+/// };
+/// let bids = vec![bid("a", 10.0), bid("b", 12.0), bid("c", 8.0)];
+///
+/// let rules = PodRulesBuilder::default().pod_duration(Some(30)).build().unwrap();
+/// let pod = PodAssembler::new(rules, "Exchange").assemble(&bids);
+///
+/// assert_eq!(pod.ads.len(), 2);
+/// assert_eq!(pod.ads[0].bid.id, "b");
+/// assert_eq!(pod.excluded[0].bid.id, "c");
+/// ```
+#[derive(Debug, Clone)]
+pub struct PodAssembler {
+    rules: PodRules,
+    ad_system: String,
+}
+
+impl PodAssembler {
+    /// Creates an assembler, `ad_system` names us in the `AdSystem` of the
+    /// Wrapper written for bids which only return a `nurl`
+    pub fn new(rules: PodRules, ad_system: &str) -> Self {
+        Self {
+            rules,
+            ad_system: ad_system.to_string(),
+        }
+    }
+
+    pub fn rules(&self) -> &PodRules {
+        &self.rules
+    }
+
+    /// Selects the bids filling the pod
+    pub fn assemble<'a>(&self, bids: impl IntoIterator<Item = &'a Bid>) -> Pod<'a> {
+        let mut candidates: Vec<&Bid> = bids.into_iter().collect();
+        candidates.sort_by(|a, b| b.price.total_cmp(&a.price));
+
+        let dedup = &self.rules.deduplication;
+        let no_dedup = dedup.contains(&pod_deduplication_settings::NO_DEDUP);
+
+        let mut excluded = Vec::new();
+        let mut first = None;
+        let mut last = None;
+        let mut middle = Vec::new();
+        let mut count = 0;
+        let mut total_duration = 0;
+        let mut seen_keys: HashSet<(u32, String)> = HashSet::new();
+
+        for bid in candidates {
+            let mut exclude = |reason| excluded.push(ExcludedBid { bid, reason });
+
+            let (ad, duration) = match self.candidate(bid) {
+                Ok(candidate) => candidate,
+                Err(reason) => {
+                    exclude(reason);
+                    continue;
+                }
+            };
+
+            if self.rules.max_ads.is_some_and(|max| count >= max) {
+                exclude(PodExclusion::PodFull);
+                continue;
+            }
+
+            if let Some(pod_duration) = self.rules.pod_duration {
+                let remaining = pod_duration.saturating_sub(total_duration);
+                if duration > remaining {
+                    exclude(PodExclusion::PodDurationExceeded {
+                        duration,
+                        remaining,
+                    });
+                    continue;
+                }
+            }
+
+            let slot = match bid.slotinpod {
+                slot_position_in_pod::FIRST if first.is_none() => Slot::First,
+                slot_position_in_pod::LAST if last.is_none() => Slot::Last,
+                slot_position_in_pod::FIRST_OR_LAST if first.is_none() => Slot::First,
+                slot_position_in_pod::FIRST_OR_LAST if last.is_none() => Slot::Last,
+                slot_position_in_pod::FIRST
+                | slot_position_in_pod::LAST
+                | slot_position_in_pod::FIRST_OR_LAST => {
+                    exclude(PodExclusion::SlotTaken {
+                        slot: bid.slotinpod,
+                    });
+                    continue;
+                }
+                _ => Slot::Any,
+            };
+
+            let keys = if no_dedup {
+                Vec::new()
+            } else {
+                dedup_keys(bid, &ad, dedup)
+            };
+            if let Some((setting, value)) = keys.iter().find(|key| seen_keys.contains(*key)) {
+                exclude(PodExclusion::Duplicate {
+                    setting: *setting,
+                    value: value.clone(),
+                });
+                continue;
+            }
+            seen_keys.extend(keys);
+
+            count += 1;
+            total_duration += duration;
+
+            let placed = (bid, ad, duration);
+            match slot {
+                Slot::First => first = Some(placed),
+                Slot::Last => last = Some(placed),
+                Slot::Any => middle.push(placed),
+            }
+        }
+
+        let ads = first
+            .into_iter()
+            .chain(middle)
+            .chain(last)
+            .enumerate()
+            .map(|(i, (bid, ad, duration))| PodAd {
+                bid,
+                sequence: i as u32 + 1,
+                duration,
+                ad,
+            })
+            .collect();
+
+        Pod {
+            pod_id: self.rules.pod_id.clone(),
+            ads,
+            excluded,
+        }
+    }
+
+    /// Applies the per-ad rules, returning the ad to emit and its duration
+    fn candidate(&self, bid: &Bid) -> Result<(Ad, u32), PodExclusion> {
+        let ad = match bid.adm_oneof.as_ref() {
+            Some(AdmOneof::Adm(adm)) if !adm.trim().is_empty() => {
+                let vast = Vast::parse(adm).map_err(|e| PodExclusion::InvalidMarkup {
+                    reason: e.to_string(),
+                })?;
+                let mut ad =
+                    vast.ads
+                        .into_iter()
+                        .next()
+                        .ok_or_else(|| PodExclusion::InvalidMarkup {
+                            reason: "VAST contains no Ad".to_string(),
+                        })?;
+                if ad.id.is_none() {
+                    ad.id = Some(bid.id.clone());
+                }
+                ad
+            }
+            Some(AdmOneof::AdmNative(_)) => {
+                return Err(PodExclusion::InvalidMarkup {
+                    reason: "Native markup in a video pod".to_string(),
+                });
+            }
+            _ if !bid.nurl.trim().is_empty() => Ad {
+                id: Some(bid.id.clone()),
+                content: AdContent::Wrapper(Wrapper {
+                    ad_system: Some(AdSystem {
+                        name: self.ad_system.clone(),
+                        version: None,
+                    }),
+                    vast_ad_tag_uri: bid.nurl.trim().to_string(),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            _ => return Err(PodExclusion::MissingMarkup),
+        };
+
+        let duration = if bid.dur > 0 {
+            bid.dur as u32
+        } else {
+            ad.creatives()
+                .iter()
+                .filter_map(|c| c.linear.as_ref())
+                .find_map(|linear| linear.duration)
+                .map(|d| d.as_secs_f64().round() as u32)
+                .filter(|&d| d > 0)
+                .ok_or(PodExclusion::MissingDuration)?
+        };
+
+        if !self.rules.duration_allowed(duration) {
+            return Err(PodExclusion::DurationNotAllowed { duration });
+        }
+
+        if let Some(min) = self.rules.min_cpm_per_second {
+            let cpm_per_second = bid.price / duration as f64;
+            if cpm_per_second < min {
+                return Err(PodExclusion::BelowMinCpmPerSecond { cpm_per_second });
+            }
+        }
+
+        Ok((ad, duration))
+    }
+}
+
+enum Slot {
+    First,
+    Last,
+    Any,
+}
+
+/// Values a bid is deduplicated on, as `(setting, value)` pairs
+fn dedup_keys(bid: &Bid, ad: &Ad, settings: &[u32]) -> Vec<(u32, String)> {
+    let mut keys = Vec::new();
+
+    for &setting in settings {
+        match setting {
+            pod_deduplication_settings::ADOMAIN => keys.extend(
+                bid.adomain
+                    .iter()
+                    .map(|d| (setting, d.trim().to_ascii_lowercase())),
+            ),
+            pod_deduplication_settings::IAB_CONTENT_TAXONOMY => {
+                keys.extend(bid.cat.iter().map(|c| (setting, c.trim().to_string())))
+            }
+            pod_deduplication_settings::CREATIVE_ID if !bid.crid.is_empty() => {
+                keys.push((setting, bid.crid.clone()))
+            }
+            pod_deduplication_settings::MEDIAFILE_URL => keys.extend(
+                ad.creatives()
+                    .iter()
+                    .filter_map(|c| c.linear.as_ref())
+                    .flat_map(|linear| &linear.media_files)
+                    .map(|file| (setting, file.url.clone())),
+            ),
+            _ => {}
+        }
+    }
+
+    keys.retain(|(_, value)| !value.is_empty());
+    keys
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn bid(id: &str, price: f64, dur: i32) -> Bid {
+        Bid {
+            id: id.to_string(),
+            impid: "1".to_string(),
+            price,
+            dur,
+            crid: format!("crid-{id}"),
+            nurl: format!("https://bidder.example.com/vast?id={id}"),
+            ..Default::default()
+        }
+    }
+
+    fn vast_bid(id: &str, price: f64, duration: &str, media: &str) -> Bid {
+        let adm = format!(
+            r#"<VAST version="4.0"><Ad><InLine><AdSystem>Bidder</AdSystem><AdTitle>{id}</AdTitle>
+            <Creatives><Creative><Linear><Duration>{duration}</Duration><MediaFiles>
+            <MediaFile delivery="progressive" type="video/mp4"><![CDATA[{media}]]></MediaFile>
+            </MediaFiles></Linear></Creative></Creatives></InLine></Ad></VAST>"#
+        );
+
+        Bid {
+            adm_oneof: Some(AdmOneof::Adm(adm)),
+            nurl: String::new(),
+            ..bid(id, price, 0)
+        }
+    }
+
+    fn assemble<'a>(rules: PodRules, bids: &'a [Bid]) -> Pod<'a> {
+        PodAssembler::new(rules, "Exchange").assemble(bids)
+    }
+
+    fn ids<'a>(pod: &'a Pod) -> Vec<&'a str> {
+        pod.ads.iter().map(|ad| ad.bid.id.as_str()).collect()
+    }
+
+    fn reason<'a>(pod: &'a Pod, id: &str) -> &'a PodExclusion {
+        &pod.excluded.iter().find(|e| e.bid.id == id).unwrap().reason
+    }
+
+    #[test]
+    fn test_fills_pod_duration_by_price() {
+        let bids = vec![
+            bid("a", 10.0, 15),
+            bid("b", 20.0, 30),
+            bid("c", 15.0, 30),
+            bid("d", 5.0, 15),
+        ];
+        let rules = PodRulesBuilder::default()
+            .pod_duration(Some(60))
+            .build()
+            .unwrap();
+
+        let pod = assemble(rules, &bids);
+
+        assert_eq!(ids(&pod), vec!["b", "c"]);
+        assert_eq!(pod.total_duration(), 60);
+        assert_eq!(
+            reason(&pod, "a"),
+            &PodExclusion::PodDurationExceeded {
+                duration: 15,
+                remaining: 0
+            }
+        );
+        assert_eq!(
+            reason(&pod, "a").loss_reason(),
+            lossreason::LOST_TO_HIGHER_BID
+        );
+    }
+
+    #[test]
+    fn test_max_ads_and_per_ad_rules() {
+        let bids = vec![
+            bid("a", 10.0, 15),
+            bid("b", 9.0, 20),
+            bid("c", 8.0, 15),
+            bid("d", 7.0, 15),
+            bid("e", 1.0, 15),
+        ];
+        let rules = PodRulesBuilder::default()
+            .max_ads(Some(2))
+            .required_durations(vec![15, 30])
+            .min_cpm_per_second(Some(0.2))
+            .build()
+            .unwrap();
+
+        let pod = assemble(rules, &bids);
+
+        assert_eq!(ids(&pod), vec!["a", "c"]);
+        assert_eq!(
+            reason(&pod, "b"),
+            &PodExclusion::DurationNotAllowed { duration: 20 }
+        );
+        assert_eq!(reason(&pod, "d"), &PodExclusion::PodFull);
+        assert!(matches!(
+            reason(&pod, "e"),
+            PodExclusion::BelowMinCpmPerSecond { .. }
+        ));
+    }
+
+    #[test]
+    fn test_slot_in_pod() {
+        let mut first = bid("first", 5.0, 15);
+        first.slotinpod = slot_position_in_pod::FIRST;
+        let mut first_too = bid("first_too", 4.0, 15);
+        first_too.slotinpod = slot_position_in_pod::FIRST;
+        let mut either = bid("either", 3.0, 15);
+        either.slotinpod = slot_position_in_pod::FIRST_OR_LAST;
+        let bids = vec![bid("any", 1.0, 15), either, first_too, first];
+
+        let pod = assemble(PodRules::default(), &bids);
+
+        assert_eq!(ids(&pod), vec!["first", "any", "either"]);
+        assert_eq!(
+            pod.ads.iter().map(|ad| ad.sequence).collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+        assert_eq!(
+            reason(&pod, "first_too"),
+            &PodExclusion::SlotTaken {
+                slot: slot_position_in_pod::FIRST
+            }
+        );
+    }
+
+    #[test]
+    fn test_deduplication() {
+        let mut a = bid("a", 10.0, 15);
+        a.adomain = vec!["Brand.com".to_string()];
+        a.cat = vec!["IAB1".to_string()];
+        let mut b = bid("b", 9.0, 15);
+        b.adomain = vec!["brand.com".to_string()];
+        let mut c = bid("c", 8.0, 15);
+        c.cat = vec!["IAB1".to_string()];
+        let mut d = bid("d", 7.0, 15);
+        d.crid = a.crid.clone();
+        let bids = vec![a, b, c, d];
+
+        let rules = PodRulesBuilder::default()
+            .deduplication(vec![
+                pod_deduplication_settings::ADOMAIN,
+                pod_deduplication_settings::IAB_CONTENT_TAXONOMY,
+                pod_deduplication_settings::CREATIVE_ID,
+            ])
+            .build()
+            .unwrap();
+        let pod = assemble(rules.clone(), &bids);
+
+        assert_eq!(ids(&pod), vec!["a"]);
+        assert_eq!(
+            reason(&pod, "b"),
+            &PodExclusion::Duplicate {
+                setting: pod_deduplication_settings::ADOMAIN,
+                value: "brand.com".to_string()
+            }
+        );
+        assert_eq!(
+            reason(&pod, "c").loss_reason(),
+            lossreason::CREATIVE_FILTERED_CATEGORY_EXCLUSIONS
+        );
+        assert!(matches!(
+            reason(&pod, "d"),
+            PodExclusion::Duplicate {
+                setting: pod_deduplication_settings::CREATIVE_ID,
+                ..
+            }
+        ));
+
+        let rules = PodRules {
+            deduplication: vec![
+                pod_deduplication_settings::ADOMAIN,
+                pod_deduplication_settings::NO_DEDUP,
+            ],
+            ..rules
+        };
+        assert_eq!(assemble(rules, &bids).ads.len(), 4);
+    }
+
+    #[test]
+    fn test_duration_and_markup_from_vast() {
+        let bids = vec![
+            vast_bid("a", 10.0, "00:00:15", "https://cdn.example.com/a.mp4"),
+            vast_bid("b", 9.0, "00:00:15", "https://cdn.example.com/a.mp4"),
+            vast_bid("c", 8.0, "00:00:30.2", "https://cdn.example.com/c.mp4"),
+            Bid {
+                adm_oneof: Some(AdmOneof::Adm("<div>banner</div>".to_string())),
+                ..bid("d", 7.0, 15)
+            },
+            Bid {
+                nurl: String::new(),
+                ..bid("e", 6.0, 15)
+            },
+        ];
+        let rules = PodRulesBuilder::default()
+            .deduplication(vec![pod_deduplication_settings::MEDIAFILE_URL])
+            .build()
+            .unwrap();
+
+        let pod = assemble(rules, &bids);
+
+        assert_eq!(ids(&pod), vec!["a", "c"]);
+        assert_eq!(pod.ads[1].duration, 30);
+        assert!(matches!(reason(&pod, "b"), PodExclusion::Duplicate { .. }));
+        assert!(matches!(
+            reason(&pod, "d"),
+            PodExclusion::InvalidMarkup { .. }
+        ));
+        assert_eq!(reason(&pod, "e"), &PodExclusion::MissingMarkup);
+        assert_eq!(reason(&pod, "e").loss_reason(), lossreason::MISSING_MARKUP);
+    }
+
+    #[test]
+    fn test_vast_pod_output() {
+        let bids = vec![
+            vast_bid("a", 10.0, "00:00:15", "https://cdn.example.com/a.mp4"),
+            bid("b", 9.0, 30),
+        ];
+        let pod = assemble(PodRules::default(), &bids);
+
+        let xml = pod.to_vast("4.2").to_xml_string().unwrap();
+        let vast = Vast::parse(&xml).unwrap();
+
+        assert_eq!(vast.ads.len(), 2);
+        assert_eq!(vast.ads[0].sequence, Some(1));
+        assert_eq!(vast.ads[0].id.as_deref(), Some("a"));
+        assert_eq!(
+            vast.linears().next().unwrap().duration,
+            Some(Duration::from_secs(15))
+        );
+
+        assert_eq!(vast.ads[1].sequence, Some(2));
+        let wrapper = vast.ads[1].wrapper().unwrap();
+        assert_eq!(
+            wrapper.vast_ad_tag_uri,
+            "https://bidder.example.com/vast?id=b"
+        );
+        assert_eq!(wrapper.ad_system.as_ref().unwrap().name, "Exchange");
+    }
+
+    #[test]
+    fn test_vmap_output() {
+        let bids = vec![bid("a", 10.0, 15), bid("b", 9.0, 15)];
+        let rules = PodRulesBuilder::default()
+            .pod_id(Some("mid-1".to_string()))
+            .build()
+            .unwrap();
+        let pod = assemble(rules, &bids);
+
+        let xml = pod.to_vmap("4.0", "00:10:00.000").unwrap();
+        let vmap = XmlElement::parse(&xml).unwrap();
+
+        assert_eq!(vmap.name, "vmap:VMAP");
+        let ad_break = vmap.child("vmap:AdBreak").unwrap();
+        assert_eq!(ad_break.attr("timeOffset"), Some("00:10:00.000"));
+        assert_eq!(ad_break.attr("breakId"), Some("mid-1"));
+
+        let data = ad_break
+            .child("vmap:AdSource")
+            .unwrap()
+            .child("vmap:VASTAdData")
+            .unwrap();
+        let vast = Vast::from_element(data.child("VAST").unwrap().clone()).unwrap();
+        assert_eq!(vast.ads.len(), 2);
+        assert_eq!(vast.ads[1].sequence, Some(2));
+    }
+}
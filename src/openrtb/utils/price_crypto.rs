@@ -0,0 +1,380 @@
+//! Encryption of winning prices substituted for `${AUCTION_PRICE}`.
+//!
+//! [`HmacPriceCrypter`] implements the HMAC-SHA1 scheme most exchanges use
+//! for clearing prices: a 16 byte initialization vector, the price XORed
+//! with a pad derived from it, and a 4 byte integrity signature, encoded as
+//! web-safe base64. [`AesGcmPriceCrypter`] is a simpler AES-256-GCM variant
+//! for beacons that only we decrypt.
+//!
+//! Prices are carried as micros of the currency unit (CPM), e.g. `1.5` is
+//! `1_500_000`.
+
+use aes_gcm::Aes256Gcm;
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, Nonce, OsRng};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+
+type HmacSha1 = Hmac<Sha1>;
+
+const MICROS: f64 = 1_000_000.0;
+
+/// Why an encrypted price could not be decrypted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PriceCryptoError {
+    /// Not valid base64
+    InvalidEncoding,
+    /// The decoded price does not have the size of the scheme
+    InvalidLength { expected: usize, actual: usize },
+    /// The signature does not match, the price was tampered with or
+    /// encrypted with other keys
+    IntegrityCheckFailed,
+    /// A key is not valid for the scheme, e.g. not 32 bytes for AES-256
+    InvalidKey,
+}
+
+impl std::fmt::Display for PriceCryptoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PriceCryptoError::InvalidEncoding => write!(f, "encrypted price is not base64"),
+            PriceCryptoError::InvalidLength { expected, actual } => {
+                write!(f, "encrypted price is {actual} bytes, expected {expected}")
+            }
+            PriceCryptoError::IntegrityCheckFailed => {
+                write!(f, "encrypted price failed the integrity check")
+            }
+            PriceCryptoError::InvalidKey => write!(f, "invalid price encryption key"),
+        }
+    }
+}
+
+impl std::error::Error for PriceCryptoError {}
+
+/// A scheme to encrypt winning prices
+pub trait PriceCrypter: Send + Sync {
+    /// Encrypts a price in micros, returning URL-safe text
+    fn encrypt_micros(&self, micros: u64) -> String;
+
+    /// Decrypts and authenticates a price made by
+    /// [`PriceCrypter::encrypt_micros`]
+    fn decrypt_micros(&self, encrypted: &str) -> Result<u64, PriceCryptoError>;
+
+    /// Encrypts a price, rounded to micros
+    fn encrypt_price(&self, price: f64) -> String {
+        self.encrypt_micros(price_to_micros(price))
+    }
+
+    fn decrypt_price(&self, encrypted: &str) -> Result<f64, PriceCryptoError> {
+        Ok(self.decrypt_micros(encrypted)? as f64 / MICROS)
+    }
+}
+
+/// Converts a price to micros, negative prices become zero
+pub fn price_to_micros(price: f64) -> u64 {
+    (price * MICROS).round() as u64
+}
+
+/// The HMAC-SHA1 winning price encryption used by most exchanges.
+///
+/// # Example
+/// ```
+/// use rtb::openrtb::utils::price_crypto::{HmacPriceCrypter, PriceCrypter};
+///
+/// let crypter = HmacPriceCrypter::from_base64(
+///     "skU7Ax_NL5pPAFyKdkfZjZz2-VhIN8bjj1rVFOaJ_5o=",
+///     "arO23ykdNqUQ5LEoQ0FVmPkBd7xB5CO89PDZlSjpFxo=",
+/// )
+/// .unwrap();
+///
+/// let micros = crypter.decrypt_micros("YWJjMTIzZGVmNDU2Z2hpN7fhCuPemCce_6msaw").unwrap();
+/// assert_eq!(micros, 100);
+///
+/// let encrypted = crypter.encrypt_price(1.5);
+/// assert_eq!(crypter.decrypt_price(&encrypted).unwrap(), 1.5);
+/// ```
+#[derive(Clone)]
+pub struct HmacPriceCrypter {
+    encryption_key: Vec<u8>,
+    integrity_key: Vec<u8>,
+}
+
+impl HmacPriceCrypter {
+    const IV_LEN: usize = 16;
+    const PRICE_LEN: usize = 8;
+    const SIGNATURE_LEN: usize = 4;
+    const LEN: usize = Self::IV_LEN + Self::PRICE_LEN + Self::SIGNATURE_LEN;
+
+    pub fn new(encryption_key: impl Into<Vec<u8>>, integrity_key: impl Into<Vec<u8>>) -> Self {
+        Self {
+            encryption_key: encryption_key.into(),
+            integrity_key: integrity_key.into(),
+        }
+    }
+
+    /// Creates a crypter from keys in web-safe base64, the form exchanges
+    /// hand them out in
+    pub fn from_base64(
+        encryption_key: &str,
+        integrity_key: &str,
+    ) -> Result<Self, PriceCryptoError> {
+        Ok(Self::new(
+            decode_web_safe(encryption_key)?,
+            decode_web_safe(integrity_key)?,
+        ))
+    }
+
+    /// Encrypts with a chosen initialization vector, which must not repeat.
+    /// [`PriceCrypter::encrypt_micros`] uses a random one.
+    pub fn encrypt_with_iv(&self, micros: u64, iv: [u8; 16]) -> String {
+        let price = micros.to_be_bytes();
+        let pad = self.pad(&iv);
+
+        let mut out = Vec::with_capacity(Self::LEN);
+        out.extend_from_slice(&iv);
+        out.extend(price.iter().zip(pad).map(|(p, k)| p ^ k));
+        out.extend_from_slice(&self.signature(&price, &iv)[..Self::SIGNATURE_LEN]);
+
+        URL_SAFE_NO_PAD.encode(out)
+    }
+
+    fn pad(&self, iv: &[u8]) -> Vec<u8> {
+        let mut mac = <HmacSha1 as Mac>::new_from_slice(&self.encryption_key)
+            .expect("HMAC accepts keys of any length");
+        mac.update(iv);
+        mac.finalize().into_bytes().to_vec()
+    }
+
+    fn signature(&self, price: &[u8], iv: &[u8]) -> Vec<u8> {
+        self.integrity_mac(price, iv)
+            .finalize()
+            .into_bytes()
+            .to_vec()
+    }
+
+    fn integrity_mac(&self, price: &[u8], iv: &[u8]) -> HmacSha1 {
+        let mut mac = <HmacSha1 as Mac>::new_from_slice(&self.integrity_key)
+            .expect("HMAC accepts keys of any length");
+        mac.update(price);
+        mac.update(iv);
+        mac
+    }
+}
+
+impl PriceCrypter for HmacPriceCrypter {
+    fn encrypt_micros(&self, micros: u64) -> String {
+        let mut iv = [0u8; 16];
+        OsRng.fill_bytes(&mut iv);
+        self.encrypt_with_iv(micros, iv)
+    }
+
+    fn decrypt_micros(&self, encrypted: &str) -> Result<u64, PriceCryptoError> {
+        let bytes = decode_web_safe(encrypted)?;
+        if bytes.len() != Self::LEN {
+            return Err(PriceCryptoError::InvalidLength {
+                expected: Self::LEN,
+                actual: bytes.len(),
+            });
+        }
+
+        let (iv, rest) = bytes.split_at(Self::IV_LEN);
+        let (cipher, signature) = rest.split_at(Self::PRICE_LEN);
+
+        let mut price = [0u8; 8];
+        for ((p, c), k) in price.iter_mut().zip(cipher).zip(self.pad(iv)) {
+            *p = c ^ k;
+        }
+
+        self.integrity_mac(&price, iv)
+            .verify_truncated_left(signature)
+            .map_err(|_| PriceCryptoError::IntegrityCheckFailed)?;
+
+        Ok(u64::from_be_bytes(price))
+    }
+}
+
+impl std::fmt::Debug for HmacPriceCrypter {
+    // never print the keys
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HmacPriceCrypter").finish_non_exhaustive()
+    }
+}
+
+/// AES-256-GCM price encryption for our own beacons: a 12 byte nonce
+/// followed by the encrypted price and its 16 byte tag, as base64url.
+#[derive(Clone)]
+pub struct AesGcmPriceCrypter {
+    cipher: Aes256Gcm,
+}
+
+impl AesGcmPriceCrypter {
+    const NONCE_LEN: usize = 12;
+    const LEN: usize = Self::NONCE_LEN + 8 + 16;
+
+    /// Creates a crypter from a 32 byte key
+    pub fn new(key: &[u8]) -> Result<Self, PriceCryptoError> {
+        let cipher = <Aes256Gcm as KeyInit>::new_from_slice(key)
+            .map_err(|_| PriceCryptoError::InvalidKey)?;
+        Ok(Self { cipher })
+    }
+}
+
+impl PriceCrypter for AesGcmPriceCrypter {
+    fn encrypt_micros(&self, micros: u64) -> String {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let cipher = self
+            .cipher
+            .encrypt(&nonce, micros.to_be_bytes().as_ref())
+            .expect("encrypting 8 bytes cannot fail");
+
+        let mut out = nonce.to_vec();
+        out.extend(cipher);
+        URL_SAFE_NO_PAD.encode(out)
+    }
+
+    fn decrypt_micros(&self, encrypted: &str) -> Result<u64, PriceCryptoError> {
+        let bytes = decode_web_safe(encrypted)?;
+        if bytes.len() != Self::LEN {
+            return Err(PriceCryptoError::InvalidLength {
+                expected: Self::LEN,
+                actual: bytes.len(),
+            });
+        }
+
+        let (nonce, cipher) = bytes.split_at(Self::NONCE_LEN);
+        let price = self
+            .cipher
+            .decrypt(Nonce::<Aes256Gcm>::from_slice(nonce), cipher)
+            .map_err(|_| PriceCryptoError::IntegrityCheckFailed)?;
+
+        let price: [u8; 8] = price
+            .try_into()
+            .map_err(|_| PriceCryptoError::IntegrityCheckFailed)?;
+        Ok(u64::from_be_bytes(price))
+    }
+}
+
+impl std::fmt::Debug for AesGcmPriceCrypter {
+    // never print the key
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AesGcmPriceCrypter").finish_non_exhaustive()
+    }
+}
+
+/// Decodes web-safe base64, tolerating padding and the standard alphabet
+fn decode_web_safe(s: &str) -> Result<Vec<u8>, PriceCryptoError> {
+    let normalized: String = s
+        .trim()
+        .trim_end_matches('=')
+        .chars()
+        .map(|c| match c {
+            '+' => '-',
+            '/' => '_',
+            c => c,
+        })
+        .collect();
+
+    URL_SAFE_NO_PAD
+        .decode(normalized)
+        .map_err(|_| PriceCryptoError::InvalidEncoding)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENCRYPTION_KEY: &str = "skU7Ax_NL5pPAFyKdkfZjZz2-VhIN8bjj1rVFOaJ_5o=";
+    const INTEGRITY_KEY: &str = "arO23ykdNqUQ5LEoQ0FVmPkBd7xB5CO89PDZlSjpFxo=";
+
+    fn hmac_crypter() -> HmacPriceCrypter {
+        HmacPriceCrypter::from_base64(ENCRYPTION_KEY, INTEGRITY_KEY).unwrap()
+    }
+
+    #[test]
+    fn test_hmac_reference_vectors() {
+        let crypter = hmac_crypter();
+        let vectors = [
+            ("YWJjMTIzZGVmNDU2Z2hpN7fhCuPemCce_6msaw", 100),
+            ("YWJjMTIzZGVmNDU2Z2hpN7fhCuPemCAWJRxOgA", 1900),
+            ("YWJjMTIzZGVmNDU2Z2hpN7fhCuPemC32prpWWw", 2700),
+        ];
+
+        for (encrypted, micros) in vectors {
+            assert_eq!(crypter.decrypt_micros(encrypted), Ok(micros));
+            assert_eq!(
+                crypter.encrypt_with_iv(micros, *b"abc123def456ghi7"),
+                encrypted
+            );
+        }
+    }
+
+    #[test]
+    fn test_hmac_round_trip() {
+        let crypter = hmac_crypter();
+        let a = crypter.encrypt_price(2.345);
+        let b = crypter.encrypt_price(2.345);
+
+        assert_ne!(a, b, "each price gets a fresh IV");
+        assert_eq!(crypter.decrypt_price(&a), Ok(2.345));
+        assert_eq!(crypter.decrypt_price(&format!("{a}==")), Ok(2.345));
+    }
+
+    #[test]
+    fn test_hmac_rejects_tampering() {
+        let crypter = hmac_crypter();
+        let encrypted = crypter.encrypt_with_iv(1_000_000, *b"abc123def456ghi7");
+
+        let mut bytes = URL_SAFE_NO_PAD.decode(&encrypted).unwrap();
+        bytes[20] ^= 1;
+        assert_eq!(
+            crypter.decrypt_micros(&URL_SAFE_NO_PAD.encode(bytes)),
+            Err(PriceCryptoError::IntegrityCheckFailed)
+        );
+
+        let other = HmacPriceCrypter::new(b"other".to_vec(), b"keys".to_vec());
+        assert_eq!(
+            other.decrypt_micros(&encrypted),
+            Err(PriceCryptoError::IntegrityCheckFailed)
+        );
+
+        assert_eq!(
+            crypter.decrypt_micros("YWJj"),
+            Err(PriceCryptoError::InvalidLength {
+                expected: 28,
+                actual: 3
+            })
+        );
+        assert_eq!(
+            crypter.decrypt_micros("not base64!"),
+            Err(PriceCryptoError::InvalidEncoding)
+        );
+    }
+
+    #[test]
+    fn test_aes_gcm_round_trip() {
+        let crypter = AesGcmPriceCrypter::new(&[7u8; 32]).unwrap();
+        let encrypted = crypter.encrypt_price(0.42);
+
+        assert_eq!(crypter.decrypt_price(&encrypted), Ok(0.42));
+        assert!(!encrypted.contains(['+', '/', '=']));
+
+        let other = AesGcmPriceCrypter::new(&[8u8; 32]).unwrap();
+        assert_eq!(
+            other.decrypt_micros(&encrypted),
+            Err(PriceCryptoError::IntegrityCheckFailed)
+        );
+
+        assert_eq!(
+            AesGcmPriceCrypter::new(&[7u8; 16]).unwrap_err(),
+            PriceCryptoError::InvalidKey
+        );
+    }
+
+    #[test]
+    fn test_price_to_micros() {
+        assert_eq!(price_to_micros(1.5), 1_500_000);
+        assert_eq!(price_to_micros(0.1 + 0.2), 300_000);
+        assert_eq!(price_to_micros(-1.0), 0);
+    }
+}
@@ -0,0 +1,541 @@
+//! HTTPS compliance of creatives served into secure impressions.
+//!
+//! When `imp.secure` is set every resource the creative loads must use
+//! https, or the browser blocks it as mixed content. [`SecureScanner`] finds
+//! the plain http URLs of banner HTML, VAST and native markup, and rewrites
+//! those on hosts known to serve the same content over https.
+
+use super::native::{native_response_to_string, parse_native_response_wrapped};
+use super::sniff::{attribute_name, in_namespace_attr, root_element};
+use crate::bid_request::Imp;
+use crate::bid_response::Bid;
+use crate::bid_response::bid::AdmOneof;
+use crate::openrtb::spec::lossreason;
+use crate::{NativeResponse, native_response};
+use derive_builder::Builder;
+use quick_xml::Reader;
+use quick_xml::events::Event;
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+/// A plain http URL found in a creative
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct InsecureUrl {
+    pub url: String,
+    /// Where the URL was found, e.g. `<img src>`, `MediaFile` or
+    /// `assets[id=2].img.url`
+    pub location: String,
+    /// The host is one of [`SecureScanner::rewrite_domains`]
+    pub rewritable: bool,
+}
+
+/// A creative which loads resources over plain http that could not be
+/// rewritten
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NotSecure {
+    pub urls: Vec<InsecureUrl>,
+}
+
+impl NotSecure {
+    /// Loss reason to report to the bidder, see
+    /// [`crate::openrtb::spec::lossreason`]
+    pub fn loss_reason(&self) -> u32 {
+        lossreason::CREATIVE_FILTERED_NOT_SECURE
+    }
+}
+
+impl std::fmt::Display for NotSecure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "creative loads {} insecure URL(s)", self.urls.len())?;
+        if let Some(first) = self.urls.first() {
+            write!(f, ", e.g. {} in {}", first.url, first.location)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for NotSecure {}
+
+/// Scans creatives for plain http resources.
+///
+/// Only URLs the creative loads count: click-through destinations such as
+/// `<a href>`, VAST `ClickThrough` and the native `link.url` are navigations
+/// and may stay on http.
+///
+/// # Example
+/// ```
+/// use rtb::openrtb::utils::secure::SecureScannerBuilder;
+///
+/// let scanner = SecureScannerBuilder::default()
+///     .rewrite_domains(vec!["cdn.example.com".to_string()])
+///     .build()
+///     .unwrap();
+///
+/// let adm = r#"<img src="http://cdn.example.com/ad.png">"#;
+/// let secured = scanner.secure_markup(adm).unwrap();
+/// assert_eq!(secured, r#"<img src="https://cdn.example.com/ad.png">"#);
+///
+/// let err = scanner
+///     .secure_markup(r#"<img src="http://other.example.com/ad.png">"#)
+///     .unwrap_err();
+/// assert_eq!(err.urls[0].location, "<img src>");
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default, Builder)]
+pub struct SecureScanner {
+    /// Hosts known to serve the same content over https. A URL on one of
+    /// these hosts or their subdomains is rewritten instead of rejected.
+    #[builder(default)]
+    pub rewrite_domains: Vec<String>,
+}
+
+/// A plain http URL at byte offset `start` of the scanned text
+struct Finding {
+    start: usize,
+    url: String,
+    location: String,
+}
+
+impl SecureScanner {
+    /// Insecure URLs of textual markup: HTML, VAST, DAAST or native JSON
+    pub fn scan_markup(&self, adm: &str) -> Vec<InsecureUrl> {
+        self.secure_text(&mut adm.to_string())
+    }
+
+    /// Insecure URLs of the bid markup, whether textual or a native message
+    pub fn scan_bid(&self, bid: &Bid) -> Vec<InsecureUrl> {
+        match bid.adm_oneof.as_ref() {
+            Some(AdmOneof::Adm(adm)) => self.scan_markup(adm),
+            Some(AdmOneof::AdmNative(native)) => self.secure_native(&mut native.clone()),
+            None => Vec::new(),
+        }
+    }
+
+    /// Returns the markup with rewritable URLs switched to https.
+    ///
+    /// # Errors
+    /// Returns [`NotSecure`] with the URLs that could not be rewritten.
+    pub fn secure_markup(&self, adm: &str) -> Result<String, NotSecure> {
+        let mut secured = adm.to_string();
+        check(self.secure_text(&mut secured))?;
+        Ok(secured)
+    }
+
+    /// Switches the rewritable URLs of the bid markup to https, returning how
+    /// many were rewritten. The bid is left untouched on error.
+    ///
+    /// # Errors
+    /// Returns [`NotSecure`] with the URLs that could not be rewritten.
+    pub fn secure_bid(&self, bid: &mut Bid) -> Result<usize, NotSecure> {
+        match bid.adm_oneof.as_mut() {
+            Some(AdmOneof::Adm(adm)) => {
+                let mut secured = adm.clone();
+                let rewritten = check(self.secure_text(&mut secured))?;
+                *adm = secured;
+                Ok(rewritten)
+            }
+            Some(AdmOneof::AdmNative(native)) => {
+                let mut secured = native.clone();
+                let rewritten = check(self.secure_native(&mut secured))?;
+                *native = secured;
+                Ok(rewritten)
+            }
+            None => Ok(0),
+        }
+    }
+
+    /// Like [`SecureScanner::secure_bid`], but only when the impression
+    /// requires secure creatives
+    pub fn enforce(&self, imp: &Imp, bid: &mut Bid) -> Result<usize, NotSecure> {
+        if !imp.secure {
+            return Ok(0);
+        }
+        self.secure_bid(bid)
+    }
+
+    fn secure_text(&self, markup: &mut String) -> Vec<InsecureUrl> {
+        let is_json = markup
+            .trim_start_matches('\u{feff}')
+            .trim_start()
+            .starts_with('{');
+        let native = if is_json {
+            parse_native_response_wrapped(markup).ok()
+        } else {
+            None
+        };
+
+        if let Some((mut native, wrapped)) = native {
+            let mut insecure = self.secure_native(&mut native);
+            if insecure.iter().any(|u| u.rewritable) {
+                match native_response_to_string(&native, wrapped) {
+                    Ok(secured) => *markup = secured,
+                    Err(_) => insecure.iter_mut().for_each(|u| u.rewritable = false),
+                }
+            }
+            return insecure;
+        }
+
+        let mut findings = Vec::new();
+        let is_xml_ad = root_element(markup).is_some_and(|(name, wrapped)| {
+            !wrapped && (name.eq_ignore_ascii_case("vast") || name.eq_ignore_ascii_case("daast"))
+        });
+        if !is_xml_ad || find_vast(markup, &mut findings).is_err() {
+            // anything unrecognised is scanned in full, mostly HTML
+            findings.clear();
+            find_html(markup, 0..markup.len(), &mut findings);
+        }
+
+        self.apply(markup, findings)
+    }
+
+    fn secure_native(&self, response: &mut NativeResponse) -> Vec<InsecureUrl> {
+        use native_response::asset::AssetOneof;
+
+        let mut insecure = Vec::new();
+
+        for asset in response.assets.iter_mut() {
+            let at = format!("assets[id={}]", asset.id);
+            match asset.asset_oneof.as_mut() {
+                Some(AssetOneof::Img(img)) => {
+                    insecure.extend(self.secure_url(&mut img.url, &format!("{at}.img.url")));
+                }
+                Some(AssetOneof::Video(video)) => {
+                    let location = format!("{at}.video.vasttag");
+                    insecure.extend(self.secure_text(&mut video.vasttag).into_iter().map(|u| {
+                        InsecureUrl {
+                            location: format!("{location} {}", u.location),
+                            ..u
+                        }
+                    }));
+                }
+                _ => {}
+            }
+
+            if let Some(link) = asset.link.as_mut() {
+                for (i, url) in link.clicktrackers.iter_mut().enumerate() {
+                    insecure.extend(self.secure_url(url, &format!("{at}.link.clicktrackers[{i}]")));
+                }
+            }
+        }
+
+        if let Some(link) = response.link.as_mut() {
+            for (i, url) in link.clicktrackers.iter_mut().enumerate() {
+                insecure.extend(self.secure_url(url, &format!("link.clicktrackers[{i}]")));
+            }
+        }
+
+        for (i, url) in response.imptrackers.iter_mut().enumerate() {
+            insecure.extend(self.secure_url(url, &format!("imptrackers[{i}]")));
+        }
+
+        for (i, tracker) in response.eventtrackers.iter_mut().enumerate() {
+            insecure.extend(self.secure_url(&mut tracker.url, &format!("eventtrackers[{i}].url")));
+        }
+
+        let mut findings = Vec::new();
+        find_html(
+            &response.jstracker,
+            0..response.jstracker.len(),
+            &mut findings,
+        );
+        insecure.extend(
+            self.apply(&mut response.jstracker, findings)
+                .into_iter()
+                .map(|u| InsecureUrl {
+                    location: format!("jstracker {}", u.location),
+                    ..u
+                }),
+        );
+
+        insecure
+    }
+
+    fn secure_url(&self, url: &mut String, location: &str) -> Vec<InsecureUrl> {
+        let mut findings = Vec::new();
+        find_text(url, 0..url.len(), location, &mut findings);
+        self.apply(url, findings)
+    }
+
+    /// Rewrites the findings on known hosts to https, `findings` must be in
+    /// order of their offset
+    fn apply(&self, text: &mut String, findings: Vec<Finding>) -> Vec<InsecureUrl> {
+        let mut secured = String::new();
+        let mut copied = 0;
+
+        let insecure = findings
+            .into_iter()
+            .map(|finding| {
+                let rewritable = self.is_rewritable(&finding.url);
+                if rewritable {
+                    secured.push_str(&text[copied..finding.start]);
+                    secured.push_str("https");
+                    copied = finding.start + "http".len();
+                }
+                InsecureUrl {
+                    url: finding.url,
+                    location: finding.location,
+                    rewritable,
+                }
+            })
+            .collect();
+
+        if copied > 0 {
+            secured.push_str(&text[copied..]);
+            *text = secured;
+        }
+        insecure
+    }
+
+    fn is_rewritable(&self, url: &str) -> bool {
+        let host = url["http://".len()..]
+            .split(['/', '?', '#', ':'])
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+
+        self.rewrite_domains.iter().any(|domain| {
+            let domain = domain.trim().trim_start_matches('.').to_ascii_lowercase();
+            !domain.is_empty()
+                && (host == domain
+                    || host
+                        .strip_suffix(domain.as_str())
+                        .is_some_and(|sub| sub.ends_with('.')))
+        })
+    }
+}
+
+/// Rewritten count if every insecure URL could be rewritten
+fn check(insecure: Vec<InsecureUrl>) -> Result<usize, NotSecure> {
+    let rewritten = insecure.iter().filter(|u| u.rewritable).count();
+    let urls: Vec<_> = insecure.into_iter().filter(|u| !u.rewritable).collect();
+
+    if urls.is_empty() {
+        Ok(rewritten)
+    } else {
+        Err(NotSecure { urls })
+    }
+}
+
+/// Collects the URLs of VAST text and CDATA nodes. `HTMLResource` content
+/// is scanned as HTML and click-through destinations are skipped.
+fn find_vast(doc: &str, findings: &mut Vec<Finding>) -> quick_xml::Result<()> {
+    let mut reader = Reader::from_str(doc);
+    let mut path: Vec<String> = Vec::new();
+
+    loop {
+        let start = reader.buffer_position() as usize;
+        match reader.read_event()? {
+            Event::Start(e) => path.push(String::from_utf8_lossy(e.name().as_ref()).into_owned()),
+            Event::End(_) => {
+                path.pop();
+            }
+            Event::Text(_) | Event::CData(_) => {
+                let range = start..reader.buffer_position() as usize;
+                match path.last().map(String::as_str) {
+                    Some("HTMLResource") => find_html(doc, range, findings),
+                    Some(name) if !name.ends_with("ClickThrough") => {
+                        find_text(doc, range, name, findings)
+                    }
+                    _ => {}
+                }
+            }
+            Event::Eof => return Ok(()),
+            _ => {}
+        }
+    }
+}
+
+/// Collects the URLs of an HTML fragment, skipping namespace identifiers
+/// and link or form destinations
+fn find_html(doc: &str, range: Range<usize>, findings: &mut Vec<Finding>) {
+    let lower = doc[range.clone()].to_ascii_lowercase();
+
+    for (i, _) in lower.match_indices("http://") {
+        if in_namespace_attr(&lower, i) {
+            continue;
+        }
+
+        let before = &lower[..i];
+        let location = match (open_tag(before), attribute_name(before)) {
+            (Some("a" | "area"), Some("href")) | (Some("form"), Some("action")) => continue,
+            (Some(tag), Some(attr)) => format!("<{tag} {attr}>"),
+            (Some(tag), None) => format!("<{tag}>"),
+            (None, _) => "text".to_string(),
+        };
+
+        findings.push(Finding {
+            start: range.start + i,
+            url: url_at(doc, range.start + i).to_string(),
+            location,
+        });
+    }
+}
+
+/// Collects every URL of a text node or URL field
+fn find_text(doc: &str, range: Range<usize>, location: &str, findings: &mut Vec<Finding>) {
+    let lower = doc[range.clone()].to_ascii_lowercase();
+
+    for (i, _) in lower.match_indices("http://") {
+        findings.push(Finding {
+            start: range.start + i,
+            url: url_at(doc, range.start + i).to_string(),
+            location: location.to_string(),
+        });
+    }
+}
+
+/// Name of the tag `before` ends inside of, e.g. `img` for `<img src="`
+fn open_tag(before: &str) -> Option<&str> {
+    let lt = before.rfind('<')?;
+    let tag = &before[lt + 1..];
+    if tag.contains('>') {
+        return None;
+    }
+
+    let end = tag
+        .find(|c: char| !c.is_ascii_alphanumeric())
+        .unwrap_or(tag.len());
+    (end > 0).then(|| &tag[..end])
+}
+
+fn url_at(doc: &str, start: usize) -> &str {
+    let rest = &doc[start..];
+    let end = rest
+        .find(|c: char| {
+            c.is_ascii_whitespace() || matches!(c, '"' | '\'' | '<' | '>' | ')' | ']' | '\\' | '`')
+        })
+        .unwrap_or(rest.len());
+    &rest[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scanner(domains: &[&str]) -> SecureScanner {
+        SecureScannerBuilder::default()
+            .rewrite_domains(domains.iter().map(|d| d.to_string()).collect())
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_html_resources() {
+        let adm = r#"<a href="http://landing.example.com"><img src="http://cdn.example.com/a.png"></a>
+            <div style="background:url(http://cdn.example.com/bg.png)"></div>
+            <script>document.write('http://t.example.com/px');</script>"#;
+
+        let insecure = scanner(&[]).scan_markup(adm);
+        let found: Vec<_> = insecure
+            .iter()
+            .map(|u| (u.url.as_str(), u.location.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("http://cdn.example.com/a.png", "<img src>"),
+                ("http://cdn.example.com/bg.png", "<div style>"),
+                ("http://t.example.com/px", "text"),
+            ]
+        );
+        assert!(insecure.iter().all(|u| !u.rewritable));
+    }
+
+    #[test]
+    fn test_secure_html_is_untouched() {
+        let adm =
+            r#"<a href="http://landing.example.com"><img src="https://cdn.example.com/a.png"></a>"#;
+        assert!(scanner(&[]).scan_markup(adm).is_empty());
+        assert_eq!(scanner(&[]).secure_markup(adm).unwrap(), adm);
+    }
+
+    #[test]
+    fn test_rewrite_known_domains() {
+        let scanner = scanner(&["example.com"]);
+        let adm = r#"<img src="HTTP://cdn.example.com/a.png"><img src="http://example.com/b.png">"#;
+
+        assert_eq!(
+            scanner.secure_markup(adm).unwrap(),
+            r#"<img src="https://cdn.example.com/a.png"><img src="https://example.com/b.png">"#
+        );
+
+        let err = scanner
+            .secure_markup(r#"<img src="http://notexample.com/a.png">"#)
+            .unwrap_err();
+        assert_eq!(err.urls.len(), 1);
+        assert_eq!(err.loss_reason(), lossreason::CREATIVE_FILTERED_NOT_SECURE);
+    }
+
+    #[test]
+    fn test_vast() {
+        let vast = r#"<VAST xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" version="3.0"><Ad><InLine>
+            <Impression><![CDATA[http://t.example.com/imp?a=1&b=2]]></Impression>
+            <Creatives><Creative><Linear>
+                <TrackingEvents><Tracking event="start">http://t.example.com/start?a=1&amp;b=2</Tracking></TrackingEvents>
+                <VideoClicks><ClickThrough>http://landing.example.com</ClickThrough></VideoClicks>
+                <MediaFiles><MediaFile type="video/mp4">https://cdn.example.com/ad.mp4</MediaFile></MediaFiles>
+            </Linear></Creative>
+            <Creative><CompanionAds><Companion>
+                <HTMLResource><![CDATA[<img src="http://cdn.example.com/c.png">]]></HTMLResource>
+            </Companion></CompanionAds></Creative></Creatives>
+        </InLine></Ad></VAST>"#;
+
+        let insecure = scanner(&[]).scan_markup(vast);
+        let found: Vec<_> = insecure
+            .iter()
+            .map(|u| (u.url.as_str(), u.location.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("http://t.example.com/imp?a=1&b=2", "Impression"),
+                ("http://t.example.com/start?a=1&amp;b=2", "Tracking"),
+                ("http://cdn.example.com/c.png", "<img src>"),
+            ]
+        );
+
+        let secured = scanner(&["example.com"]).secure_markup(vast).unwrap();
+        assert!(secured.contains("https://t.example.com/start?a=1&amp;b=2"));
+        assert!(secured.contains("<ClickThrough>http://landing.example.com</ClickThrough>"));
+        assert!(secured.contains(r#"xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance""#));
+    }
+
+    #[test]
+    fn test_native() {
+        let adm = r#"{"native":{"ver":"1.2","assets":[
+            {"id":1,"img":{"url":"http://cdn.example.com/main.png"}},
+            {"id":2,"title":{"text":"http://not-a-resource.example.com"}}],
+            "link":{"url":"http://landing.example.com","clicktrackers":["http://t.example.com/c"]},
+            "eventtrackers":[{"event":1,"method":1,"url":"https://t.example.com/imp"}]}}"#;
+
+        let insecure = scanner(&[]).scan_markup(adm);
+        let locations: Vec<_> = insecure.iter().map(|u| u.location.as_str()).collect();
+        assert_eq!(
+            locations,
+            vec!["assets[id=1].img.url", "link.clicktrackers[0]"]
+        );
+
+        let secured = scanner(&["example.com"]).secure_markup(adm).unwrap();
+        let (native, wrapped) = parse_native_response_wrapped(&secured).unwrap();
+        assert!(wrapped);
+        assert!(scanner(&[]).secure_native(&mut native.clone()).is_empty());
+        assert_eq!(native.link.unwrap().url, "http://landing.example.com");
+    }
+
+    #[test]
+    fn test_enforce_only_on_secure_imps() {
+        let scanner = scanner(&[]);
+        let adm = r#"<img src="http://cdn.example.com/a.png">"#;
+        let mut bid = Bid {
+            adm_oneof: Some(AdmOneof::Adm(adm.to_string())),
+            ..Default::default()
+        };
+
+        let mut imp = Imp::default();
+        assert_eq!(scanner.enforce(&imp, &mut bid), Ok(0));
+
+        imp.secure = true;
+        let err = scanner.enforce(&imp, &mut bid).unwrap_err();
+        assert_eq!(err.urls[0].url, "http://cdn.example.com/a.png");
+        assert_eq!(bid.adm_oneof, Some(AdmOneof::Adm(adm.to_string())));
+    }
+}
@@ -0,0 +1,466 @@
+//! Structured classification of bid markup.
+//!
+//! [`sniff_bid`] and [`sniff_adm`] report the [`AdFormat`] of a creative
+//! together with how sure the guess is and the traits that matter when
+//! serving it, such as MRAID or insecure resources.
+
+use super::adm::{AdFormat, is_mraid, vast_format};
+use super::native::{native_response_to_string, parse_native_response_wrapped};
+use crate::bid_response::Bid;
+use crate::bid_response::bid::AdmOneof;
+use crate::openrtb::spec::creative_markup_types;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// How reliable a detected format is
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Confidence {
+    /// A fallback, e.g. plain text treated as a banner
+    Low,
+    /// The markup had to be recovered from surrounding junk or is incomplete
+    Medium,
+    /// Signalled by `bid.mtype`, or well formed markup of a single format
+    High,
+}
+
+/// Traits of the markup, independent of its format
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub struct AdmSignals {
+    /// Loads `mraid.js` or calls the MRAID API
+    pub is_mraid: bool,
+    /// No resource is loaded over plain http
+    pub is_secure: bool,
+    /// Nothing but `<script>` tags, the creative is rendered by the script
+    pub is_script_only: bool,
+    /// The adm is a bare image URL
+    pub is_image_url: bool,
+    /// VAST whose ad is a Wrapper rather than an InLine
+    pub is_vast_wrapper: bool,
+    pub is_daast: bool,
+    /// The markup was found inside junk, e.g. text or a CDATA section
+    pub is_wrapped: bool,
+}
+
+/// Outcome of sniffing bid markup
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AdmSniff {
+    pub format: AdFormat,
+    pub confidence: Confidence,
+    /// What the markup alone looks like, which may disagree with `format`
+    /// when the format came from `bid.mtype`
+    pub markup_format: Option<AdFormat>,
+    pub signals: AdmSignals,
+}
+
+/// Sniffs the markup of a bid, trusting `bid.mtype` for the format when set
+pub fn sniff_bid(bid: &Bid) -> Option<AdmSniff> {
+    let sniff = bid.adm_oneof.as_ref().and_then(sniff_adm);
+    let Some(mtype) = mtype_format(bid) else {
+        return sniff;
+    };
+
+    Some(AdmSniff {
+        format: mtype,
+        confidence: Confidence::High,
+        markup_format: sniff.as_ref().map(|s| s.format),
+        signals: sniff.map(|s| s.signals).unwrap_or_default(),
+    })
+}
+
+/// Format signalled by `bid.mtype`, if set to a known value
+pub(crate) fn mtype_format(bid: &Bid) -> Option<AdFormat> {
+    match bid.mtype as u32 {
+        creative_markup_types::BANNER => Some(AdFormat::Banner),
+        creative_markup_types::VIDEO => Some(AdFormat::Video),
+        creative_markup_types::AUDIO => Some(AdFormat::Audio),
+        creative_markup_types::NATIVE => Some(AdFormat::Native),
+        _ => None,
+    }
+}
+
+/// Sniffs textual or protobuf native markup, `None` if the adm is empty
+pub fn sniff_adm(adm: &AdmOneof) -> Option<AdmSniff> {
+    match adm {
+        AdmOneof::Adm(s) => sniff_markup(s),
+        AdmOneof::AdmNative(native) => {
+            // a response which fails to serialise cannot be vouched for
+            let is_secure =
+                native_response_to_string(native, false).is_ok_and(|text| is_secure(&text));
+            Some(AdmSniff {
+                format: AdFormat::Native,
+                confidence: Confidence::High,
+                markup_format: Some(AdFormat::Native),
+                signals: AdmSignals {
+                    is_secure,
+                    ..Default::default()
+                },
+            })
+        }
+    }
+}
+
+/// Sniffs textual markup, `None` if it is empty.
+///
+/// JSON is parsed as a native response, XML is recognised as VAST or DAAST
+/// even when preceded by comments, declarations or junk, and everything
+/// else is treated as a banner: HTML, MRAID, script tags or an image URL.
+pub fn sniff_markup(adm: &str) -> Option<AdmSniff> {
+    let s = adm.trim_start_matches('\u{feff}').trim();
+    if s.is_empty() {
+        return None;
+    }
+
+    let mut signals = AdmSignals {
+        is_secure: is_secure(s),
+        ..Default::default()
+    };
+
+    let (format, confidence) = if s.starts_with('{') || s.starts_with('[') {
+        sniff_json(s)
+    } else if is_bare_url(s) {
+        sniff_url(s, &mut signals)
+    } else {
+        sniff_xml(s, &mut signals)
+    };
+
+    if format == AdFormat::Banner {
+        signals.is_mraid = is_mraid(s);
+        signals.is_script_only = is_script_only(s);
+    }
+
+    Some(AdmSniff {
+        format,
+        confidence,
+        markup_format: Some(format),
+        signals,
+    })
+}
+
+/// Name of the first element of the markup, skipping leading whitespace,
+/// comments, declarations, a CDATA opener and any text. The flag is set
+/// when something other than whitespace, comments and declarations had to
+/// be skipped.
+pub(crate) fn root_element(markup: &str) -> Option<(&str, bool)> {
+    let mut s = markup.trim_start_matches('\u{feff}');
+    let mut wrapped = false;
+
+    loop {
+        s = s.trim_start();
+
+        let skip_to = if s.starts_with("<!--") {
+            Some("-->")
+        } else if s.starts_with("<?") {
+            Some("?>")
+        } else if s
+            .get(..9)
+            .is_some_and(|p| p.eq_ignore_ascii_case("<!doctype"))
+        {
+            Some(">")
+        } else {
+            None
+        };
+        if let Some(end) = skip_to {
+            s = &s[s.find(end)? + end.len()..];
+            continue;
+        }
+
+        if let Some(rest) = s.strip_prefix("<![CDATA[") {
+            s = rest;
+            wrapped = true;
+            continue;
+        }
+
+        if let Some(rest) = s.strip_prefix('<') {
+            let end = rest
+                .find(|c: char| c.is_ascii_whitespace() || c == '>' || c == '/')
+                .unwrap_or(rest.len());
+            if end > 0 {
+                return Some((&rest[..end], wrapped));
+            }
+        }
+
+        // text before the markup, e.g. a stray wrapper or escaped prefix
+        let next = match s.find('<')? {
+            0 => s[1..].find('<')? + 1,
+            i => i,
+        };
+        s = &s[next..];
+        wrapped = true;
+    }
+}
+
+fn sniff_json(s: &str) -> (AdFormat, Confidence) {
+    if let Ok((native, wrapped)) = parse_native_response_wrapped(s) {
+        if wrapped || !native.assets.is_empty() || native.link.is_some() {
+            return (AdFormat::Native, Confidence::High);
+        }
+    }
+
+    match serde_json::from_str::<Value>(s) {
+        Ok(Value::Object(map)) if map.contains_key("native") || map.contains_key("assets") => {
+            (AdFormat::Native, Confidence::Medium)
+        }
+        Ok(_) => (AdFormat::Banner, Confidence::Low),
+        Err(_) if s.contains("native") => (AdFormat::Native, Confidence::Low),
+        Err(_) => (AdFormat::Banner, Confidence::Low),
+    }
+}
+
+fn sniff_url(s: &str, signals: &mut AdmSignals) -> (AdFormat, Confidence) {
+    const IMAGE_EXTENSIONS: &[&str] = &[".png", ".jpg", ".jpeg", ".gif", ".webp", ".svg"];
+
+    let path = s
+        .split(['?', '#'])
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+
+    if IMAGE_EXTENSIONS.iter().any(|ext| path.ends_with(ext)) {
+        signals.is_image_url = true;
+        (AdFormat::Banner, Confidence::Medium)
+    } else if path.ends_with(".xml") || path.contains("vast") {
+        // most likely a VAST tag URL
+        (AdFormat::Video, Confidence::Low)
+    } else {
+        (AdFormat::Banner, Confidence::Low)
+    }
+}
+
+fn sniff_xml(s: &str, signals: &mut AdmSignals) -> (AdFormat, Confidence) {
+    let confidence = |wrapped: bool| {
+        if wrapped {
+            Confidence::Medium
+        } else {
+            Confidence::High
+        }
+    };
+
+    match root_element(s) {
+        Some((name, wrapped)) if name.eq_ignore_ascii_case("vast") => {
+            signals.is_wrapped = wrapped;
+            signals.is_vast_wrapper = has_wrapper(s);
+            (vast_format(s), confidence(wrapped))
+        }
+        Some((name, wrapped)) if name.eq_ignore_ascii_case("daast") => {
+            signals.is_wrapped = wrapped;
+            signals.is_daast = true;
+            (AdFormat::Audio, confidence(wrapped))
+        }
+        Some((_, wrapped)) => match embedded_vast(s) {
+            // VAST nested within other markup, e.g. an HTML comment or a
+            // proprietary envelope
+            Some(vast) => {
+                signals.is_wrapped = true;
+                signals.is_vast_wrapper = has_wrapper(vast);
+                (vast_format(vast), Confidence::Medium)
+            }
+            None => {
+                signals.is_wrapped = wrapped;
+                (AdFormat::Banner, Confidence::High)
+            }
+        },
+        None => (AdFormat::Banner, Confidence::Low),
+    }
+}
+
+/// Whether the VAST holds a `Wrapper` ad, matching the tag in any case as
+/// lenient players do
+fn has_wrapper(vast: &str) -> bool {
+    let lower = vast.to_ascii_lowercase();
+    lower.match_indices("<wrapper").any(|(i, tag)| {
+        lower[i + tag.len()..]
+            .starts_with(|c: char| c == '>' || c == '/' || c.is_ascii_whitespace())
+    })
+}
+
+fn embedded_vast(s: &str) -> Option<&str> {
+    let lower = s.to_ascii_lowercase();
+    let start = lower.find("<vast")?;
+    let end = lower.rfind("</vast>")? + "</vast>".len();
+    (start < end).then(|| &s[start..end])
+}
+
+fn is_bare_url(s: &str) -> bool {
+    let lower = s.get(..8).unwrap_or(s).to_ascii_lowercase();
+    (lower.starts_with("https://") || lower.starts_with("http://") || lower.starts_with("//"))
+        && !s.contains(|c: char| c.is_ascii_whitespace() || c == '<')
+}
+
+/// True if the markup consists of one or more script tags and comments only
+fn is_script_only(s: &str) -> bool {
+    let lower = s.to_ascii_lowercase();
+    let mut rest = lower.trim();
+    let mut scripts = 0;
+
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix("<!--") {
+            let Some(end) = after.find("-->") else {
+                return false;
+            };
+            rest = after[end + 3..].trim_start();
+        } else if rest.starts_with("<script") {
+            let Some(end) = rest.find("</script>") else {
+                return false;
+            };
+            rest = rest[end + "</script>".len()..].trim_start();
+            scripts += 1;
+        } else {
+            return false;
+        }
+    }
+
+    scripts > 0
+}
+
+/// True if no plain http URL appears outside XML namespace declarations
+pub(crate) fn is_secure(s: &str) -> bool {
+    let lower = s.to_ascii_lowercase();
+
+    lower
+        .match_indices("http://")
+        .all(|(i, _)| in_namespace_attr(&lower, i))
+}
+
+/// Whether the URL at `i` is the value of an `xmlns` or `schemaLocation`
+/// attribute, which are identifiers and never fetched
+pub(crate) fn in_namespace_attr(lower: &str, i: usize) -> bool {
+    attribute_name(&lower[..i])
+        .is_some_and(|name| name.starts_with("xmlns") || name.ends_with("schemalocation"))
+}
+
+/// Name of the attribute whose quoted value contains the end of `before`,
+/// e.g. `src` for `<img src="https://a.example.com/x.png`
+pub(crate) fn attribute_name(before: &str) -> Option<&str> {
+    let quote = before.rfind(['"', '\''])?;
+    let name = before[..quote].trim_end().strip_suffix('=')?;
+
+    name.trim_end()
+        .rsplit(|c: char| c.is_ascii_whitespace() || c == '<')
+        .next()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sniff(adm: &str) -> AdmSniff {
+        sniff_markup(adm).unwrap()
+    }
+
+    #[test]
+    fn test_root_element() {
+        assert_eq!(
+            root_element("<VAST version=\"4.0\">"),
+            Some(("VAST", false))
+        );
+        assert_eq!(
+            root_element(
+                "\u{feff}<?xml version=\"1.0\"?>\n<!-- ad server 1 --><!DOCTYPE x><VAST/>"
+            ),
+            Some(("VAST", false))
+        );
+        assert_eq!(
+            root_element("<![CDATA[<VAST></VAST>]]>"),
+            Some(("VAST", true))
+        );
+        assert_eq!(root_element("junk <div>"), Some(("div", true)));
+        assert_eq!(root_element("no markup"), None);
+        assert_eq!(root_element("<!-- unterminated"), None);
+    }
+
+    #[test]
+    fn test_vast_confidence() {
+        let clean = sniff(r#"<?xml version="1.0"?><VAST version="3.0"><Ad><InLine/></Ad></VAST>"#);
+        assert_eq!(clean.format, AdFormat::Video);
+        assert_eq!(clean.confidence, Confidence::High);
+        assert!(!clean.signals.is_vast_wrapper);
+
+        let junk = sniff(r#"callback(<VAST version="3.0"><Ad><Wrapper/></Ad></VAST>)"#);
+        assert_eq!(junk.format, AdFormat::Video);
+        assert_eq!(junk.confidence, Confidence::Medium);
+        assert!(junk.signals.is_wrapped);
+        assert!(junk.signals.is_vast_wrapper);
+
+        let lowercase =
+            sniff(r#"<vast version="3.0"><ad><wrapper fallbackOnNoAd="true"/></ad></vast>"#);
+        assert!(lowercase.signals.is_vast_wrapper);
+
+        let vendor =
+            sniff(r#"<VAST version="3.0"><Ad><InLine><WrapperInfo/></InLine></Ad></VAST>"#);
+        assert!(!vendor.signals.is_vast_wrapper);
+
+        let in_html = sniff(r#"<html><!-- <VAST version="2.0"><Ad/></VAST> --></html>"#);
+        assert_eq!(in_html.format, AdFormat::Video);
+        assert_eq!(in_html.confidence, Confidence::Medium);
+    }
+
+    #[test]
+    fn test_json_native() {
+        let native = sniff(r#"{"ver":"1.2","assets":[{"id":1,"title":{"text":"T"}}]}"#);
+        assert_eq!(native.format, AdFormat::Native);
+        assert_eq!(native.confidence, Confidence::High);
+
+        let not_native = sniff(r#"{"creative":"native-looking"}"#);
+        assert_eq!(not_native.format, AdFormat::Banner);
+        assert_eq!(not_native.confidence, Confidence::Low);
+
+        let broken = sniff(r#"{"native":{"ver":"1.2""#);
+        assert_eq!(broken.format, AdFormat::Native);
+        assert_eq!(broken.confidence, Confidence::Low);
+    }
+
+    #[test]
+    fn test_banner_signals() {
+        let script = sniff(r#"<script src="https://cdn.example.com/tag.js"></script>"#);
+        assert_eq!(script.format, AdFormat::Banner);
+        assert!(script.signals.is_script_only);
+        assert!(script.signals.is_secure);
+        assert!(!script.signals.is_mraid);
+
+        let mraid = sniff(r#"<script src="mraid.js"></script><div>Ad</div>"#);
+        assert!(mraid.signals.is_mraid);
+        assert!(!mraid.signals.is_script_only);
+
+        let image = sniff("https://cdn.example.com/banner.PNG?cb=1");
+        assert!(image.signals.is_image_url);
+        assert_eq!(image.confidence, Confidence::Medium);
+
+        let text = sniff("Buy now");
+        assert_eq!(text.format, AdFormat::Banner);
+        assert_eq!(text.confidence, Confidence::Low);
+    }
+
+    #[test]
+    fn test_is_secure() {
+        assert!(is_secure(r#"<img src="https://a.example.com/x.png">"#));
+        assert!(is_secure(r#"<img src="//a.example.com/x.png">"#));
+        assert!(!is_secure(r#"<img src="HTTP://a.example.com/x.png">"#));
+        assert!(is_secure(
+            r#"<VAST xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" xsi:schemaLocation="http://www.iab.com/VAST http://www.iab.com/vast.xsd">"#
+        ));
+        assert!(!is_secure(
+            r#"<VAST xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance"><Impression><![CDATA[http://t.example.com]]></Impression>"#
+        ));
+    }
+
+    #[test]
+    fn test_mtype_is_honoured() {
+        let bid = Bid {
+            mtype: creative_markup_types::AUDIO as i32,
+            adm_oneof: Some(AdmOneof::Adm("<VAST version=\"4.0\"></VAST>".to_string())),
+            ..Default::default()
+        };
+
+        let sniff = sniff_bid(&bid).unwrap();
+        assert_eq!(sniff.format, AdFormat::Audio);
+        assert_eq!(sniff.markup_format, Some(AdFormat::Video));
+        assert_eq!(sniff.confidence, Confidence::High);
+
+        let bid = Bid {
+            mtype: creative_markup_types::VIDEO as i32,
+            ..Default::default()
+        };
+        assert_eq!(sniff_bid(&bid).unwrap().markup_format, None);
+        assert!(sniff_bid(&Bid::default()).is_none());
+    }
+}
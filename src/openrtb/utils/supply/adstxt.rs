@@ -0,0 +1,242 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use strum::{Display, EnumString};
+
+/// Seller relationship declared by an ads.txt record
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash, EnumString, Display)]
+#[strum(ascii_case_insensitive, serialize_all = "UPPERCASE")]
+pub enum Relationship {
+    /// The publisher directly controls the seller account
+    Direct,
+    /// The publisher authorised another entity to resell the inventory
+    Reseller,
+}
+
+/// A single authorised seller line from an ads.txt or app-ads.txt file
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AdsTxtRecord {
+    /// Canonical domain of the advertising system, lowercased
+    pub domain: String,
+    /// Publisher account id within the advertising system
+    pub publisher_id: String,
+    /// Declared relationship of the account to the publisher
+    pub relationship: Relationship,
+    /// Optional certification authority id (e.g. TAG id)
+    pub cert_authority_id: Option<String>,
+}
+
+/// A `KEY=value` variable line such as `CONTACT` or `OWNERDOMAIN`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AdsTxtVariable {
+    /// Variable name, uppercased
+    pub name: String,
+    pub value: String,
+}
+
+/// A line which could not be interpreted, retained for diagnostics
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AdsTxtLineError {
+    /// 1-based line number within the source file
+    pub line: usize,
+    pub reason: String,
+}
+
+/// Parsed contents of an ads.txt or app-ads.txt file.
+///
+/// Parsing is lenient as required by the IAB specification: malformed lines are
+/// skipped and reported in [`AdsTxt::errors`] rather than failing the whole file.
+///
+/// # Example
+/// ```
+/// use rtb::openrtb::utils::supply::{AdsTxt, Relationship};
+///
+/// let ads_txt = AdsTxt::parse("exchange.com, pub-123, DIRECT, abc123\n");
+/// let record = ads_txt.find("exchange.com", "pub-123").unwrap();
+/// assert_eq!(record.relationship, Relationship::Direct);
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AdsTxt {
+    pub records: Vec<AdsTxtRecord>,
+    pub variables: Vec<AdsTxtVariable>,
+    pub errors: Vec<AdsTxtLineError>,
+}
+
+impl AdsTxt {
+    /// Parses ads.txt content from a string
+    pub fn parse(content: &str) -> Self {
+        let mut ads_txt = AdsTxt::default();
+        let content = content.trim_start_matches('\u{feff}');
+
+        for (idx, raw_line) in content.lines().enumerate() {
+            let line_no = idx + 1;
+
+            // Strip comments, then extension data which follows a semicolon
+            let line = raw_line.split('#').next().unwrap_or("");
+            let line = line.split(';').next().unwrap_or("").trim();
+
+            if line.is_empty() {
+                continue;
+            }
+
+            if !line.contains(',') {
+                if let Some((name, value)) = line.split_once('=') {
+                    ads_txt.variables.push(AdsTxtVariable {
+                        name: name.trim().to_ascii_uppercase(),
+                        value: value.trim().to_string(),
+                    });
+                } else {
+                    ads_txt.errors.push(AdsTxtLineError {
+                        line: line_no,
+                        reason: "not a record or variable".to_string(),
+                    });
+                }
+                continue;
+            }
+
+            match parse_record(line) {
+                Ok(record) => ads_txt.records.push(record),
+                Err(reason) => ads_txt.errors.push(AdsTxtLineError {
+                    line: line_no,
+                    reason,
+                }),
+            }
+        }
+
+        ads_txt
+    }
+
+    /// Parses ads.txt content from raw bytes, replacing invalid UTF-8 sequences
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        Self::parse(&String::from_utf8_lossy(bytes))
+    }
+
+    /// Reads and parses an ads.txt file from disk
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let bytes =
+            std::fs::read(path).with_context(|| format!("Failed to read ads.txt {:?}", path))?;
+
+        Ok(Self::from_bytes(&bytes))
+    }
+
+    /// Finds the record for the given advertising system domain and publisher id.
+    ///
+    /// Domains are compared case-insensitively, publisher ids exactly.
+    pub fn find(&self, domain: &str, publisher_id: &str) -> Option<&AdsTxtRecord> {
+        self.records.iter().find(|r| {
+            r.domain.eq_ignore_ascii_case(domain.trim()) && r.publisher_id == publisher_id.trim()
+        })
+    }
+
+    /// Returns all records belonging to the given advertising system domain
+    pub fn records_for<'a>(&'a self, domain: &'a str) -> impl Iterator<Item = &'a AdsTxtRecord> {
+        self.records
+            .iter()
+            .filter(move |r| r.domain.eq_ignore_ascii_case(domain.trim()))
+    }
+
+    /// Returns the first value of a variable (e.g. `OWNERDOMAIN`), matched case-insensitively
+    pub fn variable(&self, name: &str) -> Option<&str> {
+        self.variables
+            .iter()
+            .find(|v| v.name.eq_ignore_ascii_case(name))
+            .map(|v| v.value.as_str())
+    }
+}
+
+fn parse_record(line: &str) -> Result<AdsTxtRecord, String> {
+    let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+
+    if fields.len() < 3 {
+        return Err(format!(
+            "expected at least 3 fields, found {}",
+            fields.len()
+        ));
+    }
+
+    if fields[0].is_empty() || fields[1].is_empty() {
+        return Err("empty domain or publisher id".to_string());
+    }
+
+    let relationship = fields[2]
+        .parse::<Relationship>()
+        .map_err(|_| format!("unknown relationship '{}'", fields[2]))?;
+
+    let cert_authority_id = fields
+        .get(3)
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_string());
+
+    Ok(AdsTxtRecord {
+        domain: fields[0].to_ascii_lowercase(),
+        publisher_id: fields[1].to_string(),
+        relationship,
+        cert_authority_id,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = "# ads.txt for example.com
+CONTACT=adops@example.com
+OWNERDOMAIN=example.com
+Exchange.com, pub-123, DIRECT, f08c47fec0942fa0
+reseller.net, 9876, RESELLER # trailing comment
+ssp.io, abc, direct;extension=data
+broken line here
+bad.com, 1, PARTNER
+";
+
+    #[test]
+    fn test_parse_records() {
+        let ads_txt = AdsTxt::parse(SAMPLE);
+
+        assert_eq!(ads_txt.records.len(), 3);
+        assert_eq!(ads_txt.records[0].domain, "exchange.com");
+        assert_eq!(ads_txt.records[0].relationship, Relationship::Direct);
+        assert_eq!(
+            ads_txt.records[0].cert_authority_id.as_deref(),
+            Some("f08c47fec0942fa0")
+        );
+        assert_eq!(ads_txt.records[1].relationship, Relationship::Reseller);
+        assert_eq!(ads_txt.records[1].cert_authority_id, None);
+        assert_eq!(ads_txt.records[2].relationship, Relationship::Direct);
+    }
+
+    #[test]
+    fn test_parse_variables() {
+        let ads_txt = AdsTxt::parse(SAMPLE);
+
+        assert_eq!(ads_txt.variable("contact"), Some("adops@example.com"));
+        assert_eq!(ads_txt.variable("OWNERDOMAIN"), Some("example.com"));
+        assert_eq!(ads_txt.variable("MANAGERDOMAIN"), None);
+    }
+
+    #[test]
+    fn test_parse_errors_are_reported() {
+        let ads_txt = AdsTxt::parse(SAMPLE);
+
+        assert_eq!(ads_txt.errors.len(), 2);
+        assert_eq!(ads_txt.errors[0].line, 7);
+        assert!(ads_txt.errors[1].reason.contains("PARTNER"));
+    }
+
+    #[test]
+    fn test_find_is_case_insensitive_on_domain() {
+        let ads_txt = AdsTxt::parse(SAMPLE);
+
+        assert!(ads_txt.find("EXCHANGE.COM", "pub-123").is_some());
+        assert!(ads_txt.find("exchange.com", "PUB-123").is_none());
+        assert_eq!(ads_txt.records_for("reseller.net").count(), 1);
+    }
+
+    #[test]
+    fn test_from_bytes_with_bom() {
+        let ads_txt = AdsTxt::from_bytes(b"\xef\xbb\xbfexchange.com, 1, DIRECT");
+        assert_eq!(ads_txt.records.len(), 1);
+        assert_eq!(ads_txt.records[0].domain, "exchange.com");
+    }
+}
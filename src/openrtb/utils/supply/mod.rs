@@ -0,0 +1,13 @@
+//! Supply chain authorisation helpers
+//!
+//! Parsers for `ads.txt`/`app-ads.txt` and `sellers.json` records loaded from
+//! local files or bytes, plus a [`SupplyVerifier`] which checks a bid request's
+//! inventory, publisher and `schain` against them.
+
+mod adstxt;
+mod sellers;
+mod verify;
+
+pub use adstxt::*;
+pub use sellers::*;
+pub use verify::*;
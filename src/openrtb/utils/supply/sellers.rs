@@ -0,0 +1,142 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// The `seller_type` of a sellers.json entry
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum SellerType {
+    /// Inventory is owned by the seller, corresponds to ads.txt DIRECT
+    Publisher,
+    /// Inventory is resold by the seller, corresponds to ads.txt RESELLER
+    Intermediary,
+    /// Seller sells both owned and resold inventory
+    Both,
+    /// Any value not defined by the specification
+    #[serde(other)]
+    Unknown,
+}
+
+/// A single entry of the `sellers` array in a sellers.json file
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Seller {
+    pub seller_id: String,
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Business domain of the seller, absent when confidential
+    #[serde(default)]
+    pub domain: Option<String>,
+    pub seller_type: SellerType,
+    #[serde(default, deserialize_with = "lenient_bool")]
+    pub is_confidential: bool,
+    #[serde(default, deserialize_with = "lenient_bool")]
+    pub is_passthrough: bool,
+    #[serde(default)]
+    pub comment: Option<String>,
+}
+
+/// Parsed contents of an advertising system's sellers.json file.
+///
+/// Unknown top-level attributes and seller attributes are ignored.
+///
+/// # Example
+/// ```
+/// use rtb::openrtb::utils::supply::{SellerType, SellersJson};
+///
+/// let json = r#"{"version":"1.0","sellers":[
+///     {"seller_id":"pub-123","seller_type":"PUBLISHER","domain":"example.com"}
+/// ]}"#;
+/// let sellers = SellersJson::from_slice(json.as_bytes()).unwrap();
+/// assert_eq!(sellers.find("pub-123").unwrap().seller_type, SellerType::Publisher);
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SellersJson {
+    #[serde(default)]
+    pub contact_email: Option<String>,
+    #[serde(default)]
+    pub contact_address: Option<String>,
+    #[serde(default)]
+    pub version: Option<String>,
+    #[serde(default)]
+    pub sellers: Vec<Seller>,
+}
+
+impl SellersJson {
+    /// Parses sellers.json content from raw bytes
+    pub fn from_slice(bytes: &[u8]) -> Result<Self> {
+        let bytes = bytes.strip_prefix(b"\xef\xbb\xbf").unwrap_or(bytes);
+        serde_json::from_slice(bytes).context("Failed to parse sellers.json")
+    }
+
+    /// Reads and parses a sellers.json file from disk
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path)
+            .with_context(|| format!("Failed to read sellers.json {:?}", path))?;
+
+        Self::from_slice(&bytes)
+    }
+
+    /// Finds a seller entry by its `seller_id`
+    pub fn find(&self, seller_id: &str) -> Option<&Seller> {
+        self.sellers
+            .iter()
+            .find(|s| s.seller_id == seller_id.trim())
+    }
+}
+
+/// Some publishers of sellers.json emit flags as 0/1 rather than booleans
+fn lenient_bool<'de, D>(deserializer: D) -> Result<bool, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    crate::compat::bool_as_int::De::deserialize(deserializer).map(|v| v.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = r#"{
+        "contact_email": "adops@exchange.com",
+        "version": "1.0",
+        "identifiers": [{"name": "TAG-ID", "value": "28cb65e5bbc0bd5f"}],
+        "sellers": [
+            {"seller_id": "pub-123", "name": "Example Publisher", "domain": "example.com", "seller_type": "PUBLISHER"},
+            {"seller_id": "res-9", "seller_type": "INTERMEDIARY", "is_confidential": 1},
+            {"seller_id": "both-1", "seller_type": "BOTH", "is_passthrough": true},
+            {"seller_id": "odd-1", "seller_type": "SOMETHING"}
+        ]
+    }"#;
+
+    #[test]
+    fn test_parse_sellers() {
+        let sellers = SellersJson::from_slice(SAMPLE.as_bytes()).unwrap();
+
+        assert_eq!(sellers.contact_email.as_deref(), Some("adops@exchange.com"));
+        assert_eq!(sellers.sellers.len(), 4);
+
+        let publisher = sellers.find("pub-123").unwrap();
+        assert_eq!(publisher.seller_type, SellerType::Publisher);
+        assert_eq!(publisher.domain.as_deref(), Some("example.com"));
+        assert!(!publisher.is_confidential);
+    }
+
+    #[test]
+    fn test_lenient_flags_and_unknown_type() {
+        let sellers = SellersJson::from_slice(SAMPLE.as_bytes()).unwrap();
+
+        assert!(sellers.find("res-9").unwrap().is_confidential);
+        assert!(sellers.find("both-1").unwrap().is_passthrough);
+        assert_eq!(
+            sellers.find("odd-1").unwrap().seller_type,
+            SellerType::Unknown
+        );
+        assert!(sellers.find("missing").is_none());
+    }
+
+    #[test]
+    fn test_invalid_json_fails() {
+        assert!(SellersJson::from_slice(b"{not json").is_err());
+    }
+}
@@ -0,0 +1,389 @@
+use super::adstxt::{AdsTxt, Relationship};
+use super::sellers::{SellerType, SellersJson};
+use crate::BidRequest;
+use crate::bid_request::DistributionchannelOneof;
+use crate::openrtb::spec::nobidreason;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Final authorisation decision for a bid request
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Verdict {
+    /// Seller is authorised by the publisher as a DIRECT seller
+    Direct,
+    /// Seller is authorised by the publisher as a RESELLER
+    Reseller,
+    /// Records were available and the seller is not authorised
+    Unauthorized,
+    /// Not enough information was available to reach a decision
+    Unverifiable,
+}
+
+/// Explanation accompanying a [`Verdict`]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Reason {
+    /// The seller matched an ads.txt record and all sellers.json checks passed
+    Authorized,
+    /// Neither `site.domain` nor `app.bundle` was present
+    MissingInventoryId,
+    /// `publisher.id` was required but absent
+    MissingPublisherId,
+    /// No ads.txt was loaded for the site domain or app bundle
+    AdsTxtUnavailable { inventory_id: String },
+    /// The publisher's ads.txt does not list the selling account
+    NoMatchingRecord { domain: String, seller_id: String },
+    /// `source.schain.complete` is not set and a complete chain is required
+    IncompleteSupplyChain,
+    /// A supply chain node is missing from its advertising system's sellers.json
+    SellerNotListed { asi: String, sid: String },
+    /// The ads.txt relationship contradicts the sellers.json seller type
+    RelationshipMismatch {
+        asi: String,
+        sid: String,
+        relationship: Relationship,
+        seller_type: SellerType,
+    },
+}
+
+/// Outcome of [`SupplyVerifier::verify`]: a typed verdict plus the reason behind it
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Verification {
+    pub verdict: Verdict,
+    pub reason: Reason,
+}
+
+impl Verification {
+    fn new(verdict: Verdict, reason: Reason) -> Self {
+        Self { verdict, reason }
+    }
+
+    /// True if the seller was authorised as either DIRECT or RESELLER
+    pub fn is_authorized(&self) -> bool {
+        matches!(self.verdict, Verdict::Direct | Verdict::Reseller)
+    }
+
+    /// Suggested no-bid reason for a rejected request, see
+    /// [`crate::openrtb::spec::nobidreason`]. Returns `None` when authorised.
+    pub fn nbr(&self) -> Option<u32> {
+        match (&self.verdict, &self.reason) {
+            (Verdict::Direct | Verdict::Reseller, _) => None,
+            (_, Reason::IncompleteSupplyChain) => Some(nobidreason::INCOMPLETE_SUPPLYCHAIN),
+            (_, Reason::SellerNotListed { .. } | Reason::RelationshipMismatch { .. }) => {
+                Some(nobidreason::BLOCKED_SUPPLYCHAIN_NODE)
+            }
+            (Verdict::Unverifiable, _) => Some(nobidreason::ADS_TXT_AUTHORIZATION_UNAVAILABLE),
+            (Verdict::Unauthorized, _) => Some(nobidreason::ADS_TXT_AUTHORIZATION_VIOLATION),
+        }
+    }
+}
+
+/// Verifies bid requests against locally loaded ads.txt/app-ads.txt and
+/// sellers.json records. No fetching is performed; callers load and refresh
+/// the records on their own schedule.
+///
+/// The selling account checked against the publisher's ads.txt is the first
+/// `source.schain` node when a chain is present, otherwise the configured
+/// `ad_system_domain` together with `publisher.id`.
+///
+/// # Example
+/// ```
+/// use rtb::BidRequest;
+/// use rtb::openrtb::utils::supply::{AdsTxt, SupplyVerifier, Verdict};
+///
+/// let mut verifier = SupplyVerifier::new("exchange.com");
+/// verifier.add_ads_txt("example.com", AdsTxt::parse("exchange.com, pub-1, DIRECT"));
+///
+/// let request: BidRequest = serde_json::from_str(
+///     r#"{"id":"1","site":{"domain":"example.com","publisher":{"id":"pub-1"}}}"#,
+/// ).unwrap();
+///
+/// assert_eq!(verifier.verify(&request).verdict, Verdict::Direct);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct SupplyVerifier {
+    ad_system_domain: String,
+    ads_txt: HashMap<String, AdsTxt>,
+    sellers_json: HashMap<String, SellersJson>,
+    require_complete_chain: bool,
+}
+
+impl SupplyVerifier {
+    /// Creates a verifier for requests received from the given advertising
+    /// system (the domain which appears in field #1 of ads.txt records)
+    pub fn new(ad_system_domain: &str) -> Self {
+        Self {
+            ad_system_domain: ad_system_domain.trim().to_ascii_lowercase(),
+            ..Default::default()
+        }
+    }
+
+    /// Registers the ads.txt of a site domain, or the app-ads.txt of an app bundle
+    pub fn add_ads_txt(&mut self, inventory_id: &str, ads_txt: AdsTxt) -> &mut Self {
+        self.ads_txt
+            .insert(inventory_id.trim().to_ascii_lowercase(), ads_txt);
+        self
+    }
+
+    /// Registers the sellers.json published by an advertising system domain
+    pub fn add_sellers_json(&mut self, ad_system_domain: &str, sellers: SellersJson) -> &mut Self {
+        self.sellers_json
+            .insert(ad_system_domain.trim().to_ascii_lowercase(), sellers);
+        self
+    }
+
+    /// Rejects requests whose supply chain is not flagged as complete
+    pub fn require_complete_chain(&mut self, require: bool) -> &mut Self {
+        self.require_complete_chain = require;
+        self
+    }
+
+    /// Decides whether the seller of the request is authorised by the publisher
+    pub fn verify(&self, request: &BidRequest) -> Verification {
+        let (inventory_id, publisher_id) = match &request.distributionchannel_oneof {
+            Some(DistributionchannelOneof::Site(site)) => (
+                site.domain.as_str(),
+                site.publisher.as_ref().map(|p| p.id.as_str()),
+            ),
+            Some(DistributionchannelOneof::App(app)) => (
+                app.bundle.as_str(),
+                app.publisher.as_ref().map(|p| p.id.as_str()),
+            ),
+            _ => ("", None),
+        };
+
+        let inventory_id = inventory_id.trim().to_ascii_lowercase();
+        if inventory_id.is_empty() {
+            return Verification::new(Verdict::Unverifiable, Reason::MissingInventoryId);
+        }
+
+        let schain = request.source.as_ref().and_then(|s| s.schain.as_ref());
+
+        if self.require_complete_chain && !schain.is_some_and(|c| c.complete) {
+            return Verification::new(Verdict::Unauthorized, Reason::IncompleteSupplyChain);
+        }
+
+        let Some(ads_txt) = self.ads_txt.get(&inventory_id) else {
+            return Verification::new(
+                Verdict::Unverifiable,
+                Reason::AdsTxtUnavailable { inventory_id },
+            );
+        };
+
+        // The account the publisher sold through is the first node of the chain,
+        // or the immediate upstream system when no chain is supplied
+        let (seller_domain, seller_id) = match schain.and_then(|c| c.nodes.first()) {
+            Some(node) => (node.asi.to_ascii_lowercase(), node.sid.clone()),
+            None => match publisher_id.filter(|id| !id.is_empty()) {
+                Some(id) => (self.ad_system_domain.clone(), id.to_string()),
+                None => {
+                    return Verification::new(Verdict::Unverifiable, Reason::MissingPublisherId);
+                }
+            },
+        };
+
+        let Some(record) = ads_txt.find(&seller_domain, &seller_id) else {
+            return Verification::new(
+                Verdict::Unauthorized,
+                Reason::NoMatchingRecord {
+                    domain: seller_domain,
+                    seller_id,
+                },
+            );
+        };
+
+        // Every node whose advertising system sellers.json is loaded must be listed.
+        // The first node must additionally agree with the ads.txt relationship.
+        let nodes = schain
+            .map(|c| {
+                c.nodes
+                    .iter()
+                    .map(|n| (n.asi.to_ascii_lowercase(), n.sid.clone()))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_else(|| vec![(seller_domain.clone(), seller_id.clone())]);
+
+        for (idx, (asi, sid)) in nodes.into_iter().enumerate() {
+            let Some(sellers) = self.sellers_json.get(&asi) else {
+                continue;
+            };
+
+            let Some(seller) = sellers.find(&sid) else {
+                return Verification::new(
+                    Verdict::Unauthorized,
+                    Reason::SellerNotListed { asi, sid },
+                );
+            };
+
+            if idx == 0 && !relationship_matches(record.relationship, seller.seller_type) {
+                return Verification::new(
+                    Verdict::Unauthorized,
+                    Reason::RelationshipMismatch {
+                        asi,
+                        sid,
+                        relationship: record.relationship,
+                        seller_type: seller.seller_type,
+                    },
+                );
+            }
+        }
+
+        let verdict = match record.relationship {
+            Relationship::Direct => Verdict::Direct,
+            Relationship::Reseller => Verdict::Reseller,
+        };
+
+        Verification::new(verdict, Reason::Authorized)
+    }
+}
+
+fn relationship_matches(relationship: Relationship, seller_type: SellerType) -> bool {
+    matches!(
+        (relationship, seller_type),
+        (_, SellerType::Both)
+            | (Relationship::Direct, SellerType::Publisher)
+            | (Relationship::Reseller, SellerType::Intermediary)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADS_TXT: &str = "exchange.com, pub-1, DIRECT
+exchange.com, res-2, RESELLER
+ssp.io, 77, RESELLER
+";
+
+    const SELLERS: &str = r#"{"sellers":[
+        {"seller_id":"pub-1","seller_type":"PUBLISHER"},
+        {"seller_id":"res-2","seller_type":"PUBLISHER"}
+    ]}"#;
+
+    fn verifier() -> SupplyVerifier {
+        let mut verifier = SupplyVerifier::new("Exchange.com");
+        verifier
+            .add_ads_txt("example.com", AdsTxt::parse(ADS_TXT))
+            .add_ads_txt("com.example.app", AdsTxt::parse(ADS_TXT))
+            .add_sellers_json(
+                "exchange.com",
+                SellersJson::from_slice(SELLERS.as_bytes()).unwrap(),
+            );
+        verifier
+    }
+
+    fn request(json: &str) -> BidRequest {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn test_direct_site_without_schain() {
+        let req =
+            request(r#"{"id":"1","site":{"domain":"example.com","publisher":{"id":"pub-1"}}}"#);
+        let result = verifier().verify(&req);
+
+        assert_eq!(result.verdict, Verdict::Direct);
+        assert_eq!(result.reason, Reason::Authorized);
+        assert!(result.is_authorized());
+        assert_eq!(result.nbr(), None);
+    }
+
+    #[test]
+    fn test_reseller_app_via_schain() {
+        let req = request(
+            r#"{"id":"1","app":{"bundle":"com.example.app"},
+            "source":{"schain":{"complete":1,"ver":"1.0","nodes":[
+                {"asi":"ssp.io","sid":"77","hp":1},
+                {"asi":"exchange.com","sid":"pub-1","hp":1}
+            ]}}}"#,
+        );
+        let result = verifier().verify(&req);
+
+        assert_eq!(result.verdict, Verdict::Reseller);
+    }
+
+    #[test]
+    fn test_unknown_seller_is_unauthorized() {
+        let req =
+            request(r#"{"id":"1","site":{"domain":"example.com","publisher":{"id":"nope"}}}"#);
+        let result = verifier().verify(&req);
+
+        assert_eq!(result.verdict, Verdict::Unauthorized);
+        assert!(matches!(result.reason, Reason::NoMatchingRecord { .. }));
+        assert_eq!(
+            result.nbr(),
+            Some(nobidreason::ADS_TXT_AUTHORIZATION_VIOLATION)
+        );
+    }
+
+    #[test]
+    fn test_missing_ads_txt_is_unverifiable() {
+        let req = request(r#"{"id":"1","site":{"domain":"other.com","publisher":{"id":"pub-1"}}}"#);
+        let result = verifier().verify(&req);
+
+        assert_eq!(result.verdict, Verdict::Unverifiable);
+        assert_eq!(
+            result.nbr(),
+            Some(nobidreason::ADS_TXT_AUTHORIZATION_UNAVAILABLE)
+        );
+    }
+
+    #[test]
+    fn test_relationship_mismatch_with_sellers_json() {
+        let req =
+            request(r#"{"id":"1","site":{"domain":"example.com","publisher":{"id":"res-2"}}}"#);
+        let result = verifier().verify(&req);
+
+        assert_eq!(result.verdict, Verdict::Unauthorized);
+        assert!(matches!(
+            result.reason,
+            Reason::RelationshipMismatch {
+                relationship: Relationship::Reseller,
+                seller_type: SellerType::Publisher,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_schain_node_missing_from_sellers_json() {
+        let req = request(
+            r#"{"id":"1","site":{"domain":"example.com"},
+            "source":{"schain":{"complete":1,"nodes":[
+                {"asi":"exchange.com","sid":"pub-1"},
+                {"asi":"exchange.com","sid":"ghost"}
+            ]}}}"#,
+        );
+        let result = verifier().verify(&req);
+
+        assert_eq!(result.verdict, Verdict::Unauthorized);
+        assert_eq!(
+            result.reason,
+            Reason::SellerNotListed {
+                asi: "exchange.com".to_string(),
+                sid: "ghost".to_string()
+            }
+        );
+        assert_eq!(result.nbr(), Some(nobidreason::BLOCKED_SUPPLYCHAIN_NODE));
+    }
+
+    #[test]
+    fn test_incomplete_chain_rejected_when_required() {
+        let mut verifier = verifier();
+        verifier.require_complete_chain(true);
+
+        let req =
+            request(r#"{"id":"1","site":{"domain":"example.com","publisher":{"id":"pub-1"}}}"#);
+        let result = verifier.verify(&req);
+
+        assert_eq!(result.reason, Reason::IncompleteSupplyChain);
+        assert_eq!(result.nbr(), Some(nobidreason::INCOMPLETE_SUPPLYCHAIN));
+    }
+
+    #[test]
+    fn test_missing_inventory_id() {
+        let req = request(r#"{"id":"1"}"#);
+        let result = verifier().verify(&req);
+
+        assert_eq!(result.reason, Reason::MissingInventoryId);
+    }
+}
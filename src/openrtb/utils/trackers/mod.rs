@@ -1,4 +1,6 @@
+mod native;
 mod pixels;
 mod vast;
+pub use native::*;
 pub use pixels::*;
 pub use vast::*;
@@ -0,0 +1,394 @@
+use super::pixels::{html_script, validate_url};
+use crate::bid_response::Bid;
+use crate::bid_response::bid::AdmOneof;
+use crate::openrtb::utils::native::{native_response_to_string, parse_native_response_wrapped};
+use crate::spec::adcom::{event_tracking_methods, event_types};
+use crate::{NativeResponse, native_response};
+use anyhow::{Result, bail};
+use derive_builder::Builder;
+use serde::{Deserialize, Serialize};
+
+/// Where impression trackers are placed within a native response
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub enum NativeTrackerPlacement {
+    /// Use `eventtrackers` unless the response is declared as Native 1.0/1.1
+    /// or only carries legacy `imptrackers`/`jstracker` trackers
+    #[default]
+    Auto,
+    /// Native 1.2 `eventtrackers` objects
+    EventTrackers,
+    /// Deprecated `imptrackers` and `jstracker` fields
+    Legacy,
+}
+
+/// An arbitrary `eventtrackers` entry, e.g. a viewability tracker
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct NativeEventTracker {
+    /// Event type, see [`crate::spec::adcom::event_types`]
+    pub event: u32,
+    /// Tracking method, see [`crate::spec::adcom::event_tracking_methods`]
+    pub method: u32,
+    pub url: String,
+}
+
+/// Native tracking URLs to inject into a native response
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default, Builder)]
+pub struct NativeTrackers {
+    /// Image pixel fired when impression occurs
+    #[builder(default)]
+    pub impression: Option<String>,
+
+    /// JavaScript tracker loaded when impression occurs
+    #[builder(default)]
+    pub impression_js: Option<String>,
+
+    /// Fired when user clicks the ad
+    #[builder(default)]
+    pub click_tracking: Option<String>,
+
+    /// Additional event trackers, only injected as `eventtrackers`
+    #[builder(default)]
+    pub event_trackers: Vec<NativeEventTracker>,
+
+    /// Where impression trackers are placed
+    #[builder(default)]
+    pub placement: NativeTrackerPlacement,
+}
+
+/// Injects trackers into a native response.
+///
+/// Impression trackers go into `eventtrackers` with the AdCom event and method
+/// codes, or into the legacy `imptrackers`/`jstracker` fields depending on
+/// [`NativeTrackers::placement`]. Click trackers are appended to the main
+/// `link.clicktrackers` and to every asset which carries its own link, since an
+/// asset link replaces the main link when that asset is clicked.
+///
+/// # Errors
+/// Returns an error, leaving the response untouched, if a tracker URL is
+/// invalid, or if a click tracker was provided but the response has no link
+/// to attach it to.
+pub fn inject_native_trackers(
+    response: &mut NativeResponse,
+    trackers: &NativeTrackers,
+) -> Result<()> {
+    let legacy = match trackers.placement {
+        NativeTrackerPlacement::EventTrackers => false,
+        NativeTrackerPlacement::Legacy => true,
+        NativeTrackerPlacement::Auto => uses_legacy_trackers(response),
+    };
+
+    // everything is checked up front, so that a failure leaves the response
+    // as it was
+    let urls = [
+        &trackers.impression,
+        &trackers.impression_js,
+        &trackers.click_tracking,
+    ];
+    let event_urls = trackers.event_trackers.iter().map(|tracker| &tracker.url);
+    for url in urls.into_iter().flatten().chain(event_urls) {
+        validate_url(url)?;
+    }
+
+    let script = match trackers.impression_js.as_deref() {
+        Some(url) if legacy => Some(html_script(url)?),
+        _ => None,
+    };
+
+    let has_link =
+        response.link.is_some() || response.assets.iter().any(|asset| asset.link.is_some());
+    if trackers.click_tracking.is_some() && !has_link {
+        bail!("Click tracker was provided but the native response has no link");
+    }
+
+    if let Some(url) = trackers.impression.as_deref() {
+        if legacy {
+            response.imptrackers.push(url.to_string());
+        } else {
+            push_event_tracker(
+                response,
+                event_types::IMPRESSION,
+                event_tracking_methods::IMAGE_PIXEL,
+                url,
+            );
+        }
+    }
+
+    if let Some(script) = script {
+        response.jstracker.push_str(&script);
+    } else if let Some(url) = trackers.impression_js.as_deref() {
+        push_event_tracker(
+            response,
+            event_types::IMPRESSION,
+            event_tracking_methods::JAVASCRIPT,
+            url,
+        );
+    }
+
+    for tracker in &trackers.event_trackers {
+        push_event_tracker(response, tracker.event, tracker.method, &tracker.url);
+    }
+
+    if let Some(url) = trackers.click_tracking.as_deref() {
+        let asset_links = response
+            .assets
+            .iter_mut()
+            .filter_map(|asset| asset.link.as_mut());
+        for link in response.link.iter_mut().chain(asset_links) {
+            link.clicktrackers.push(url.to_string());
+        }
+    }
+
+    Ok(())
+}
+
+/// Injects trackers into textual native markup, preserving the
+/// `{"native":{...}}` wrapper if the markup used one.
+pub fn inject_native_adm_trackers(adm: &str, trackers: &NativeTrackers) -> Result<String> {
+    let (mut response, wrapped) = parse_native_response_wrapped(adm)?;
+    inject_native_trackers(&mut response, trackers)?;
+    native_response_to_string(&response, wrapped)
+}
+
+/// Injects trackers into a native bid, whether its markup is textual adm
+/// (JSON) or an [`AdmOneof::AdmNative`] message (protobuf). The adm keeps the
+/// form it was received in.
+pub fn inject_native_bid_trackers(bid: &mut Bid, trackers: &NativeTrackers) -> Result<()> {
+    match bid.adm_oneof.as_mut() {
+        Some(AdmOneof::Adm(adm)) => {
+            *adm = inject_native_adm_trackers(adm, trackers)?;
+            Ok(())
+        }
+        Some(AdmOneof::AdmNative(response)) => inject_native_trackers(response, trackers),
+        None => bail!("No adm present on bid"),
+    }
+}
+
+fn uses_legacy_trackers(response: &NativeResponse) -> bool {
+    let ver = response.ver.trim();
+    if ver.starts_with("1.0") || ver.starts_with("1.1") {
+        return true;
+    }
+
+    response.eventtrackers.is_empty()
+        && (!response.imptrackers.is_empty() || !response.jstracker.is_empty())
+}
+
+fn push_event_tracker(response: &mut NativeResponse, event: u32, method: u32, url: &str) {
+    response.eventtrackers.push(native_response::EventTracker {
+        event: event as i32,
+        method: method as i32,
+        url: url.to_string(),
+        ..Default::default()
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NATIVE_12: &str = r#"{"ver":"1.2","assets":[
+        {"id":1,"title":{"text":"Title"}},
+        {"id":2,"img":{"url":"https://cdn.example.com/a.png"},"link":{"url":"https://example.com/asset"}}
+    ],"link":{"url":"https://example.com/click","clicktrackers":["https://existing.com/c"]}}"#;
+
+    const NATIVE_11_WRAPPED: &str = r#"{"native":{"ver":"1.1","assets":[],
+        "link":{"url":"https://example.com/click"},
+        "imptrackers":["https://existing.com/imp"]}}"#;
+
+    fn trackers() -> NativeTrackers {
+        NativeTrackersBuilder::default()
+            .impression(Some("https://billing.example.com/imp".to_string()))
+            .impression_js(Some("https://billing.example.com/imp.js".to_string()))
+            .click_tracking(Some("https://billing.example.com/click".to_string()))
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_event_trackers_for_native_12() {
+        let adm = inject_native_adm_trackers(NATIVE_12, &trackers()).unwrap();
+        let response = parse_native_response_wrapped(&adm).unwrap().0;
+
+        assert_eq!(response.eventtrackers.len(), 2);
+        assert_eq!(
+            response.eventtrackers[0].event,
+            event_types::IMPRESSION as i32
+        );
+        assert_eq!(
+            response.eventtrackers[0].method,
+            event_tracking_methods::IMAGE_PIXEL as i32
+        );
+        assert_eq!(
+            response.eventtrackers[1].method,
+            event_tracking_methods::JAVASCRIPT as i32
+        );
+        assert!(response.imptrackers.is_empty());
+    }
+
+    #[test]
+    fn test_click_trackers_on_main_and_asset_links() {
+        let adm = inject_native_adm_trackers(NATIVE_12, &trackers()).unwrap();
+        let response = parse_native_response_wrapped(&adm).unwrap().0;
+
+        let link = response.link.as_ref().unwrap();
+        assert_eq!(
+            link.clicktrackers,
+            vec![
+                "https://existing.com/c".to_string(),
+                "https://billing.example.com/click".to_string()
+            ]
+        );
+
+        let asset_link = response.asset(2).unwrap().link.as_ref().unwrap();
+        assert_eq!(
+            asset_link.clicktrackers,
+            vec!["https://billing.example.com/click"]
+        );
+        assert!(response.asset(1).unwrap().link.is_none());
+    }
+
+    #[test]
+    fn test_legacy_trackers_preserve_wrapper() {
+        let adm = inject_native_adm_trackers(NATIVE_11_WRAPPED, &trackers()).unwrap();
+        assert!(adm.starts_with(r#"{"native":"#));
+
+        let (response, wrapped) = parse_native_response_wrapped(&adm).unwrap();
+        assert!(wrapped);
+        assert_eq!(
+            response.imptrackers,
+            vec![
+                "https://existing.com/imp",
+                "https://billing.example.com/imp"
+            ]
+        );
+        assert_eq!(
+            response.jstracker,
+            r#"<script src="https://billing.example.com/imp.js"></script>"#
+        );
+        assert!(response.eventtrackers.is_empty());
+    }
+
+    #[test]
+    fn test_explicit_placement_overrides_auto() {
+        let trackers = NativeTrackersBuilder::default()
+            .impression(Some("https://billing.example.com/imp".to_string()))
+            .placement(NativeTrackerPlacement::EventTrackers)
+            .build()
+            .unwrap();
+
+        let adm = inject_native_adm_trackers(NATIVE_11_WRAPPED, &trackers).unwrap();
+        let response = parse_native_response_wrapped(&adm).unwrap().0;
+
+        assert_eq!(response.eventtrackers.len(), 1);
+        assert_eq!(response.imptrackers.len(), 1);
+    }
+
+    #[test]
+    fn test_custom_event_trackers() {
+        let trackers = NativeTrackersBuilder::default()
+            .event_trackers(vec![NativeEventTracker {
+                event: event_types::VIEWABLE_MRC50,
+                method: event_tracking_methods::IMAGE_PIXEL,
+                url: "https://viewability.example.com/v".to_string(),
+            }])
+            .build()
+            .unwrap();
+
+        let adm = inject_native_adm_trackers(NATIVE_12, &trackers).unwrap();
+        let response = parse_native_response_wrapped(&adm).unwrap().0;
+
+        assert_eq!(
+            response.eventtrackers[0].event,
+            event_types::VIEWABLE_MRC50 as i32
+        );
+    }
+
+    #[test]
+    fn test_inject_into_adm_native_bid() {
+        let response = parse_native_response_wrapped(NATIVE_12).unwrap().0;
+        let mut bid = Bid {
+            id: "1".to_string(),
+            impid: "1".to_string(),
+            adm_oneof: Some(AdmOneof::AdmNative(response)),
+            ..Default::default()
+        };
+
+        inject_native_bid_trackers(&mut bid, &trackers()).unwrap();
+
+        let Some(AdmOneof::AdmNative(response)) = &bid.adm_oneof else {
+            panic!("expected native adm to be preserved");
+        };
+        assert_eq!(response.eventtrackers.len(), 2);
+    }
+
+    #[test]
+    fn test_inject_into_textual_bid() {
+        let mut bid = Bid {
+            id: "1".to_string(),
+            impid: "1".to_string(),
+            adm_oneof: Some(AdmOneof::Adm(NATIVE_12.to_string())),
+            ..Default::default()
+        };
+
+        inject_native_bid_trackers(&mut bid, &trackers()).unwrap();
+
+        let Some(AdmOneof::Adm(adm)) = &bid.adm_oneof else {
+            panic!("expected textual adm to be preserved");
+        };
+        assert!(adm.contains("https://billing.example.com/imp"));
+    }
+
+    #[test]
+    fn test_click_without_link_fails() {
+        let trackers = NativeTrackersBuilder::default()
+            .click_tracking(Some("https://billing.example.com/click".to_string()))
+            .build()
+            .unwrap();
+
+        let result = inject_native_adm_trackers(r#"{"ver":"1.2","assets":[]}"#, &trackers);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_invalid_url_fails() {
+        let trackers = NativeTrackersBuilder::default()
+            .impression(Some("javascript:alert(1)".to_string()))
+            .build()
+            .unwrap();
+
+        assert!(inject_native_adm_trackers(NATIVE_12, &trackers).is_err());
+    }
+
+    #[test]
+    fn test_failure_leaves_response_untouched() {
+        let original = parse_native_response_wrapped(NATIVE_11_WRAPPED).unwrap().0;
+
+        let bad_click = NativeTrackersBuilder::default()
+            .impression(Some("https://billing.example.com/imp".to_string()))
+            .click_tracking(Some("javascript:alert(1)".to_string()))
+            .build()
+            .unwrap();
+        let bad_script = NativeTrackersBuilder::default()
+            .impression(Some("https://billing.example.com/imp".to_string()))
+            .impression_js(Some(r#"https://a.example.com/x.js"><b>"#.to_string()))
+            .build()
+            .unwrap();
+        let no_link = NativeTrackersBuilder::default()
+            .impression(Some("https://billing.example.com/imp".to_string()))
+            .click_tracking(Some("https://billing.example.com/click".to_string()))
+            .build()
+            .unwrap();
+
+        for trackers in [bad_click, bad_script] {
+            let mut response = original.clone();
+            assert!(inject_native_trackers(&mut response, &trackers).is_err());
+            assert_eq!(response, original);
+        }
+
+        let mut response = original.clone();
+        response.link = None;
+        let unlinked = response.clone();
+        assert!(inject_native_trackers(&mut response, &no_link).is_err());
+        assert_eq!(response, unlinked);
+    }
+}
@@ -3,6 +3,8 @@
 pub enum PixelError {
     EmptyUrl,
     InvalidScheme,
+    /// A quote or angle bracket, which would break out of an HTML attribute
+    UnsafeCharacter,
 }
 
 impl std::fmt::Display for PixelError {
@@ -10,6 +12,7 @@ impl std::fmt::Display for PixelError {
         match self {
             PixelError::EmptyUrl => write!(f, "URL cannot be empty"),
             PixelError::InvalidScheme => write!(f, "URL must start with http:// or https://"),
+            PixelError::UnsafeCharacter => write!(f, r#"URL must not contain `"`, `<` or `>`"#),
         }
     }
 }
@@ -17,7 +20,7 @@ impl std::fmt::Display for PixelError {
 impl std::error::Error for PixelError {}
 
 /// Validates a URL for use in a tracking pixel
-fn validate_url(url: &str) -> Result<(), PixelError> {
+pub(crate) fn validate_url(url: &str) -> Result<(), PixelError> {
     let trimmed = url.trim();
 
     if trimmed.is_empty() {
@@ -31,6 +34,18 @@ fn validate_url(url: &str) -> Result<(), PixelError> {
     Ok(())
 }
 
+/// Generates a `<script>` tag loading a JavaScript tracker. URLs holding
+/// characters which would end the `src` attribute are rejected rather than
+/// escaped, since a well formed URL percent-encodes them.
+pub(crate) fn html_script(url: &str) -> Result<String, PixelError> {
+    validate_url(url)?;
+    if url.contains(['"', '<', '>']) {
+        return Err(PixelError::UnsafeCharacter);
+    }
+
+    Ok(format!(r#"<script src="{url}"></script>"#))
+}
+
 /// Generates HTML for a 1x1 transparent tracking pixel
 ///
 /// # Arguments
@@ -116,6 +131,22 @@ mod tests {
         assert_eq!(result.unwrap_err(), PixelError::InvalidScheme);
     }
 
+    #[test]
+    fn test_html_script() {
+        assert_eq!(
+            html_script("https://example.com/t.js?a=1&b=2").unwrap(),
+            r#"<script src="https://example.com/t.js?a=1&b=2"></script>"#
+        );
+        assert_eq!(
+            html_script(r#"https://example.com/t.js"></script><script src="x"#).unwrap_err(),
+            PixelError::UnsafeCharacter
+        );
+        assert_eq!(
+            html_script("javascript:alert(1)").unwrap_err(),
+            PixelError::InvalidScheme
+        );
+    }
+
     #[test]
     fn test_html_pixel_preserves_query_params() {
         let result = html_pixel("https://example.com/track?a=1&b=2");
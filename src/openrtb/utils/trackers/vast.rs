@@ -1,9 +1,26 @@
+use crate::openrtb::utils::vast::{
+    JavaScriptResource, Offset, Tracking, Verification, XmlElement, parse_version,
+};
 use anyhow::{Result, bail};
 use derive_builder::Builder;
 use quick_xml::events::{BytesCData, BytesEnd, BytesStart, Event};
 use quick_xml::{Reader, Writer};
 use serde::{Deserialize, Serialize};
-use std::io::Cursor;
+
+/// Version assumed when the document does not declare one
+const LATEST_VERSION: (u32, u32) = (4, 3);
+/// DAAST 1.0 is modelled on VAST 3.0 and shares its elements
+const DAAST_VERSION: (u32, u32) = (3, 0);
+
+/// Linear events which have no meaning without a picture
+const VIDEO_ONLY_EVENTS: &[&str] = &[
+    "fullscreen",
+    "exitFullscreen",
+    "expand",
+    "collapse",
+    "playerExpand",
+    "playerCollapse",
+];
 
 /// VAST tracking event URLs to inject into a VAST video document
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default, Builder)]
@@ -16,6 +33,10 @@ pub struct VastTrackers {
     #[builder(default)]
     pub error: Option<String>,
 
+    /// Fired when the creative is first displayed
+    #[builder(default)]
+    pub creative_view: Option<String>,
+
     /// Fired when video playback starts
     #[builder(default)]
     pub start: Option<String>,
@@ -56,53 +77,337 @@ pub struct VastTrackers {
     #[builder(default)]
     pub rewind: Option<String>,
 
-    /// Fired when video is skipped
+    /// Fired when video is skipped (VAST 3.0+)
     #[builder(default)]
     pub skip: Option<String>,
 
-    /// Fired when linear ad is closed
+    /// Fired when linear ad is closed (VAST 3.0+)
     #[builder(default)]
     pub close_linear: Option<String>,
 
-    /// Fired when user clicks the ad
+    /// Fired when the media is loaded and buffered (VAST 4.0+)
+    #[builder(default)]
+    pub loaded: Option<String>,
+
+    /// Fired when the ad will not be played (VAST 4.0+)
+    #[builder(default)]
+    pub not_used: Option<String>,
+
+    /// Fired on any other interaction with the ad (VAST 4.0+)
+    #[builder(default)]
+    pub other_ad_interaction: Option<String>,
+
+    /// Fired when the player is expanded, `expand` before VAST 4.0
+    #[builder(default)]
+    pub player_expand: Option<String>,
+
+    /// Fired when the player is collapsed, `collapse` before VAST 4.0
+    #[builder(default)]
+    pub player_collapse: Option<String>,
+
+    /// Fired when the ad meets viewability criteria (VAST 4.0+)
+    #[builder(default)]
+    pub viewable: Option<String>,
+
+    /// Fired when the ad did not meet viewability criteria (VAST 4.0+)
+    #[builder(default)]
+    pub not_viewable: Option<String>,
+
+    /// Fired when viewability could not be determined (VAST 4.0+)
+    #[builder(default)]
+    pub view_undetermined: Option<String>,
+
+    /// Fired when user clicks the ad, as Linear `VideoClicks/ClickTracking`
+    /// and `NonLinearClickTracking`
     #[builder(default)]
     pub click_tracking: Option<String>,
+
+    /// Fired when user clicks a companion ad
+    #[builder(default)]
+    pub companion_click_tracking: Option<String>,
+
+    /// Fired when a companion ad is displayed
+    #[builder(default)]
+    pub companion_creative_view: Option<String>,
+
+    /// Further trackers, for `progress` offsets, events without a dedicated
+    /// field, or additional URLs for an event
+    #[builder(default)]
+    pub additional: Vec<VastTracker>,
+
+    /// Measurement vendor scripts, written as `AdVerifications` on VAST 4.1+
+    /// and as an `AdVerifications` extension on older versions
+    #[builder(default)]
+    pub verifications: Vec<VastVerification>,
 }
 
-/// Injects tracking URLs into a VAST 2.0+ XML document.
-/// Applies inline, wrapper, and linear event trackers where the spec allows, wrapping
-/// all URLs in CDATA to preserve special characters.
-///
-/// # Errors
-/// Returns an error if:
-/// - XML parsing fails
-/// - No InLine or Wrapper tag is found
+/// An OMID verification script to load alongside the ad
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct VastVerification {
+    /// Vendor key, e.g. `company.com-omid`
+    pub vendor: String,
+    /// URL of the OMID `JavaScriptResource`
+    pub javascript_resource: String,
+    /// Whether the script may be skipped where no browser is available
+    pub browser_optional: bool,
+    /// Opaque `VerificationParameters` passed to the script
+    pub verification_parameters: Option<String>,
+    /// Fired when the script could not be executed, `[REASON]` carries the cause
+    pub verification_not_executed: Option<String>,
+}
 
-const INLINE_ALLOWED_PREFIX: &[&[u8]] = &[
-    b"AdSystem",
-    b"AdTitle",
-    b"AdServingId",
-    b"Category",
-    b"Categories",
-    b"Description",
-    b"Advertiser",
-    b"Pricing",
-    b"Survey",
-    b"Error",
-    b"Impression",
+impl VastVerification {
+    pub fn new(vendor: impl Into<String>, javascript_resource: impl Into<String>) -> Self {
+        Self {
+            vendor: vendor.into(),
+            javascript_resource: javascript_resource.into(),
+            browser_optional: false,
+            verification_parameters: None,
+            verification_not_executed: None,
+        }
+    }
+
+    fn to_element(&self) -> XmlElement {
+        Verification {
+            vendor: Some(self.vendor.clone()),
+            javascript_resources: vec![JavaScriptResource {
+                api_framework: Some("omid".to_string()),
+                browser_optional: Some(self.browser_optional),
+                url: self.javascript_resource.clone(),
+            }],
+            verification_parameters: self.verification_parameters.clone(),
+            tracking_events: self
+                .verification_not_executed
+                .iter()
+                .map(|url| Tracking::new("verificationNotExecuted", url))
+                .collect(),
+            attributes: Vec::new(),
+            extra: Vec::new(),
+        }
+        .to_element()
+    }
+}
+
+/// Event a [`VastTracker`] is attached to
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum VastEvent {
+    Impression,
+    Error,
+    Viewable,
+    NotViewable,
+    ViewUndetermined,
+    /// Linear `VideoClicks/ClickTracking` and `NonLinearClickTracking`
+    ClickTracking,
+    CompanionClickTracking,
+    CompanionCreativeView,
+    /// A Linear `Tracking` event by name, e.g. `start` or `fullscreen`
+    Tracking(String),
+    /// A Linear `progress` event at an offset, `HH:MM:SS[.mmm]` or `n%`
+    Progress(String),
+}
+
+/// A single tracking URL for an event
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct VastTracker {
+    pub event: VastEvent,
+    pub url: String,
+}
+
+impl VastTracker {
+    pub fn new(event: VastEvent, url: impl Into<String>) -> Self {
+        Self {
+            event,
+            url: url.into(),
+        }
+    }
+}
+
+/// A `Tracking` element to write
+struct TrackingRef<'a> {
+    event: &'a str,
+    offset: Option<&'a str>,
+    url: &'a str,
+}
+
+/// Trackers grouped by where they are placed, for one VAST version
+#[derive(Default)]
+struct TrackerPlan<'a> {
+    impressions: Vec<&'a str>,
+    errors: Vec<&'a str>,
+    viewable: Vec<&'a str>,
+    not_viewable: Vec<&'a str>,
+    view_undetermined: Vec<&'a str>,
+    linear: Vec<TrackingRef<'a>>,
+    non_linear: Vec<TrackingRef<'a>>,
+    companion: Vec<TrackingRef<'a>>,
+    clicks: Vec<&'a str>,
+    companion_clicks: Vec<&'a str>,
+    verifications: &'a [VastVerification],
+    /// `AdVerifications` is a direct child of InLine/Wrapper (VAST 4.1+)
+    native_verifications: bool,
+}
+
+impl<'a> TrackerPlan<'a> {
+    fn new(trackers: &'a VastTrackers, version: (u32, u32)) -> Result<Self> {
+        let v3 = version >= (3, 0);
+        let v4 = version >= (4, 0);
+        let mut plan = TrackerPlan {
+            verifications: &trackers.verifications,
+            native_verifications: version >= (4, 1),
+            ..Default::default()
+        };
+
+        let push = |list: &mut Vec<&'a str>, url: &'a Option<String>| {
+            if let Some(url) = url {
+                list.push(url.as_str());
+            }
+        };
+        push(&mut plan.impressions, &trackers.impression);
+        push(&mut plan.errors, &trackers.error);
+        push(&mut plan.viewable, &trackers.viewable);
+        push(&mut plan.not_viewable, &trackers.not_viewable);
+        push(&mut plan.view_undetermined, &trackers.view_undetermined);
+        push(&mut plan.clicks, &trackers.click_tracking);
+        push(
+            &mut plan.companion_clicks,
+            &trackers.companion_click_tracking,
+        );
+
+        let linear_events: [(&str, &Option<String>, bool); 18] = [
+            ("creativeView", &trackers.creative_view, true),
+            ("start", &trackers.start, true),
+            ("firstQuartile", &trackers.first_quartile, true),
+            ("midpoint", &trackers.midpoint, true),
+            ("thirdQuartile", &trackers.third_quartile, true),
+            ("complete", &trackers.complete, true),
+            ("mute", &trackers.mute, true),
+            ("unmute", &trackers.unmute, true),
+            ("pause", &trackers.pause, true),
+            ("resume", &trackers.resume, true),
+            ("rewind", &trackers.rewind, true),
+            ("skip", &trackers.skip, v3),
+            ("closeLinear", &trackers.close_linear, v3),
+            ("loaded", &trackers.loaded, v4),
+            ("notUsed", &trackers.not_used, v4),
+            ("otherAdInteraction", &trackers.other_ad_interaction, v4),
+            (
+                if v4 { "playerExpand" } else { "expand" },
+                &trackers.player_expand,
+                true,
+            ),
+            (
+                if v4 { "playerCollapse" } else { "collapse" },
+                &trackers.player_collapse,
+                true,
+            ),
+        ];
+
+        for (event, url, supported) in linear_events {
+            if let (Some(url), true) = (url, supported) {
+                plan.linear.push(TrackingRef {
+                    event,
+                    offset: None,
+                    url,
+                });
+            }
+        }
+
+        let tracking = |event: &'a str, url: &'a Option<String>, supported: bool| {
+            url.as_deref().filter(|_| supported).map(|url| TrackingRef {
+                event,
+                offset: None,
+                url,
+            })
+        };
+        plan.non_linear
+            .extend(tracking("creativeView", &trackers.creative_view, true));
+        plan.non_linear.extend(tracking(
+            "otherAdInteraction",
+            &trackers.other_ad_interaction,
+            v4,
+        ));
+        plan.companion.extend(tracking(
+            "creativeView",
+            &trackers.companion_creative_view,
+            true,
+        ));
+
+        for tracker in &trackers.additional {
+            let url = tracker.url.as_str();
+            match &tracker.event {
+                VastEvent::Impression => plan.impressions.push(url),
+                VastEvent::Error => plan.errors.push(url),
+                VastEvent::Viewable => plan.viewable.push(url),
+                VastEvent::NotViewable => plan.not_viewable.push(url),
+                VastEvent::ViewUndetermined => plan.view_undetermined.push(url),
+                VastEvent::ClickTracking => plan.clicks.push(url),
+                VastEvent::CompanionClickTracking => plan.companion_clicks.push(url),
+                VastEvent::CompanionCreativeView => plan.companion.push(TrackingRef {
+                    event: "creativeView",
+                    offset: None,
+                    url,
+                }),
+                VastEvent::Tracking(event) => plan.linear.push(TrackingRef {
+                    event,
+                    offset: None,
+                    url,
+                }),
+                VastEvent::Progress(offset) => {
+                    if Offset::parse(offset).is_none() {
+                        bail!("Invalid progress offset {offset:?}");
+                    }
+                    if v3 {
+                        plan.linear.push(TrackingRef {
+                            event: "progress",
+                            offset: Some(offset.trim()),
+                            url,
+                        });
+                    }
+                }
+            }
+        }
+
+        if !v4 {
+            plan.viewable.clear();
+            plan.not_viewable.clear();
+            plan.view_undetermined.clear();
+        }
+
+        Ok(plan)
+    }
+
+    fn has_viewability(&self) -> bool {
+        !self.viewable.is_empty()
+            || !self.not_viewable.is_empty()
+            || !self.view_undetermined.is_empty()
+    }
+}
+
+const INLINE_ALLOWED_PREFIX: &[&str] = &[
+    "AdSystem",
+    "AdTitle",
+    "AdServingId",
+    "Category",
+    "Categories",
+    "Description",
+    "Advertiser",
+    "Pricing",
+    "Survey",
+    "Error",
+    "Impression",
 ];
 
-const WRAPPER_ALLOWED_PREFIX: &[&[u8]] = &[
-    b"AdSystem",
-    b"VASTAdTagURI",
-    b"AdServingId",
-    b"Category",
-    b"Categories",
-    b"Description",
-    b"Pricing",
-    b"Survey",
-    b"Error",
-    b"Impression",
+const WRAPPER_ALLOWED_PREFIX: &[&str] = &[
+    "AdSystem",
+    "VASTAdTagURI",
+    "AdServingId",
+    "Category",
+    "Categories",
+    "Description",
+    "Pricing",
+    "Survey",
+    "Error",
+    "Impression",
 ];
 
 #[derive(Debug)]
@@ -111,79 +416,148 @@ enum AdContainerKind {
     Wrapper,
 }
 
-struct AdContainerState<'a> {
+/// Where verifications are written, see [`VastTrackers::verifications`]
+enum VerificationTarget {
+    /// A new `AdVerifications` element (VAST 4.1+)
+    NewAdVerifications,
+    /// Within an existing `AdVerifications` element (VAST 4.1+)
+    IntoAdVerifications,
+    /// An `Extension` within an existing `Extensions` element
+    IntoExtensions,
+    /// A new `Extensions` element holding the `Extension`
+    NewExtensions,
+}
+
+struct AdContainerState {
     kind: AdContainerKind,
-    impression: Option<&'a str>,
-    error: Option<&'a str>,
-    impression_injected: bool,
-    error_injected: bool,
+    header_injected: bool,
     seen_vast_ad_tag_uri: bool,
+    verifications_injected: bool,
+    /// A direct `ViewableImpression` child was found and injected into
+    has_viewable_impression: bool,
+    /// Output offset just after the injected header, where elements the
+    /// container turns out to lack are written once its end is reached
+    insert_at: Option<usize>,
 }
 
-impl<'a> AdContainerState<'a> {
-    fn new(kind: AdContainerKind, trackers: &'a VastTrackers) -> Self {
+impl AdContainerState {
+    fn new(kind: AdContainerKind) -> Self {
         Self {
             kind,
-            impression: trackers.impression.as_deref(),
-            error: trackers.error.as_deref(),
-            impression_injected: false,
-            error_injected: false,
+            header_injected: false,
             seen_vast_ad_tag_uri: false,
+            verifications_injected: false,
+            has_viewable_impression: false,
+            insert_at: None,
         }
     }
 
-    fn has_pending(&self) -> bool {
-        (self.impression.is_some() && !self.impression_injected)
-            || (self.error.is_some() && !self.error_injected)
-    }
-
     fn inject_if_needed<W: std::io::Write>(
         &mut self,
         writer: &mut Writer<W>,
+        plan: &TrackerPlan,
         impression_injected: &mut bool,
         error_injected: &mut bool,
     ) -> Result<()> {
-        if !self.has_pending() {
+        if self.header_injected {
             return Ok(());
         }
+        self.header_injected = true;
 
-        if let Some(url) = self.impression {
-            if !self.impression_injected {
-                write_element(writer, "Impression", url)?;
-                self.impression_injected = true;
-                *impression_injected = true;
-            }
+        for url in &plan.impressions {
+            write_element(writer, "Impression", url)?;
+            *impression_injected = true;
+        }
+
+        for url in &plan.errors {
+            write_element(writer, "Error", url)?;
+            *error_injected = true;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the `ViewableImpression` and `AdVerifications` the container
+    /// did not have among its direct children
+    fn write_missing<W: std::io::Write>(
+        &mut self,
+        writer: &mut Writer<W>,
+        plan: &TrackerPlan,
+    ) -> Result<()> {
+        if !self.has_viewable_impression && plan.has_viewability() {
+            writer.write_event(Event::Start(BytesStart::new("ViewableImpression")))?;
+            inject_viewability(writer, plan)?;
+            writer.write_event(Event::End(BytesEnd::new("ViewableImpression")))?;
+        }
+
+        if plan.native_verifications {
+            self.inject_verifications(writer, plan, VerificationTarget::NewAdVerifications)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the verifications once, shaped for where they are placed
+    fn inject_verifications<W: std::io::Write>(
+        &mut self,
+        writer: &mut Writer<W>,
+        plan: &TrackerPlan,
+        target: VerificationTarget,
+    ) -> Result<()> {
+        if self.verifications_injected || plan.verifications.is_empty() {
+            return Ok(());
+        }
+        self.verifications_injected = true;
+
+        let mut ad_verifications = XmlElement::new("AdVerifications");
+        for verification in plan.verifications {
+            ad_verifications.push(verification.to_element());
         }
 
-        if let Some(url) = self.error {
-            if !self.error_injected {
-                write_element(writer, "Error", url)?;
-                self.error_injected = true;
-                *error_injected = true;
+        let mut extension = XmlElement::new("Extension");
+        extension.set_attr("type", "AdVerifications");
+
+        match target {
+            VerificationTarget::NewAdVerifications => ad_verifications.write(writer)?,
+            VerificationTarget::IntoAdVerifications => {
+                for verification in ad_verifications.elements() {
+                    verification.write(writer)?;
+                }
+            }
+            VerificationTarget::IntoExtensions => {
+                extension.push(ad_verifications);
+                extension.write(writer)?;
+            }
+            VerificationTarget::NewExtensions => {
+                extension.push(ad_verifications);
+                let mut extensions = XmlElement::new("Extensions");
+                extensions.push(extension);
+                extensions.write(writer)?;
             }
         }
 
         Ok(())
     }
 
-    fn should_skip_child(&self, child_name: &[u8]) -> bool {
+    fn should_skip_child(&self, child_name: &str) -> bool {
         let allowed = match self.kind {
             AdContainerKind::Inline => INLINE_ALLOWED_PREFIX,
             AdContainerKind::Wrapper => WRAPPER_ALLOWED_PREFIX,
         };
 
-        allowed.iter().any(|tag| *tag == child_name)
+        allowed.contains(&child_name)
     }
 
     fn maybe_inject_before_child<W: std::io::Write>(
         &mut self,
-        child_name: &[u8],
+        child_name: &str,
         current_depth: usize,
         writer: &mut Writer<W>,
+        plan: &TrackerPlan,
         impression_injected: &mut bool,
         error_injected: &mut bool,
     ) -> Result<()> {
-        if current_depth != 0 || !self.has_pending() {
+        if current_depth != 0 || self.header_injected {
             return Ok(());
         }
 
@@ -195,111 +569,227 @@ impl<'a> AdContainerState<'a> {
             return Ok(());
         }
 
-        self.inject_if_needed(writer, impression_injected, error_injected)
+        self.inject_if_needed(writer, plan, impression_injected, error_injected)
     }
 
-    fn on_direct_child_end(&mut self, child_name: &[u8]) {
-        if matches!(self.kind, AdContainerKind::Wrapper) && child_name == b"VASTAdTagURI" {
+    fn on_direct_child_end(&mut self, child_name: &str) {
+        if matches!(self.kind, AdContainerKind::Wrapper) && child_name == "VASTAdTagURI" {
             self.seen_vast_ad_tag_uri = true;
         }
     }
+}
 
-    fn finalize<W: std::io::Write>(
-        &mut self,
-        writer: &mut Writer<W>,
-        impression_injected: &mut bool,
-        error_injected: &mut bool,
-    ) -> Result<()> {
-        self.inject_if_needed(writer, impression_injected, error_injected)
-    }
+/// Per creative state for click and companion injection
+#[derive(Default)]
+struct CreativeState {
+    video_clicks_seen: bool,
+    clicks_injected: bool,
+    tracking_events_seen: bool,
 }
 
+/// Injects tracking URLs into a VAST 2.0+ XML document.
+/// Applies inline, wrapper, and linear event trackers where the spec allows, wrapping
+/// all URLs in CDATA to preserve special characters.
+///
+/// Placement follows the declared VAST version: events a version does not
+/// define are skipped, and `playerExpand`/`playerCollapse` are written as
+/// `expand`/`collapse` before VAST 4.0. `TrackingEvents` only receive the
+/// events of their own context (Linear, NonLinearAds or Companion), and are
+/// created for a Companion which has none.
+///
+/// # Errors
+/// Returns an error if:
+/// - XML parsing fails
+/// - No InLine or Wrapper tag is found
+/// - A `progress` offset is invalid
 pub fn inject_vast_trackers(vast_xml: &str, trackers: &VastTrackers) -> Result<String> {
+    inject_trackers(vast_xml, trackers, false)
+}
+
+/// Like [`inject_vast_trackers`], also creating `TrackingEvents` for a Linear
+/// which has none, as the documents generated by the VAST builders do
+pub(crate) fn inject_generated_vast_trackers(
+    vast_xml: &str,
+    trackers: &VastTrackers,
+) -> Result<String> {
+    inject_trackers(vast_xml, trackers, true)
+}
+
+fn inject_trackers(
+    vast_xml: &str,
+    trackers: &VastTrackers,
+    create_linear_tracking: bool,
+) -> Result<String> {
     let mut reader = Reader::from_str(vast_xml);
-    reader.config_mut().trim_text(true);
+    reader.config_mut().trim_text(false);
     reader.config_mut().expand_empty_elements = true;
 
-    let mut writer = Writer::new(Cursor::new(Vec::new()));
+    let mut writer = Writer::new(Vec::new());
     let mut buf = Vec::new();
 
+    let mut plan = TrackerPlan::new(trackers, LATEST_VERSION)?;
     let mut found_ad_container = false;
     let mut impression_injected = false;
     let mut error_injected = false;
 
     let mut ad_state: Option<AdContainerState> = None;
     let mut ad_direct_depth: usize = 0;
-    let mut non_linear_depth: usize = 0;
-    let click_tracking_url = trackers.click_tracking.as_deref();
+    let mut path: Vec<String> = Vec::new();
+    let mut linear = CreativeState::default();
+    let mut companion = CreativeState::default();
 
     loop {
         match reader.read_event_into(&mut buf)? {
             Event::Start(ref e) => {
-                let name = e.name();
-                let name_slice = name.as_ref();
+                let name = String::from_utf8_lossy(e.name().as_ref()).into_owned();
+                let parent = path.last().map(String::as_str);
+
+                if name == "VAST" {
+                    let version = e
+                        .try_get_attribute("version")?
+                        .map(|a| a.unescape_value().map(|v| v.into_owned()))
+                        .transpose()?
+                        .and_then(|v| parse_version(&v))
+                        .unwrap_or(LATEST_VERSION);
+                    plan = TrackerPlan::new(trackers, version)?;
+                } else if name == "DAAST" && path.is_empty() {
+                    plan = TrackerPlan::new(trackers, DAAST_VERSION)?;
+                }
 
-                let is_ad_container = name_slice == b"InLine" || name_slice == b"Wrapper";
+                let is_ad_container = name == "InLine" || name == "Wrapper";
                 if is_ad_container {
                     found_ad_container = true;
                     writer.write_event(Event::Start(e.clone()))?;
 
-                    let kind = if name_slice == b"InLine" {
+                    let kind = if name == "InLine" {
                         AdContainerKind::Inline
                     } else {
                         AdContainerKind::Wrapper
                     };
 
-                    ad_state = Some(AdContainerState::new(kind, trackers));
+                    ad_state = Some(AdContainerState::new(kind));
                     ad_direct_depth = 0;
+                    path.push(name);
                     continue;
                 }
 
                 if let Some(ref mut state) = ad_state {
                     state.maybe_inject_before_child(
-                        name_slice,
+                        &name,
                         ad_direct_depth,
                         &mut writer,
+                        &plan,
                         &mut impression_injected,
                         &mut error_injected,
                     )?;
+                    if state.header_injected && state.insert_at.is_none() {
+                        state.insert_at = Some(writer.get_ref().len());
+                    }
                     ad_direct_depth += 1;
                 }
 
-                if name_slice == b"TrackingEvents" {
-                    writer.write_event(Event::Start(e.clone()))?;
-                    inject_video_events(&mut writer, trackers)?;
-                } else if name_slice == b"NonLinear" {
-                    writer.write_event(Event::Start(e.clone()))?;
-                    if click_tracking_url.is_some() {
-                        non_linear_depth += 1;
+                match (parent, name.as_str()) {
+                    (Some("VideoClicks"), child) if child != "ClickThrough" => {
+                        inject_clicks(&mut writer, &plan, &mut linear)?;
                     }
-                } else {
-                    writer.write_event(Event::Start(e.clone()))?;
+                    (Some("Linear"), "Icons") if !linear.video_clicks_seen => {
+                        inject_video_clicks(&mut writer, &plan, &mut linear)?;
+                    }
+                    (Some("Companion"), "TrackingEvents") => {
+                        inject_companion_clicks(&mut writer, &plan, &mut companion)?;
+                    }
+                    _ => {}
                 }
+
+                writer.write_event(Event::Start(e.clone()))?;
+
+                match (parent, name.as_str()) {
+                    (_, "Linear") => linear = CreativeState::default(),
+                    (_, "Companion") => companion = CreativeState::default(),
+                    (Some("Linear"), "TrackingEvents") => {
+                        linear.tracking_events_seen = true;
+                        inject_tracking_events(&mut writer, &plan.linear)?;
+                    }
+                    (Some("NonLinearAds"), "TrackingEvents") => {
+                        inject_tracking_events(&mut writer, &plan.non_linear)?;
+                    }
+                    (Some("Companion"), "TrackingEvents") => {
+                        companion.tracking_events_seen = true;
+                        inject_tracking_events(&mut writer, &plan.companion)?;
+                    }
+                    (Some("Linear"), "VideoClicks") => linear.video_clicks_seen = true,
+                    (Some("InLine" | "Wrapper"), "ViewableImpression") => {
+                        if let Some(ref mut state) = ad_state {
+                            state.has_viewable_impression = true;
+                        }
+                        inject_viewability(&mut writer, &plan)?;
+                    }
+                    (Some("InLine" | "Wrapper"), "AdVerifications")
+                        if plan.native_verifications =>
+                    {
+                        if let Some(ref mut state) = ad_state {
+                            state.inject_verifications(
+                                &mut writer,
+                                &plan,
+                                VerificationTarget::IntoAdVerifications,
+                            )?;
+                        }
+                    }
+                    (Some("InLine" | "Wrapper"), "Extensions") if !plan.native_verifications => {
+                        if let Some(ref mut state) = ad_state {
+                            state.inject_verifications(
+                                &mut writer,
+                                &plan,
+                                VerificationTarget::IntoExtensions,
+                            )?;
+                        }
+                    }
+                    _ => {}
+                }
+
+                path.push(name);
             }
             Event::End(ref e) => {
-                let name = e.name();
-                let name_slice = name.as_ref();
+                let name = String::from_utf8_lossy(e.name().as_ref()).into_owned();
+                path.pop();
+                let parent = path.last().map(String::as_str);
 
-                let is_ad_container = name_slice == b"InLine" || name_slice == b"Wrapper";
+                let is_ad_container = name == "InLine" || name == "Wrapper";
                 if is_ad_container {
                     if let Some(ref mut state) = ad_state {
-                        state.finalize(
+                        state.inject_if_needed(
                             &mut writer,
+                            &plan,
                             &mut impression_injected,
                             &mut error_injected,
                         )?;
+
+                        // Only now is it known which elements are missing, they
+                        // go right after the header as the spec orders them
+                        let mut missing = Writer::new(Vec::new());
+                        state.write_missing(&mut missing, &plan)?;
+                        let output = writer.get_mut();
+                        let at = state.insert_at.unwrap_or(output.len());
+                        output.splice(at..at, missing.into_inner());
+
+                        if !plan.native_verifications {
+                            state.inject_verifications(
+                                &mut writer,
+                                &plan,
+                                VerificationTarget::NewExtensions,
+                            )?;
+                        }
                     }
 
                     writer.write_event(Event::End(e.clone()))?;
                     ad_state = None;
                     ad_direct_depth = 0;
-                    non_linear_depth = 0;
                     continue;
                 }
 
                 if let Some(ref mut state) = ad_state {
                     if ad_direct_depth == 1 {
-                        state.on_direct_child_end(name_slice);
+                        state.on_direct_child_end(&name);
                     }
 
                     if ad_direct_depth > 0 {
@@ -307,13 +797,33 @@ pub fn inject_vast_trackers(vast_xml: &str, trackers: &VastTrackers) -> Result<S
                     }
                 }
 
-                if name_slice == b"NonLinear" {
-                    if let Some(url) = click_tracking_url {
-                        if non_linear_depth > 0 {
-                            non_linear_depth -= 1;
+                match (parent, name.as_str()) {
+                    (Some("Linear"), "VideoClicks") => {
+                        inject_clicks(&mut writer, &plan, &mut linear)?;
+                    }
+                    (_, "Linear") => {
+                        if create_linear_tracking
+                            && !linear.tracking_events_seen
+                            && !plan.linear.is_empty()
+                        {
+                            write_tracking_events(&mut writer, &plan.linear)?;
+                        }
+                        if !linear.video_clicks_seen {
+                            inject_video_clicks(&mut writer, &plan, &mut linear)?;
+                        }
+                    }
+                    (_, "NonLinear") => {
+                        for url in &plan.clicks {
                             write_element(&mut writer, "NonLinearClickTracking", url)?;
                         }
                     }
+                    (_, "Companion") => {
+                        inject_companion_clicks(&mut writer, &plan, &mut companion)?;
+                        if !companion.tracking_events_seen && !plan.companion.is_empty() {
+                            write_tracking_events(&mut writer, &plan.companion)?;
+                        }
+                    }
+                    _ => {}
                 }
 
                 writer.write_event(Event::End(e.clone()))?;
@@ -328,21 +838,43 @@ pub fn inject_vast_trackers(vast_xml: &str, trackers: &VastTrackers) -> Result<S
         bail!("No InLine or Wrapper tag found in VAST XML");
     }
 
-    if trackers.impression.is_some() && !impression_injected {
+    if !plan.impressions.is_empty() && !impression_injected {
         bail!(
             "Impression tracker was provided but could not be injected - VAST structure may be invalid"
         );
     }
-    if trackers.error.is_some() && !error_injected {
+    if !plan.errors.is_empty() && !error_injected {
         bail!(
             "Error tracker was provided but could not be injected - VAST structure may be invalid"
         );
     }
 
-    let output = writer.into_inner().into_inner();
+    let output = writer.into_inner();
     String::from_utf8(output).map_err(|e| e.into())
 }
 
+/// Injects tracking URLs into an audio ad, VAST with audio media files or a
+/// DAAST document.
+///
+/// Works like [`inject_vast_trackers`] but leaves out events an audio player
+/// never fires: `fullscreen`, `exitFullscreen`, player expand/collapse and
+/// viewability, so reporting does not wait on them.
+pub fn inject_audio_trackers(xml: &str, trackers: &VastTrackers) -> Result<String> {
+    let mut trackers = trackers.clone();
+    trackers.player_expand = None;
+    trackers.player_collapse = None;
+    trackers.viewable = None;
+    trackers.not_viewable = None;
+    trackers.view_undetermined = None;
+    trackers.additional.retain(|tracker| match &tracker.event {
+        VastEvent::Tracking(event) => !VIDEO_ONLY_EVENTS.contains(&event.as_str()),
+        VastEvent::Viewable | VastEvent::NotViewable | VastEvent::ViewUndetermined => false,
+        _ => true,
+    });
+
+    inject_vast_trackers(xml, &trackers)
+}
+
 /// Helper to write a simple element with CDATA content (for URLs)
 fn write_element<W: std::io::Write>(
     writer: &mut Writer<W>,
@@ -355,39 +887,97 @@ fn write_element<W: std::io::Write>(
     Ok(())
 }
 
-/// Helper to write a tracking event with event attribute
-fn inject_tracking_event<W: std::io::Write>(
+/// Helper to write a `TrackingEvents` element holding the given events
+fn write_tracking_events<W: std::io::Write>(
+    writer: &mut Writer<W>,
+    events: &[TrackingRef],
+) -> Result<()> {
+    writer.write_event(Event::Start(BytesStart::new("TrackingEvents")))?;
+    inject_tracking_events(writer, events)?;
+    writer.write_event(Event::End(BytesEnd::new("TrackingEvents")))?;
+    Ok(())
+}
+
+/// Helper to write tracking events, with the offset attribute for progress events
+fn inject_tracking_events<W: std::io::Write>(
     writer: &mut Writer<W>,
-    event_type: &str,
-    url: &Option<String>,
+    events: &[TrackingRef],
 ) -> Result<()> {
-    if let Some(url) = url {
+    for tracking in events {
         let mut elem = BytesStart::new("Tracking");
-        elem.push_attribute(("event", event_type));
+        elem.push_attribute(("event", tracking.event));
+        if let Some(offset) = tracking.offset {
+            elem.push_attribute(("offset", offset));
+        }
         writer.write_event(Event::Start(elem))?;
-        writer.write_event(Event::CData(BytesCData::new(url)))?;
+        writer.write_event(Event::CData(BytesCData::new(tracking.url)))?;
         writer.write_event(Event::End(BytesEnd::new("Tracking")))?;
     }
     Ok(())
 }
 
-/// Inject all video event trackers into Linear TrackingEvents
-fn inject_video_events<W: std::io::Write>(
+/// Write the Viewable, NotViewable and ViewUndetermined children of a ViewableImpression
+fn inject_viewability<W: std::io::Write>(writer: &mut Writer<W>, plan: &TrackerPlan) -> Result<()> {
+    for url in &plan.viewable {
+        write_element(writer, "Viewable", url)?;
+    }
+    for url in &plan.not_viewable {
+        write_element(writer, "NotViewable", url)?;
+    }
+    for url in &plan.view_undetermined {
+        write_element(writer, "ViewUndetermined", url)?;
+    }
+    Ok(())
+}
+
+/// Write Linear ClickTracking once, inside an existing VideoClicks
+fn inject_clicks<W: std::io::Write>(
     writer: &mut Writer<W>,
-    trackers: &VastTrackers,
+    plan: &TrackerPlan,
+    state: &mut CreativeState,
+) -> Result<()> {
+    if state.clicks_injected {
+        return Ok(());
+    }
+    state.clicks_injected = true;
+
+    for url in &plan.clicks {
+        write_element(writer, "ClickTracking", url)?;
+    }
+    Ok(())
+}
+
+/// Write a VideoClicks element for a Linear creative which has none
+fn inject_video_clicks<W: std::io::Write>(
+    writer: &mut Writer<W>,
+    plan: &TrackerPlan,
+    state: &mut CreativeState,
+) -> Result<()> {
+    if state.clicks_injected || plan.clicks.is_empty() {
+        return Ok(());
+    }
+
+    writer.write_event(Event::Start(BytesStart::new("VideoClicks")))?;
+    inject_clicks(writer, plan, state)?;
+    writer.write_event(Event::End(BytesEnd::new("VideoClicks")))?;
+    state.video_clicks_seen = true;
+    Ok(())
+}
+
+/// Write CompanionClickTracking once, ahead of the companion's TrackingEvents
+fn inject_companion_clicks<W: std::io::Write>(
+    writer: &mut Writer<W>,
+    plan: &TrackerPlan,
+    state: &mut CreativeState,
 ) -> Result<()> {
-    inject_tracking_event(writer, "start", &trackers.start)?;
-    inject_tracking_event(writer, "firstQuartile", &trackers.first_quartile)?;
-    inject_tracking_event(writer, "midpoint", &trackers.midpoint)?;
-    inject_tracking_event(writer, "thirdQuartile", &trackers.third_quartile)?;
-    inject_tracking_event(writer, "complete", &trackers.complete)?;
-    inject_tracking_event(writer, "mute", &trackers.mute)?;
-    inject_tracking_event(writer, "unmute", &trackers.unmute)?;
-    inject_tracking_event(writer, "pause", &trackers.pause)?;
-    inject_tracking_event(writer, "resume", &trackers.resume)?;
-    inject_tracking_event(writer, "rewind", &trackers.rewind)?;
-    inject_tracking_event(writer, "skip", &trackers.skip)?;
-    inject_tracking_event(writer, "closeLinear", &trackers.close_linear)?;
+    if state.clicks_injected {
+        return Ok(());
+    }
+    state.clicks_injected = true;
+
+    for url in &plan.companion_clicks {
+        write_element(writer, "CompanionClickTracking", url)?;
+    }
     Ok(())
 }
 
@@ -848,4 +1438,384 @@ mod tests {
         let output = result.unwrap();
         assert!(!output.contains("event=\"start\""));
     }
+
+    const VAST_FULL: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<VAST version="4.2">
+  <Ad id="full">
+    <InLine>
+      <AdSystem>Test Ad System</AdSystem>
+      <AdTitle>Full Ad</AdTitle>
+      <Creatives>
+        <Creative>
+          <Linear>
+            <Duration>00:00:30</Duration>
+            <TrackingEvents>
+              <Tracking event="start"><![CDATA[https://existing.com/start]]></Tracking>
+            </TrackingEvents>
+            <VideoClicks>
+              <ClickThrough><![CDATA[https://advertiser.com]]></ClickThrough>
+              <ClickTracking><![CDATA[https://existing.com/click]]></ClickTracking>
+            </VideoClicks>
+            <MediaFiles>
+              <MediaFile>https://example.com/video.mp4</MediaFile>
+            </MediaFiles>
+          </Linear>
+        </Creative>
+        <Creative>
+          <NonLinearAds>
+            <NonLinear>
+              <StaticResource>https://example.com/overlay.jpg</StaticResource>
+            </NonLinear>
+            <TrackingEvents>
+            </TrackingEvents>
+          </NonLinearAds>
+        </Creative>
+        <Creative>
+          <CompanionAds>
+            <Companion width="300" height="250">
+              <StaticResource creativeType="image/png">https://example.com/c.png</StaticResource>
+              <CompanionClickThrough>https://advertiser.com/c</CompanionClickThrough>
+              <TrackingEvents>
+                <Tracking event="creativeView"><![CDATA[https://existing.com/cv]]></Tracking>
+              </TrackingEvents>
+            </Companion>
+            <Companion width="728" height="90">
+              <StaticResource creativeType="image/png">https://example.com/l.png</StaticResource>
+            </Companion>
+          </CompanionAds>
+        </Creative>
+      </Creatives>
+    </InLine>
+  </Ad>
+</VAST>"#;
+
+    fn section<'a>(xml: &'a str, open: &str, close: &str) -> &'a str {
+        let start = xml.find(open).unwrap();
+        let end = start + xml[start..].find(close).unwrap();
+        &xml[start..end]
+    }
+
+    #[test]
+    fn test_linear_click_tracking_in_video_clicks() {
+        let trackers = VastTrackersBuilder::default()
+            .click_tracking(Some("https://billing.example.com/click".to_string()))
+            .build()
+            .unwrap();
+
+        let result = inject_vast_trackers(VAST_FULL, &trackers).unwrap();
+        let clicks = section(&result, "<VideoClicks>", "</VideoClicks>");
+
+        let through = clicks.find("<ClickThrough>").unwrap();
+        let ours = clicks
+            .find("<ClickTracking><![CDATA[https://billing.example.com/click]]></ClickTracking>")
+            .unwrap();
+        assert!(through < ours);
+        assert!(clicks.contains("https://existing.com/click"));
+        assert_eq!(result.matches("<VideoClicks>").count(), 1);
+    }
+
+    #[test]
+    fn test_linear_click_tracking_creates_video_clicks() {
+        let trackers = VastTrackersBuilder::default()
+            .click_tracking(Some("https://billing.example.com/click".to_string()))
+            .build()
+            .unwrap();
+
+        let result = inject_vast_trackers(VAST_INLINE, &trackers).unwrap();
+
+        assert!(result.contains(
+            "<VideoClicks><ClickTracking><![CDATA[https://billing.example.com/click]]></ClickTracking></VideoClicks></Linear>"
+        ));
+    }
+
+    #[test]
+    fn test_multiple_urls_and_progress() {
+        let trackers = VastTrackersBuilder::default()
+            .impression(Some("https://a.example.com/imp".to_string()))
+            .start(Some("https://a.example.com/start".to_string()))
+            .additional(vec![
+                VastTracker::new(VastEvent::Impression, "https://b.example.com/imp"),
+                VastTracker::new(
+                    VastEvent::Tracking("start".to_string()),
+                    "https://b.example.com/start",
+                ),
+                VastTracker::new(
+                    VastEvent::Progress("00:00:05".to_string()),
+                    "https://a.example.com/p5",
+                ),
+                VastTracker::new(VastEvent::ClickTracking, "https://b.example.com/click"),
+            ])
+            .build()
+            .unwrap();
+
+        let result = inject_vast_trackers(VAST_FULL, &trackers).unwrap();
+
+        assert!(result.contains("<Impression><![CDATA[https://b.example.com/imp]]></Impression>"));
+        assert_eq!(result.matches(r#"event="start""#).count(), 3);
+        assert!(result.contains(
+            r#"<Tracking event="progress" offset="00:00:05"><![CDATA[https://a.example.com/p5]]></Tracking>"#
+        ));
+        assert!(
+            result
+                .contains("<ClickTracking><![CDATA[https://b.example.com/click]]></ClickTracking>")
+        );
+    }
+
+    #[test]
+    fn test_invalid_progress_offset_fails() {
+        let trackers = VastTrackersBuilder::default()
+            .additional(vec![VastTracker::new(
+                VastEvent::Progress("soon".to_string()),
+                "https://a.example.com/p",
+            )])
+            .build()
+            .unwrap();
+
+        assert!(inject_vast_trackers(VAST_FULL, &trackers).is_err());
+    }
+
+    #[test]
+    fn test_new_linear_events() {
+        let trackers = VastTrackersBuilder::default()
+            .creative_view(Some("https://a.example.com/cv".to_string()))
+            .loaded(Some("https://a.example.com/loaded".to_string()))
+            .not_used(Some("https://a.example.com/notused".to_string()))
+            .other_ad_interaction(Some("https://a.example.com/other".to_string()))
+            .player_expand(Some("https://a.example.com/expand".to_string()))
+            .player_collapse(Some("https://a.example.com/collapse".to_string()))
+            .build()
+            .unwrap();
+
+        let result = inject_vast_trackers(VAST_FULL, &trackers).unwrap();
+        let linear = section(&result, "<Linear>", "</Linear>");
+
+        for event in [
+            "creativeView",
+            "loaded",
+            "notUsed",
+            "otherAdInteraction",
+            "playerExpand",
+            "playerCollapse",
+        ] {
+            assert!(linear.contains(&format!(r#"event="{event}""#)), "{event}");
+        }
+
+        let non_linear = section(&result, "<NonLinearAds>", "</NonLinearAds>");
+        assert!(non_linear.contains(r#"event="creativeView""#));
+        assert!(non_linear.contains(r#"event="otherAdInteraction""#));
+        assert!(!non_linear.contains(r#"event="loaded""#));
+    }
+
+    #[test]
+    fn test_version_aware_placement() {
+        let vast3 = VAST_FULL.replace(r#"version="4.2""#, r#"version="3.0""#);
+        let trackers = VastTrackersBuilder::default()
+            .player_expand(Some("https://a.example.com/expand".to_string()))
+            .loaded(Some("https://a.example.com/loaded".to_string()))
+            .skip(Some("https://a.example.com/skip".to_string()))
+            .viewable(Some("https://a.example.com/viewable".to_string()))
+            .build()
+            .unwrap();
+
+        let result = inject_vast_trackers(&vast3, &trackers).unwrap();
+        assert!(result.contains(r#"event="expand""#));
+        assert!(!result.contains(r#"event="playerExpand""#));
+        assert!(!result.contains(r#"event="loaded""#));
+        assert!(result.contains(r#"event="skip""#));
+        assert!(!result.contains("<ViewableImpression>"));
+
+        let vast2 = VAST_FULL.replace(r#"version="4.2""#, r#"version="2.0""#);
+        let result = inject_vast_trackers(&vast2, &trackers).unwrap();
+        assert!(!result.contains(r#"event="skip""#));
+    }
+
+    #[test]
+    fn test_viewable_impression() {
+        let trackers = VastTrackersBuilder::default()
+            .impression(Some("https://a.example.com/imp".to_string()))
+            .viewable(Some("https://a.example.com/viewable".to_string()))
+            .not_viewable(Some("https://a.example.com/notviewable".to_string()))
+            .view_undetermined(Some("https://a.example.com/undetermined".to_string()))
+            .build()
+            .unwrap();
+
+        let result = inject_vast_trackers(VAST_FULL, &trackers).unwrap();
+        assert!(result.contains(
+            "<ViewableImpression><Viewable><![CDATA[https://a.example.com/viewable]]></Viewable>\
+             <NotViewable><![CDATA[https://a.example.com/notviewable]]></NotViewable>\
+             <ViewUndetermined><![CDATA[https://a.example.com/undetermined]]></ViewUndetermined>\
+             </ViewableImpression>"
+        ));
+
+        let existing = VAST_FULL.replace(
+            "<AdTitle>Full Ad</AdTitle>",
+            r#"<AdTitle>Full Ad</AdTitle><ViewableImpression id="v"><Viewable><![CDATA[https://existing.com/v]]></Viewable></ViewableImpression>"#,
+        );
+        let result = inject_vast_trackers(&existing, &trackers).unwrap();
+        assert_eq!(result.matches("<ViewableImpression").count(), 1);
+
+        let viewable = section(&result, "<ViewableImpression", "</ViewableImpression>");
+        assert!(viewable.contains("https://existing.com/v"));
+        assert!(viewable.contains("https://a.example.com/viewable"));
+    }
+
+    #[test]
+    fn test_companion_trackers() {
+        let trackers = VastTrackersBuilder::default()
+            .start(Some("https://a.example.com/start".to_string()))
+            .companion_click_tracking(Some("https://a.example.com/cclick".to_string()))
+            .companion_creative_view(Some("https://a.example.com/ccv".to_string()))
+            .build()
+            .unwrap();
+
+        let result = inject_vast_trackers(VAST_FULL, &trackers).unwrap();
+        let companions = section(&result, "<CompanionAds>", "</CompanionAds>");
+
+        assert!(!companions.contains(r#"event="start""#));
+        assert_eq!(
+            companions
+                .matches("<CompanionClickTracking><![CDATA[https://a.example.com/cclick]]></CompanionClickTracking>")
+                .count(),
+            2
+        );
+        assert_eq!(
+            companions
+                .matches(r#"<Tracking event="creativeView"><![CDATA[https://a.example.com/ccv]]></Tracking>"#)
+                .count(),
+            2
+        );
+
+        let first = section(companions, "<Companion ", "</Companion>");
+        assert!(
+            first.find("<CompanionClickTracking>").unwrap()
+                < first.find("<TrackingEvents>").unwrap()
+        );
+
+        let non_linear = section(&result, "<NonLinearAds>", "</NonLinearAds>");
+        assert!(!non_linear.contains(r#"event="start""#));
+    }
+
+    fn verification_trackers() -> VastTrackers {
+        let mut verification =
+            VastVerification::new("company.com-omid", "https://verify.example.com/omid.js");
+        verification.verification_parameters = Some(r#"{"id":"a&b"}"#.to_string());
+        verification.verification_not_executed =
+            Some("https://verify.example.com/ne?r=[REASON]".to_string());
+
+        VastTrackersBuilder::default()
+            .verifications(vec![verification])
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_ad_verifications_created() {
+        let vast = VAST_INLINE.replace(r#"version="4.0""#, r#"version="4.2""#);
+        let result = inject_vast_trackers(&vast, &verification_trackers()).unwrap();
+
+        let verifications = section(&result, "<AdVerifications>", "</AdVerifications>");
+        assert!(verifications.contains(r#"<Verification vendor="company.com-omid">"#));
+        assert!(verifications.contains(
+            r#"<JavaScriptResource apiFramework="omid" browserOptional="false"><![CDATA[https://verify.example.com/omid.js]]></JavaScriptResource>"#
+        ));
+        assert!(verifications.contains(
+            r#"<Tracking event="verificationNotExecuted"><![CDATA[https://verify.example.com/ne?r=[REASON]]]></Tracking>"#
+        ));
+        assert!(verifications.contains(
+            r#"<VerificationParameters><![CDATA[{"id":"a&b"}]]></VerificationParameters>"#
+        ));
+        assert!(result.find("<AdVerifications>").unwrap() < result.find("<Creatives>").unwrap());
+        assert!(!result.contains("<Extensions>"));
+    }
+
+    #[test]
+    fn test_existing_ad_verifications_extended() {
+        let vast = VAST_INLINE
+            .replace(r#"version="4.0""#, r#"version="4.1""#)
+            .replace(
+                "<Creatives>",
+                r#"<AdVerifications><Verification vendor="other"/></AdVerifications><Creatives>"#,
+            );
+        let result = inject_vast_trackers(&vast, &verification_trackers()).unwrap();
+
+        assert_eq!(result.matches("<AdVerifications>").count(), 1);
+        let verifications = section(&result, "<AdVerifications>", "</AdVerifications>");
+        assert!(verifications.contains(r#"<Verification vendor="other"></Verification>"#));
+        assert!(verifications.contains(r#"<Verification vendor="company.com-omid">"#));
+    }
+
+    #[test]
+    fn test_nested_elements_do_not_count_as_existing() {
+        let mut trackers = verification_trackers();
+        trackers.viewable = Some("https://a.example.com/viewable".to_string());
+
+        let vast = VAST_INLINE
+            .replace(r#"version="4.0""#, r#"version="4.2""#)
+            .replace(
+                "</Creatives>",
+                r#"</Creatives><Extensions><Extension type="vendor"><ViewableImpression/><AdVerifications/></Extension></Extensions>"#,
+            );
+        let result = inject_vast_trackers(&vast, &trackers).unwrap();
+
+        let created = result.find("<ViewableImpression>").unwrap();
+        assert!(created < result.find("<AdVerifications>").unwrap());
+        assert!(result.find("<AdVerifications>").unwrap() < result.find("<Creatives>").unwrap());
+        assert!(result[created..].contains("https://a.example.com/viewable"));
+
+        let extension = section(&result, "<Extension type=\"vendor\">", "</Extension>");
+        assert!(!extension.contains("https://a.example.com/viewable"));
+        assert!(!extension.contains("company.com-omid"));
+    }
+
+    #[test]
+    fn test_verifications_extension_before_4_1() {
+        let result = inject_vast_trackers(VAST_INLINE, &verification_trackers()).unwrap();
+
+        let extensions = section(&result, "<Extensions>", "</Extensions>");
+        assert!(extensions.contains(r#"<Extension type="AdVerifications"><AdVerifications>"#));
+        assert!(extensions.contains(r#"<Verification vendor="company.com-omid">"#));
+        assert!(result.find("</Creatives>").unwrap() < result.find("<Extensions>").unwrap());
+
+        let vast = VAST_INLINE.replace(
+            "</Creatives>",
+            r#"</Creatives><Extensions><Extension type="other"/></Extensions>"#,
+        );
+        let result = inject_vast_trackers(&vast, &verification_trackers()).unwrap();
+
+        assert_eq!(result.matches("<Extensions>").count(), 1);
+        let extensions = section(&result, "<Extensions>", "</Extensions>");
+        assert!(extensions.contains(r#"<Extension type="other"></Extension>"#));
+        assert!(extensions.contains(r#"<Extension type="AdVerifications">"#));
+    }
+
+    #[test]
+    fn test_audio_trackers_skip_video_events() {
+        let daast = VAST_INLINE
+            .replace(r#"<VAST version="4.0">"#, r#"<DAAST version="1.0">"#)
+            .replace("</VAST>", "</DAAST>");
+        let trackers = VastTrackersBuilder::default()
+            .impression(Some("https://a.example.com/imp".to_string()))
+            .start(Some("https://a.example.com/start".to_string()))
+            .skip(Some("https://a.example.com/skip".to_string()))
+            .loaded(Some("https://a.example.com/loaded".to_string()))
+            .player_expand(Some("https://a.example.com/expand".to_string()))
+            .viewable(Some("https://a.example.com/viewable".to_string()))
+            .additional(vec![VastTracker::new(
+                VastEvent::Tracking("fullscreen".to_string()),
+                "https://a.example.com/fullscreen",
+            )])
+            .build()
+            .unwrap();
+
+        let result = inject_audio_trackers(&daast, &trackers).unwrap();
+
+        assert!(result.contains("<Impression><![CDATA[https://a.example.com/imp]]></Impression>"));
+        assert!(result.contains(r#"event="start""#));
+        // DAAST follows VAST 3.0 placement
+        assert!(result.contains(r#"event="skip""#));
+        assert!(!result.contains(r#"event="loaded""#));
+        assert!(!result.contains("expand"));
+        assert!(!result.contains("fullscreen"));
+        assert!(!result.contains("ViewableImpression"));
+    }
 }
@@ -0,0 +1,537 @@
+use super::element::XmlElement;
+use super::model::{
+    Ad, AdContent, AdSystem, Creative, InLine, Linear, MediaFile, Offset, Pricing, TrackedUrl,
+    Vast, VideoClicks, Wrapper, parse_version,
+};
+use crate::openrtb::utils::trackers::{
+    VastEvent, VastTrackers, inject_generated_vast_trackers, validate_url,
+};
+use anyhow::{Context, Result, bail};
+use derive_builder::Builder;
+use std::time::Duration;
+
+/// Version written by the VAST builders unless one is set
+pub const DEFAULT_VAST_VERSION: &str = "4.2";
+
+/// A Wrapper ad pointing the player at another VAST tag, e.g. a bidder's
+/// `nurl`, carrying our own trackers.
+///
+/// # Example
+/// ```
+/// use rtb::openrtb::utils::trackers::VastTrackersBuilder;
+/// use rtb::openrtb::utils::vast::{Vast, VastWrapperAdBuilder};
+///
+/// let xml = VastWrapperAdBuilder::default()
+///     .ad_system("Exchange".to_string())
+///     .vast_ad_tag_uri("https://bidder.example.com/vast?id=1&p=2".to_string())
+///     .trackers(
+///         VastTrackersBuilder::default()
+///             .impression(Some("https://exchange.example.com/imp".to_string()))
+///             .build()
+///             .unwrap(),
+///     )
+///     .build()
+///     .unwrap()
+///     .to_xml_string()
+///     .unwrap();
+///
+/// let vast = Vast::parse(&xml).unwrap();
+/// let wrapper = vast.ads[0].wrapper().unwrap();
+/// assert_eq!(wrapper.vast_ad_tag_uri, "https://bidder.example.com/vast?id=1&p=2");
+/// ```
+#[derive(Debug, Clone, PartialEq, Builder)]
+pub struct VastWrapperAd {
+    /// VAST version to emit, 2.0 to 4.3
+    #[builder(default = "DEFAULT_VAST_VERSION.to_string()")]
+    pub version: String,
+
+    #[builder(default)]
+    pub id: Option<String>,
+
+    /// Name of the ad server returning the wrapper
+    pub ad_system: String,
+
+    #[builder(default)]
+    pub ad_system_version: Option<String>,
+
+    /// URL of the wrapped VAST tag
+    pub vast_ad_tag_uri: String,
+
+    /// VAST 3.0+ wrapper attributes
+    #[builder(default)]
+    pub follow_additional_wrappers: Option<bool>,
+
+    #[builder(default)]
+    pub allow_multiple_ads: Option<bool>,
+
+    #[builder(default)]
+    pub fallback_on_no_ad: Option<bool>,
+
+    /// VAST 3.0+
+    #[builder(default)]
+    pub pricing: Option<Pricing>,
+
+    /// Trackers to add, at least one impression tracker is required
+    #[builder(default)]
+    pub trackers: VastTrackers,
+}
+
+impl VastWrapperAd {
+    /// Builds the document model without trackers
+    ///
+    /// # Errors
+    /// Returns an error if the version is unsupported or a URL is invalid
+    pub fn to_vast(&self) -> Result<Vast> {
+        let version = validate_version(&self.version)?;
+        validate_url(&self.vast_ad_tag_uri)
+            .with_context(|| format!("Invalid VASTAdTagURI {}", self.vast_ad_tag_uri))?;
+
+        let v3 = version >= (3, 0);
+        let wrapper = Wrapper {
+            ad_system: Some(AdSystem {
+                name: self.ad_system.clone(),
+                version: self.ad_system_version.clone(),
+            }),
+            vast_ad_tag_uri: self.vast_ad_tag_uri.trim().to_string(),
+            follow_additional_wrappers: self.follow_additional_wrappers.filter(|_| v3),
+            allow_multiple_ads: self.allow_multiple_ads.filter(|_| v3),
+            fallback_on_no_ad: self.fallback_on_no_ad.filter(|_| v3),
+            pricing: self.pricing.clone().filter(|_| v3),
+            // gives linear trackers and click tracking a place to go
+            creatives: vec![Creative {
+                linear: Some(Linear::default()),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        Ok(single_ad_vast(
+            &self.version,
+            self.id.clone(),
+            AdContent::Wrapper(wrapper),
+        ))
+    }
+
+    /// Serialises the wrapper with its trackers
+    ///
+    /// # Errors
+    /// Returns an error if the document is invalid, see [`Self::to_vast`],
+    /// or no impression tracker was provided
+    pub fn to_xml_string(&self) -> Result<String> {
+        render(self.to_vast()?, &self.trackers)
+    }
+}
+
+/// An InLine ad with a single Linear creative, e.g. generated from a media
+/// catalogue.
+///
+/// # Example
+/// ```
+/// use rtb::openrtb::utils::trackers::VastTrackersBuilder;
+/// use rtb::openrtb::utils::vast::{MediaFile, Vast, VastInLineAdBuilder};
+/// use std::time::Duration;
+///
+/// let xml = VastInLineAdBuilder::default()
+///     .ad_system("Exchange".to_string())
+///     .ad_title("Spring Sale".to_string())
+///     .duration(Duration::from_secs(30))
+///     .media_files(vec![MediaFile {
+///         url: "https://cdn.example.com/ad.mp4".to_string(),
+///         mime_type: Some("video/mp4".to_string()),
+///         width: Some(1280),
+///         height: Some(720),
+///         ..Default::default()
+///     }])
+///     .trackers(
+///         VastTrackersBuilder::default()
+///             .impression(Some("https://exchange.example.com/imp".to_string()))
+///             .start(Some("https://exchange.example.com/start".to_string()))
+///             .build()
+///             .unwrap(),
+///     )
+///     .build()
+///     .unwrap()
+///     .to_xml_string()
+///     .unwrap();
+///
+/// let vast = Vast::parse(&xml).unwrap();
+/// let linear = vast.linears().next().unwrap();
+/// assert_eq!(linear.duration, Some(Duration::from_secs(30)));
+/// assert_eq!(linear.tracking_events[0].event, "start");
+/// ```
+#[derive(Debug, Clone, PartialEq, Builder)]
+pub struct VastInLineAd {
+    /// VAST version to emit, 2.0 to 4.3
+    #[builder(default = "DEFAULT_VAST_VERSION.to_string()")]
+    pub version: String,
+
+    #[builder(default)]
+    pub id: Option<String>,
+
+    /// Name of the ad server serving the ad
+    pub ad_system: String,
+
+    #[builder(default)]
+    pub ad_system_version: Option<String>,
+
+    pub ad_title: String,
+
+    /// VAST 4.0+
+    #[builder(default)]
+    pub ad_serving_id: Option<String>,
+
+    #[builder(default)]
+    pub description: Option<String>,
+
+    #[builder(default)]
+    pub advertiser: Option<String>,
+
+    /// VAST 3.0+
+    #[builder(default)]
+    pub pricing: Option<Pricing>,
+
+    #[builder(default)]
+    pub creative_id: Option<String>,
+
+    /// VAST 4.0+ `UniversalAdId`, written as `unknown` when not set since
+    /// VAST 4 requires one
+    #[builder(default)]
+    pub universal_ad_id: Option<String>,
+
+    #[builder(default = r#""unknown".to_string()"#)]
+    pub universal_ad_id_registry: String,
+
+    pub duration: Duration,
+
+    /// VAST 3.0+
+    #[builder(default)]
+    pub skip_offset: Option<Offset>,
+
+    /// At least one is required, `delivery` defaults to `progressive`
+    pub media_files: Vec<MediaFile>,
+
+    /// Landing page opened when the ad is clicked
+    #[builder(default)]
+    pub click_through: Option<String>,
+
+    /// Trackers to add, at least one impression tracker is required
+    #[builder(default)]
+    pub trackers: VastTrackers,
+}
+
+impl VastInLineAd {
+    /// Builds the document model without trackers
+    ///
+    /// # Errors
+    /// Returns an error if the version is unsupported, there are no media
+    /// files, or a URL is invalid
+    pub fn to_vast(&self) -> Result<Vast> {
+        let version = validate_version(&self.version)?;
+        let v3 = version >= (3, 0);
+        let v4 = version >= (4, 0);
+
+        if self.media_files.is_empty() {
+            bail!("At least one MediaFile is required");
+        }
+
+        let mut media_files = self.media_files.clone();
+        for file in media_files.iter_mut() {
+            validate_url(&file.url).with_context(|| format!("Invalid MediaFile {}", file.url))?;
+            file.url = file.url.trim().to_string();
+            if file.delivery.is_none() {
+                file.delivery = Some("progressive".to_string());
+            }
+        }
+
+        let video_clicks = match &self.click_through {
+            Some(url) => {
+                validate_url(url).with_context(|| format!("Invalid ClickThrough {url}"))?;
+                Some(VideoClicks {
+                    click_through: Some(TrackedUrl::new(url.trim())),
+                    ..Default::default()
+                })
+            }
+            None => None,
+        };
+
+        let mut creative = Creative {
+            id: self.creative_id.clone(),
+            linear: Some(Linear {
+                skip_offset: self.skip_offset.filter(|_| v3),
+                duration: Some(self.duration),
+                media_files,
+                video_clicks,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        if v4 {
+            let id = self.universal_ad_id.as_deref().unwrap_or("unknown");
+            let mut universal_ad_id = XmlElement::with_text("UniversalAdId", id);
+            universal_ad_id.set_attr("idRegistry", &self.universal_ad_id_registry);
+            creative.extra.push(universal_ad_id);
+        }
+
+        let inline = InLine {
+            ad_system: Some(AdSystem {
+                name: self.ad_system.clone(),
+                version: self.ad_system_version.clone(),
+            }),
+            ad_title: Some(self.ad_title.clone()),
+            ad_serving_id: self.ad_serving_id.clone().filter(|_| v4),
+            description: self.description.clone(),
+            advertiser: self.advertiser.clone().filter(|_| v4),
+            pricing: self.pricing.clone().filter(|_| v3),
+            creatives: vec![creative],
+            ..Default::default()
+        };
+
+        Ok(single_ad_vast(
+            &self.version,
+            self.id.clone(),
+            AdContent::InLine(inline),
+        ))
+    }
+
+    /// Serialises the ad with its trackers
+    ///
+    /// # Errors
+    /// Returns an error if the document is invalid, see [`Self::to_vast`],
+    /// or no impression tracker was provided
+    pub fn to_xml_string(&self) -> Result<String> {
+        render(self.to_vast()?, &self.trackers)
+    }
+}
+
+fn validate_version(version: &str) -> Result<(u32, u32)> {
+    match parse_version(version) {
+        Some(v) if ((2, 0)..=(4, 3)).contains(&v) => Ok(v),
+        _ => bail!("Unsupported VAST version {version}"),
+    }
+}
+
+fn single_ad_vast(version: &str, id: Option<String>, content: AdContent) -> Vast {
+    Vast {
+        version: version.to_string(),
+        ads: vec![Ad {
+            id,
+            content,
+            ..Default::default()
+        }],
+        ..Default::default()
+    }
+}
+
+/// Serialises the document and injects the trackers, so the builders share
+/// the version aware placement of `inject_vast_trackers`
+fn render(vast: Vast, trackers: &VastTrackers) -> Result<String> {
+    let has_impression = trackers.impression.is_some()
+        || trackers
+            .additional
+            .iter()
+            .any(|t| t.event == VastEvent::Impression);
+    if !has_impression {
+        bail!("At least one impression tracker is required");
+    }
+
+    inject_generated_vast_trackers(&vast.to_xml_string()?, trackers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::openrtb::utils::trackers::{VastTrackersBuilder, VastVerification};
+
+    fn trackers() -> VastTrackers {
+        VastTrackersBuilder::default()
+            .impression(Some("https://exchange.example.com/imp?a=1&b=2".to_string()))
+            .error(Some(
+                "https://exchange.example.com/err?code=[ERRORCODE]".to_string(),
+            ))
+            .start(Some("https://exchange.example.com/start".to_string()))
+            .click_tracking(Some("https://exchange.example.com/click".to_string()))
+            .build()
+            .unwrap()
+    }
+
+    fn inline() -> VastInLineAdBuilder {
+        let mut builder = VastInLineAdBuilder::default();
+        builder
+            .id(Some("ad-1".to_string()))
+            .ad_system("Exchange".to_string())
+            .ad_title("Fish & Chips".to_string())
+            .duration(Duration::from_millis(15500))
+            .media_files(vec![MediaFile {
+                url: "https://cdn.example.com/ad.mp4?w=1280&h=720".to_string(),
+                mime_type: Some("video/mp4".to_string()),
+                width: Some(1280),
+                height: Some(720),
+                ..Default::default()
+            }])
+            .click_through(Some(
+                "https://advertiser.example.com/?utm=a&b=c".to_string(),
+            ))
+            .trackers(trackers());
+        builder
+    }
+
+    #[test]
+    fn test_inline_round_trip() {
+        let ad = inline().build().unwrap();
+        let xml = ad.to_xml_string().unwrap();
+
+        assert!(xml.contains(
+            "<MediaFile delivery=\"progressive\" type=\"video/mp4\" width=\"1280\" height=\"720\"><![CDATA[https://cdn.example.com/ad.mp4?w=1280&h=720]]></MediaFile>"
+        ));
+        assert!(xml.contains(
+            "<Impression><![CDATA[https://exchange.example.com/imp?a=1&b=2]]></Impression>"
+        ));
+        assert!(xml.contains("<AdTitle>Fish &amp; Chips</AdTitle>"));
+
+        let vast = Vast::parse(&xml).unwrap();
+        assert_eq!(vast.version, "4.2");
+        assert_eq!(vast.ads[0].id.as_deref(), Some("ad-1"));
+
+        let inline = vast.ads[0].inline().unwrap();
+        assert_eq!(inline.ad_title.as_deref(), Some("Fish & Chips"));
+        assert_eq!(
+            inline.impressions[0].url,
+            "https://exchange.example.com/imp?a=1&b=2"
+        );
+        assert_eq!(
+            inline.errors,
+            vec!["https://exchange.example.com/err?code=[ERRORCODE]"]
+        );
+
+        let creative = &inline.creatives[0];
+        assert_eq!(creative.extra[0].name, "UniversalAdId");
+        assert_eq!(creative.extra[0].attr("idRegistry"), Some("unknown"));
+
+        let linear = creative.linear.as_ref().unwrap();
+        assert_eq!(linear.duration, Some(Duration::from_millis(15500)));
+        assert_eq!(linear.tracking_events[0].event, "start");
+
+        let clicks = linear.video_clicks.as_ref().unwrap();
+        assert_eq!(
+            clicks.click_through.as_ref().unwrap().url,
+            "https://advertiser.example.com/?utm=a&b=c"
+        );
+        assert_eq!(
+            clicks.click_tracking[0].url,
+            "https://exchange.example.com/click"
+        );
+
+        // serialising the parsed document again is stable
+        assert_eq!(Vast::parse(&vast.to_xml_string().unwrap()).unwrap(), vast);
+    }
+
+    #[test]
+    fn test_version_gating() {
+        let ad = inline()
+            .version("2.0".to_string())
+            .ad_serving_id(Some("serving".to_string()))
+            .skip_offset(Some(Offset::Time(Duration::from_secs(5))))
+            .pricing(Some(Pricing {
+                model: Some("CPM".to_string()),
+                currency: Some("USD".to_string()),
+                value: "1.50".to_string(),
+            }))
+            .build()
+            .unwrap();
+
+        let xml = ad.to_xml_string().unwrap();
+        assert!(xml.contains(r#"<VAST version="2.0">"#));
+        assert!(!xml.contains("UniversalAdId"));
+        assert!(!xml.contains("AdServingId"));
+        assert!(!xml.contains("skipoffset"));
+        assert!(!xml.contains("Pricing"));
+
+        let vast = Vast::parse(&xml).unwrap();
+        assert!(vast.linears().next().unwrap().skip_offset.is_none());
+    }
+
+    #[test]
+    fn test_wrapper_round_trip() {
+        let mut trackers = trackers();
+        trackers.verifications = vec![VastVerification::new(
+            "company.com-omid",
+            "https://verify.example.com/omid.js",
+        )];
+
+        let ad = VastWrapperAdBuilder::default()
+            .ad_system("Exchange".to_string())
+            .vast_ad_tag_uri("https://bidder.example.com/vast?id=1&p=${AUCTION_PRICE}".to_string())
+            .follow_additional_wrappers(Some(false))
+            .trackers(trackers)
+            .build()
+            .unwrap();
+
+        let xml = ad.to_xml_string().unwrap();
+        assert!(xml.contains(
+            "<VASTAdTagURI><![CDATA[https://bidder.example.com/vast?id=1&p=${AUCTION_PRICE}]]></VASTAdTagURI>"
+        ));
+
+        let vast = Vast::parse(&xml).unwrap();
+        let wrapper = vast.ads[0].wrapper().unwrap();
+        assert_eq!(
+            wrapper.vast_ad_tag_uri,
+            "https://bidder.example.com/vast?id=1&p=${AUCTION_PRICE}"
+        );
+        assert_eq!(wrapper.follow_additional_wrappers, Some(false));
+        assert_eq!(wrapper.impressions.len(), 1);
+        assert_eq!(
+            wrapper.ad_verifications[0].vendor.as_deref(),
+            Some("company.com-omid")
+        );
+
+        let linear = wrapper.creatives[0].linear.as_ref().unwrap();
+        assert_eq!(linear.tracking_events[0].event, "start");
+        assert_eq!(
+            linear.video_clicks.as_ref().unwrap().click_tracking[0].url,
+            "https://exchange.example.com/click"
+        );
+    }
+
+    #[test]
+    fn test_invalid_input_fails() {
+        assert!(
+            inline()
+                .version("5.0".to_string())
+                .build()
+                .unwrap()
+                .to_vast()
+                .is_err()
+        );
+        assert!(
+            inline()
+                .media_files(vec![])
+                .build()
+                .unwrap()
+                .to_vast()
+                .is_err()
+        );
+        assert!(
+            inline()
+                .click_through(Some("javascript:alert(1)".to_string()))
+                .build()
+                .unwrap()
+                .to_vast()
+                .is_err()
+        );
+        assert!(
+            inline()
+                .trackers(VastTrackers::default())
+                .build()
+                .unwrap()
+                .to_xml_string()
+                .is_err()
+        );
+
+        let wrapper = VastWrapperAdBuilder::default()
+            .ad_system("Exchange".to_string())
+            .vast_ad_tag_uri("ftp://bidder.example.com/vast".to_string())
+            .build()
+            .unwrap();
+        assert!(wrapper.to_vast().is_err());
+    }
+}
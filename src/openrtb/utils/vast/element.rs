@@ -0,0 +1,346 @@
+use anyhow::{Context, Result, bail};
+use quick_xml::escape::{resolve_predefined_entity, unescape};
+use quick_xml::events::{BytesCData, BytesDecl, BytesEnd, BytesStart, BytesText, Event};
+use quick_xml::{Reader, Writer};
+use std::io::Cursor;
+
+/// Deepest element nesting accepted by [`XmlElement::parse`]. VAST needs
+/// far less, the limit keeps hostile documents from exhausting the stack
+/// when the tree is written or dropped.
+pub const MAX_XML_DEPTH: usize = 128;
+
+/// A node within an [`XmlElement`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum XmlNode {
+    Element(XmlElement),
+    /// Unescaped character data
+    Text(String),
+    /// Raw content of a `<![CDATA[...]]>` section
+    CData(String),
+    Comment(String),
+}
+
+/// A generic, order preserving XML element.
+///
+/// Used by the typed VAST model to carry elements it does not know about
+/// (extensions, vendor specific nodes, newer spec additions) through a parse
+/// and serialise round trip untouched.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct XmlElement {
+    pub name: String,
+    pub attributes: Vec<(String, String)>,
+    pub children: Vec<XmlNode>,
+}
+
+impl XmlElement {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Creates an element holding escaped text content
+    pub fn with_text(name: impl Into<String>, text: impl Into<String>) -> Self {
+        let mut el = Self::new(name);
+        el.children.push(XmlNode::Text(text.into()));
+        el
+    }
+
+    /// Creates an element holding CDATA content, as VAST recommends for URLs
+    pub fn with_cdata(name: impl Into<String>, text: impl Into<String>) -> Self {
+        let mut el = Self::new(name);
+        el.children.push(XmlNode::CData(text.into()));
+        el
+    }
+
+    /// Parses the root element of an XML document, skipping any declaration,
+    /// doctype or comments preceding it. Fails for elements nested deeper
+    /// than [`MAX_XML_DEPTH`].
+    pub fn parse(xml: &str) -> Result<Self> {
+        let mut reader = Reader::from_str(xml);
+        reader.config_mut().trim_text(false);
+
+        let mut stack: Vec<XmlElement> = Vec::new();
+
+        loop {
+            let event = reader
+                .read_event()
+                .with_context(|| format!("Invalid XML at {}", reader.buffer_position()))?;
+
+            match event {
+                Event::Start(e) => {
+                    if stack.len() >= MAX_XML_DEPTH {
+                        bail!("XML nested deeper than {MAX_XML_DEPTH} elements");
+                    }
+                    stack.push(Self::from_start(&e)?)
+                }
+                Event::Empty(e) => {
+                    let el = Self::from_start(&e)?;
+                    match stack.last_mut() {
+                        Some(parent) => parent.children.push(XmlNode::Element(el)),
+                        None => return Ok(el),
+                    }
+                }
+                Event::End(_) => {
+                    let Some(mut el) = stack.pop() else {
+                        bail!("Unexpected closing tag");
+                    };
+                    el.strip_formatting();
+
+                    match stack.last_mut() {
+                        Some(parent) => parent.children.push(XmlNode::Element(el)),
+                        None => return Ok(el),
+                    }
+                }
+                Event::Text(e) => {
+                    if let Some(el) = stack.last_mut() {
+                        let raw = std::str::from_utf8(&e)?;
+                        el.push_text(&unescape(raw)?);
+                    }
+                }
+                Event::GeneralRef(e) => {
+                    if let Some(el) = stack.last_mut() {
+                        let name = std::str::from_utf8(&e)?;
+                        if let Some(c) = e.resolve_char_ref()? {
+                            el.push_text(c.encode_utf8(&mut [0; 4]));
+                        } else if let Some(s) = resolve_predefined_entity(name) {
+                            el.push_text(s);
+                        } else {
+                            el.push_text(&format!("&{name};"));
+                        }
+                    }
+                }
+                Event::CData(e) => {
+                    if let Some(el) = stack.last_mut() {
+                        let text = std::str::from_utf8(&e)?;
+                        el.children.push(XmlNode::CData(text.to_string()));
+                    }
+                }
+                Event::Comment(e) => {
+                    if let Some(el) = stack.last_mut() {
+                        let text = std::str::from_utf8(&e)?;
+                        el.children.push(XmlNode::Comment(text.to_string()));
+                    }
+                }
+                Event::Eof => bail!("No root element found"),
+                _ => {}
+            }
+        }
+    }
+
+    /// Serialises the element, prefixed with an XML declaration
+    pub fn to_xml_string(&self) -> Result<String> {
+        let mut writer = Writer::new(Cursor::new(Vec::new()));
+        writer.write_event(Event::Decl(BytesDecl::new("1.0", Some("UTF-8"), None)))?;
+        self.write(&mut writer)?;
+
+        let output = writer.into_inner().into_inner();
+        String::from_utf8(output).map_err(|e| e.into())
+    }
+
+    /// Writes the element and its subtree
+    pub fn write<W: std::io::Write>(&self, writer: &mut Writer<W>) -> Result<()> {
+        let mut start = BytesStart::new(self.name.as_str());
+        for (k, v) in &self.attributes {
+            start.push_attribute((k.as_str(), v.as_str()));
+        }
+
+        if self.children.is_empty() {
+            writer.write_event(Event::Empty(start))?;
+            return Ok(());
+        }
+
+        writer.write_event(Event::Start(start))?;
+        for child in &self.children {
+            match child {
+                XmlNode::Element(el) => el.write(writer)?,
+                XmlNode::Text(text) => writer.write_event(Event::Text(BytesText::new(text)))?,
+                XmlNode::CData(text) => {
+                    // "]]>" cannot appear within a CDATA section, so split it
+                    // across two adjacent sections
+                    let parts: Vec<&str> = text.split("]]>").collect();
+                    let last = parts.len() - 1;
+                    for (i, part) in parts.into_iter().enumerate() {
+                        let head = if i > 0 { ">" } else { "" };
+                        let tail = if i < last { "]]" } else { "" };
+                        let section = format!("{head}{part}{tail}");
+                        writer.write_event(Event::CData(BytesCData::new(section)))?;
+                    }
+                }
+                XmlNode::Comment(text) => {
+                    writer.write_event(Event::Comment(BytesText::from_escaped(text)))?
+                }
+            }
+        }
+        writer.write_event(Event::End(BytesEnd::new(self.name.as_str())))?;
+
+        Ok(())
+    }
+
+    /// Value of an attribute
+    pub fn attr(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Sets an attribute, replacing any existing value
+    pub fn set_attr(&mut self, name: &str, value: impl Into<String>) {
+        let value = value.into();
+        match self.attributes.iter_mut().find(|(k, _)| k == name) {
+            Some((_, v)) => *v = value,
+            None => self.attributes.push((name.to_string(), value)),
+        }
+    }
+
+    /// Removes and returns an attribute
+    pub fn take_attr(&mut self, name: &str) -> Option<String> {
+        let i = self.attributes.iter().position(|(k, _)| k == name)?;
+        Some(self.attributes.remove(i).1)
+    }
+
+    /// Child elements, skipping text and comments
+    pub fn elements(&self) -> impl Iterator<Item = &XmlElement> {
+        self.children.iter().filter_map(|n| match n {
+            XmlNode::Element(el) => Some(el),
+            _ => None,
+        })
+    }
+
+    /// First child element with the given name
+    pub fn child(&self, name: &str) -> Option<&XmlElement> {
+        self.elements().find(|el| el.name == name)
+    }
+
+    /// Mutable first child element with the given name
+    pub fn child_mut(&mut self, name: &str) -> Option<&mut XmlElement> {
+        self.children.iter_mut().find_map(|n| match n {
+            XmlNode::Element(el) if el.name == name => Some(el),
+            _ => None,
+        })
+    }
+
+    /// Appends a child element
+    pub fn push(&mut self, child: XmlElement) -> &mut Self {
+        self.children.push(XmlNode::Element(child));
+        self
+    }
+
+    /// Text and CDATA content concatenated and trimmed
+    pub fn text(&self) -> String {
+        let mut out = String::new();
+        for child in &self.children {
+            match child {
+                XmlNode::Text(t) | XmlNode::CData(t) => out.push_str(t),
+                _ => {}
+            }
+        }
+
+        out.trim().to_string()
+    }
+
+    /// Consumes the element returning its child elements
+    pub(crate) fn into_elements(self) -> impl Iterator<Item = XmlElement> {
+        self.children.into_iter().filter_map(|n| match n {
+            XmlNode::Element(el) => Some(el),
+            _ => None,
+        })
+    }
+
+    fn from_start(e: &BytesStart) -> Result<Self> {
+        let name = std::str::from_utf8(e.name().as_ref())?.to_string();
+        let mut attributes = Vec::new();
+
+        for attr in e.attributes() {
+            let attr = attr?;
+            let key = std::str::from_utf8(attr.key.as_ref())?.to_string();
+            attributes.push((key, attr.unescape_value()?.into_owned()));
+        }
+
+        Ok(Self {
+            name,
+            attributes,
+            children: Vec::new(),
+        })
+    }
+
+    fn push_text(&mut self, text: &str) {
+        if let Some(XmlNode::Text(last)) = self.children.last_mut() {
+            last.push_str(text);
+        } else {
+            self.children.push(XmlNode::Text(text.to_string()));
+        }
+    }
+
+    /// Drops indentation between child elements, keeping text of leaf elements
+    fn strip_formatting(&mut self) {
+        let has_elements = self
+            .children
+            .iter()
+            .any(|n| matches!(n, XmlNode::Element(_)));
+
+        if has_elements {
+            self.children
+                .retain(|n| !matches!(n, XmlNode::Text(t) if t.trim().is_empty()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_write_round_trip() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Root a="1">
+  <!-- note -->
+  <Url><![CDATA[https://example.com/?a=1&b=2]]></Url>
+  <Title>Fish &amp; Chips</Title>
+  <Empty flag="x"/>
+</Root>"#;
+
+        let root = XmlElement::parse(xml).unwrap();
+        assert_eq!(root.attr("a"), Some("1"));
+        assert_eq!(
+            root.child("Url").unwrap().text(),
+            "https://example.com/?a=1&b=2"
+        );
+        assert_eq!(root.child("Title").unwrap().text(), "Fish & Chips");
+        assert_eq!(root.child("Empty").unwrap().attr("flag"), Some("x"));
+
+        let out = root.to_xml_string().unwrap();
+        assert!(out.contains("<![CDATA[https://example.com/?a=1&b=2]]>"));
+        assert!(out.contains("<Title>Fish &amp; Chips</Title>"));
+        assert!(out.contains("<!-- note -->"));
+        assert!(out.contains(r#"<Empty flag="x"/>"#));
+
+        assert_eq!(XmlElement::parse(&out).unwrap(), root);
+    }
+
+    #[test]
+    fn test_cdata_terminator_is_split() {
+        let el = XmlElement::with_cdata("Html", "a]]>b");
+        let out = el.to_xml_string().unwrap();
+
+        assert!(out.contains("<![CDATA[a]]]]><![CDATA[>b]]>"));
+        assert_eq!(XmlElement::parse(&out).unwrap().text(), "a]]>b");
+    }
+
+    #[test]
+    fn test_nesting_is_limited() {
+        let nested = |depth: usize| "<a>".repeat(depth) + &"</a>".repeat(depth);
+
+        assert!(XmlElement::parse(&nested(MAX_XML_DEPTH)).is_ok());
+        assert!(XmlElement::parse(&nested(MAX_XML_DEPTH + 1)).is_err());
+        assert!(XmlElement::parse(&nested(100_000)).is_err());
+    }
+
+    #[test]
+    fn test_invalid_xml_fails() {
+        assert!(XmlElement::parse("<a><b></a>").is_err());
+        assert!(XmlElement::parse("").is_err());
+    }
+}
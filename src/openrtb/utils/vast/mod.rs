@@ -0,0 +1,8 @@
+mod builder;
+mod element;
+mod model;
+mod resolve;
+pub use builder::*;
+pub use element::*;
+pub use model::*;
+pub use resolve::*;
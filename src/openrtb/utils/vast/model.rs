@@ -0,0 +1,1653 @@
+use super::element::XmlElement;
+use anyhow::{Result, bail};
+use std::fmt;
+use std::time::Duration;
+
+type Attributes = Vec<(String, String)>;
+
+/// A VAST 2.0 - 4.3 document.
+///
+/// Known elements are parsed into typed fields, anything else is kept in the
+/// `extra` list of its parent (and unknown attributes in `attributes`) so that
+/// it survives [`Vast::to_xml_string`]. Known children are written back in
+/// spec order, followed by preserved unknown ones. Comments are only kept
+/// within preserved unknown elements, since the typed model has no place for
+/// them between known ones.
+///
+/// # Example
+/// ```
+/// use rtb::openrtb::utils::vast::Vast;
+/// use std::time::Duration;
+///
+/// let vast = Vast::parse(r#"<VAST version="4.0"><Ad id="1"><InLine>
+///     <AdSystem>Example</AdSystem>
+///     <Creatives><Creative><Linear>
+///         <Duration>00:00:15</Duration>
+///         <MediaFiles>
+///             <MediaFile delivery="progressive" type="video/mp4" width="640" height="360">
+///                 <![CDATA[https://cdn.example.com/ad.mp4]]>
+///             </MediaFile>
+///         </MediaFiles>
+///     </Linear></Creative></Creatives>
+/// </InLine></Ad></VAST>"#).unwrap();
+///
+/// let linear = vast.linears().next().unwrap();
+/// assert_eq!(linear.duration, Some(Duration::from_secs(15)));
+/// assert_eq!(linear.media_files[0].mime_type.as_deref(), Some("video/mp4"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Vast {
+    pub version: String,
+    pub ads: Vec<Ad>,
+    /// Root level `Error` URLs, fired when there is no ad to serve
+    pub errors: Vec<String>,
+    pub attributes: Attributes,
+    pub extra: Vec<XmlElement>,
+}
+
+/// An `Ad` element, holding either an InLine or Wrapper ad
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Ad {
+    pub id: Option<String>,
+    /// Position within an ad pod
+    pub sequence: Option<u32>,
+    pub conditional_ad: Option<bool>,
+    /// VAST 4.1 `adType`, e.g. `video`, `audio` or `hybrid`
+    pub ad_type: Option<String>,
+    pub content: AdContent,
+    pub attributes: Attributes,
+    pub extra: Vec<XmlElement>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AdContent {
+    InLine(InLine),
+    Wrapper(Wrapper),
+}
+
+impl Default for AdContent {
+    fn default() -> Self {
+        AdContent::InLine(InLine::default())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct InLine {
+    pub ad_system: Option<AdSystem>,
+    pub ad_title: Option<String>,
+    pub ad_serving_id: Option<String>,
+    pub description: Option<String>,
+    pub advertiser: Option<String>,
+    pub pricing: Option<Pricing>,
+    pub errors: Vec<String>,
+    pub impressions: Vec<TrackedUrl>,
+    pub viewable_impression: Option<ViewableImpression>,
+    pub ad_verifications: Vec<Verification>,
+    pub creatives: Vec<Creative>,
+    /// `Extension` elements within `Extensions`
+    pub extensions: Vec<XmlElement>,
+    pub attributes: Attributes,
+    pub extra: Vec<XmlElement>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Wrapper {
+    pub ad_system: Option<AdSystem>,
+    pub vast_ad_tag_uri: String,
+    pub follow_additional_wrappers: Option<bool>,
+    pub allow_multiple_ads: Option<bool>,
+    pub fallback_on_no_ad: Option<bool>,
+    pub pricing: Option<Pricing>,
+    pub errors: Vec<String>,
+    pub impressions: Vec<TrackedUrl>,
+    pub viewable_impression: Option<ViewableImpression>,
+    pub ad_verifications: Vec<Verification>,
+    pub creatives: Vec<Creative>,
+    /// `Extension` elements within `Extensions`
+    pub extensions: Vec<XmlElement>,
+    pub attributes: Attributes,
+    pub extra: Vec<XmlElement>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct AdSystem {
+    pub name: String,
+    pub version: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Pricing {
+    /// Pricing model, e.g. `CPM`
+    pub model: Option<String>,
+    pub currency: Option<String>,
+    pub value: String,
+}
+
+/// A URL carrying an optional `id`, e.g. `Impression` or `ClickTracking`
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TrackedUrl {
+    pub id: Option<String>,
+    pub url: String,
+    pub attributes: Attributes,
+}
+
+impl TrackedUrl {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            id: None,
+            url: url.into(),
+            attributes: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ViewableImpression {
+    pub id: Option<String>,
+    pub viewable: Vec<String>,
+    pub not_viewable: Vec<String>,
+    pub view_undetermined: Vec<String>,
+    pub attributes: Attributes,
+    pub extra: Vec<XmlElement>,
+}
+
+/// An `AdVerifications/Verification` entry (VAST 4.1+, or within an
+/// `Extension` on older versions)
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Verification {
+    pub vendor: Option<String>,
+    pub javascript_resources: Vec<JavaScriptResource>,
+    pub verification_parameters: Option<String>,
+    pub tracking_events: Vec<Tracking>,
+    pub attributes: Attributes,
+    pub extra: Vec<XmlElement>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct JavaScriptResource {
+    /// Usually `omid`
+    pub api_framework: Option<String>,
+    pub browser_optional: Option<bool>,
+    pub url: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Creative {
+    pub id: Option<String>,
+    pub sequence: Option<u32>,
+    pub ad_id: Option<String>,
+    pub api_framework: Option<String>,
+    pub linear: Option<Linear>,
+    pub non_linear_ads: Option<NonLinearAds>,
+    pub companion_ads: Option<CompanionAds>,
+    pub attributes: Attributes,
+    /// e.g. `UniversalAdId`, `CreativeExtensions`
+    pub extra: Vec<XmlElement>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Linear {
+    pub skip_offset: Option<Offset>,
+    pub ad_parameters: Option<String>,
+    pub duration: Option<Duration>,
+    pub media_files: Vec<MediaFile>,
+    /// Non `MediaFile` children of `MediaFiles`, e.g. `Mezzanine`
+    pub media_files_extra: Vec<XmlElement>,
+    pub tracking_events: Vec<Tracking>,
+    pub video_clicks: Option<VideoClicks>,
+    /// e.g. `Icons`
+    pub extra: Vec<XmlElement>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct VideoClicks {
+    pub click_through: Option<TrackedUrl>,
+    pub click_tracking: Vec<TrackedUrl>,
+    pub custom_click: Vec<TrackedUrl>,
+    pub extra: Vec<XmlElement>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct MediaFile {
+    pub id: Option<String>,
+    pub url: String,
+    /// `progressive` or `streaming`
+    pub delivery: Option<String>,
+    pub mime_type: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub codec: Option<String>,
+    pub bitrate: Option<u32>,
+    pub min_bitrate: Option<u32>,
+    pub max_bitrate: Option<u32>,
+    pub scalable: Option<bool>,
+    pub maintain_aspect_ratio: Option<bool>,
+    pub api_framework: Option<String>,
+    pub attributes: Attributes,
+}
+
+/// A `Tracking` element within `TrackingEvents`
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Tracking {
+    pub event: String,
+    /// Only used by `progress` events
+    pub offset: Option<Offset>,
+    pub url: String,
+    pub attributes: Attributes,
+}
+
+impl Tracking {
+    pub fn new(event: impl Into<String>, url: impl Into<String>) -> Self {
+        Self {
+            event: event.into(),
+            offset: None,
+            url: url.into(),
+            attributes: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct NonLinearAds {
+    pub non_linears: Vec<NonLinear>,
+    pub tracking_events: Vec<Tracking>,
+    pub extra: Vec<XmlElement>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct NonLinear {
+    pub id: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub resources: Vec<Resource>,
+    pub click_through: Option<String>,
+    pub click_tracking: Vec<TrackedUrl>,
+    pub attributes: Attributes,
+    pub extra: Vec<XmlElement>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct CompanionAds {
+    /// `all`, `any` or `none`
+    pub required: Option<String>,
+    pub companions: Vec<Companion>,
+    pub extra: Vec<XmlElement>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Companion {
+    pub id: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub resources: Vec<Resource>,
+    pub alt_text: Option<String>,
+    pub click_through: Option<String>,
+    pub click_tracking: Vec<TrackedUrl>,
+    pub tracking_events: Vec<Tracking>,
+    pub attributes: Attributes,
+    pub extra: Vec<XmlElement>,
+}
+
+/// Creative resource of a NonLinear or Companion ad
+#[derive(Debug, Clone, PartialEq)]
+pub enum Resource {
+    Static {
+        creative_type: Option<String>,
+        url: String,
+    },
+    IFrame(String),
+    Html(String),
+}
+
+/// A time offset, as used by `skipoffset` and `progress` trackers
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Offset {
+    Time(Duration),
+    Percent(f32),
+}
+
+impl Offset {
+    /// Parses `HH:MM:SS[.mmm]` or `n%`, with `n` between 0 and 100
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        match s.strip_suffix('%') {
+            Some(p) => p
+                .trim()
+                .parse()
+                .ok()
+                .filter(|p| (0.0..=100.0).contains(p))
+                .map(Offset::Percent),
+            None => parse_timecode(s).map(Offset::Time),
+        }
+    }
+
+    /// Resolves the offset to a point in time for a creative of `duration`
+    pub fn resolve(&self, duration: Duration) -> Duration {
+        match self {
+            Offset::Time(t) => *t,
+            Offset::Percent(p) if p.is_nan() => Duration::ZERO,
+            Offset::Percent(p) => {
+                let fraction = f64::from(p.clamp(0.0, 100.0)) / 100.0;
+                Duration::try_from_secs_f64(duration.as_secs_f64() * fraction).unwrap_or(duration)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Offset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Offset::Time(t) => f.write_str(&format_timecode(*t)),
+            Offset::Percent(p) => write!(f, "{p}%"),
+        }
+    }
+}
+
+/// Parses a VAST `HH:MM:SS` or `HH:MM:SS.mmm` timecode
+pub fn parse_timecode(s: &str) -> Option<Duration> {
+    let mut parts = s.trim().split(':');
+    let hours: u64 = parts.next()?.parse().ok()?;
+    let minutes: u64 = parts.next()?.parse().ok()?;
+    let seconds: f64 = parts.next()?.parse().ok()?;
+
+    if parts.next().is_some() || minutes >= 60 || !(0.0..60.0).contains(&seconds) {
+        return None;
+    }
+
+    let secs = hours.checked_mul(3600)?.checked_add(minutes * 60)?;
+    Duration::from_secs(secs).checked_add(Duration::from_secs_f64(seconds))
+}
+
+/// Formats a duration as a VAST timecode, with milliseconds only when non zero
+pub fn format_timecode(d: Duration) -> String {
+    let secs = d.as_secs();
+    let millis = d.subsec_millis();
+    let base = format!(
+        "{:02}:{:02}:{:02}",
+        secs / 3600,
+        (secs / 60) % 60,
+        secs % 60
+    );
+
+    if millis == 0 {
+        base
+    } else {
+        format!("{base}.{millis:03}")
+    }
+}
+
+impl Vast {
+    /// Parses a VAST document
+    pub fn parse(xml: &str) -> Result<Self> {
+        Self::from_element(XmlElement::parse(xml)?)
+    }
+
+    pub fn from_element(mut el: XmlElement) -> Result<Self> {
+        if !el.name.eq_ignore_ascii_case("vast") {
+            bail!("Root element is <{}>, expected <VAST>", el.name);
+        }
+
+        let mut vast = Vast {
+            version: el.take_attr("version").unwrap_or_default(),
+            attributes: std::mem::take(&mut el.attributes),
+            ..Default::default()
+        };
+
+        for child in el.into_elements() {
+            match child.name.as_str() {
+                "Ad" => vast.ads.push(Ad::from_element(child)?),
+                "Error" => vast.errors.push(child.text()),
+                _ => vast.extra.push(child),
+            }
+        }
+
+        Ok(vast)
+    }
+
+    pub fn to_element(&self) -> XmlElement {
+        let mut el = XmlElement::new("VAST");
+        el.attributes
+            .push(("version".to_string(), self.version.clone()));
+        el.attributes.extend(self.attributes.iter().cloned());
+
+        for ad in &self.ads {
+            el.push(ad.to_element());
+        }
+        push_urls(&mut el, "Error", &self.errors);
+        push_extra(&mut el, &self.extra);
+
+        el
+    }
+
+    /// Serialises the document with an XML declaration
+    pub fn to_xml_string(&self) -> Result<String> {
+        self.to_element().to_xml_string()
+    }
+
+    /// `(major, minor)` of the declared version, `(0, 0)` if unparseable
+    pub fn version_number(&self) -> (u32, u32) {
+        parse_version(&self.version).unwrap_or((0, 0))
+    }
+
+    /// Whether the declared version is at least `major.minor`
+    pub fn version_at_least(&self, major: u32, minor: u32) -> bool {
+        self.version_number() >= (major, minor)
+    }
+
+    /// A document without ads is a "no ad" response
+    pub fn is_empty(&self) -> bool {
+        self.ads.is_empty()
+    }
+
+    pub fn creatives(&self) -> impl Iterator<Item = &Creative> {
+        self.ads.iter().flat_map(|ad| ad.creatives().iter())
+    }
+
+    pub fn linears(&self) -> impl Iterator<Item = &Linear> {
+        self.creatives().filter_map(|c| c.linear.as_ref())
+    }
+
+    pub fn media_files(&self) -> impl Iterator<Item = &MediaFile> {
+        self.linears().flat_map(|l| l.media_files.iter())
+    }
+
+    /// Whether this is an audio ad, either declared with `adType="audio"`
+    /// (VAST 4.1+) or with only `audio/*` media files
+    pub fn is_audio(&self) -> bool {
+        if self
+            .ads
+            .iter()
+            .any(|ad| ad.ad_type.as_deref() == Some("audio"))
+        {
+            return true;
+        }
+
+        let mut files = self.media_files().peekable();
+        files.peek().is_some() && files.all(MediaFile::is_audio)
+    }
+}
+
+impl Ad {
+    pub fn from_element(mut el: XmlElement) -> Result<Self> {
+        let mut ad = Ad {
+            id: el.take_attr("id"),
+            sequence: el.take_attr("sequence").and_then(|v| v.trim().parse().ok()),
+            conditional_ad: el.take_attr("conditionalAd").and_then(|v| parse_bool(&v)),
+            ad_type: el.take_attr("adType"),
+            attributes: std::mem::take(&mut el.attributes),
+            ..Default::default()
+        };
+
+        let mut content = None;
+        for child in el.into_elements() {
+            match child.name.as_str() {
+                "InLine" if content.is_none() => {
+                    content = Some(AdContent::InLine(InLine::from_element(child)))
+                }
+                "Wrapper" if content.is_none() => {
+                    content = Some(AdContent::Wrapper(Wrapper::from_element(child)))
+                }
+                _ => ad.extra.push(child),
+            }
+        }
+
+        let Some(content) = content else {
+            bail!(
+                "Ad {} has neither an InLine nor a Wrapper",
+                ad.id.as_deref().unwrap_or("<no id>")
+            );
+        };
+        ad.content = content;
+
+        Ok(ad)
+    }
+
+    pub fn to_element(&self) -> XmlElement {
+        let mut el = XmlElement::new("Ad");
+        push_attr(&mut el, "id", &self.id);
+        push_attr(&mut el, "sequence", &self.sequence);
+        push_attr(&mut el, "conditionalAd", &self.conditional_ad);
+        push_attr(&mut el, "adType", &self.ad_type);
+        el.attributes.extend(self.attributes.iter().cloned());
+
+        el.push(match &self.content {
+            AdContent::InLine(inline) => inline.to_element(),
+            AdContent::Wrapper(wrapper) => wrapper.to_element(),
+        });
+        push_extra(&mut el, &self.extra);
+
+        el
+    }
+
+    pub fn inline(&self) -> Option<&InLine> {
+        match &self.content {
+            AdContent::InLine(inline) => Some(inline),
+            _ => None,
+        }
+    }
+
+    pub fn wrapper(&self) -> Option<&Wrapper> {
+        match &self.content {
+            AdContent::Wrapper(wrapper) => Some(wrapper),
+            _ => None,
+        }
+    }
+
+    pub fn creatives(&self) -> &Vec<Creative> {
+        match &self.content {
+            AdContent::InLine(inline) => &inline.creatives,
+            AdContent::Wrapper(wrapper) => &wrapper.creatives,
+        }
+    }
+
+    pub fn creatives_mut(&mut self) -> &mut Vec<Creative> {
+        match &mut self.content {
+            AdContent::InLine(inline) => &mut inline.creatives,
+            AdContent::Wrapper(wrapper) => &mut wrapper.creatives,
+        }
+    }
+
+    pub fn impressions_mut(&mut self) -> &mut Vec<TrackedUrl> {
+        match &mut self.content {
+            AdContent::InLine(inline) => &mut inline.impressions,
+            AdContent::Wrapper(wrapper) => &mut wrapper.impressions,
+        }
+    }
+
+    pub fn errors_mut(&mut self) -> &mut Vec<String> {
+        match &mut self.content {
+            AdContent::InLine(inline) => &mut inline.errors,
+            AdContent::Wrapper(wrapper) => &mut wrapper.errors,
+        }
+    }
+}
+
+impl InLine {
+    pub fn from_element(mut el: XmlElement) -> Self {
+        let mut inline = InLine {
+            attributes: std::mem::take(&mut el.attributes),
+            ..Default::default()
+        };
+
+        for child in el.into_elements() {
+            match child.name.as_str() {
+                "AdSystem" => inline.ad_system = Some(AdSystem::from_element(child)),
+                "AdTitle" => inline.ad_title = Some(child.text()),
+                "AdServingId" => inline.ad_serving_id = Some(child.text()),
+                "Description" => inline.description = Some(child.text()),
+                "Advertiser" => inline.advertiser = Some(child.text()),
+                "Pricing" => inline.pricing = Some(Pricing::from_element(child)),
+                "Error" => inline.errors.push(child.text()),
+                "Impression" => inline.impressions.push(TrackedUrl::from_element(child)),
+                "ViewableImpression" => {
+                    inline.viewable_impression = Some(ViewableImpression::from_element(child))
+                }
+                "AdVerifications" => inline.ad_verifications.extend(parse_verifications(child)),
+                "Creatives" => inline.creatives.extend(parse_creatives(child)),
+                "Extensions" => inline.extensions.extend(child.into_elements()),
+                _ => inline.extra.push(child),
+            }
+        }
+
+        inline
+    }
+
+    pub fn to_element(&self) -> XmlElement {
+        let mut el = XmlElement::new("InLine");
+        el.attributes.extend(self.attributes.iter().cloned());
+
+        if let Some(ad_system) = &self.ad_system {
+            el.push(ad_system.to_element());
+        }
+        push_text(&mut el, "AdTitle", &self.ad_title);
+        push_text(&mut el, "AdServingId", &self.ad_serving_id);
+        push_text(&mut el, "Description", &self.description);
+        push_text(&mut el, "Advertiser", &self.advertiser);
+        if let Some(pricing) = &self.pricing {
+            el.push(pricing.to_element());
+        }
+        push_urls(&mut el, "Error", &self.errors);
+        push_tracked_urls(&mut el, "Impression", &self.impressions);
+        if let Some(viewable) = &self.viewable_impression {
+            el.push(viewable.to_element());
+        }
+        push_verifications(&mut el, &self.ad_verifications);
+        push_extra(&mut el, &self.extra);
+        push_creatives(&mut el, &self.creatives);
+        push_extensions(&mut el, &self.extensions);
+
+        el
+    }
+}
+
+impl Wrapper {
+    pub fn from_element(mut el: XmlElement) -> Self {
+        let mut wrapper = Wrapper {
+            follow_additional_wrappers: el
+                .take_attr("followAdditionalWrappers")
+                .and_then(|v| parse_bool(&v)),
+            allow_multiple_ads: el
+                .take_attr("allowMultipleAds")
+                .and_then(|v| parse_bool(&v)),
+            fallback_on_no_ad: el.take_attr("fallbackOnNoAd").and_then(|v| parse_bool(&v)),
+            attributes: std::mem::take(&mut el.attributes),
+            ..Default::default()
+        };
+
+        for child in el.into_elements() {
+            match child.name.as_str() {
+                "AdSystem" => wrapper.ad_system = Some(AdSystem::from_element(child)),
+                "VASTAdTagURI" => wrapper.vast_ad_tag_uri = child.text(),
+                "Pricing" => wrapper.pricing = Some(Pricing::from_element(child)),
+                "Error" => wrapper.errors.push(child.text()),
+                "Impression" => wrapper.impressions.push(TrackedUrl::from_element(child)),
+                "ViewableImpression" => {
+                    wrapper.viewable_impression = Some(ViewableImpression::from_element(child))
+                }
+                "AdVerifications" => wrapper.ad_verifications.extend(parse_verifications(child)),
+                "Creatives" => wrapper.creatives.extend(parse_creatives(child)),
+                "Extensions" => wrapper.extensions.extend(child.into_elements()),
+                _ => wrapper.extra.push(child),
+            }
+        }
+
+        wrapper
+    }
+
+    pub fn to_element(&self) -> XmlElement {
+        let mut el = XmlElement::new("Wrapper");
+        push_attr(
+            &mut el,
+            "followAdditionalWrappers",
+            &self.follow_additional_wrappers,
+        );
+        push_attr(&mut el, "allowMultipleAds", &self.allow_multiple_ads);
+        push_attr(&mut el, "fallbackOnNoAd", &self.fallback_on_no_ad);
+        el.attributes.extend(self.attributes.iter().cloned());
+
+        if let Some(ad_system) = &self.ad_system {
+            el.push(ad_system.to_element());
+        }
+        el.push(XmlElement::with_cdata(
+            "VASTAdTagURI",
+            &self.vast_ad_tag_uri,
+        ));
+        if let Some(pricing) = &self.pricing {
+            el.push(pricing.to_element());
+        }
+        push_urls(&mut el, "Error", &self.errors);
+        push_tracked_urls(&mut el, "Impression", &self.impressions);
+        if let Some(viewable) = &self.viewable_impression {
+            el.push(viewable.to_element());
+        }
+        push_verifications(&mut el, &self.ad_verifications);
+        push_extra(&mut el, &self.extra);
+        push_creatives(&mut el, &self.creatives);
+        push_extensions(&mut el, &self.extensions);
+
+        el
+    }
+}
+
+impl AdSystem {
+    pub fn from_element(mut el: XmlElement) -> Self {
+        AdSystem {
+            version: el.take_attr("version"),
+            name: el.text(),
+        }
+    }
+
+    pub fn to_element(&self) -> XmlElement {
+        let mut el = XmlElement::with_text("AdSystem", &self.name);
+        push_attr(&mut el, "version", &self.version);
+        el
+    }
+}
+
+impl Pricing {
+    pub fn from_element(mut el: XmlElement) -> Self {
+        Pricing {
+            model: el.take_attr("model"),
+            currency: el.take_attr("currency"),
+            value: el.text(),
+        }
+    }
+
+    pub fn to_element(&self) -> XmlElement {
+        let mut el = XmlElement::with_cdata("Pricing", &self.value);
+        push_attr(&mut el, "model", &self.model);
+        push_attr(&mut el, "currency", &self.currency);
+        el
+    }
+}
+
+impl TrackedUrl {
+    pub fn from_element(mut el: XmlElement) -> Self {
+        TrackedUrl {
+            id: el.take_attr("id"),
+            url: el.text(),
+            attributes: std::mem::take(&mut el.attributes),
+        }
+    }
+
+    pub fn to_element(&self, name: &str) -> XmlElement {
+        let mut el = XmlElement::with_cdata(name, &self.url);
+        push_attr(&mut el, "id", &self.id);
+        el.attributes.extend(self.attributes.iter().cloned());
+        el
+    }
+}
+
+impl ViewableImpression {
+    pub fn from_element(mut el: XmlElement) -> Self {
+        let mut viewable = ViewableImpression {
+            id: el.take_attr("id"),
+            attributes: std::mem::take(&mut el.attributes),
+            ..Default::default()
+        };
+
+        for child in el.into_elements() {
+            match child.name.as_str() {
+                "Viewable" => viewable.viewable.push(child.text()),
+                "NotViewable" => viewable.not_viewable.push(child.text()),
+                "ViewUndetermined" => viewable.view_undetermined.push(child.text()),
+                _ => viewable.extra.push(child),
+            }
+        }
+
+        viewable
+    }
+
+    pub fn to_element(&self) -> XmlElement {
+        let mut el = XmlElement::new("ViewableImpression");
+        push_attr(&mut el, "id", &self.id);
+        push_urls(&mut el, "Viewable", &self.viewable);
+        push_urls(&mut el, "NotViewable", &self.not_viewable);
+        push_urls(&mut el, "ViewUndetermined", &self.view_undetermined);
+        el.attributes.extend(self.attributes.iter().cloned());
+        push_extra(&mut el, &self.extra);
+        el
+    }
+}
+
+impl Verification {
+    pub fn from_element(mut el: XmlElement) -> Self {
+        let mut verification = Verification {
+            vendor: el.take_attr("vendor"),
+            attributes: std::mem::take(&mut el.attributes),
+            ..Default::default()
+        };
+
+        for mut child in el.into_elements() {
+            match child.name.as_str() {
+                "JavaScriptResource" => {
+                    verification.javascript_resources.push(JavaScriptResource {
+                        api_framework: child.take_attr("apiFramework"),
+                        browser_optional: child
+                            .take_attr("browserOptional")
+                            .and_then(|v| parse_bool(&v)),
+                        url: child.text(),
+                    })
+                }
+                "VerificationParameters" => {
+                    verification.verification_parameters = Some(child.text())
+                }
+                "TrackingEvents" => verification
+                    .tracking_events
+                    .extend(parse_tracking_events(child)),
+                _ => verification.extra.push(child),
+            }
+        }
+
+        verification
+    }
+
+    pub fn to_element(&self) -> XmlElement {
+        let mut el = XmlElement::new("Verification");
+        push_attr(&mut el, "vendor", &self.vendor);
+        el.attributes.extend(self.attributes.iter().cloned());
+
+        for resource in &self.javascript_resources {
+            let mut js = XmlElement::with_cdata("JavaScriptResource", &resource.url);
+            push_attr(&mut js, "apiFramework", &resource.api_framework);
+            push_attr(&mut js, "browserOptional", &resource.browser_optional);
+            el.push(js);
+        }
+        push_extra(&mut el, &self.extra);
+        push_tracking_events(&mut el, &self.tracking_events);
+        if let Some(params) = &self.verification_parameters {
+            el.push(XmlElement::with_cdata("VerificationParameters", params));
+        }
+
+        el
+    }
+}
+
+impl Creative {
+    pub fn from_element(mut el: XmlElement) -> Self {
+        let mut creative = Creative {
+            id: el.take_attr("id"),
+            sequence: el.take_attr("sequence").and_then(|v| v.trim().parse().ok()),
+            ad_id: el.take_attr("adId").or_else(|| el.take_attr("AdID")),
+            api_framework: el.take_attr("apiFramework"),
+            attributes: std::mem::take(&mut el.attributes),
+            ..Default::default()
+        };
+
+        for child in el.into_elements() {
+            match child.name.as_str() {
+                "Linear" => creative.linear = Some(Linear::from_element(child)),
+                "NonLinearAds" => creative.non_linear_ads = Some(NonLinearAds::from_element(child)),
+                "CompanionAds" => creative.companion_ads = Some(CompanionAds::from_element(child)),
+                _ => creative.extra.push(child),
+            }
+        }
+
+        creative
+    }
+
+    pub fn to_element(&self) -> XmlElement {
+        let mut el = XmlElement::new("Creative");
+        push_attr(&mut el, "id", &self.id);
+        push_attr(&mut el, "sequence", &self.sequence);
+        push_attr(&mut el, "adId", &self.ad_id);
+        push_attr(&mut el, "apiFramework", &self.api_framework);
+        el.attributes.extend(self.attributes.iter().cloned());
+
+        push_extra(&mut el, &self.extra);
+        if let Some(linear) = &self.linear {
+            el.push(linear.to_element());
+        }
+        if let Some(non_linear_ads) = &self.non_linear_ads {
+            el.push(non_linear_ads.to_element());
+        }
+        if let Some(companion_ads) = &self.companion_ads {
+            el.push(companion_ads.to_element());
+        }
+
+        el
+    }
+}
+
+impl Linear {
+    pub fn from_element(mut el: XmlElement) -> Self {
+        let mut linear = Linear {
+            skip_offset: el.take_attr("skipoffset").and_then(|v| Offset::parse(&v)),
+            ..Default::default()
+        };
+
+        for child in el.into_elements() {
+            match child.name.as_str() {
+                "AdParameters" => linear.ad_parameters = Some(child.text()),
+                "Duration" => linear.duration = parse_timecode(&child.text()),
+                "MediaFiles" => {
+                    for file in child.into_elements() {
+                        if file.name == "MediaFile" {
+                            linear.media_files.push(MediaFile::from_element(file));
+                        } else {
+                            linear.media_files_extra.push(file);
+                        }
+                    }
+                }
+                "TrackingEvents" => linear.tracking_events.extend(parse_tracking_events(child)),
+                "VideoClicks" => linear.video_clicks = Some(VideoClicks::from_element(child)),
+                _ => linear.extra.push(child),
+            }
+        }
+
+        linear
+    }
+
+    pub fn to_element(&self) -> XmlElement {
+        let mut el = XmlElement::new("Linear");
+        if let Some(offset) = &self.skip_offset {
+            el.set_attr("skipoffset", offset.to_string());
+        }
+
+        if let Some(params) = &self.ad_parameters {
+            el.push(XmlElement::with_cdata("AdParameters", params));
+        }
+        if let Some(duration) = self.duration {
+            el.push(XmlElement::with_text("Duration", format_timecode(duration)));
+        }
+        if !self.media_files.is_empty() || !self.media_files_extra.is_empty() {
+            let mut files = XmlElement::new("MediaFiles");
+            for file in &self.media_files {
+                files.push(file.to_element());
+            }
+            push_extra(&mut files, &self.media_files_extra);
+            el.push(files);
+        }
+        push_tracking_events(&mut el, &self.tracking_events);
+        if let Some(clicks) = &self.video_clicks {
+            el.push(clicks.to_element());
+        }
+        push_extra(&mut el, &self.extra);
+
+        el
+    }
+}
+
+impl VideoClicks {
+    pub fn from_element(el: XmlElement) -> Self {
+        let mut clicks = VideoClicks::default();
+
+        for child in el.into_elements() {
+            match child.name.as_str() {
+                "ClickThrough" => clicks.click_through = Some(TrackedUrl::from_element(child)),
+                "ClickTracking" => clicks.click_tracking.push(TrackedUrl::from_element(child)),
+                "CustomClick" => clicks.custom_click.push(TrackedUrl::from_element(child)),
+                _ => clicks.extra.push(child),
+            }
+        }
+
+        clicks
+    }
+
+    pub fn to_element(&self) -> XmlElement {
+        let mut el = XmlElement::new("VideoClicks");
+        if let Some(through) = &self.click_through {
+            el.push(through.to_element("ClickThrough"));
+        }
+        push_tracked_urls(&mut el, "ClickTracking", &self.click_tracking);
+        push_tracked_urls(&mut el, "CustomClick", &self.custom_click);
+        push_extra(&mut el, &self.extra);
+        el
+    }
+}
+
+impl MediaFile {
+    /// Whether the mime type is `audio/*`
+    pub fn is_audio(&self) -> bool {
+        self.mime_type
+            .as_deref()
+            .is_some_and(|t| t.trim().to_ascii_lowercase().starts_with("audio/"))
+    }
+
+    pub fn from_element(mut el: XmlElement) -> Self {
+        MediaFile {
+            id: el.take_attr("id"),
+            delivery: el.take_attr("delivery"),
+            mime_type: el.take_attr("type"),
+            width: el.take_attr("width").and_then(|v| v.trim().parse().ok()),
+            height: el.take_attr("height").and_then(|v| v.trim().parse().ok()),
+            codec: el.take_attr("codec"),
+            bitrate: el.take_attr("bitrate").and_then(|v| v.trim().parse().ok()),
+            min_bitrate: el
+                .take_attr("minBitrate")
+                .and_then(|v| v.trim().parse().ok()),
+            max_bitrate: el
+                .take_attr("maxBitrate")
+                .and_then(|v| v.trim().parse().ok()),
+            scalable: el.take_attr("scalable").and_then(|v| parse_bool(&v)),
+            maintain_aspect_ratio: el
+                .take_attr("maintainAspectRatio")
+                .and_then(|v| parse_bool(&v)),
+            api_framework: el.take_attr("apiFramework"),
+            url: el.text(),
+            attributes: std::mem::take(&mut el.attributes),
+        }
+    }
+
+    pub fn to_element(&self) -> XmlElement {
+        let mut el = XmlElement::with_cdata("MediaFile", &self.url);
+        push_attr(&mut el, "id", &self.id);
+        push_attr(&mut el, "delivery", &self.delivery);
+        push_attr(&mut el, "type", &self.mime_type);
+        push_attr(&mut el, "width", &self.width);
+        push_attr(&mut el, "height", &self.height);
+        push_attr(&mut el, "codec", &self.codec);
+        push_attr(&mut el, "bitrate", &self.bitrate);
+        push_attr(&mut el, "minBitrate", &self.min_bitrate);
+        push_attr(&mut el, "maxBitrate", &self.max_bitrate);
+        push_attr(&mut el, "scalable", &self.scalable);
+        push_attr(&mut el, "maintainAspectRatio", &self.maintain_aspect_ratio);
+        push_attr(&mut el, "apiFramework", &self.api_framework);
+        el.attributes.extend(self.attributes.iter().cloned());
+        el
+    }
+}
+
+impl Tracking {
+    pub fn from_element(mut el: XmlElement) -> Self {
+        Tracking {
+            event: el.take_attr("event").unwrap_or_default(),
+            offset: el.take_attr("offset").and_then(|v| Offset::parse(&v)),
+            url: el.text(),
+            attributes: std::mem::take(&mut el.attributes),
+        }
+    }
+
+    pub fn to_element(&self) -> XmlElement {
+        let mut el = XmlElement::with_cdata("Tracking", &self.url);
+        el.set_attr("event", &self.event);
+        if let Some(offset) = &self.offset {
+            el.set_attr("offset", offset.to_string());
+        }
+        el.attributes.extend(self.attributes.iter().cloned());
+        el
+    }
+}
+
+impl NonLinearAds {
+    pub fn from_element(el: XmlElement) -> Self {
+        let mut ads = NonLinearAds::default();
+
+        for child in el.into_elements() {
+            match child.name.as_str() {
+                "NonLinear" => ads.non_linears.push(NonLinear::from_element(child)),
+                "TrackingEvents" => ads.tracking_events.extend(parse_tracking_events(child)),
+                _ => ads.extra.push(child),
+            }
+        }
+
+        ads
+    }
+
+    pub fn to_element(&self) -> XmlElement {
+        let mut el = XmlElement::new("NonLinearAds");
+        for non_linear in &self.non_linears {
+            el.push(non_linear.to_element());
+        }
+        push_tracking_events(&mut el, &self.tracking_events);
+        push_extra(&mut el, &self.extra);
+        el
+    }
+}
+
+impl NonLinear {
+    pub fn from_element(mut el: XmlElement) -> Self {
+        let mut non_linear = NonLinear {
+            id: el.take_attr("id"),
+            width: el.take_attr("width").and_then(|v| v.trim().parse().ok()),
+            height: el.take_attr("height").and_then(|v| v.trim().parse().ok()),
+            attributes: std::mem::take(&mut el.attributes),
+            ..Default::default()
+        };
+
+        for child in el.into_elements() {
+            match child.name.as_str() {
+                "NonLinearClickThrough" => non_linear.click_through = Some(child.text()),
+                "NonLinearClickTracking" => non_linear
+                    .click_tracking
+                    .push(TrackedUrl::from_element(child)),
+                _ => match Resource::from_element(child) {
+                    Ok(resource) => non_linear.resources.push(resource),
+                    Err(child) => non_linear.extra.push(child),
+                },
+            }
+        }
+
+        non_linear
+    }
+
+    pub fn to_element(&self) -> XmlElement {
+        let mut el = XmlElement::new("NonLinear");
+        push_attr(&mut el, "id", &self.id);
+        push_attr(&mut el, "width", &self.width);
+        push_attr(&mut el, "height", &self.height);
+        el.attributes.extend(self.attributes.iter().cloned());
+
+        for resource in &self.resources {
+            el.push(resource.to_element());
+        }
+        push_extra(&mut el, &self.extra);
+        if let Some(url) = &self.click_through {
+            el.push(XmlElement::with_cdata("NonLinearClickThrough", url));
+        }
+        push_tracked_urls(&mut el, "NonLinearClickTracking", &self.click_tracking);
+
+        el
+    }
+}
+
+impl CompanionAds {
+    pub fn from_element(mut el: XmlElement) -> Self {
+        let mut ads = CompanionAds {
+            required: el.take_attr("required"),
+            ..Default::default()
+        };
+
+        for child in el.into_elements() {
+            match child.name.as_str() {
+                "Companion" => ads.companions.push(Companion::from_element(child)),
+                _ => ads.extra.push(child),
+            }
+        }
+
+        ads
+    }
+
+    pub fn to_element(&self) -> XmlElement {
+        let mut el = XmlElement::new("CompanionAds");
+        push_attr(&mut el, "required", &self.required);
+        for companion in &self.companions {
+            el.push(companion.to_element());
+        }
+        push_extra(&mut el, &self.extra);
+        el
+    }
+}
+
+impl Companion {
+    pub fn from_element(mut el: XmlElement) -> Self {
+        let mut companion = Companion {
+            id: el.take_attr("id"),
+            width: el.take_attr("width").and_then(|v| v.trim().parse().ok()),
+            height: el.take_attr("height").and_then(|v| v.trim().parse().ok()),
+            attributes: std::mem::take(&mut el.attributes),
+            ..Default::default()
+        };
+
+        for child in el.into_elements() {
+            match child.name.as_str() {
+                "AltText" => companion.alt_text = Some(child.text()),
+                "CompanionClickThrough" => companion.click_through = Some(child.text()),
+                "CompanionClickTracking" => companion
+                    .click_tracking
+                    .push(TrackedUrl::from_element(child)),
+                "TrackingEvents" => companion
+                    .tracking_events
+                    .extend(parse_tracking_events(child)),
+                _ => match Resource::from_element(child) {
+                    Ok(resource) => companion.resources.push(resource),
+                    Err(child) => companion.extra.push(child),
+                },
+            }
+        }
+
+        companion
+    }
+
+    pub fn to_element(&self) -> XmlElement {
+        let mut el = XmlElement::new("Companion");
+        push_attr(&mut el, "id", &self.id);
+        push_attr(&mut el, "width", &self.width);
+        push_attr(&mut el, "height", &self.height);
+        el.attributes.extend(self.attributes.iter().cloned());
+
+        for resource in &self.resources {
+            el.push(resource.to_element());
+        }
+        push_extra(&mut el, &self.extra);
+        push_text(&mut el, "AltText", &self.alt_text);
+        if let Some(url) = &self.click_through {
+            el.push(XmlElement::with_cdata("CompanionClickThrough", url));
+        }
+        push_tracked_urls(&mut el, "CompanionClickTracking", &self.click_tracking);
+        push_tracking_events(&mut el, &self.tracking_events);
+
+        el
+    }
+}
+
+impl Resource {
+    /// Parses a resource element, handing back any other element unchanged
+    pub fn from_element(mut el: XmlElement) -> Result<Self, XmlElement> {
+        match el.name.as_str() {
+            "StaticResource" => Ok(Resource::Static {
+                creative_type: el.take_attr("creativeType"),
+                url: el.text(),
+            }),
+            "IFrameResource" => Ok(Resource::IFrame(el.text())),
+            "HTMLResource" => Ok(Resource::Html(el.text())),
+            _ => Err(el),
+        }
+    }
+
+    pub fn to_element(&self) -> XmlElement {
+        match self {
+            Resource::Static { creative_type, url } => {
+                let mut el = XmlElement::with_cdata("StaticResource", url);
+                push_attr(&mut el, "creativeType", creative_type);
+                el
+            }
+            Resource::IFrame(url) => XmlElement::with_cdata("IFrameResource", url),
+            Resource::Html(html) => XmlElement::with_cdata("HTMLResource", html),
+        }
+    }
+}
+
+/// Parses a `major.minor` VAST version attribute
+pub(crate) fn parse_version(version: &str) -> Option<(u32, u32)> {
+    let mut parts = version.trim().split('.');
+    let major = parts.next()?.trim().parse().ok()?;
+    let minor = parts
+        .next()
+        .and_then(|p| p.trim().parse().ok())
+        .unwrap_or(0);
+    Some((major, minor))
+}
+
+fn parse_bool(v: &str) -> Option<bool> {
+    match v.trim() {
+        "true" | "1" => Some(true),
+        "false" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+fn parse_creatives(el: XmlElement) -> impl Iterator<Item = Creative> {
+    el.into_elements()
+        .filter(|c| c.name == "Creative")
+        .map(Creative::from_element)
+}
+
+fn parse_verifications(el: XmlElement) -> impl Iterator<Item = Verification> {
+    el.into_elements()
+        .filter(|c| c.name == "Verification")
+        .map(Verification::from_element)
+}
+
+fn parse_tracking_events(el: XmlElement) -> impl Iterator<Item = Tracking> {
+    el.into_elements()
+        .filter(|c| c.name == "Tracking")
+        .map(Tracking::from_element)
+}
+
+fn push_attr<T: ToString>(el: &mut XmlElement, name: &str, value: &Option<T>) {
+    if let Some(value) = value {
+        el.set_attr(name, value.to_string());
+    }
+}
+
+fn push_text(el: &mut XmlElement, name: &str, value: &Option<String>) {
+    if let Some(value) = value {
+        el.push(XmlElement::with_text(name, value));
+    }
+}
+
+fn push_urls(el: &mut XmlElement, name: &str, urls: &[String]) {
+    for url in urls {
+        el.push(XmlElement::with_cdata(name, url));
+    }
+}
+
+fn push_tracked_urls(el: &mut XmlElement, name: &str, urls: &[TrackedUrl]) {
+    for url in urls {
+        el.push(url.to_element(name));
+    }
+}
+
+fn push_extra(el: &mut XmlElement, extra: &[XmlElement]) {
+    for child in extra {
+        el.push(child.clone());
+    }
+}
+
+fn push_tracking_events(el: &mut XmlElement, events: &[Tracking]) {
+    if events.is_empty() {
+        return;
+    }
+
+    let mut tracking = XmlElement::new("TrackingEvents");
+    for event in events {
+        tracking.push(event.to_element());
+    }
+    el.push(tracking);
+}
+
+fn push_verifications(el: &mut XmlElement, verifications: &[Verification]) {
+    if verifications.is_empty() {
+        return;
+    }
+
+    let mut container = XmlElement::new("AdVerifications");
+    for verification in verifications {
+        container.push(verification.to_element());
+    }
+    el.push(container);
+}
+
+fn push_creatives(el: &mut XmlElement, creatives: &[Creative]) {
+    let mut container = XmlElement::new("Creatives");
+    for creative in creatives {
+        container.push(creative.to_element());
+    }
+    el.push(container);
+}
+
+fn push_extensions(el: &mut XmlElement, extensions: &[XmlElement]) {
+    if extensions.is_empty() {
+        return;
+    }
+
+    let mut container = XmlElement::new("Extensions");
+    push_extra(&mut container, extensions);
+    el.push(container);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INLINE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<VAST version="4.2" xmlns="http://www.iab.com/VAST">
+  <Ad id="ad-1" sequence="1" adType="video">
+    <InLine>
+      <AdSystem version="1.0">Example Server</AdSystem>
+      <Error><![CDATA[https://example.com/error?code=[ERRORCODE]]]></Error>
+      <Impression id="imp-1"><![CDATA[https://example.com/imp]]></Impression>
+      <Pricing model="CPM" currency="USD"><![CDATA[2.50]]></Pricing>
+      <AdServingId>serving-1</AdServingId>
+      <AdTitle>Example &amp; Co</AdTitle>
+      <Category authority="https://iabtechlab.com">IAB1</Category>
+      <AdVerifications>
+        <Verification vendor="vendor.com-omid">
+          <JavaScriptResource apiFramework="omid" browserOptional="true"><![CDATA[https://vendor.com/omid.js]]></JavaScriptResource>
+          <VerificationParameters><![CDATA[{"k":"v"}]]></VerificationParameters>
+        </Verification>
+      </AdVerifications>
+      <Creatives>
+        <Creative id="c-1" sequence="1" adId="ad-id-1">
+          <UniversalAdId idRegistry="ad-id.org">CNPA0484000H</UniversalAdId>
+          <Linear skipoffset="00:00:05">
+            <Duration>00:00:30.500</Duration>
+            <MediaFiles>
+              <MediaFile delivery="progressive" type="video/mp4" width="1280" height="720" bitrate="2000" vendorAttr="x"><![CDATA[https://cdn.example.com/ad.mp4]]></MediaFile>
+              <Mezzanine delivery="progressive" type="video/mp4" width="1920" height="1080"><![CDATA[https://cdn.example.com/mezz.mp4]]></Mezzanine>
+            </MediaFiles>
+            <TrackingEvents>
+              <Tracking event="start"><![CDATA[https://example.com/start]]></Tracking>
+              <Tracking event="progress" offset="25%"><![CDATA[https://example.com/p25]]></Tracking>
+            </TrackingEvents>
+            <VideoClicks>
+              <ClickThrough><![CDATA[https://advertiser.com]]></ClickThrough>
+              <ClickTracking id="ct"><![CDATA[https://example.com/click]]></ClickTracking>
+            </VideoClicks>
+            <Icons><Icon program="AdChoices"/></Icons>
+          </Linear>
+        </Creative>
+        <Creative id="c-2">
+          <CompanionAds required="any">
+            <Companion width="300" height="250">
+              <StaticResource creativeType="image/png"><![CDATA[https://cdn.example.com/c.png]]></StaticResource>
+              <CompanionClickThrough><![CDATA[https://advertiser.com/c]]></CompanionClickThrough>
+              <TrackingEvents>
+                <Tracking event="creativeView"><![CDATA[https://example.com/cv]]></Tracking>
+              </TrackingEvents>
+            </Companion>
+          </CompanionAds>
+        </Creative>
+      </Creatives>
+      <Extensions>
+        <Extension type="custom"><Data>kept</Data></Extension>
+      </Extensions>
+    </InLine>
+  </Ad>
+</VAST>"#;
+
+    const WRAPPER: &str = r#"<VAST version="3.0">
+  <Ad id="w-1">
+    <Wrapper followAdditionalWrappers="false">
+      <AdSystem>Wrapper Server</AdSystem>
+      <VASTAdTagURI><![CDATA[https://next.example.com/vast?x=1&y=2]]></VASTAdTagURI>
+      <Impression><![CDATA[https://wrapper.example.com/imp]]></Impression>
+      <Creatives>
+        <Creative>
+          <NonLinearAds>
+            <NonLinear width="480" height="70">
+              <NonLinearClickTracking><![CDATA[https://wrapper.example.com/nlc]]></NonLinearClickTracking>
+            </NonLinear>
+          </NonLinearAds>
+        </Creative>
+      </Creatives>
+    </Wrapper>
+  </Ad>
+</VAST>"#;
+
+    #[test]
+    fn test_parse_inline() {
+        let vast = Vast::parse(INLINE).unwrap();
+        assert_eq!(vast.version, "4.2");
+        assert!(vast.version_at_least(4, 1));
+        assert!(!vast.version_at_least(4, 3));
+
+        let ad = &vast.ads[0];
+        assert_eq!(ad.id.as_deref(), Some("ad-1"));
+        assert_eq!(ad.sequence, Some(1));
+        assert_eq!(ad.ad_type.as_deref(), Some("video"));
+
+        let inline = ad.inline().unwrap();
+        assert_eq!(inline.ad_system.as_ref().unwrap().name, "Example Server");
+        assert_eq!(inline.ad_title.as_deref(), Some("Example & Co"));
+        assert_eq!(
+            inline.errors,
+            vec!["https://example.com/error?code=[ERRORCODE]"]
+        );
+        assert_eq!(inline.impressions[0].id.as_deref(), Some("imp-1"));
+        assert_eq!(inline.pricing.as_ref().unwrap().value, "2.50");
+        assert_eq!(inline.extra[0].name, "Category");
+        assert_eq!(inline.extensions[0].attr("type"), Some("custom"));
+
+        let verification = &inline.ad_verifications[0];
+        assert_eq!(verification.vendor.as_deref(), Some("vendor.com-omid"));
+        assert_eq!(
+            verification.javascript_resources[0].browser_optional,
+            Some(true)
+        );
+        assert_eq!(
+            verification.verification_parameters.as_deref(),
+            Some(r#"{"k":"v"}"#)
+        );
+    }
+
+    #[test]
+    fn test_parse_linear() {
+        let vast = Vast::parse(INLINE).unwrap();
+        let linear = vast.linears().next().unwrap();
+
+        assert_eq!(linear.duration, Some(Duration::from_millis(30_500)));
+        assert_eq!(
+            linear.skip_offset,
+            Some(Offset::Time(Duration::from_secs(5)))
+        );
+        assert_eq!(linear.extra[0].name, "Icons");
+
+        let file = &linear.media_files[0];
+        assert_eq!(file.url, "https://cdn.example.com/ad.mp4");
+        assert_eq!(file.mime_type.as_deref(), Some("video/mp4"));
+        assert_eq!(
+            (file.width, file.height, file.bitrate),
+            (Some(1280), Some(720), Some(2000))
+        );
+        assert_eq!(
+            file.attributes,
+            vec![("vendorAttr".to_string(), "x".to_string())]
+        );
+        assert_eq!(linear.media_files_extra[0].name, "Mezzanine");
+
+        assert_eq!(
+            linear.tracking_events[1].offset,
+            Some(Offset::Percent(25.0))
+        );
+        let clicks = linear.video_clicks.as_ref().unwrap();
+        assert_eq!(
+            clicks.click_through.as_ref().unwrap().url,
+            "https://advertiser.com"
+        );
+        assert_eq!(clicks.click_tracking[0].id.as_deref(), Some("ct"));
+
+        let companion = &vast
+            .creatives()
+            .nth(1)
+            .unwrap()
+            .companion_ads
+            .as_ref()
+            .unwrap()
+            .companions[0];
+        assert_eq!(companion.width, Some(300));
+        assert!(
+            matches!(&companion.resources[0], Resource::Static { url, .. } if url == "https://cdn.example.com/c.png")
+        );
+        assert_eq!(companion.tracking_events[0].event, "creativeView");
+    }
+
+    #[test]
+    fn test_parse_wrapper() {
+        let vast = Vast::parse(WRAPPER).unwrap();
+        let wrapper = vast.ads[0].wrapper().unwrap();
+
+        assert_eq!(
+            wrapper.vast_ad_tag_uri,
+            "https://next.example.com/vast?x=1&y=2"
+        );
+        assert_eq!(wrapper.follow_additional_wrappers, Some(false));
+        assert_eq!(wrapper.impressions.len(), 1);
+
+        let non_linear = &wrapper.creatives[0]
+            .non_linear_ads
+            .as_ref()
+            .unwrap()
+            .non_linears[0];
+        assert_eq!(
+            non_linear.click_tracking[0].url,
+            "https://wrapper.example.com/nlc"
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for xml in [INLINE, WRAPPER] {
+            let vast = Vast::parse(xml).unwrap();
+            let out = vast.to_xml_string().unwrap();
+            assert_eq!(Vast::parse(&out).unwrap(), vast);
+        }
+    }
+
+    #[test]
+    fn test_round_trip_preserves_unknown_elements() {
+        let out = Vast::parse(INLINE).unwrap().to_xml_string().unwrap();
+
+        assert!(out.contains(r#"<Category authority="https://iabtechlab.com">IAB1</Category>"#));
+        assert!(out.contains(r#"<Extension type="custom"><Data>kept</Data></Extension>"#));
+        assert!(
+            out.contains(r#"<UniversalAdId idRegistry="ad-id.org">CNPA0484000H</UniversalAdId>"#)
+        );
+        assert!(out.contains(r#"xmlns="http://www.iab.com/VAST""#));
+        assert!(out.contains("<![CDATA[https://cdn.example.com/ad.mp4]]>"));
+    }
+
+    #[test]
+    fn test_round_trip_preserves_vendor_elements_and_attributes() {
+        let xml = r#"<VAST version="4.1">
+  <Ad id="1">
+    <InLine vendor:flag="1">
+      <AdSystem>Example</AdSystem>
+      <Impression id="i" vendor:slot="top"><![CDATA[https://example.com/imp]]></Impression>
+      <ViewableImpression id="v" vendor:mode="x">
+        <Viewable><![CDATA[https://example.com/viewable]]></Viewable>
+        <vendor:ViewableData><Percent>50</Percent></vendor:ViewableData>
+      </ViewableImpression>
+      <AdVerifications>
+        <Verification vendor="v.com" vendor:id="7">
+          <JavaScriptResource apiFramework="omid"><![CDATA[https://v.com/omid.js]]></JavaScriptResource>
+        </Verification>
+      </AdVerifications>
+      <Creatives><Creative><Linear>
+        <Duration>00:00:10</Duration>
+        <TrackingEvents>
+          <Tracking event="start" vendor:beacon="b"><![CDATA[https://example.com/start]]></Tracking>
+        </TrackingEvents>
+        <VideoClicks>
+          <ClickThrough><![CDATA[https://advertiser.com]]></ClickThrough>
+          <vendor:ClickMacro name="m"/>
+        </VideoClicks>
+      </Linear></Creative></Creatives>
+    </InLine>
+  </Ad>
+  <Ad id="2">
+    <Wrapper vendor:chain="2">
+      <AdSystem>Example</AdSystem>
+      <VASTAdTagURI><![CDATA[https://next.example.com/vast]]></VASTAdTagURI>
+    </Wrapper>
+  </Ad>
+</VAST>"#;
+
+        let vast = Vast::parse(xml).unwrap();
+        let out = vast.to_xml_string().unwrap();
+
+        for expected in [
+            r#"<InLine vendor:flag="1">"#,
+            r#"<Impression id="i" vendor:slot="top">"#,
+            r#"<ViewableImpression id="v" vendor:mode="x">"#,
+            "<vendor:ViewableData><Percent>50</Percent></vendor:ViewableData>",
+            r#"<Verification vendor="v.com" vendor:id="7">"#,
+            r#"<Tracking event="start" vendor:beacon="b">"#,
+            r#"<vendor:ClickMacro name="m"/>"#,
+            r#"<Wrapper vendor:chain="2">"#,
+        ] {
+            assert!(out.contains(expected), "{expected} missing from {out}");
+        }
+        assert_eq!(Vast::parse(&out).unwrap(), vast);
+    }
+
+    #[test]
+    fn test_is_audio() {
+        let audio = Vast::parse(
+            r#"<VAST version="4.0"><Ad><InLine><Creatives><Creative><Linear>
+            <MediaFiles>
+                <MediaFile type="audio/mpeg">https://cdn.example.com/a.mp3</MediaFile>
+                <MediaFile type="audio/aac">https://cdn.example.com/a.aac</MediaFile>
+            </MediaFiles>
+            </Linear></Creative></Creatives></InLine></Ad></VAST>"#,
+        )
+        .unwrap();
+        assert!(audio.is_audio());
+
+        let mut mixed = audio.clone();
+        mixed.ads[0].creatives_mut()[0]
+            .linear
+            .as_mut()
+            .unwrap()
+            .media_files[1]
+            .mime_type = Some("video/mp4".to_string());
+        assert!(!mixed.is_audio());
+
+        mixed.ads[0].ad_type = Some("audio".to_string());
+        assert!(mixed.is_audio());
+
+        assert!(!Vast::default().is_audio());
+    }
+
+    #[test]
+    fn test_timecodes() {
+        assert_eq!(parse_timecode("00:01:02"), Some(Duration::from_secs(62)));
+        assert_eq!(
+            parse_timecode("01:00:00.250"),
+            Some(Duration::from_millis(3_600_250))
+        );
+        assert_eq!(parse_timecode("00:61:00"), None);
+        assert_eq!(parse_timecode("15"), None);
+
+        assert_eq!(format_timecode(Duration::from_secs(62)), "00:01:02");
+        assert_eq!(
+            format_timecode(Duration::from_millis(1_500)),
+            "00:00:01.500"
+        );
+
+        let offset = Offset::parse("50%").unwrap();
+        assert_eq!(
+            offset.resolve(Duration::from_secs(30)),
+            Duration::from_secs(15)
+        );
+        assert_eq!(offset.to_string(), "50%");
+
+        assert_eq!(parse_timecode("5124095576030431:59:59"), None);
+        assert_eq!(parse_timecode("18446744073709551615:00:00"), None);
+        for invalid in ["NaN%", "inf%", "-5%", "101%"] {
+            assert_eq!(Offset::parse(invalid), None, "{invalid}");
+        }
+        assert_eq!(
+            Offset::Percent(f32::NAN).resolve(Duration::from_secs(30)),
+            Duration::ZERO
+        );
+    }
+
+    #[test]
+    fn test_invalid_documents() {
+        assert!(Vast::parse("<VMAP/>").is_err());
+        assert!(Vast::parse(r#"<VAST version="4.0"><Ad id="1"></Ad></VAST>"#).is_err());
+        assert!(Vast::parse(r#"<VAST version="4.0"/>"#).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,715 @@
+use super::model::{Ad, AdContent, InLine, Vast, Wrapper};
+use crate::common::{Clock, MonotonicClock};
+use anyhow::Result;
+use derive_builder::Builder;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Duration;
+
+/// Source of VAST documents for wrapper resolution.
+///
+/// Implemented for closures and for a `HashMap` of url to document, so tests
+/// and offline pipelines can serve tags from memory.
+///
+/// Fetching is blocking: a resolver backed by a network fetcher must run off
+/// the async workers, e.g. inside `actix_web::web::block` or
+/// `tokio::task::spawn_blocking`, as every wrapper hop waits on the fetch.
+pub trait VastFetcher {
+    /// Fetches the document at `url`, giving up after `timeout`
+    fn fetch(&self, url: &str, timeout: Duration) -> Result<String>;
+}
+
+impl<F> VastFetcher for F
+where
+    F: Fn(&str, Duration) -> Result<String>,
+{
+    fn fetch(&self, url: &str, timeout: Duration) -> Result<String> {
+        self(url, timeout)
+    }
+}
+
+impl VastFetcher for HashMap<String, String> {
+    fn fetch(&self, url: &str, _timeout: Duration) -> Result<String> {
+        self.get(url)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("No VAST document for {url}"))
+    }
+}
+
+/// Limits applied while following a wrapper chain
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Builder)]
+pub struct VastResolverConfig {
+    /// Maximum number of wrappers to follow, IAB recommends 5
+    #[builder(default = "5")]
+    pub max_depth: usize,
+
+    /// Deadline for resolving the whole chain
+    #[builder(default = "Duration::from_millis(3000)")]
+    pub total_timeout: Duration,
+
+    /// Upper bound for a single fetch, further capped by the time remaining
+    #[builder(default = "Duration::from_millis(1000)")]
+    pub fetch_timeout: Duration,
+}
+
+impl Default for VastResolverConfig {
+    fn default() -> Self {
+        VastResolverConfigBuilder::default().build().unwrap()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResolveErrorKind {
+    /// The chain was not resolved before the deadline
+    Timeout,
+    /// A wrapper tag could not be fetched
+    Fetch { url: String, reason: String },
+    /// A fetched document was not valid VAST
+    Parse { url: String, reason: String },
+    /// More wrappers than [`VastResolverConfig::max_depth`]
+    DepthExceeded { max_depth: usize },
+    /// A wrapper points back at a tag already in the chain
+    Cycle { url: String },
+    /// A wrapper tag returned no ads
+    NoAds { url: String },
+    /// A wrapper with `followAdditionalWrappers="false"` led to another wrapper
+    WrapperNotAllowed { url: String },
+    /// A wrapper has no `VASTAdTagURI`
+    MissingAdTagUri,
+}
+
+/// Failure to resolve a wrapper chain
+#[derive(Debug, Clone, PartialEq)]
+pub struct VastResolveError {
+    pub kind: ResolveErrorKind,
+    /// `Error` URLs of every wrapper on the failed path, to be fired with
+    /// [`VastResolveError::code`] substituted for `[ERRORCODE]`
+    pub error_urls: Vec<String>,
+}
+
+impl VastResolveError {
+    fn new(kind: ResolveErrorKind) -> Self {
+        Self {
+            kind,
+            error_urls: Vec::new(),
+        }
+    }
+
+    /// VAST error code describing the failure
+    pub fn code(&self) -> u32 {
+        match self.kind {
+            ResolveErrorKind::Timeout | ResolveErrorKind::Fetch { .. } => 301,
+            ResolveErrorKind::DepthExceeded { .. } | ResolveErrorKind::Cycle { .. } => 302,
+            ResolveErrorKind::NoAds { .. } => 303,
+            ResolveErrorKind::Parse { .. } => 100,
+            ResolveErrorKind::WrapperNotAllowed { .. } | ResolveErrorKind::MissingAdTagUri => 300,
+        }
+    }
+}
+
+impl fmt::Display for VastResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            ResolveErrorKind::Timeout => write!(f, "VAST wrapper chain timed out"),
+            ResolveErrorKind::Fetch { url, reason } => {
+                write!(f, "Failed to fetch VAST from {url}: {reason}")
+            }
+            ResolveErrorKind::Parse { url, reason } => {
+                write!(f, "Invalid VAST from {url}: {reason}")
+            }
+            ResolveErrorKind::DepthExceeded { max_depth } => {
+                write!(f, "VAST wrapper chain exceeds {max_depth} wrappers")
+            }
+            ResolveErrorKind::Cycle { url } => write!(f, "VAST wrapper cycle at {url}"),
+            ResolveErrorKind::NoAds { url } => write!(f, "No ads returned by {url}"),
+            ResolveErrorKind::WrapperNotAllowed { url } => {
+                write!(
+                    f,
+                    "Wrapper returned by {url} but additional wrappers are not allowed"
+                )
+            }
+            ResolveErrorKind::MissingAdTagUri => write!(f, "Wrapper has no VASTAdTagURI"),
+        }
+    }
+}
+
+impl std::error::Error for VastResolveError {}
+
+/// Outcome of resolving a VAST document
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolvedVast {
+    /// The document with every wrapper replaced by its final InLine ad(s),
+    /// carrying the trackers of all wrappers above it
+    pub vast: Vast,
+    /// Every tag URL fetched, in order
+    pub fetched: Vec<String>,
+    /// Ads of the original document which could not be resolved
+    pub failures: Vec<VastResolveError>,
+}
+
+/// Follows `Wrapper/VASTAdTagURI` chains to their InLine ads.
+///
+/// Impressions, errors, viewability trackers, verifications and creative
+/// level tracking of every wrapper are merged into the final InLine ad, as
+/// the player would otherwise fire them while unwrapping.
+///
+/// Resolution blocks on the [`VastFetcher`], see its docs for use from
+/// async handlers.
+///
+/// # Example
+/// ```
+/// use rtb::openrtb::utils::vast::{VastResolver, VastResolverConfig};
+/// use std::collections::HashMap;
+///
+/// let mut tags = HashMap::new();
+/// tags.insert(
+///     "https://ads.example.com/inline".to_string(),
+///     r#"<VAST version="4.0"><Ad><InLine><Creatives/></InLine></Ad></VAST>"#.to_string(),
+/// );
+///
+/// let resolver = VastResolver::new(tags, VastResolverConfig::default());
+/// let resolved = resolver
+///     .resolve(r#"<VAST version="4.0"><Ad><Wrapper>
+///         <VASTAdTagURI>https://ads.example.com/inline</VASTAdTagURI>
+///         <Impression>https://wrapper.example.com/imp</Impression>
+///     </Wrapper></Ad></VAST>"#)
+///     .unwrap();
+///
+/// let inline = resolved.vast.ads[0].inline().unwrap();
+/// assert_eq!(inline.impressions[0].url, "https://wrapper.example.com/imp");
+/// ```
+pub struct VastResolver<F, C = MonotonicClock> {
+    fetcher: F,
+    clock: C,
+    config: VastResolverConfig,
+}
+
+struct ResolveState {
+    started_ms: u64,
+    fetched: Vec<String>,
+    path: Vec<String>,
+    failures: Vec<VastResolveError>,
+}
+
+impl<F: VastFetcher> VastResolver<F> {
+    pub fn new(fetcher: F, config: VastResolverConfig) -> Self {
+        Self {
+            fetcher,
+            clock: MonotonicClock::default(),
+            config,
+        }
+    }
+}
+
+impl<F: VastFetcher, C: Clock> VastResolver<F, C> {
+    /// Uses the given clock for the resolution deadline
+    pub fn with_clock<C2: Clock>(self, clock: C2) -> VastResolver<F, C2> {
+        VastResolver {
+            fetcher: self.fetcher,
+            clock,
+            config: self.config,
+        }
+    }
+
+    /// Resolves every ad of a VAST document
+    pub fn resolve(&self, xml: &str) -> Result<ResolvedVast, VastResolveError> {
+        let vast = Vast::parse(xml).map_err(|e| {
+            VastResolveError::new(ResolveErrorKind::Parse {
+                url: String::new(),
+                reason: e.to_string(),
+            })
+        })?;
+
+        self.resolve_vast(vast)
+    }
+
+    /// Fetches and resolves the VAST document at `url`
+    pub fn resolve_url(&self, url: &str) -> Result<ResolvedVast, VastResolveError> {
+        let mut state = self.new_state();
+        let vast = self.fetch(url, &mut state)?;
+        state.path.push(url.trim().to_string());
+
+        self.resolve_with(vast, state)
+    }
+
+    /// Resolves every ad of an already parsed document.
+    ///
+    /// Ads which fail are dropped and reported in [`ResolvedVast::failures`].
+    /// Fails only when the document had ads and none could be resolved.
+    pub fn resolve_vast(&self, vast: Vast) -> Result<ResolvedVast, VastResolveError> {
+        let state = self.new_state();
+        self.resolve_with(vast, state)
+    }
+
+    fn new_state(&self) -> ResolveState {
+        ResolveState {
+            started_ms: self.clock.now_ms(),
+            fetched: Vec::new(),
+            path: Vec::new(),
+            failures: Vec::new(),
+        }
+    }
+
+    fn resolve_with(
+        &self,
+        mut vast: Vast,
+        mut state: ResolveState,
+    ) -> Result<ResolvedVast, VastResolveError> {
+        let had_ads = !vast.ads.is_empty();
+        let mut ads = Vec::new();
+
+        for ad in std::mem::take(&mut vast.ads) {
+            match self.resolve_ad(ad, 0, &mut state) {
+                Ok(resolved) => ads.extend(resolved),
+                Err(e) => state.failures.push(e),
+            }
+        }
+
+        if had_ads && ads.is_empty() {
+            return Err(state.failures.remove(0));
+        }
+
+        vast.ads = ads;
+        Ok(ResolvedVast {
+            vast,
+            fetched: state.fetched,
+            failures: state.failures,
+        })
+    }
+
+    fn resolve_ad(
+        &self,
+        mut ad: Ad,
+        depth: usize,
+        state: &mut ResolveState,
+    ) -> Result<Vec<Ad>, VastResolveError> {
+        let wrapper = match &mut ad.content {
+            AdContent::InLine(_) => return Ok(vec![ad]),
+            AdContent::Wrapper(wrapper) => std::mem::take(wrapper),
+        };
+
+        // Failures of pod siblings recorded below this wrapper also carry its
+        // error URLs
+        let recorded = state.failures.len();
+        let result = self.follow_wrapper(&ad, &wrapper, depth, state);
+        for e in state.failures[recorded..].iter_mut() {
+            prepend_error_urls(e, &wrapper);
+        }
+
+        result.map_err(|mut e| {
+            prepend_error_urls(&mut e, &wrapper);
+            e
+        })
+    }
+
+    fn follow_wrapper(
+        &self,
+        ad: &Ad,
+        wrapper: &Wrapper,
+        depth: usize,
+        state: &mut ResolveState,
+    ) -> Result<Vec<Ad>, VastResolveError> {
+        if depth >= self.config.max_depth {
+            return Err(VastResolveError::new(ResolveErrorKind::DepthExceeded {
+                max_depth: self.config.max_depth,
+            }));
+        }
+
+        let url = wrapper.vast_ad_tag_uri.trim();
+        if url.is_empty() {
+            return Err(VastResolveError::new(ResolveErrorKind::MissingAdTagUri));
+        }
+        if state.path.iter().any(|u| u == url) {
+            return Err(VastResolveError::new(ResolveErrorKind::Cycle {
+                url: url.to_string(),
+            }));
+        }
+
+        let vast = self.fetch(url, state)?;
+        if vast.ads.is_empty() {
+            return Err(VastResolveError::new(ResolveErrorKind::NoAds {
+                url: url.to_string(),
+            }));
+        }
+
+        let children = if wrapper.allow_multiple_ads == Some(true) {
+            vast.ads
+        } else {
+            vast.ads.into_iter().take(1).collect()
+        };
+
+        // With allowMultipleAds every child is resolved on its own: failed
+        // children are recorded and the wrapper fails only if none resolved
+        state.path.push(url.to_string());
+        let mut resolved = Vec::new();
+        let mut errors = Vec::new();
+
+        for child in children {
+            let result =
+                if wrapper.follow_additional_wrappers == Some(false) && child.wrapper().is_some() {
+                    Err(VastResolveError::new(ResolveErrorKind::WrapperNotAllowed {
+                        url: url.to_string(),
+                    }))
+                } else {
+                    self.resolve_ad(child, depth + 1, state)
+                };
+
+            match result {
+                Ok(ads) => resolved.extend(ads),
+                Err(e) => errors.push(e),
+            }
+        }
+        state.path.pop();
+
+        if resolved.is_empty() && !errors.is_empty() {
+            let e = errors.remove(0);
+            state.failures.append(&mut errors);
+            return Err(e);
+        }
+        state.failures.append(&mut errors);
+
+        for child in resolved.iter_mut() {
+            if let AdContent::InLine(inline) = &mut child.content {
+                merge_wrapper(inline, wrapper);
+            }
+            if ad.sequence.is_some() && wrapper.allow_multiple_ads != Some(true) {
+                child.sequence = ad.sequence;
+            }
+        }
+
+        Ok(resolved)
+    }
+
+    fn fetch(&self, url: &str, state: &mut ResolveState) -> Result<Vast, VastResolveError> {
+        let total_ms = self.config.total_timeout.as_millis() as u64;
+        let elapsed = self.clock.now_ms().saturating_sub(state.started_ms);
+        if elapsed >= total_ms {
+            return Err(VastResolveError::new(ResolveErrorKind::Timeout));
+        }
+
+        let timeout = self
+            .config
+            .fetch_timeout
+            .min(Duration::from_millis(total_ms - elapsed));
+
+        state.fetched.push(url.to_string());
+        let xml = self.fetcher.fetch(url, timeout).map_err(|e| {
+            VastResolveError::new(ResolveErrorKind::Fetch {
+                url: url.to_string(),
+                reason: e.to_string(),
+            })
+        })?;
+
+        if self.clock.now_ms().saturating_sub(state.started_ms) > total_ms {
+            return Err(VastResolveError::new(ResolveErrorKind::Timeout));
+        }
+
+        Vast::parse(&xml).map_err(|e| {
+            VastResolveError::new(ResolveErrorKind::Parse {
+                url: url.to_string(),
+                reason: e.to_string(),
+            })
+        })
+    }
+}
+
+fn prepend_error_urls(e: &mut VastResolveError, wrapper: &Wrapper) {
+    let mut error_urls = wrapper.errors.clone();
+    error_urls.append(&mut e.error_urls);
+    e.error_urls = error_urls;
+}
+
+/// Merges the tracking of a wrapper into the InLine ad it resolved to.
+///
+/// Wrapper creatives carry no media, so their linear tracking events and
+/// click trackers are added to every linear creative of the InLine ad, and
+/// likewise for NonLinear and Companion creatives.
+pub fn merge_wrapper(inline: &mut InLine, wrapper: &Wrapper) {
+    inline
+        .impressions
+        .extend(wrapper.impressions.iter().cloned());
+    inline.errors.extend(wrapper.errors.iter().cloned());
+    inline
+        .ad_verifications
+        .extend(wrapper.ad_verifications.iter().cloned());
+
+    if let Some(viewable) = &wrapper.viewable_impression {
+        let target = inline
+            .viewable_impression
+            .get_or_insert_with(Default::default);
+        target.viewable.extend(viewable.viewable.iter().cloned());
+        target
+            .not_viewable
+            .extend(viewable.not_viewable.iter().cloned());
+        target
+            .view_undetermined
+            .extend(viewable.view_undetermined.iter().cloned());
+    }
+
+    for source in &wrapper.creatives {
+        for creative in inline.creatives.iter_mut() {
+            if let (Some(from), Some(to)) = (&source.linear, &mut creative.linear) {
+                to.tracking_events
+                    .extend(from.tracking_events.iter().cloned());
+                if let Some(from_clicks) = &from.video_clicks {
+                    let to_clicks = to.video_clicks.get_or_insert_with(Default::default);
+                    to_clicks
+                        .click_tracking
+                        .extend(from_clicks.click_tracking.iter().cloned());
+                    to_clicks
+                        .custom_click
+                        .extend(from_clicks.custom_click.iter().cloned());
+                }
+            }
+
+            if let (Some(from), Some(to)) = (&source.non_linear_ads, &mut creative.non_linear_ads) {
+                to.tracking_events
+                    .extend(from.tracking_events.iter().cloned());
+                for from_nl in &from.non_linears {
+                    for to_nl in to.non_linears.iter_mut() {
+                        to_nl
+                            .click_tracking
+                            .extend(from_nl.click_tracking.iter().cloned());
+                    }
+                }
+            }
+
+            if let (Some(from), Some(to)) = (&source.companion_ads, &mut creative.companion_ads) {
+                for from_c in &from.companions {
+                    for to_c in to.companions.iter_mut() {
+                        to_c.click_tracking
+                            .extend(from_c.click_tracking.iter().cloned());
+                        to_c.tracking_events
+                            .extend(from_c.tracking_events.iter().cloned());
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    fn wrapper(next: &str, name: &str) -> String {
+        format!(
+            r#"<VAST version="4.0"><Ad id="{name}"><Wrapper>
+                <AdSystem>{name}</AdSystem>
+                <VASTAdTagURI><![CDATA[{next}]]></VASTAdTagURI>
+                <Error><![CDATA[https://{name}.example.com/error]]></Error>
+                <Impression><![CDATA[https://{name}.example.com/imp]]></Impression>
+                <Creatives><Creative><Linear>
+                    <TrackingEvents>
+                        <Tracking event="start"><![CDATA[https://{name}.example.com/start]]></Tracking>
+                    </TrackingEvents>
+                    <VideoClicks>
+                        <ClickTracking><![CDATA[https://{name}.example.com/click]]></ClickTracking>
+                    </VideoClicks>
+                </Linear></Creative></Creatives>
+            </Wrapper></Ad></VAST>"#
+        )
+    }
+
+    const INLINE: &str = r#"<VAST version="4.0"><Ad id="final"><InLine>
+        <AdSystem>final</AdSystem>
+        <Impression><![CDATA[https://final.example.com/imp]]></Impression>
+        <Creatives><Creative><Linear>
+            <Duration>00:00:15</Duration>
+            <MediaFiles><MediaFile type="video/mp4"><![CDATA[https://cdn.example.com/a.mp4]]></MediaFile></MediaFiles>
+        </Linear></Creative></Creatives>
+    </InLine></Ad></VAST>"#;
+
+    fn tags(entries: &[(&str, String)]) -> HashMap<String, String> {
+        entries
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn test_resolves_and_merges_chain() {
+        let fetcher = tags(&[
+            ("https://b/vast", wrapper("https://c/vast", "b")),
+            ("https://c/vast", INLINE.to_string()),
+        ]);
+
+        let resolver = VastResolver::new(fetcher, VastResolverConfig::default());
+        let resolved = resolver.resolve(&wrapper("https://b/vast", "a")).unwrap();
+
+        assert_eq!(resolved.fetched, vec!["https://b/vast", "https://c/vast"]);
+        assert!(resolved.failures.is_empty());
+
+        let inline = resolved.vast.ads[0].inline().unwrap();
+        let impressions: Vec<&str> = inline.impressions.iter().map(|i| i.url.as_str()).collect();
+        assert_eq!(
+            impressions,
+            vec![
+                "https://final.example.com/imp",
+                "https://b.example.com/imp",
+                "https://a.example.com/imp"
+            ]
+        );
+        assert_eq!(inline.errors.len(), 2);
+
+        let linear = inline.creatives[0].linear.as_ref().unwrap();
+        assert_eq!(linear.tracking_events.len(), 2);
+        assert_eq!(
+            linear.video_clicks.as_ref().unwrap().click_tracking.len(),
+            2
+        );
+        assert_eq!(linear.media_files.len(), 1);
+    }
+
+    #[test]
+    fn test_depth_limit() {
+        let fetcher = tags(&[
+            ("https://b/vast", wrapper("https://c/vast", "b")),
+            ("https://c/vast", INLINE.to_string()),
+        ]);
+
+        let config = VastResolverConfigBuilder::default()
+            .max_depth(1)
+            .build()
+            .unwrap();
+        let err = VastResolver::new(fetcher, config)
+            .resolve(&wrapper("https://b/vast", "a"))
+            .unwrap_err();
+
+        assert_eq!(err.kind, ResolveErrorKind::DepthExceeded { max_depth: 1 });
+        assert_eq!(err.code(), 302);
+        assert_eq!(
+            err.error_urls,
+            vec!["https://a.example.com/error", "https://b.example.com/error"]
+        );
+    }
+
+    #[test]
+    fn test_cycle_detection() {
+        let fetcher = tags(&[
+            ("https://b/vast", wrapper("https://c/vast", "b")),
+            ("https://c/vast", wrapper("https://b/vast", "c")),
+        ]);
+
+        let err = VastResolver::new(fetcher, VastResolverConfig::default())
+            .resolve(&wrapper("https://b/vast", "a"))
+            .unwrap_err();
+
+        assert_eq!(
+            err.kind,
+            ResolveErrorKind::Cycle {
+                url: "https://b/vast".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_fetch_failure_and_no_ads() {
+        let fetcher = tags(&[("https://empty/vast", r#"<VAST version="4.0"/>"#.to_string())]);
+        let resolver = VastResolver::new(fetcher, VastResolverConfig::default());
+
+        let err = resolver
+            .resolve(&wrapper("https://missing/vast", "a"))
+            .unwrap_err();
+        assert!(matches!(err.kind, ResolveErrorKind::Fetch { .. }));
+        assert_eq!(err.code(), 301);
+
+        let err = resolver
+            .resolve(&wrapper("https://empty/vast", "a"))
+            .unwrap_err();
+        assert_eq!(err.code(), 303);
+    }
+
+    #[test]
+    fn test_follow_additional_wrappers_false() {
+        let fetcher = tags(&[
+            ("https://b/vast", wrapper("https://c/vast", "b")),
+            ("https://c/vast", INLINE.to_string()),
+        ]);
+
+        let root = wrapper("https://b/vast", "a")
+            .replace("<Wrapper>", r#"<Wrapper followAdditionalWrappers="false">"#);
+        let err = VastResolver::new(fetcher, VastResolverConfig::default())
+            .resolve(&root)
+            .unwrap_err();
+
+        assert!(matches!(
+            err.kind,
+            ResolveErrorKind::WrapperNotAllowed { .. }
+        ));
+    }
+
+    #[test]
+    fn test_multiple_ads_keep_resolved_siblings() {
+        let pod = r#"<VAST version="4.0">
+            <Ad id="one" sequence="1"><Wrapper>
+                <VASTAdTagURI>https://missing/vast</VASTAdTagURI>
+                <Error>https://one.example.com/error</Error>
+            </Wrapper></Ad>
+            <Ad id="two" sequence="2"><InLine><AdSystem>two</AdSystem></InLine></Ad>
+        </VAST>"#;
+        let fetcher = tags(&[("https://b/vast", pod.to_string())]);
+
+        let root = wrapper("https://b/vast", "a")
+            .replace("<Wrapper>", r#"<Wrapper allowMultipleAds="true">"#);
+        let resolved = VastResolver::new(fetcher, VastResolverConfig::default())
+            .resolve(&root)
+            .unwrap();
+
+        assert_eq!(resolved.vast.ads.len(), 1);
+        assert_eq!(resolved.vast.ads[0].id.as_deref(), Some("two"));
+        assert_eq!(resolved.failures.len(), 1);
+        assert!(matches!(
+            resolved.failures[0].kind,
+            ResolveErrorKind::Fetch { .. }
+        ));
+        assert_eq!(
+            resolved.failures[0].error_urls,
+            vec![
+                "https://a.example.com/error",
+                "https://one.example.com/error"
+            ]
+        );
+    }
+
+    struct StepClock(AtomicU64);
+
+    impl Clock for StepClock {
+        fn now_ms(&self) -> u64 {
+            self.0.fetch_add(400, Ordering::SeqCst)
+        }
+    }
+
+    #[test]
+    fn test_deadline_caps_fetch_timeout() {
+        let fetcher = |url: &str, timeout: Duration| -> Result<String> {
+            assert!(timeout <= Duration::from_millis(1000));
+            match url {
+                "https://b/vast" => Ok(wrapper("https://c/vast", "b")),
+                _ => Ok(INLINE.to_string()),
+            }
+        };
+
+        let config = VastResolverConfigBuilder::default()
+            .total_timeout(Duration::from_millis(1000))
+            .build()
+            .unwrap();
+        let err = VastResolver::new(fetcher, config)
+            .with_clock(StepClock(AtomicU64::new(0)))
+            .resolve(&wrapper("https://b/vast", "a"))
+            .unwrap_err();
+
+        assert_eq!(err.kind, ResolveErrorKind::Timeout);
+    }
+
+    #[test]
+    fn test_inline_document_is_unchanged() {
+        let resolver = VastResolver::new(HashMap::new(), VastResolverConfig::default());
+        let resolved = resolver.resolve(INLINE).unwrap();
+
+        assert!(resolved.fetched.is_empty());
+        assert_eq!(resolved.vast, Vast::parse(INLINE).unwrap());
+    }
+}
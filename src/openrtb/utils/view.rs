@@ -0,0 +1,468 @@
+//! Borrowed view of a JSON bid request for hot-path filtering.
+//!
+//! [`BidRequestView`] reads the fields bidders commonly filter on in a
+//! single pass over the raw bytes, skipping everything else without
+//! allocating. Strings borrow from the input unless they contain escapes.
+//! Only requests that pass the filters need to be promoted to a full
+//! [`BidRequest`] with [`BidRequestView::to_bid_request`].
+//!
+//! # Example
+//! ```
+//! use rtb::openrtb::utils::view::BidRequestView;
+//!
+//! let body = br#"{"id":"r1","imp":[{"id":"1","bidfloor":0.5}],"app":{"bundle":"com.example"}}"#;
+//! let view = BidRequestView::parse(body).unwrap();
+//! if view.app_bundle() == Some("com.example") && view.max_bidfloor() < 1.0 {
+//!     let request = view.to_bid_request().unwrap();
+//!     assert_eq!(request.imp.len(), 1);
+//! }
+//! ```
+
+use crate::BidRequest;
+use serde::de::{Error as _, IgnoredAny};
+use serde::{Deserialize, Deserializer};
+use std::borrow::Cow;
+use std::ops::Deref;
+
+/// Commonly filtered fields of a JSON bid request, borrowed from its bytes
+#[derive(Debug, Default, Deserialize)]
+pub struct BidRequestView<'a> {
+    #[serde(skip)]
+    raw: &'a [u8],
+    #[serde(borrow, default)]
+    id: Cow<'a, str>,
+    #[serde(borrow, default)]
+    imp: Vec<ImpView<'a>>,
+    #[serde(borrow)]
+    site: Option<SiteView<'a>>,
+    #[serde(borrow)]
+    app: Option<AppView<'a>>,
+    #[serde(borrow)]
+    device: Option<DeviceView<'a>>,
+    #[serde(borrow)]
+    user: Option<UserView<'a>>,
+    regs: Option<RegsView>,
+    #[serde(default, deserialize_with = "flag")]
+    test: bool,
+    #[serde(default, deserialize_with = "uint")]
+    tmax: Option<u32>,
+    #[serde(borrow, default)]
+    cur: Vec<Str<'a>>,
+    #[serde(borrow, default)]
+    bcat: Vec<Str<'a>>,
+    #[serde(borrow, default)]
+    badv: Vec<Str<'a>>,
+}
+
+/// Impression fields of a [`BidRequestView`]
+#[derive(Debug, Default, Deserialize)]
+pub struct ImpView<'a> {
+    #[serde(borrow, default)]
+    id: Cow<'a, str>,
+    #[serde(borrow)]
+    tagid: Option<Str<'a>>,
+    #[serde(default, deserialize_with = "float")]
+    bidfloor: f64,
+    #[serde(borrow)]
+    bidfloorcur: Option<Str<'a>>,
+    #[serde(default, deserialize_with = "flag")]
+    instl: bool,
+    #[serde(default, deserialize_with = "flag")]
+    secure: bool,
+    banner: Option<IgnoredAny>,
+    video: Option<IgnoredAny>,
+    audio: Option<IgnoredAny>,
+    native: Option<IgnoredAny>,
+}
+
+/// A string borrowed from the input unless it contains escapes. Serde only
+/// borrows a `Cow` directly in a field, not inside an `Option` or `Vec`.
+#[derive(Debug, Default, Deserialize)]
+struct Str<'a>(#[serde(borrow)] Cow<'a, str>);
+
+impl Deref for Str<'_> {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct PublisherView<'a> {
+    #[serde(borrow)]
+    id: Option<Str<'a>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct SiteView<'a> {
+    #[serde(borrow)]
+    id: Option<Str<'a>>,
+    #[serde(borrow)]
+    domain: Option<Str<'a>>,
+    #[serde(borrow)]
+    page: Option<Str<'a>>,
+    #[serde(borrow)]
+    publisher: Option<PublisherView<'a>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct AppView<'a> {
+    #[serde(borrow)]
+    id: Option<Str<'a>>,
+    #[serde(borrow)]
+    bundle: Option<Str<'a>>,
+    #[serde(borrow)]
+    publisher: Option<PublisherView<'a>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct GeoView<'a> {
+    #[serde(borrow)]
+    country: Option<Str<'a>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct DeviceView<'a> {
+    #[serde(borrow)]
+    ua: Option<Str<'a>>,
+    #[serde(borrow)]
+    ip: Option<Str<'a>>,
+    #[serde(borrow)]
+    ipv6: Option<Str<'a>>,
+    #[serde(borrow)]
+    ifa: Option<Str<'a>>,
+    #[serde(borrow)]
+    os: Option<Str<'a>>,
+    #[serde(default, deserialize_with = "uint")]
+    devicetype: Option<u32>,
+    #[serde(borrow)]
+    geo: Option<GeoView<'a>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct UserView<'a> {
+    #[serde(borrow)]
+    id: Option<Str<'a>>,
+    #[serde(borrow)]
+    buyeruid: Option<Str<'a>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct RegsView {
+    #[serde(default, deserialize_with = "flag")]
+    coppa: bool,
+    #[serde(default, deserialize_with = "flag")]
+    gdpr: bool,
+}
+
+/// Accepts `0`/`1` as well as `true`/`false`
+fn flag<'de, D: Deserializer<'de>>(deserializer: D) -> Result<bool, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Flag {
+        Bool(bool),
+        Int(i64),
+    }
+
+    Ok(match Option::<Flag>::deserialize(deserializer)? {
+        Some(Flag::Bool(b)) => b,
+        Some(Flag::Int(i)) => i != 0,
+        None => false,
+    })
+}
+
+/// Numbers as written by pbjson, which quotes some of them
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Number<'a> {
+    Int(i64),
+    Float(f64),
+    Str(#[serde(borrow)] Cow<'a, str>),
+}
+
+/// Accepts a number or a numeric string
+fn float<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    Ok(match Option::<Number>::deserialize(deserializer)? {
+        Some(Number::Int(i)) => i as f64,
+        Some(Number::Float(f)) => f,
+        Some(Number::Str(s)) => s.trim().parse().map_err(D::Error::custom)?,
+        None => 0.0,
+    })
+}
+
+/// Accepts an integer or an integer string. Negative and out of range values
+/// read as unset rather than failing the whole request.
+fn uint<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u32>, D::Error> {
+    let int = match Option::<Number>::deserialize(deserializer)? {
+        Some(Number::Int(i)) => i,
+        Some(Number::Float(f)) if f.fract() == 0.0 => {
+            return Ok((0.0..=u32::MAX as f64).contains(&f).then_some(f as u32));
+        }
+        Some(Number::Float(f)) => {
+            return Err(D::Error::custom(format_args!(
+                "expected an integer, got {f}"
+            )));
+        }
+        Some(Number::Str(s)) => s.trim().parse::<i64>().map_err(D::Error::custom)?,
+        None => return Ok(None),
+    };
+    Ok(u32::try_from(int).ok())
+}
+
+impl<'a> BidRequestView<'a> {
+    /// Reads the view from a JSON bid request. Fields outside the view are
+    /// skipped, but the whole document must be valid JSON.
+    pub fn parse(raw: &'a [u8]) -> serde_json::Result<Self> {
+        let mut view: Self = serde_json::from_slice(raw)?;
+        view.raw = raw;
+        Ok(view)
+    }
+
+    /// Deserializes the full request from the bytes the view was read from
+    pub fn to_bid_request(&self) -> serde_json::Result<BidRequest> {
+        serde_json::from_slice(self.raw)
+    }
+
+    /// The bytes the view was read from
+    pub fn raw(&self) -> &'a [u8] {
+        self.raw
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn imps(&self) -> &[ImpView<'a>] {
+        &self.imp
+    }
+
+    /// Highest floor over all impressions, 0 without impressions
+    pub fn max_bidfloor(&self) -> f64 {
+        self.imp.iter().map(|imp| imp.bidfloor).fold(0.0, f64::max)
+    }
+
+    pub fn is_app(&self) -> bool {
+        self.app.is_some()
+    }
+
+    pub fn is_site(&self) -> bool {
+        self.site.is_some()
+    }
+
+    pub fn app_id(&self) -> Option<&str> {
+        self.app.as_ref()?.id.as_deref()
+    }
+
+    pub fn app_bundle(&self) -> Option<&str> {
+        self.app.as_ref()?.bundle.as_deref()
+    }
+
+    pub fn site_id(&self) -> Option<&str> {
+        self.site.as_ref()?.id.as_deref()
+    }
+
+    pub fn site_domain(&self) -> Option<&str> {
+        self.site.as_ref()?.domain.as_deref()
+    }
+
+    pub fn site_page(&self) -> Option<&str> {
+        self.site.as_ref()?.page.as_deref()
+    }
+
+    /// `app.publisher.id`, or `site.publisher.id`
+    pub fn publisher_id(&self) -> Option<&str> {
+        let publisher = match (&self.app, &self.site) {
+            (Some(app), _) => app.publisher.as_ref(),
+            (None, Some(site)) => site.publisher.as_ref(),
+            (None, None) => None,
+        };
+        publisher?.id.as_deref()
+    }
+
+    pub fn device_ua(&self) -> Option<&str> {
+        self.device.as_ref()?.ua.as_deref()
+    }
+
+    /// `device.ip`, or `device.ipv6`
+    pub fn device_ip(&self) -> Option<&str> {
+        let device = self.device.as_ref()?;
+        device.ip.as_deref().or(device.ipv6.as_deref())
+    }
+
+    pub fn device_ifa(&self) -> Option<&str> {
+        self.device.as_ref()?.ifa.as_deref()
+    }
+
+    pub fn device_os(&self) -> Option<&str> {
+        self.device.as_ref()?.os.as_deref()
+    }
+
+    pub fn device_type(&self) -> Option<u32> {
+        self.device.as_ref()?.devicetype
+    }
+
+    pub fn device_country(&self) -> Option<&str> {
+        self.device.as_ref()?.geo.as_ref()?.country.as_deref()
+    }
+
+    pub fn user_id(&self) -> Option<&str> {
+        self.user.as_ref()?.id.as_deref()
+    }
+
+    pub fn buyeruid(&self) -> Option<&str> {
+        self.user.as_ref()?.buyeruid.as_deref()
+    }
+
+    pub fn coppa(&self) -> bool {
+        self.regs.as_ref().is_some_and(|regs| regs.coppa)
+    }
+
+    pub fn gdpr(&self) -> bool {
+        self.regs.as_ref().is_some_and(|regs| regs.gdpr)
+    }
+
+    pub fn is_test(&self) -> bool {
+        self.test
+    }
+
+    pub fn tmax(&self) -> Option<u32> {
+        self.tmax
+    }
+
+    pub fn cur(&self) -> impl Iterator<Item = &str> {
+        self.cur.iter().map(|c| &*c.0)
+    }
+
+    pub fn bcat(&self) -> impl Iterator<Item = &str> {
+        self.bcat.iter().map(|c| &*c.0)
+    }
+
+    pub fn badv(&self) -> impl Iterator<Item = &str> {
+        self.badv.iter().map(|c| &*c.0)
+    }
+}
+
+impl ImpView<'_> {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn tagid(&self) -> Option<&str> {
+        self.tagid.as_deref()
+    }
+
+    pub fn bidfloor(&self) -> f64 {
+        self.bidfloor
+    }
+
+    pub fn bidfloorcur(&self) -> Option<&str> {
+        self.bidfloorcur.as_deref()
+    }
+
+    pub fn is_instl(&self) -> bool {
+        self.instl
+    }
+
+    pub fn is_secure(&self) -> bool {
+        self.secure
+    }
+
+    pub fn has_banner(&self) -> bool {
+        self.banner.is_some()
+    }
+
+    pub fn has_video(&self) -> bool {
+        self.video.is_some()
+    }
+
+    pub fn has_audio(&self) -> bool {
+        self.audio.is_some()
+    }
+
+    pub fn has_native(&self) -> bool {
+        self.native.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = include_str!("../../../test_data/sample_bid_request.json");
+
+    #[test]
+    fn test_view_matches_full_request() {
+        let view = BidRequestView::parse(SAMPLE.as_bytes()).unwrap();
+        let request = view.to_bid_request().unwrap();
+
+        assert_eq!(view.id(), request.id);
+        assert_eq!(view.imps().len(), request.imp.len());
+        assert_eq!(view.imps()[0].id(), "1");
+        assert_eq!(view.imps()[0].bidfloor(), 0.5);
+        assert!(view.imps()[0].has_banner());
+        assert!(view.imps()[0].is_secure());
+        assert!(!view.imps()[0].has_video());
+        assert_eq!(view.site_domain(), Some("example.com"));
+        assert_eq!(view.app_bundle(), None);
+        assert_eq!(view.device_ip(), Some("192.168.1.1"));
+        assert_eq!(view.device_type(), Some(2));
+        assert_eq!(view.user_id(), Some("user123"));
+        assert_eq!(view.tmax(), Some(200));
+        assert!(!view.is_test());
+    }
+
+    #[test]
+    fn test_view_borrows_unescaped_strings() {
+        let body = br#"{
+            "id": "r\"1",
+            "imp": [{"id": "1", "bidfloor": 0.2}, {"id": "2", "bidfloor": 1.5}],
+            "app": {"bundle": "com.example", "publisher": {"id": "p1"}},
+            "device": {"ipv6": "::1", "geo": {"country": "DEU", "ext": {"x": [1, 2]}}},
+            "regs": {"coppa": true, "gdpr": 1},
+            "test": 1,
+            "bcat": ["IAB25"]
+        }"#;
+        let view = BidRequestView::parse(body).unwrap();
+
+        assert_eq!(view.id(), "r\"1");
+        assert!(matches!(view.id, Cow::Owned(_)));
+        assert!(matches!(
+            view.app.as_ref().unwrap().bundle,
+            Some(Str(Cow::Borrowed("com.example")))
+        ));
+        assert_eq!(view.publisher_id(), Some("p1"));
+        assert_eq!(view.device_country(), Some("DEU"));
+        assert_eq!(view.device_ip(), Some("::1"));
+        assert_eq!(view.max_bidfloor(), 1.5);
+        assert!(view.coppa() && view.gdpr() && view.is_test());
+        assert_eq!(view.bcat().collect::<Vec<_>>(), ["IAB25"]);
+    }
+
+    #[test]
+    fn test_view_reads_quoted_numbers() {
+        let body = br#"{
+            "id": "r1",
+            "imp": [{"id": "1", "bidfloor": "0.25"}, {"id": "2", "bidfloor": 2}],
+            "device": {"devicetype": "4"},
+            "tmax": -1
+        }"#;
+        let view = BidRequestView::parse(body).unwrap();
+
+        assert_eq!(view.imps()[0].bidfloor(), 0.25);
+        assert_eq!(view.max_bidfloor(), 2.0);
+        assert_eq!(view.device_type(), Some(4));
+        assert_eq!(view.tmax(), None);
+
+        let view = BidRequestView::parse(br#"{"id":"r1","tmax":"120"}"#).unwrap();
+        assert_eq!(view.tmax(), Some(120));
+        assert!(BidRequestView::parse(br#"{"id":"r1","tmax":"soon"}"#).is_err());
+        assert!(BidRequestView::parse(br#"{"id":"r1","imp":[{"bidfloor":"x"}]}"#).is_err());
+    }
+
+    #[test]
+    fn test_view_rejects_invalid_json() {
+        assert!(BidRequestView::parse(br#"{"id":"r1","imp":[}"#).is_err());
+        assert!(BidRequestView::parse(br#"{"id":"r1"} trailing"#).is_err());
+    }
+}
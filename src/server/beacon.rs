@@ -0,0 +1,328 @@
+use crate::common::{DataUrl, UnsubstitutedMacro};
+#[cfg(feature = "crypto")]
+use crate::common::{SignatureError, UrlSigning};
+use actix_web::body::BoxBody;
+use actix_web::dev::Payload;
+use actix_web::http::header;
+use actix_web::{FromRequest, HttpRequest, HttpResponse, Responder, ResponseError};
+use serde::de::DeserializeOwned;
+use std::fmt;
+use std::future::{Ready, ready};
+use std::ops::Deref;
+
+/// A transparent 1x1 GIF
+const PIXEL: &[u8] = &[
+    0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x01, 0x00, 0x01, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xff, 0xff, 0xff, 0x21, 0xf9, 0x04, 0x01, 0x00, 0x00, 0x00, 0x00, 0x2c, 0x00, 0x00, 0x00, 0x00,
+    0x01, 0x00, 0x01, 0x00, 0x00, 0x02, 0x02, 0x44, 0x01, 0x00, 0x3b,
+];
+
+/// Extractor decoding the query string of a tracking request into `T`,
+/// the receiving end of [`DataUrl::encode`].
+///
+/// With the `crypto` feature, when a `UrlSigning` is registered as app data,
+/// the URL is parsed with `DataUrl::from_verified`: it must carry a valid
+/// signature made by `DataUrl::finalize_signed`, and is accepted once.
+/// Otherwise it is parsed with [`DataUrl::from`]. Parameters still holding a
+/// macro are rejected, see [`UnsubstitutedMacro`].
+///
+/// # Example
+///
+/// ```ignore
+/// use actix_web::{App, web};
+/// use rtb::common::{Keyring, MemoryNonceSet, UrlSigningBuilder};
+/// use rtb::server::beacon::{Beacon, BeaconReply};
+/// use serde::Deserialize;
+/// use std::sync::Arc;
+///
+/// #[derive(Deserialize)]
+/// struct Win {
+///     #[serde(rename = "a")]
+///     auction_id: String,
+///     #[serde(rename = "p")]
+///     price: f64,
+/// }
+///
+/// async fn win(beacon: Beacon<Win>) -> BeaconReply {
+///     println!("won {} at {}", beacon.auction_id, beacon.price);
+///     BeaconReply::Pixel
+/// }
+///
+/// let signing = UrlSigningBuilder::default()
+///     .keyring(Keyring::new("k1", b"secret".to_vec()))
+///     .nonces(Arc::new(MemoryNonceSet::new(100_000)))
+///     .build()
+///     .unwrap();
+///
+/// App::new()
+///     .app_data(signing)
+///     .route("/win", web::get().to(win))
+/// ```
+pub struct Beacon<T>(pub T);
+
+impl<T> Beacon<T> {
+    /// Unwrap into the decoded beacon.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for Beacon<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// Errors that can occur during beacon extraction.
+#[derive(Debug)]
+pub enum BeaconError {
+    /// The URL is not signed with the registered `UrlSigning`, expired, or
+    /// was replayed.
+    #[cfg(feature = "crypto")]
+    Signature(SignatureError),
+    /// A parameter still holds its macro, the URL was fired unsubstituted.
+    UnsubstitutedMacro(UnsubstitutedMacro),
+    /// The URL is malformed or its parameters do not decode into the schema.
+    Decode(anyhow::Error),
+}
+
+impl From<anyhow::Error> for BeaconError {
+    fn from(e: anyhow::Error) -> Self {
+        #[cfg(feature = "crypto")]
+        let e = match e.downcast::<SignatureError>() {
+            Ok(e) => return BeaconError::Signature(e),
+            Err(e) => e,
+        };
+        match e.downcast::<UnsubstitutedMacro>() {
+            Ok(e) => BeaconError::UnsubstitutedMacro(e),
+            Err(e) => BeaconError::Decode(e),
+        }
+    }
+}
+
+impl fmt::Display for BeaconError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            #[cfg(feature = "crypto")]
+            BeaconError::Signature(e) => write!(f, "Beacon signature error: {}", e),
+            BeaconError::UnsubstitutedMacro(e) => write!(f, "Beacon error: {}", e),
+            BeaconError::Decode(e) => write!(f, "Beacon decode error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for BeaconError {}
+
+impl ResponseError for BeaconError {
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            #[cfg(feature = "crypto")]
+            BeaconError::Signature(_) => HttpResponse::Forbidden().finish(),
+            BeaconError::UnsubstitutedMacro(_) => HttpResponse::BadRequest().finish(),
+            BeaconError::Decode(_) => HttpResponse::BadRequest().finish(),
+        }
+    }
+}
+
+impl<T> FromRequest for Beacon<T>
+where
+    T: DeserializeOwned + 'static,
+{
+    type Error = BeaconError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(extract(req).map(Beacon))
+    }
+}
+
+fn extract<T: DeserializeOwned>(req: &HttpRequest) -> Result<T, BeaconError> {
+    // only the path and query are signed, the scheme and host are placeholders
+    let url = format!("https://localhost{}?{}", req.path(), req.query_string());
+
+    #[cfg(feature = "crypto")]
+    let url = match req.app_data::<UrlSigning>() {
+        Some(signing) => DataUrl::from_verified(&url, signing)?,
+        None => DataUrl::from(&url)?,
+    };
+    #[cfg(not(feature = "crypto"))]
+    let url = DataUrl::from(&url)?;
+
+    Ok(url.decode()?)
+}
+
+/// Answer to a tracking request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BeaconReply {
+    /// A transparent 1x1 GIF, for beacons fired as an `<img>`
+    Pixel,
+    /// An empty 204, for beacons fired by servers or `sendBeacon`
+    NoContent,
+}
+
+impl Responder for BeaconReply {
+    type Body = BoxBody;
+
+    fn respond_to(self, _req: &HttpRequest) -> HttpResponse<Self::Body> {
+        match self {
+            BeaconReply::Pixel => HttpResponse::Ok()
+                .content_type("image/gif")
+                .insert_header((header::CACHE_CONTROL, "no-store, no-cache, must-revalidate"))
+                .body(PIXEL),
+            BeaconReply::NoContent => HttpResponse::NoContent()
+                .insert_header((header::CACHE_CONTROL, "no-store"))
+                .finish(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::body::to_bytes;
+    use actix_web::test::TestRequest;
+    use serde::Deserialize;
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Win {
+        #[serde(rename = "a")]
+        auction_id: String,
+        #[serde(rename = "p")]
+        price: f64,
+    }
+
+    #[cfg(feature = "crypto")]
+    fn signing() -> UrlSigning {
+        crate::common::UrlSigningBuilder::default()
+            .keyring(crate::common::Keyring::new("k1", b"secret".to_vec()))
+            .nonces(std::sync::Arc::new(crate::common::MemoryNonceSet::new(16)))
+            .build()
+            .unwrap()
+    }
+
+    #[cfg(feature = "crypto")]
+    fn signed_win(signing: &UrlSigning) -> String {
+        let mut url = DataUrl::new("t.example.com", "win").unwrap();
+        url.add_string("a", "abc")
+            .unwrap()
+            .add_float("p", 1.5)
+            .unwrap();
+        url.finalize_signed(signing).unwrap();
+        url.url(true).unwrap()
+    }
+
+    #[actix_web::test]
+    async fn test_extract_unsigned() {
+        let (req, mut payload) = TestRequest::get()
+            .uri("/win?a=abc&p=1.5&x=1")
+            .to_http_parts();
+
+        let beacon = Beacon::<Win>::from_request(&req, &mut payload)
+            .await
+            .unwrap();
+        assert_eq!(beacon.auction_id, "abc");
+        assert_eq!(beacon.into_inner().price, 1.5);
+
+        let (req, mut payload) = TestRequest::get().uri("/win?a=abc").to_http_parts();
+        let err = Beacon::<Win>::from_request(&req, &mut payload)
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(err, BeaconError::Decode(_)));
+        assert_eq!(err.error_response().status(), 400);
+    }
+
+    #[actix_web::test]
+    async fn test_extract_unsubstituted_macro() {
+        let (req, mut payload) = TestRequest::get()
+            .uri("/win?a=abc&p=${AUCTION_PRICE}")
+            .to_http_parts();
+
+        let err = Beacon::<Win>::from_request(&req, &mut payload)
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(err, BeaconError::UnsubstitutedMacro(ref e) if e.key == "p"));
+    }
+
+    #[cfg(feature = "crypto")]
+    #[actix_web::test]
+    async fn test_extract_signed() {
+        let signing = signing();
+        let signed = signed_win(&signing);
+        let path = signed.strip_prefix("https://t.example.com").unwrap();
+
+        let (req, mut payload) = TestRequest::get()
+            .uri(path)
+            .app_data(signing.clone())
+            .to_http_parts();
+        let beacon = Beacon::<Win>::from_request(&req, &mut payload)
+            .await
+            .unwrap();
+        assert_eq!(beacon.price, 1.5);
+
+        // each signed beacon is accepted once
+        let (req, mut payload) = TestRequest::get()
+            .uri(path)
+            .app_data(signing.clone())
+            .to_http_parts();
+        let err = Beacon::<Win>::from_request(&req, &mut payload)
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err,
+            BeaconError::Signature(SignatureError::Replayed)
+        ));
+        assert_eq!(err.error_response().status(), 403);
+
+        let tampered = path.replace("p=1.5", "p=0.01");
+        let (req, mut payload) = TestRequest::get()
+            .uri(&tampered)
+            .app_data(signing.clone())
+            .to_http_parts();
+        let err = Beacon::<Win>::from_request(&req, &mut payload)
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err,
+            BeaconError::Signature(SignatureError::Invalid)
+        ));
+        assert_eq!(err.error_response().status(), 403);
+
+        let (req, mut payload) = TestRequest::get()
+            .uri("/win?a=abc&p=1.5")
+            .app_data(signing)
+            .to_http_parts();
+        let err = Beacon::<Win>::from_request(&req, &mut payload)
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err,
+            BeaconError::Signature(SignatureError::Missing)
+        ));
+    }
+
+    #[actix_web::test]
+    async fn test_replies() {
+        let req = TestRequest::get().to_http_request();
+
+        let pixel = BeaconReply::Pixel.respond_to(&req);
+        assert_eq!(pixel.status(), 200);
+        assert_eq!(
+            pixel.headers().get(header::CONTENT_TYPE).unwrap(),
+            "image/gif"
+        );
+        assert!(pixel.headers().contains_key(header::CACHE_CONTROL));
+        let body = to_bytes(pixel.into_body()).await.unwrap();
+        assert_eq!(body.len(), 43);
+        assert!(body.starts_with(b"GIF89a"));
+
+        let empty = BeaconReply::NoContent.respond_to(&req);
+        assert_eq!(empty.status(), 204);
+    }
+}
@@ -0,0 +1,770 @@
+use crate::BidRequest;
+use crate::stream::{RecordFormat, RecordReader};
+use actix_web::dev::{Decompress, Payload, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header;
+use actix_web::web::{Bytes, BytesMut};
+use derive_builder::Builder;
+use futures_util::StreamExt;
+use futures_util::future::LocalBoxFuture;
+use prost::Message;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::future::{Ready, ready};
+use std::io::{self, BufRead, BufWriter, Write};
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Largest body captured, after decompression. Larger requests are served
+/// but not captured.
+const MAX_CAPTURE_SIZE: usize = 262_144;
+
+/// How often the capture file is flushed
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Layout of a capture file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureFormat {
+    /// One JSON bid request per line
+    Jsonl,
+    /// Protobuf bid requests, each prefixed with its length as a varint
+    Protobuf,
+}
+
+impl CaptureFormat {
+    /// Content type of the requests in a capture of this format
+    pub fn content_type(self) -> &'static str {
+        match self {
+            CaptureFormat::Jsonl => "application/json",
+            CaptureFormat::Protobuf => "application/x-protobuf",
+        }
+    }
+
+    /// Parses one captured body
+    pub fn decode(self, body: &[u8]) -> io::Result<BidRequest> {
+        match self {
+            CaptureFormat::Jsonl => serde_json::from_slice(body).map_err(io::Error::other),
+            CaptureFormat::Protobuf => BidRequest::decode(body).map_err(io::Error::other),
+        }
+    }
+
+    fn encode(self, request: &BidRequest, out: &mut Vec<u8>) -> io::Result<()> {
+        match self {
+            CaptureFormat::Jsonl => {
+                serde_json::to_writer(&mut *out, request)?;
+                out.push(b'\n');
+            }
+            CaptureFormat::Protobuf => request
+                .encode_length_delimited(out)
+                .map_err(io::Error::other)?,
+        }
+        Ok(())
+    }
+}
+
+/// Hook applied to captured requests before they are written
+pub type Redactor = Arc<dyn Fn(&mut BidRequest) + Send + Sync>;
+
+/// What is removed from captured requests
+#[derive(Clone, Default)]
+pub enum Redaction {
+    /// Requests are captured as received
+    #[default]
+    None,
+    /// Clears personal data of the user and device, see [`redact_pii`]
+    Pii,
+    Custom(Redactor),
+}
+
+impl fmt::Debug for Redaction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Redaction::None => write!(f, "None"),
+            Redaction::Pii => write!(f, "Pii"),
+            Redaction::Custom(_) => write!(f, "Custom"),
+        }
+    }
+}
+
+/// Clears the personal data of the user and device: their identifiers,
+/// including hashed ones, `user.eids`, `user.data`, `user.consent`,
+/// `user.yob`, `user.gender`, IP addresses and the latitude and longitude of
+/// `device.geo` and `user.geo`. Coarse location such as the country is kept.
+pub fn redact_pii(request: &mut BidRequest) {
+    if let Some(user) = request.user.as_mut() {
+        user.id.clear();
+        user.buyeruid.clear();
+        user.eids.clear();
+        user.data.clear();
+        user.consent.clear();
+        user.yob = 0;
+        user.gender.clear();
+        if let Some(geo) = user.geo.as_mut() {
+            geo.lat = 0.0;
+            geo.lon = 0.0;
+        }
+    }
+    if let Some(device) = request.device.as_mut() {
+        device.ip.clear();
+        device.ipv6.clear();
+        device.ifa.clear();
+        device.dpidsha1.clear();
+        device.dpidmd5.clear();
+        device.didsha1.clear();
+        device.didmd5.clear();
+        device.macsha1.clear();
+        device.macmd5.clear();
+        if let Some(geo) = device.geo.as_mut() {
+            geo.lat = 0.0;
+            geo.lon = 0.0;
+        }
+    }
+}
+
+/// Which requests a [`RequestCapture`] samples and where they go
+#[derive(Debug, Clone, Builder)]
+#[builder(build_fn(validate = "Self::validate"))]
+pub struct CaptureConfig {
+    /// Fraction of requests captured, from 0.0 to 1.0
+    #[builder(default = "0.01")]
+    pub sample_rate: f64,
+
+    #[builder(default = "CaptureFormat::Jsonl")]
+    pub format: CaptureFormat,
+
+    /// Paths whose requests are captured, all when empty
+    #[builder(default)]
+    pub paths: Vec<String>,
+
+    #[builder(default)]
+    pub redaction: Redaction,
+
+    /// Sampled requests waiting to be written, further requests are dropped.
+    /// Must be greater than 0.
+    #[builder(default = "1024")]
+    pub capacity: usize,
+}
+
+const ZERO_CAPACITY: &str = "RequestCapture capacity must be greater than 0";
+
+impl Default for CaptureConfig {
+    fn default() -> Self {
+        CaptureConfigBuilder::default().build().unwrap()
+    }
+}
+
+impl CaptureConfigBuilder {
+    fn validate(&self) -> Result<(), String> {
+        match self.capacity {
+            Some(0) => Err(ZERO_CAPACITY.to_string()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Counters of a [`RequestCapture`] since it started
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CaptureStats {
+    /// Requests written to the capture file
+    pub captured: u64,
+    /// Sampled requests dropped because the writer fell behind
+    pub dropped: u64,
+    /// Sampled requests too large, or not decodable as bid requests
+    pub skipped: u64,
+    /// Writes to the capture file which failed
+    pub failed: u64,
+}
+
+#[derive(Default)]
+struct Counters {
+    captured: AtomicU64,
+    dropped: AtomicU64,
+    skipped: AtomicU64,
+    failed: AtomicU64,
+}
+
+struct Sampled {
+    body: Bytes,
+    protobuf: bool,
+}
+
+struct Shared {
+    config: CaptureConfig,
+    sender: SyncSender<Sampled>,
+    seen: AtomicU64,
+    counters: Arc<Counters>,
+}
+
+/// Middleware writing a sample of the bid requests it serves to a capture
+/// file, to be replayed with [`crate::server::replay`].
+///
+/// Bodies are captured after decompression and handed to a background
+/// thread which redacts them and writes them out, so a slow disk drops
+/// samples rather than delaying requests. The handler still receives the
+/// body exactly as sent.
+///
+/// Requests are sampled deterministically, every `1 / sample_rate`th
+/// request is captured.
+///
+/// # Example
+///
+/// ```ignore
+/// use actix_web::web;
+/// use rtb::server::capture::{CaptureConfigBuilder, Redaction, RequestCapture};
+///
+/// let capture = RequestCapture::start(
+///     "/var/lib/bidder/capture.jsonl",
+///     CaptureConfigBuilder::default()
+///         .sample_rate(0.001)
+///         .redaction(Redaction::Pii)
+///         .build()
+///         .unwrap(),
+/// )?;
+///
+/// let configure = move |cfg: &mut web::ServiceConfig| {
+///     cfg.service(
+///         web::resource("/bid")
+///             .wrap(capture.clone())
+///             .route(web::post().to(bid_handler)),
+///     );
+/// };
+/// ```
+#[derive(Clone)]
+pub struct RequestCapture {
+    shared: Arc<Shared>,
+}
+
+impl RequestCapture {
+    /// Appends captures to the file at `path`, creating it if needed, from
+    /// a background thread which stops once every clone is dropped. A zero
+    /// capacity, e.g. from a config built by hand, is rejected as it would
+    /// drop every sample.
+    pub fn start(path: impl AsRef<Path>, config: CaptureConfig) -> io::Result<Self> {
+        if config.capacity == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, ZERO_CAPACITY));
+        }
+
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let (sender, receiver) = mpsc::sync_channel(config.capacity);
+        let counters = Arc::new(Counters::default());
+
+        {
+            let counters = counters.clone();
+            let format = config.format;
+            let redaction = config.redaction.clone();
+            thread::Builder::new()
+                .name("rtb-capture".to_string())
+                .spawn(move || run(receiver, file, format, redaction, &counters))?;
+        }
+
+        Ok(Self {
+            shared: Arc::new(Shared {
+                config,
+                sender,
+                seen: AtomicU64::new(0),
+                counters,
+            }),
+        })
+    }
+
+    pub fn stats(&self) -> CaptureStats {
+        let counters = &self.shared.counters;
+        CaptureStats {
+            captured: counters.captured.load(Ordering::Relaxed),
+            dropped: counters.dropped.load(Ordering::Relaxed),
+            skipped: counters.skipped.load(Ordering::Relaxed),
+            failed: counters.failed.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Shared {
+    fn should_sample(&self, req: &ServiceRequest) -> bool {
+        let paths = &self.config.paths;
+        if !paths.is_empty() && !paths.iter().any(|p| p == req.path()) {
+            return false;
+        }
+
+        // capture whenever the running count of sampled requests ticks over
+        let rate = self.config.sample_rate.clamp(0.0, 1.0);
+        let n = self.seen.fetch_add(1, Ordering::Relaxed) as f64;
+        ((n + 1.0) * rate).floor() > (n * rate).floor()
+    }
+
+    fn enqueue(&self, sampled: Sampled) {
+        if self.sender.try_send(sampled).is_err() {
+            self.counters.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RequestCapture
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Transform = RequestCaptureMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequestCaptureMiddleware {
+            service: Rc::new(service),
+            shared: self.shared.clone(),
+        }))
+    }
+}
+
+pub struct RequestCaptureMiddleware<S> {
+    service: Rc<S>,
+    shared: Arc<Shared>,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestCaptureMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        if !self.shared.should_sample(&req) {
+            return Box::pin(self.service.call(req));
+        }
+
+        let service = self.service.clone();
+        let shared = self.shared.clone();
+
+        Box::pin(async move {
+            let mut payload = req.take_payload();
+            let mut raw = BytesMut::new();
+            let mut complete = true;
+            while let Some(chunk) = payload.next().await {
+                raw.extend_from_slice(&chunk?);
+                // leave the rest of an oversized body to the handler's limits
+                if raw.len() > MAX_CAPTURE_SIZE {
+                    complete = false;
+                    break;
+                }
+            }
+            let raw = raw.freeze();
+
+            if complete {
+                match decompress(raw.clone(), &req).await {
+                    Some(body) => shared.enqueue(Sampled {
+                        body,
+                        protobuf: is_protobuf(&req),
+                    }),
+                    None => {
+                        shared.counters.skipped.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            } else {
+                shared.counters.skipped.fetch_add(1, Ordering::Relaxed);
+            }
+
+            let replayed = futures_util::stream::once(async move { Ok(raw) }).chain(payload);
+            req.set_payload(Payload::Stream {
+                payload: Box::pin(replayed),
+            });
+            service.call(req).await
+        })
+    }
+}
+
+fn is_protobuf(req: &ServiceRequest) -> bool {
+    req.headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains("protobuf"))
+}
+
+/// Decodes the body according to its `Content-Encoding`, `None` if it is
+/// malformed or too large
+async fn decompress(raw: Bytes, req: &ServiceRequest) -> Option<Bytes> {
+    if !req.headers().contains_key(header::CONTENT_ENCODING) {
+        return Some(raw);
+    }
+
+    let mut decoded = Decompress::from_headers(Payload::from(raw), req.headers());
+    let mut body = BytesMut::new();
+    while let Some(chunk) = decoded.next().await {
+        body.extend_from_slice(&chunk.ok()?);
+        if body.len() > MAX_CAPTURE_SIZE {
+            return None;
+        }
+    }
+    Some(body.freeze())
+}
+
+fn run(
+    receiver: Receiver<Sampled>,
+    file: File,
+    format: CaptureFormat,
+    redaction: Redaction,
+    counters: &Counters,
+) {
+    let mut writer = BufWriter::new(file);
+    let mut buf = Vec::new();
+    let mut last_flush = Instant::now();
+
+    loop {
+        match receiver.recv_timeout(FLUSH_INTERVAL) {
+            Ok(sampled) => {
+                buf.clear();
+                match encode(&sampled, format, &redaction, &mut buf) {
+                    Ok(()) => match writer.write_all(&buf) {
+                        Ok(()) => counters.captured.fetch_add(1, Ordering::Relaxed),
+                        Err(_) => counters.failed.fetch_add(1, Ordering::Relaxed),
+                    },
+                    Err(_) => counters.skipped.fetch_add(1, Ordering::Relaxed),
+                };
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+
+        if last_flush.elapsed() >= FLUSH_INTERVAL {
+            if writer.flush().is_err() {
+                counters.failed.fetch_add(1, Ordering::Relaxed);
+            }
+            last_flush = Instant::now();
+        }
+    }
+
+    if writer.flush().is_err() {
+        counters.failed.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Writes a sampled body as one record. Bodies are copied as is when they
+/// already fit the format, and re-encoded when redacted or converted.
+fn encode(
+    sampled: &Sampled,
+    format: CaptureFormat,
+    redaction: &Redaction,
+    out: &mut Vec<u8>,
+) -> io::Result<()> {
+    let received = if sampled.protobuf {
+        CaptureFormat::Protobuf
+    } else {
+        CaptureFormat::Jsonl
+    };
+
+    let verbatim = matches!(redaction, Redaction::None)
+        && received == format
+        && !(format == CaptureFormat::Jsonl && sampled.body.contains(&b'\n'));
+
+    if verbatim {
+        // still check the body is a bid request, so replays do not fail
+        received.decode(&sampled.body)?;
+        match format {
+            CaptureFormat::Jsonl => {
+                out.extend_from_slice(&sampled.body);
+                out.push(b'\n');
+            }
+            CaptureFormat::Protobuf => {
+                prost::encoding::encode_varint(sampled.body.len() as u64, out);
+                out.extend_from_slice(&sampled.body);
+            }
+        }
+        return Ok(());
+    }
+
+    let mut request = received.decode(&sampled.body)?;
+    match redaction {
+        Redaction::None => {}
+        Redaction::Pii => redact_pii(&mut request),
+        Redaction::Custom(redact) => redact(&mut request),
+    }
+    format.encode(&request, out)
+}
+
+/// Reads the bodies of a capture file one at a time
+pub struct CaptureReader<R> {
+    records: RecordReader<R>,
+}
+
+impl<R: BufRead> CaptureReader<R> {
+    pub fn new(reader: R, format: CaptureFormat) -> Self {
+        let format = match format {
+            CaptureFormat::Jsonl => RecordFormat::Jsonl,
+            CaptureFormat::Protobuf => RecordFormat::Protobuf,
+        };
+
+        Self {
+            records: RecordReader::new(reader, format).with_max_size(MAX_CAPTURE_SIZE),
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for CaptureReader<R> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.records.next().map(|record| {
+            record
+                .map(|record| record.bytes)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::{self, TestRequest};
+    use actix_web::{App, HttpResponse, web};
+
+    const REQUEST: &str = r#"{"id":"req-1","imp":[{"id":"1"}],"user":{"id":"u-1"},"device":{"ip":"10.0.0.1","ua":"agent"}}"#;
+
+    fn temp_file(name: &str) -> std::path::PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("rtb-capture-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    async fn echo_id(body: Bytes) -> HttpResponse {
+        let request: BidRequest = serde_json::from_slice(&body).unwrap();
+        HttpResponse::Ok().body(request.id)
+    }
+
+    /// Waits for the writer thread to catch up
+    fn wait_for(capture: &RequestCapture, captured: u64) {
+        for _ in 0..200 {
+            if capture.stats().captured >= captured {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        panic!(
+            "timed out waiting for {captured} captures: {:?}",
+            capture.stats()
+        );
+    }
+
+    fn read_capture(path: &Path, format: CaptureFormat) -> Vec<BidRequest> {
+        let reader = io::BufReader::new(File::open(path).unwrap());
+        CaptureReader::new(reader, format)
+            .map(|body| format.decode(&body.unwrap()).unwrap())
+            .collect()
+    }
+
+    #[actix_web::test]
+    async fn test_capture_gzip_with_redaction() {
+        let path = temp_file("gzip");
+        let capture = RequestCapture::start(
+            &path,
+            CaptureConfigBuilder::default()
+                .sample_rate(1.0)
+                .redaction(Redaction::Pii)
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+
+        let app = test::init_service(
+            App::new().service(
+                web::resource("/bid")
+                    .wrap(capture.clone())
+                    .route(web::post().to(echo_id)),
+            ),
+        )
+        .await;
+
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(REQUEST.as_bytes()).unwrap();
+        let req = TestRequest::post()
+            .uri("/bid")
+            .insert_header((header::CONTENT_ENCODING, "gzip"))
+            .set_payload(encoder.finish().unwrap())
+            .to_request();
+
+        // the handler still gets the compressed body to decode
+        let body = test::call_and_read_body(&app, req).await;
+        assert_eq!(body, "req-1");
+
+        wait_for(&capture, 1);
+        drop(app);
+        drop(capture);
+        // the writer flushes once the last sender is gone
+        std::thread::sleep(Duration::from_millis(200));
+
+        let captured = read_capture(&path, CaptureFormat::Jsonl);
+        assert_eq!(captured.len(), 1);
+        assert_eq!(captured[0].id, "req-1");
+        assert!(captured[0].user.as_ref().unwrap().id.is_empty());
+        assert!(captured[0].device.as_ref().unwrap().ip.is_empty());
+        assert_eq!(captured[0].device.as_ref().unwrap().ua, "agent");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_redact_pii() {
+        let mut request: BidRequest = serde_json::from_str(
+            r#"{
+                "id": "req-1",
+                "user": {
+                    "id": "u-1", "buyeruid": "b-1", "yob": 1980, "gender": "F", "consent": "CP",
+                    "eids": [{"source": "id5-sync.com", "uids": [{"id": "x"}]}],
+                    "data": [{"id": "d", "segment": [{"id": "s"}]}],
+                    "geo": {"lat": 52.5, "lon": 13.4, "country": "DEU"}
+                },
+                "device": {
+                    "ip": "10.0.0.1", "ifa": "ifa", "ua": "agent",
+                    "dpidsha1": "a", "dpidmd5": "b", "didsha1": "c", "didmd5": "d",
+                    "macsha1": "e", "macmd5": "f",
+                    "geo": {"lat": 52.5, "lon": 13.4, "country": "DEU"}
+                }
+            }"#,
+        )
+        .unwrap();
+        redact_pii(&mut request);
+
+        let json = serde_json::to_value(&request).unwrap();
+        assert_eq!(json["user"], serde_json::json!({"geo": {"country": "DEU"}}));
+        assert_eq!(
+            json["device"],
+            serde_json::json!({"ua": "agent", "geo": {"country": "DEU"}})
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_sampling_and_paths() {
+        let path = temp_file("sampling");
+        let capture = RequestCapture::start(
+            &path,
+            CaptureConfigBuilder::default()
+                .sample_rate(0.25)
+                .format(CaptureFormat::Protobuf)
+                .paths(vec!["/bid".to_string()])
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .wrap(capture.clone())
+                .route("/bid", web::post().to(echo_id))
+                .route("/other", web::post().to(echo_id)),
+        )
+        .await;
+
+        for uri in ["/bid", "/other"] {
+            for _ in 0..8 {
+                let req = TestRequest::post()
+                    .uri(uri)
+                    .set_payload(REQUEST)
+                    .to_request();
+                assert_eq!(test::call_and_read_body(&app, req).await, "req-1");
+            }
+        }
+
+        wait_for(&capture, 2);
+        drop(app);
+        drop(capture);
+        // the writer flushes once the last sender is gone
+        std::thread::sleep(Duration::from_millis(200));
+
+        // JSON requests converted to protobuf, user data kept
+        let captured = read_capture(&path, CaptureFormat::Protobuf);
+        assert_eq!(captured.len(), 2);
+        assert_eq!(captured[1].user.as_ref().unwrap().id, "u-1");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[actix_web::test]
+    async fn test_invalid_bodies_are_skipped() {
+        let path = temp_file("invalid");
+        let capture = RequestCapture::start(
+            &path,
+            CaptureConfigBuilder::default()
+                .sample_rate(1.0)
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+
+        let app = test::init_service(App::new().wrap(capture.clone()).route(
+            "/bid",
+            web::post().to(|| async { HttpResponse::NoContent() }),
+        ))
+        .await;
+
+        let req = TestRequest::post()
+            .uri("/bid")
+            .set_payload("not json")
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 204);
+
+        for _ in 0..200 {
+            if capture.stats().skipped == 1 {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(
+            capture.stats(),
+            CaptureStats {
+                skipped: 1,
+                ..Default::default()
+            }
+        );
+
+        drop(app);
+        drop(capture);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_reader() {
+        let jsonl = format!("{REQUEST}\n\n{REQUEST}");
+        let bodies: Vec<_> = CaptureReader::new(jsonl.as_bytes(), CaptureFormat::Jsonl)
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(bodies, [REQUEST.as_bytes(), REQUEST.as_bytes()]);
+
+        let request: BidRequest = serde_json::from_str(REQUEST).unwrap();
+        let mut frames = Vec::new();
+        request.encode_length_delimited(&mut frames).unwrap();
+        request.encode_length_delimited(&mut frames).unwrap();
+        let bodies: Vec<_> = CaptureReader::new(frames.as_slice(), CaptureFormat::Protobuf)
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(bodies.len(), 2);
+        assert_eq!(CaptureFormat::Protobuf.decode(&bodies[1]).unwrap(), request);
+
+        // a truncated frame is an error, not the end
+        let truncated = &frames[..frames.len() - 1];
+        let results: Vec<_> = CaptureReader::new(truncated, CaptureFormat::Protobuf).collect();
+        assert!(results[1].is_err());
+    }
+
+    #[test]
+    fn test_zero_capacity_rejected() {
+        assert!(CaptureConfigBuilder::default().capacity(0).build().is_err());
+
+        let path = temp_file("zero");
+        let config = CaptureConfig {
+            capacity: 0,
+            ..Default::default()
+        };
+        let err = RequestCapture::start(&path, config).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(!path.exists());
+    }
+}
@@ -1,13 +1,19 @@
+#[cfg(feature = "simd-json")]
+use crate::BidRequest;
 use crate::BidResponse;
 use crate::common::bidresponsestate::BidResponseState;
+#[cfg(feature = "simd-json")]
+use crate::openrtb::utils::view::BidRequestView;
 use actix_web::body::BoxBody;
 #[cfg(feature = "simd-json")]
 use actix_web::dev::Payload;
 #[cfg(feature = "simd-json")]
-use actix_web::web::BytesMut;
+use actix_web::http::header::{ACCEPT_ENCODING, ContentEncoding, ContentType, VARY};
+#[cfg(feature = "simd-json")]
+use actix_web::web::{Bytes, BytesMut};
 #[cfg(feature = "simd-json")]
 use actix_web::{FromRequest, ResponseError};
-use actix_web::{HttpRequest, HttpResponse, Responder};
+use actix_web::{HttpRequest, HttpResponse, HttpResponseBuilder, Responder};
 #[cfg(feature = "simd-json")]
 use futures_util::StreamExt;
 #[cfg(feature = "simd-json")]
@@ -120,6 +126,8 @@ pub enum FastJsonError {
     Payload(actix_web::Error),
     #[cfg(feature = "simd-json")]
     Decompression(String),
+    #[cfg(feature = "simd-json")]
+    View(serde_json::Error),
 }
 
 impl fmt::Display for FastJsonError {
@@ -131,6 +139,8 @@ impl fmt::Display for FastJsonError {
             FastJsonError::Payload(e) => write!(f, "Payload error: {}", e),
             #[cfg(feature = "simd-json")]
             FastJsonError::Decompression(e) => write!(f, "Decompression error: {}", e),
+            #[cfg(feature = "simd-json")]
+            FastJsonError::View(e) => write!(f, "JSON parse error: {}", e),
         }
     }
 }
@@ -145,10 +155,59 @@ impl ResponseError for FastJsonError {
             FastJsonError::Parse(_) => HttpResponse::BadRequest().finish(),
             FastJsonError::Payload(_) => HttpResponse::BadRequest().finish(),
             FastJsonError::Decompression(_) => HttpResponse::BadRequest().finish(),
+            FastJsonError::View(_) => HttpResponse::BadRequest().finish(),
         }
     }
 }
 
+#[cfg(feature = "simd-json")]
+/// Reads the body up to [`MAX_SIZE`], decompressing it when the request is
+/// gzip-encoded
+fn read_body(
+    req: &HttpRequest,
+    payload: &mut Payload,
+) -> LocalBoxFuture<'static, Result<(BytesMut, BodyStats), FastJsonError>> {
+    let mut payload = payload.take();
+
+    // Check if the request is gzip-compressed
+    let is_gzip = req
+        .headers()
+        .get("content-encoding")
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.eq_ignore_ascii_case("gzip"))
+        .unwrap_or(false);
+
+    Box::pin(async move {
+        let mut body = BytesMut::new();
+
+        while let Some(chunk) = payload.next().await {
+            let chunk = chunk.map_err(FastJsonError::Payload)?;
+
+            if (body.len() + chunk.len()) > MAX_SIZE {
+                return Err(FastJsonError::Overflow);
+            }
+
+            body.extend_from_slice(&chunk);
+        }
+
+        let encoded_bytes = body.len();
+
+        // Decompress if needed
+        let final_body = if is_gzip {
+            decompress_gzip(body)?
+        } else {
+            body
+        };
+
+        let body_stats = BodyStats {
+            encoded_bytes,
+            decoded_bytes: final_body.len(),
+            gzip: is_gzip,
+        };
+        Ok((final_body, body_stats))
+    })
+}
+
 #[cfg(feature = "simd-json")]
 impl<T> FromRequest for FastJson<T>
 where
@@ -158,73 +217,209 @@ where
     type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
 
     fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
-        let mut payload = payload.take();
+        let body = read_body(req, payload);
+
+        Box::pin(async move {
+            let (mut body, body_stats) = body.await?;
+            let value = simd_json::from_slice(body.as_mut()).map_err(FastJsonError::Parse)?;
+            Ok(FastJson { value, body_stats })
+        })
+    }
+}
+
+#[cfg(feature = "simd-json")]
+/// Extracts the request body without parsing it, so handlers can filter on
+/// a [`BidRequestView`] and only deserialize the requests they bid on.
+///
+/// ```ignore
+/// async fn bid(body: FastJsonView) -> Result<JsonBidResponseState, FastJsonError> {
+///     if body.view()?.device_country() != Some("DEU") {
+///         return Ok(JsonBidResponseState(BidResponseState::NoBid { desc: None }));
+///     }
+///     let request = body.into_bid_request()?;
+///     // ...
+/// }
+/// ```
+pub struct FastJsonView {
+    body: BytesMut,
+    body_stats: BodyStats,
+}
+
+#[cfg(feature = "simd-json")]
+impl FastJsonView {
+    /// Reads the commonly filtered fields, borrowing from the body
+    pub fn view(&self) -> Result<BidRequestView<'_>, FastJsonError> {
+        BidRequestView::parse(&self.body).map_err(FastJsonError::View)
+    }
+
+    /// Deserializes the full request with simd-json, in place
+    pub fn into_bid_request(mut self) -> Result<BidRequest, FastJsonError> {
+        simd_json::from_slice(self.body.as_mut()).map_err(FastJsonError::Parse)
+    }
+
+    /// The decompressed request body
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    pub fn body_stats(&self) -> BodyStats {
+        self.body_stats
+    }
+}
+
+#[cfg(feature = "simd-json")]
+impl FromRequest for FastJsonView {
+    type Error = FastJsonError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
 
-        // Check if the request is gzip-compressed
-        let is_gzip = req
-            .headers()
-            .get("content-encoding")
-            .and_then(|v| v.to_str().ok())
-            .map(|v| v.eq_ignore_ascii_case("gzip"))
-            .unwrap_or(false);
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let body = read_body(req, payload);
 
         Box::pin(async move {
-            let mut body = BytesMut::new();
+            let (body, body_stats) = body.await?;
+            Ok(FastJsonView { body, body_stats })
+        })
+    }
+}
+
+#[cfg(feature = "simd-json")]
+/// Responses smaller than this are not worth compressing
+const MIN_GZIP_SIZE: usize = 1024;
+
+#[cfg(feature = "simd-json")]
+/// Output buffers which grew past this are released after use
+const MAX_RETAINED_SIZE: usize = 4 * MAX_SIZE;
+
+#[cfg(feature = "simd-json")]
+thread_local! {
+    static RESPONSE_BUFFER: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+    static COMPRESSOR: RefCell<libdeflater::Compressor> = RefCell::new(
+        libdeflater::Compressor::new(libdeflater::CompressionLvl::new(1).unwrap_or_default()),
+    );
+}
 
-            while let Some(chunk) = payload.next().await {
-                let chunk = chunk.map_err(FastJsonError::Payload)?;
+#[cfg(feature = "simd-json")]
+/// Whether `Accept-Encoding` allows gzip. A `gzip` entry decides, `*` only
+/// stands in for gzip when it is not listed.
+fn accepts_gzip(req: &HttpRequest) -> bool {
+    let mut gzip = None;
+    let mut any = None;
+
+    let codings = req
+        .headers()
+        .get_all(ACCEPT_ENCODING)
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','));
+    for coding in codings {
+        let mut params = coding.split(';').map(str::trim);
+        let name = params.next().unwrap_or_default();
+        let accepted = !params
+            .filter_map(|p| p.strip_prefix("q="))
+            .any(|q| q.parse::<f32>().is_ok_and(|q| q == 0.0));
+        if name.eq_ignore_ascii_case("gzip") {
+            *gzip.get_or_insert(false) |= accepted;
+        } else if name == "*" {
+            *any.get_or_insert(false) |= accepted;
+        }
+    }
 
-                if (body.len() + chunk.len()) > MAX_SIZE {
-                    return Err(FastJsonError::Overflow);
-                }
+    gzip.or(any).unwrap_or(false)
+}
+
+#[cfg(feature = "simd-json")]
+fn compress_gzip(data: &[u8]) -> Option<Bytes> {
+    COMPRESSOR.with(|c| {
+        let mut compressor = c.borrow_mut();
+        let mut compressed = BytesMut::zeroed(compressor.gzip_compress_bound(data.len()));
+        let size = compressor.gzip_compress(data, &mut compressed).ok()?;
+        compressed.truncate(size);
+        Some(compressed.freeze())
+    })
+}
 
-                body.extend_from_slice(&chunk);
+#[cfg(feature = "simd-json")]
+/// Serializes `value` with simd-json into a reused thread-local buffer,
+/// gzipping it when `gzip` is set and the output is large enough. Returns
+/// the body and whether it was compressed.
+fn encode_json<T: serde::Serialize>(
+    value: &T,
+    gzip: bool,
+) -> Result<(Bytes, bool), simd_json::Error> {
+    RESPONSE_BUFFER.with(|b| {
+        let mut buffer = b.borrow_mut();
+        buffer.clear();
+        let result = simd_json::to_writer(&mut *buffer, value).map(|()| {
+            if gzip
+                && buffer.len() >= MIN_GZIP_SIZE
+                && let Some(compressed) = compress_gzip(&buffer)
+            {
+                return (compressed, true);
             }
+            (Bytes::copy_from_slice(&buffer), false)
+        });
 
-            let encoded_bytes = body.len();
-
-            // Decompress if needed
-            let mut final_body = if is_gzip {
-                decompress_gzip(body)?
-            } else {
-                body
-            };
-            let decoded_bytes = final_body.len();
-
-            let value = simd_json::from_slice(final_body.as_mut()).map_err(FastJsonError::Parse)?;
-            Ok(FastJson {
-                value,
-                body_stats: BodyStats {
-                    encoded_bytes,
-                    decoded_bytes,
-                    gzip: is_gzip,
-                },
-            })
-        })
+        if buffer.capacity() > MAX_RETAINED_SIZE {
+            *buffer = Vec::new();
+        }
+        result
+    })
+}
+
+#[cfg(feature = "simd-json")]
+fn json_response(
+    mut builder: HttpResponseBuilder,
+    req: &HttpRequest,
+    response: &BidResponse,
+) -> HttpResponse {
+    match encode_json(response, accepts_gzip(req)) {
+        Ok((body, gzip)) => {
+            builder
+                .content_type(ContentType::json())
+                .insert_header((VARY, "Accept-Encoding"));
+            if gzip {
+                builder.insert_header(ContentEncoding::Gzip);
+            }
+            builder.body(body)
+        }
+        Err(e) => HttpResponse::from_error(actix_web::error::ErrorInternalServerError(e)),
     }
 }
 
+#[cfg(not(feature = "simd-json"))]
+fn json_response(
+    mut builder: HttpResponseBuilder,
+    _req: &HttpRequest,
+    response: &BidResponse,
+) -> HttpResponse {
+    builder.json(response)
+}
+
+/// Responds with the JSON bid response. With the `simd-json` feature the
+/// response is serialized by simd-json and gzipped when the client accepts
+/// it.
 pub struct JsonBidResponseState(pub BidResponseState);
 
 impl Responder for JsonBidResponseState {
     type Body = BoxBody;
 
-    fn respond_to(self, _req: &HttpRequest) -> HttpResponse<Self::Body> {
+    fn respond_to(self, req: &HttpRequest) -> HttpResponse<Self::Body> {
         match self.0 {
-            BidResponseState::Bid(bidresponse) => HttpResponse::Ok().json(bidresponse),
-            BidResponseState::NoBidReason { reqid, nbr, desc } => HttpResponse::Ok()
-                .reason(desc.unwrap_or("No Bid"))
-                .json(BidResponse {
+            BidResponseState::Bid(bidresponse) => {
+                json_response(HttpResponse::Ok(), req, &bidresponse)
+            }
+            BidResponseState::NoBidReason { reqid, nbr, desc } => {
+                let mut builder = HttpResponse::Ok();
+                builder.reason(desc.unwrap_or("No Bid"));
+                let response = BidResponse {
                     id: reqid,
                     nbr: nbr as i32,
                     ..Default::default()
-                }),
-            BidResponseState::NoBid { desc } => {
-                let response = HttpResponse::NoContent()
-                    .reason(desc.unwrap_or("No Bid"))
-                    .finish();
-                response
+                };
+                json_response(builder, req, &response)
             }
+            BidResponseState::NoBid { desc } => HttpResponse::NoContent()
+                .reason(desc.unwrap_or("No Bid"))
+                .finish(),
         }
     }
 }
@@ -332,4 +527,128 @@ mod tests {
         assert_eq!(stats.decoded_bytes, body.len());
         assert!(stats.gzip);
     }
+
+    #[actix_web::test]
+    async fn fast_json_view_filters_then_promotes() {
+        let body = br#"{"id":"request-1","imp":[{"id":"1","bidfloor":0.5}],"app":{"bundle":"com.example"},"device":{"geo":{"country":"DEU"}}}"#;
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(body).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let (request, mut payload) = TestRequest::post()
+            .insert_header(("content-encoding", "gzip"))
+            .set_payload(compressed)
+            .to_http_parts();
+        let extracted = FastJsonView::from_request(&request, &mut payload)
+            .await
+            .unwrap();
+
+        assert_eq!(extracted.body(), body);
+        assert!(extracted.body_stats().gzip);
+        let view = extracted.view().unwrap();
+        assert_eq!(view.app_bundle(), Some("com.example"));
+        assert_eq!(view.device_country(), Some("DEU"));
+        assert_eq!(view.imps()[0].bidfloor(), 0.5);
+
+        let request = extracted.into_bid_request().unwrap();
+        assert_eq!(request.id, "request-1");
+        assert_eq!(request.imp.len(), 1);
+    }
+
+    fn bid_response(bids: usize) -> BidResponse {
+        serde_json::from_value(serde_json::json!({
+            "id": "request-1",
+            "cur": "USD",
+            "seatbid": [{
+                "bid": (0..bids)
+                    .map(|i| serde_json::json!({"id": format!("bid-{i}"), "impid": "1", "price": 1.5}))
+                    .collect::<Vec<_>>()
+            }]
+        }))
+        .unwrap()
+    }
+
+    async fn respond(state: BidResponseState, accept_encoding: Option<&str>) -> HttpResponse {
+        let mut request = TestRequest::post();
+        if let Some(accept_encoding) = accept_encoding {
+            request = request.insert_header((ACCEPT_ENCODING, accept_encoding));
+        }
+        JsonBidResponseState(state).respond_to(&request.to_http_request())
+    }
+
+    #[test]
+    fn test_accepts_gzip() {
+        let accepts = |value: &str| {
+            accepts_gzip(
+                &TestRequest::default()
+                    .insert_header((ACCEPT_ENCODING, value))
+                    .to_http_request(),
+            )
+        };
+
+        assert!(accepts("gzip"));
+        assert!(accepts("br, GZIP;q=0.5"));
+        assert!(accepts("*"));
+        assert!(!accepts("br, deflate"));
+        assert!(!accepts("gzip;q=0"));
+        assert!(!accepts("*, gzip;q=0"));
+        assert!(!accepts("gzip;q=0, *"));
+        assert!(accepts("gzip, *;q=0"));
+        assert!(!accepts("br, *;q=0"));
+        assert!(!accepts_gzip(&TestRequest::default().to_http_request()));
+    }
+
+    #[actix_web::test]
+    async fn json_response_matches_serde_json() {
+        let response = bid_response(2);
+        let http = respond(BidResponseState::Bid(response.clone()), None).await;
+
+        assert_eq!(http.status(), 200);
+        assert_eq!(
+            http.headers().get("content-type").unwrap(),
+            "application/json"
+        );
+        assert!(http.headers().get("content-encoding").is_none());
+        let body = actix_web::body::to_bytes(http.into_body()).await.unwrap();
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+            serde_json::to_value(&response).unwrap()
+        );
+    }
+
+    #[actix_web::test]
+    async fn json_response_is_gzipped_when_accepted() {
+        let response = bid_response(50);
+        let http = respond(BidResponseState::Bid(response.clone()), Some("gzip, br")).await;
+
+        assert_eq!(http.headers().get("content-encoding").unwrap(), "gzip");
+        assert_eq!(http.headers().get("vary").unwrap(), "Accept-Encoding");
+        let body = actix_web::body::to_bytes(http.into_body()).await.unwrap();
+        let decompressed = decompress_gzip(BytesMut::from(&body[..])).unwrap();
+        assert_eq!(
+            serde_json::from_slice::<BidResponse>(&decompressed).unwrap(),
+            response
+        );
+    }
+
+    #[actix_web::test]
+    async fn small_json_response_is_not_gzipped() {
+        let http = respond(
+            BidResponseState::NoBidReason {
+                reqid: "request-1".to_string(),
+                nbr: 2,
+                desc: Some("Blocked"),
+            },
+            Some("gzip"),
+        )
+        .await;
+
+        assert_eq!(http.status(), 200);
+        assert_eq!(http.head().reason(), "Blocked");
+        assert!(http.headers().get("content-encoding").is_none());
+        let body = actix_web::body::to_bytes(http.into_body()).await.unwrap();
+        let response: BidResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(response.id, "request-1");
+        assert_eq!(response.nbr, 2);
+    }
 }
@@ -1,5 +1,8 @@
+pub mod beacon;
+pub mod capture;
 pub mod json;
 pub mod protobuf;
+pub mod replay;
 mod server;
 
 pub use server::{Server, ServerConfig, TlsConfig};
@@ -0,0 +1,312 @@
+//! Replays captured bid requests, see [`super::capture`], against a bidder
+//! to compare its latency and answers before and after a change.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use rtb::common::bidresponsestate::BidResponseState;
+//! use rtb::server::capture::{CaptureFormat, CaptureReader};
+//! use rtb::server::replay::{HandlerTarget, ReplayConfigBuilder, replay};
+//! use std::io::BufReader;
+//!
+//! let file = BufReader::new(std::fs::File::open("capture.jsonl")?);
+//! let bodies = CaptureReader::new(file, CaptureFormat::Jsonl).map_while(Result::ok);
+//!
+//! let target = HandlerTarget::new(CaptureFormat::Jsonl, |request| async move {
+//!     my_bidder(request).await
+//! });
+//! let config = ReplayConfigBuilder::default().qps(500.0).concurrency(32).build()?;
+//!
+//! let report = replay(&config, &target, bodies).await;
+//! println!("p99 {:?}, outcomes {:?}", report.p99, report.outcomes);
+//! ```
+
+use super::capture::CaptureFormat;
+use crate::BidRequest;
+use crate::common::bidresponsestate::BidResponseState;
+use actix_web::rt::time::{Instant, sleep};
+use derive_builder::Builder;
+use futures_util::StreamExt;
+use futures_util::future::LocalBoxFuture;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::time::Duration;
+
+/// Pace and parallelism of a replay
+#[derive(Debug, Clone, Builder)]
+pub struct ReplayConfig {
+    /// Requests started per second, as fast as possible when `None`
+    #[builder(default, setter(strip_option))]
+    pub qps: Option<f64>,
+
+    /// Requests in flight at once
+    #[builder(default = "16")]
+    pub concurrency: usize,
+}
+
+impl Default for ReplayConfig {
+    fn default() -> Self {
+        ReplayConfigBuilder::default().build().unwrap()
+    }
+}
+
+/// Where replayed requests are sent
+pub trait ReplayTarget {
+    /// Sends one captured body, returning a label for the outcome, e.g. the
+    /// HTTP status, or an error
+    fn send(&self, body: Vec<u8>) -> LocalBoxFuture<'_, Result<String, String>>;
+}
+
+/// Replays into a handler in the same process, skipping the network.
+/// Outcomes are labelled `bid`, `nbr:<reason>` and `no_bid` after the
+/// [`BidResponseState`] returned.
+pub struct HandlerTarget<F> {
+    format: CaptureFormat,
+    handler: F,
+}
+
+impl<F, Fut> HandlerTarget<F>
+where
+    F: Fn(BidRequest) -> Fut,
+    Fut: Future<Output = BidResponseState>,
+{
+    pub fn new(format: CaptureFormat, handler: F) -> Self {
+        Self { format, handler }
+    }
+}
+
+impl<F, Fut> ReplayTarget for HandlerTarget<F>
+where
+    F: Fn(BidRequest) -> Fut,
+    Fut: Future<Output = BidResponseState>,
+{
+    fn send(&self, body: Vec<u8>) -> LocalBoxFuture<'_, Result<String, String>> {
+        Box::pin(async move {
+            let request = self.format.decode(&body).map_err(|e| e.to_string())?;
+            let label = match (self.handler)(request).await {
+                BidResponseState::Bid(_) => "bid".to_string(),
+                BidResponseState::NoBidReason { nbr, .. } => format!("nbr:{nbr}"),
+                BidResponseState::NoBid { .. } => "no_bid".to_string(),
+            };
+            Ok(label)
+        })
+    }
+}
+
+/// Replays over HTTP, e.g. against a [`super::Server`]. Outcomes are
+/// labelled with the response status.
+#[cfg(feature = "replay")]
+pub struct HttpTarget {
+    client: reqwest::Client,
+    url: String,
+    content_type: &'static str,
+}
+
+#[cfg(feature = "replay")]
+impl HttpTarget {
+    pub fn new(url: impl Into<String>, format: CaptureFormat, timeout: Duration) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(timeout)
+            .build()
+            .expect("reqwest client with default TLS settings");
+
+        Self {
+            client,
+            url: url.into(),
+            content_type: format.content_type(),
+        }
+    }
+}
+
+#[cfg(feature = "replay")]
+impl ReplayTarget for HttpTarget {
+    fn send(&self, body: Vec<u8>) -> LocalBoxFuture<'_, Result<String, String>> {
+        Box::pin(async move {
+            let response = self
+                .client
+                .post(&self.url)
+                .header("content-type", self.content_type)
+                .body(body)
+                .send()
+                .await
+                .map_err(|e| e.to_string())?;
+            Ok(response.status().as_u16().to_string())
+        })
+    }
+}
+
+/// Latency and outcomes of a replay
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ReplayReport {
+    /// Requests sent, including those which failed
+    pub sent: u64,
+    /// Requests which failed, also counted in `outcomes` as `error`
+    pub errors: u64,
+    pub elapsed: Duration,
+    pub p50: Duration,
+    pub p90: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+    /// Requests per outcome label
+    pub outcomes: BTreeMap<String, u64>,
+}
+
+impl ReplayReport {
+    /// Achieved requests per second
+    pub fn qps(&self) -> f64 {
+        if self.elapsed.is_zero() {
+            return 0.0;
+        }
+        self.sent as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+/// Sends every body to `target`, paced and bounded by `config`. Must run on
+/// an actix (tokio) runtime.
+pub async fn replay<T, I>(config: &ReplayConfig, target: &T, bodies: I) -> ReplayReport
+where
+    T: ReplayTarget,
+    I: IntoIterator<Item = Vec<u8>>,
+{
+    let start = Instant::now();
+    // a rate too low for a Duration saturates rather than panics
+    let interval = config
+        .qps
+        .filter(|qps| *qps > 0.0)
+        .map(|qps| Duration::try_from_secs_f64(qps.recip()).unwrap_or(Duration::MAX));
+
+    let results = futures_util::stream::iter(bodies.into_iter().enumerate())
+        .map(|(i, body)| async move {
+            if let Some(interval) = interval {
+                let offset = interval.saturating_mul(u32::try_from(i).unwrap_or(u32::MAX));
+                sleep(offset.saturating_sub(start.elapsed())).await;
+            }
+            let sent = Instant::now();
+            let outcome = target.send(body).await;
+            (sent.elapsed(), outcome)
+        })
+        .buffer_unordered(config.concurrency.max(1));
+
+    let mut latencies = Vec::new();
+    let mut report = ReplayReport::default();
+    futures_util::pin_mut!(results);
+    while let Some((latency, outcome)) = results.next().await {
+        latencies.push(latency);
+        let label = outcome.unwrap_or_else(|_| {
+            report.errors += 1;
+            "error".to_string()
+        });
+        *report.outcomes.entry(label).or_default() += 1;
+    }
+
+    report.sent = latencies.len() as u64;
+    report.elapsed = start.elapsed();
+    latencies.sort_unstable();
+    report.p50 = percentile(&latencies, 0.50);
+    report.p90 = percentile(&latencies, 0.90);
+    report.p99 = percentile(&latencies, 0.99);
+    report.max = latencies.last().copied().unwrap_or_default();
+    report
+}
+
+/// Nearest-rank percentile of sorted values
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = (p * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BidResponse;
+    use std::cell::Cell;
+
+    fn bodies(ids: &[&str]) -> Vec<Vec<u8>> {
+        ids.iter()
+            .map(|id| format!(r#"{{"id":"{id}","imp":[{{"id":"1"}}]}}"#).into_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn test_percentile() {
+        let values: Vec<Duration> = (1..=100).map(Duration::from_millis).collect();
+        assert_eq!(percentile(&values, 0.5), Duration::from_millis(50));
+        assert_eq!(percentile(&values, 0.99), Duration::from_millis(99));
+        assert_eq!(percentile(&values, 1.0), Duration::from_millis(100));
+        assert_eq!(percentile(&values[..1], 0.5), Duration::from_millis(1));
+        assert_eq!(percentile(&[], 0.5), Duration::ZERO);
+    }
+
+    #[actix_web::test]
+    async fn test_replay_into_handler() {
+        let target = HandlerTarget::new(CaptureFormat::Jsonl, |request: BidRequest| async move {
+            match request.id.as_str() {
+                "bid" => BidResponseState::Bid(BidResponse::default()),
+                "blocked" => BidResponseState::NoBidReason {
+                    reqid: request.id,
+                    nbr: 8,
+                    desc: None,
+                },
+                _ => BidResponseState::NoBid { desc: None },
+            }
+        });
+
+        let mut corpus = bodies(&["bid", "bid", "blocked", "other"]);
+        corpus.push(b"not json".to_vec());
+
+        let report = replay(&ReplayConfig::default(), &target, corpus).await;
+        assert_eq!(report.sent, 5);
+        assert_eq!(report.errors, 1);
+        assert_eq!(
+            report.outcomes,
+            BTreeMap::from([
+                ("bid".to_string(), 2),
+                ("error".to_string(), 1),
+                ("nbr:8".to_string(), 1),
+                ("no_bid".to_string(), 1),
+            ])
+        );
+        assert!(report.p50 <= report.p99 && report.p99 <= report.max);
+    }
+
+    #[actix_web::test]
+    async fn test_replay_pacing_and_concurrency() {
+        let (in_flight, peak) = (&Cell::new(0), &Cell::new(0));
+        let target = HandlerTarget::new(CaptureFormat::Jsonl, move |_| async move {
+            in_flight.set(in_flight.get() + 1);
+            peak.set(peak.get().max(in_flight.get()));
+            actix_web::rt::time::sleep(Duration::from_millis(20)).await;
+            in_flight.set(in_flight.get() - 1);
+            BidResponseState::NoBid { desc: None }
+        });
+
+        let config = ReplayConfigBuilder::default()
+            .concurrency(2)
+            .build()
+            .unwrap();
+        replay(&config, &target, bodies(&["a"; 6])).await;
+        assert_eq!(peak.get(), 2);
+
+        // 5 intervals of 10ms before the last request starts
+        let config = ReplayConfigBuilder::default()
+            .qps(100.0)
+            .concurrency(8)
+            .build()
+            .unwrap();
+        let report = replay(&config, &target, bodies(&["a"; 6])).await;
+        assert!(report.elapsed >= Duration::from_millis(70));
+        assert_eq!(report.outcomes["no_bid"], 6);
+
+        // the first request of a rate too low to pace does not wait
+        let config = ReplayConfigBuilder::default()
+            .qps(f64::MIN_POSITIVE)
+            .build()
+            .unwrap();
+        let report = replay(&config, &target, bodies(&["a"])).await;
+        assert_eq!(report.sent, 1);
+    }
+}
@@ -0,0 +1,211 @@
+#[cfg(feature = "gzip")]
+use flate2::read::MultiGzDecoder;
+#[cfg(feature = "gzip")]
+use flate2::write::GzEncoder;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// Compression of a stream. Gzip needs the `gzip` feature, zstd the `zstd`
+/// feature.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Compression {
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// Detects gzip and zstd from the first bytes of a stream
+    pub fn detect(prefix: &[u8]) -> Self {
+        if prefix.starts_with(GZIP_MAGIC) {
+            Compression::Gzip
+        } else if prefix.starts_with(ZSTD_MAGIC) {
+            Compression::Zstd
+        } else {
+            Compression::None
+        }
+    }
+
+    /// From a file name, e.g. `requests.jsonl.gz`
+    pub fn from_path(path: impl AsRef<Path>) -> Self {
+        match path.as_ref().extension().and_then(|e| e.to_str()) {
+            Some("gz") => Compression::Gzip,
+            Some("zst") => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+}
+
+#[cfg(not(feature = "gzip"))]
+fn gzip_unsupported() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        "gzip streams need the `gzip` feature",
+    )
+}
+
+#[cfg(not(feature = "zstd"))]
+fn zstd_unsupported() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        "zstd streams need the `zstd` feature",
+    )
+}
+
+/// Wraps `reader` to decompress it, detecting the compression from what
+/// the reader has buffered
+pub fn decompress<'a, R: BufRead + Send + 'a>(
+    mut reader: R,
+) -> io::Result<Box<dyn BufRead + Send + 'a>> {
+    Ok(match Compression::detect(reader.fill_buf()?) {
+        Compression::None => Box::new(reader),
+        #[cfg(feature = "gzip")]
+        Compression::Gzip => Box::new(BufReader::new(MultiGzDecoder::new(reader))),
+        #[cfg(not(feature = "gzip"))]
+        Compression::Gzip => return Err(gzip_unsupported()),
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => Box::new(BufReader::new(zstd::Decoder::with_buffer(reader)?)),
+        #[cfg(not(feature = "zstd"))]
+        Compression::Zstd => return Err(zstd_unsupported()),
+    })
+}
+
+/// Opens a file, decompressing it if needed
+pub fn open(path: impl AsRef<Path>) -> io::Result<Box<dyn BufRead + Send>> {
+    decompress(BufReader::new(File::open(path)?))
+}
+
+/// Creates a file, compressed after its extension: `.gz` or `.zst`
+pub fn create(path: impl AsRef<Path>) -> io::Result<Encoder<BufWriter<File>>> {
+    let compression = Compression::from_path(&path);
+    Encoder::new(BufWriter::new(File::create(path)?), compression)
+}
+
+/// Compresses what is written to it. [`Encoder::finish`] must be called to
+/// complete the stream.
+pub struct Encoder<W: Write> {
+    inner: Inner<W>,
+}
+
+enum Inner<W: Write> {
+    None(W),
+    #[cfg(feature = "gzip")]
+    Gzip(GzEncoder<W>),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::Encoder<'static, W>),
+}
+
+impl<W: Write> Encoder<W> {
+    pub fn new(writer: W, compression: Compression) -> io::Result<Self> {
+        let inner = match compression {
+            Compression::None => Inner::None(writer),
+            #[cfg(feature = "gzip")]
+            Compression::Gzip => {
+                Inner::Gzip(GzEncoder::new(writer, flate2::Compression::default()))
+            }
+            #[cfg(not(feature = "gzip"))]
+            Compression::Gzip => return Err(gzip_unsupported()),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => Inner::Zstd(zstd::Encoder::new(writer, 0)?),
+            #[cfg(not(feature = "zstd"))]
+            Compression::Zstd => return Err(zstd_unsupported()),
+        };
+        Ok(Self { inner })
+    }
+
+    /// Writes the end of the compressed stream and flushes the writer
+    pub fn finish(self) -> io::Result<W> {
+        // only the `None` arm is left without compression features
+        #[allow(clippy::infallible_destructuring_match)]
+        let mut writer = match self.inner {
+            Inner::None(writer) => writer,
+            #[cfg(feature = "gzip")]
+            Inner::Gzip(encoder) => encoder.finish()?,
+            #[cfg(feature = "zstd")]
+            Inner::Zstd(encoder) => encoder.finish()?,
+        };
+        writer.flush()?;
+        Ok(writer)
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.inner {
+            Inner::None(writer) => writer.write(buf),
+            #[cfg(feature = "gzip")]
+            Inner::Gzip(encoder) => encoder.write(buf),
+            #[cfg(feature = "zstd")]
+            Inner::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.inner {
+            Inner::None(writer) => writer.flush(),
+            #[cfg(feature = "gzip")]
+            Inner::Gzip(encoder) => encoder.flush(),
+            #[cfg(feature = "zstd")]
+            Inner::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn round_trip(compression: Compression) {
+        let mut encoder = Encoder::new(Vec::new(), compression).unwrap();
+        encoder.write_all(b"{\"id\":\"1\"}\n").unwrap();
+        let compressed = encoder.finish().unwrap();
+        assert_eq!(Compression::detect(&compressed), compression);
+
+        let mut out = String::new();
+        decompress(compressed.as_slice())
+            .unwrap()
+            .read_to_string(&mut out)
+            .unwrap();
+        assert_eq!(out, "{\"id\":\"1\"}\n");
+    }
+
+    #[test]
+    fn test_round_trips() {
+        round_trip(Compression::None);
+        #[cfg(feature = "gzip")]
+        round_trip(Compression::Gzip);
+        #[cfg(feature = "zstd")]
+        round_trip(Compression::Zstd);
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_concatenated_gzip_members() {
+        let mut compressed = Vec::new();
+        for line in ["a\n", "b\n"] {
+            let mut encoder = Encoder::new(Vec::new(), Compression::Gzip).unwrap();
+            encoder.write_all(line.as_bytes()).unwrap();
+            compressed.extend(encoder.finish().unwrap());
+        }
+
+        let mut out = String::new();
+        decompress(compressed.as_slice())
+            .unwrap()
+            .read_to_string(&mut out)
+            .unwrap();
+        assert_eq!(out, "a\nb\n");
+    }
+
+    #[test]
+    fn test_from_path() {
+        assert_eq!(Compression::from_path("a.jsonl.gz"), Compression::Gzip);
+        assert_eq!(Compression::from_path("a.pb.zst"), Compression::Zstd);
+        assert_eq!(Compression::from_path("a.jsonl"), Compression::None);
+    }
+}
@@ -0,0 +1,44 @@
+//! Streams of messages for offline pipelines.
+//!
+//! [`MessageReader`] decodes any generated message, e.g. [`crate::BidRequest`],
+//! from JSON lines or length-delimited protobuf, and [`MessageWriter`] writes
+//! them back. [`open`] and [`create`] add gzip or zstd compression, which
+//! need the `gzip` and `zstd` features. Errors carry the position of the
+//! record in the stream, and the `rayon` feature adds parallel decoding.
+//!
+//! # Example
+//! ```no_run
+//! use rtb::BidRequest;
+//! use rtb::bid_request::DistributionchannelOneof;
+//! use rtb::stream::{MessageReader, MessageWriter, RecordFormat};
+//!
+//! let input = rtb::stream::open("requests.jsonl.gz").unwrap();
+//! let mut output = MessageWriter::new(rtb::stream::create("apps.pb").unwrap(), RecordFormat::Protobuf);
+//!
+//! for request in MessageReader::<_, BidRequest>::jsonl(input) {
+//!     match request {
+//!         Ok(request) => {
+//!             if let Some(DistributionchannelOneof::App(_)) = request.distributionchannel_oneof {
+//!                 output.write(&request).unwrap();
+//!             }
+//!         }
+//!         Err(e) => eprintln!("{e}"),
+//!     }
+//! }
+//! output.into_inner().unwrap().finish().unwrap();
+//! ```
+
+mod compression;
+#[cfg(feature = "rayon")]
+mod parallel;
+mod reader;
+mod writer;
+
+pub use compression::{Compression, Encoder, create, decompress, open};
+#[cfg(feature = "rayon")]
+pub use parallel::ParallelReader;
+pub use reader::{
+    DEFAULT_MAX_RECORD_SIZE, MessageReader, Record, RecordFormat, RecordReader, StreamError,
+    StreamErrorKind,
+};
+pub use writer::MessageWriter;
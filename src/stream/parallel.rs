@@ -0,0 +1,92 @@
+use super::reader::{Record, RecordReader, StreamError, decode};
+use prost::Message;
+use rayon::prelude::*;
+use serde::de::DeserializeOwned;
+use std::collections::VecDeque;
+use std::io::BufRead;
+
+/// Decodes a stream in parallel batches, see
+/// [`super::MessageReader::parallel`]. Records are read on the calling
+/// thread, only decoding is spread over the rayon pool.
+pub struct ParallelReader<R, T> {
+    records: RecordReader<R>,
+    batch_size: usize,
+    batch: Vec<Result<Record, StreamError>>,
+    decoded: VecDeque<Result<T, StreamError>>,
+}
+
+impl<R, T> ParallelReader<R, T>
+where
+    R: BufRead,
+    T: Message + DeserializeOwned + Default + Send,
+{
+    pub(super) fn new(records: RecordReader<R>, batch_size: usize) -> Self {
+        let batch_size = batch_size.max(1);
+        Self {
+            records,
+            batch_size,
+            batch: Vec::with_capacity(batch_size),
+            decoded: VecDeque::with_capacity(batch_size),
+        }
+    }
+
+    fn decode_batch(&mut self) {
+        self.batch
+            .extend(self.records.by_ref().take(self.batch_size));
+
+        let format = self.records.format();
+        let decoded: Vec<_> = self
+            .batch
+            .par_drain(..)
+            .map(|record| decode(format, record))
+            .collect();
+        self.decoded.extend(decoded);
+    }
+}
+
+impl<R, T> Iterator for ParallelReader<R, T>
+where
+    R: BufRead,
+    T: Message + DeserializeOwned + Default + Send,
+{
+    type Item = Result<T, StreamError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.decoded.is_empty() {
+            self.decode_batch();
+        }
+        self.decoded.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{MessageReader, RecordFormat};
+    use crate::BidRequest;
+
+    #[test]
+    fn test_parallel_keeps_order() {
+        let mut input = String::new();
+        for i in 0..1000 {
+            if i == 500 {
+                input.push_str("broken\n");
+            }
+            input.push_str(&format!("{{\"id\":\"{i}\"}}\n"));
+        }
+
+        let results: Vec<_> =
+            MessageReader::<_, BidRequest>::new(input.as_bytes(), RecordFormat::Jsonl)
+                .parallel(64)
+                .collect();
+        assert_eq!(results.len(), 1001);
+        assert_eq!(results[500].as_ref().unwrap_err().index, 500);
+
+        let ids: Vec<String> = results
+            .into_iter()
+            .filter_map(Result::ok)
+            .map(|r| r.id)
+            .collect();
+        let expected: Vec<String> = (0..1000).map(|i| i.to_string()).collect();
+        assert_eq!(ids, expected);
+    }
+}
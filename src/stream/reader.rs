@@ -0,0 +1,464 @@
+use prost::Message;
+use serde::de::DeserializeOwned;
+use std::fmt;
+use std::io::{self, BufRead, Read};
+use std::marker::PhantomData;
+
+/// Records larger than this are rejected unless configured otherwise
+pub const DEFAULT_MAX_RECORD_SIZE: usize = 16 * 1024 * 1024;
+
+/// How messages follow each other in a stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordFormat {
+    /// One JSON object per line, blank lines are skipped
+    Jsonl,
+    /// Messages prefixed with their varint encoded length
+    Protobuf,
+}
+
+impl RecordFormat {
+    /// Decodes one record
+    pub fn decode<T>(self, bytes: &[u8]) -> Result<T, StreamErrorKind>
+    where
+        T: Message + DeserializeOwned + Default,
+    {
+        match self {
+            RecordFormat::Jsonl => serde_json::from_slice(bytes).map_err(StreamErrorKind::Json),
+            RecordFormat::Protobuf => T::decode(bytes).map_err(StreamErrorKind::Protobuf),
+        }
+    }
+}
+
+/// An undecoded record of a stream
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    /// Position of the record in the stream, from 0
+    pub index: u64,
+    /// Byte offset of the record in the uncompressed stream
+    pub offset: u64,
+    pub bytes: Vec<u8>,
+}
+
+/// Why a record could not be read
+#[derive(Debug)]
+pub enum StreamErrorKind {
+    Io(io::Error),
+    Json(serde_json::Error),
+    Protobuf(prost::DecodeError),
+    /// The record is larger than the reader's limit
+    TooLarge(u64),
+}
+
+impl fmt::Display for StreamErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StreamErrorKind::Io(e) => write!(f, "{e}"),
+            StreamErrorKind::Json(e) => write!(f, "invalid JSON: {e}"),
+            StreamErrorKind::Protobuf(e) => write!(f, "invalid protobuf: {e}"),
+            StreamErrorKind::TooLarge(len) => write!(f, "record of {len} bytes is too large"),
+        }
+    }
+}
+
+/// A record which could not be read or decoded, and where it is
+#[derive(Debug)]
+pub struct StreamError {
+    pub index: u64,
+    pub offset: u64,
+    pub kind: StreamErrorKind,
+}
+
+impl fmt::Display for StreamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "record {} at byte {}: {}",
+            self.index, self.offset, self.kind
+        )
+    }
+}
+
+impl std::error::Error for StreamError {}
+
+/// Splits a stream into its undecoded records.
+///
+/// Reading stops after an I/O error or a broken length prefix, as the
+/// following record boundaries are unknown. A JSON line which is too large
+/// is reported and skipped.
+pub struct RecordReader<R> {
+    reader: R,
+    format: RecordFormat,
+    max_size: usize,
+    index: u64,
+    offset: u64,
+    done: bool,
+}
+
+impl<R: BufRead> RecordReader<R> {
+    pub fn new(reader: R, format: RecordFormat) -> Self {
+        Self {
+            reader,
+            format,
+            max_size: DEFAULT_MAX_RECORD_SIZE,
+            index: 0,
+            offset: 0,
+            done: false,
+        }
+    }
+
+    pub fn with_max_size(mut self, max_size: usize) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    pub fn format(&self) -> RecordFormat {
+        self.format
+    }
+
+    fn error(&mut self, offset: u64, kind: StreamErrorKind) -> StreamError {
+        let error = StreamError {
+            index: self.index,
+            offset,
+            kind,
+        };
+        self.index += 1;
+        error
+    }
+
+    fn read_line(&mut self) -> Option<Result<Record, StreamError>> {
+        loop {
+            let offset = self.offset;
+            let mut line = Vec::new();
+            // a line over the limit is skipped rather than buffered
+            let limit = (self.max_size as u64).saturating_add(1);
+            let result = (&mut self.reader)
+                .take(limit)
+                .read_until(b'\n', &mut line)
+                .and_then(|read| {
+                    let newline = line.last() == Some(&b'\n');
+                    if newline || read as u64 != limit {
+                        return Ok((read as u64, newline));
+                    }
+                    let (skipped, newline) = skip_line(&mut self.reader)?;
+                    Ok((read as u64 + skipped, newline))
+                });
+            let (read, newline) = match result {
+                Ok(read) => read,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(self.error(offset, StreamErrorKind::Io(e))));
+                }
+            };
+            if read == 0 {
+                return None;
+            }
+            self.offset += read;
+
+            let len = read - u64::from(newline);
+            if len > self.max_size as u64 {
+                return Some(Err(self.error(offset, StreamErrorKind::TooLarge(len))));
+            }
+            if newline {
+                line.pop();
+            }
+            if line.trim_ascii().is_empty() {
+                continue;
+            }
+
+            let record = Record {
+                index: self.index,
+                offset,
+                bytes: line,
+            };
+            self.index += 1;
+            return Some(Ok(record));
+        }
+    }
+
+    fn read_frame(&mut self) -> Option<Result<Record, StreamError>> {
+        let offset = self.offset;
+        match self.reader.fill_buf() {
+            Ok([]) => return None,
+            Ok(_) => {}
+            Err(e) => {
+                self.done = true;
+                return Some(Err(self.error(offset, StreamErrorKind::Io(e))));
+            }
+        }
+
+        let result = self.read_length().and_then(|len| {
+            if len > self.max_size as u64 {
+                return Err(StreamErrorKind::TooLarge(len));
+            }
+            let mut bytes = vec![0; len as usize];
+            self.reader.read_exact(&mut bytes).map_err(truncated)?;
+            self.offset += len;
+            Ok(bytes)
+        });
+
+        match result {
+            Ok(bytes) => {
+                let record = Record {
+                    index: self.index,
+                    offset,
+                    bytes,
+                };
+                self.index += 1;
+                Some(Ok(record))
+            }
+            Err(kind) => {
+                self.done = true;
+                Some(Err(self.error(offset, kind)))
+            }
+        }
+    }
+
+    /// Reads a varint length prefix
+    fn read_length(&mut self) -> Result<u64, StreamErrorKind> {
+        let mut len = 0u64;
+        for shift in (0..64).step_by(7) {
+            let mut byte = [0u8];
+            self.reader.read_exact(&mut byte).map_err(truncated)?;
+            self.offset += 1;
+            len |= u64::from(byte[0] & 0x7f) << shift;
+            if byte[0] & 0x80 == 0 {
+                return Ok(len);
+            }
+        }
+        Err(StreamErrorKind::Io(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "invalid length prefix",
+        )))
+    }
+}
+
+/// Names the end of the stream in the middle of a frame
+fn truncated(e: io::Error) -> StreamErrorKind {
+    if e.kind() == io::ErrorKind::UnexpectedEof {
+        return StreamErrorKind::Io(io::Error::new(e.kind(), "truncated record"));
+    }
+    StreamErrorKind::Io(e)
+}
+
+impl<R: BufRead> Iterator for RecordReader<R> {
+    type Item = Result<Record, StreamError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        match self.format {
+            RecordFormat::Jsonl => self.read_line(),
+            RecordFormat::Protobuf => self.read_frame(),
+        }
+    }
+}
+
+/// Decodes the messages of a stream, e.g. [`crate::BidRequest`]s, one at a
+/// time. A record which fails to decode is reported and reading goes on.
+///
+/// # Example
+/// ```no_run
+/// use rtb::BidRequest;
+/// use rtb::stream::{MessageReader, RecordFormat};
+///
+/// let file = rtb::stream::open("requests.jsonl.gz").unwrap();
+/// for request in MessageReader::<_, BidRequest>::new(file, RecordFormat::Jsonl) {
+///     match request {
+///         Ok(request) => println!("{}", request.id),
+///         Err(e) => eprintln!("skipping {e}"),
+///     }
+/// }
+/// ```
+pub struct MessageReader<R, T> {
+    records: RecordReader<R>,
+    _message: PhantomData<fn() -> T>,
+}
+
+impl<R, T> MessageReader<R, T>
+where
+    R: BufRead,
+    T: Message + DeserializeOwned + Default,
+{
+    pub fn new(reader: R, format: RecordFormat) -> Self {
+        Self::from_records(RecordReader::new(reader, format))
+    }
+
+    /// Reads from a configured [`RecordReader`]
+    pub fn from_records(records: RecordReader<R>) -> Self {
+        Self {
+            records,
+            _message: PhantomData,
+        }
+    }
+
+    pub fn jsonl(reader: R) -> Self {
+        Self::new(reader, RecordFormat::Jsonl)
+    }
+
+    pub fn protobuf(reader: R) -> Self {
+        Self::new(reader, RecordFormat::Protobuf)
+    }
+
+    /// Decodes batches of `batch_size` records in parallel on the rayon
+    /// thread pool, still yielding messages in stream order
+    #[cfg(feature = "rayon")]
+    pub fn parallel(self, batch_size: usize) -> super::ParallelReader<R, T> {
+        super::ParallelReader::new(self.records, batch_size)
+    }
+}
+
+/// Decodes a record, keeping its position for errors
+pub(super) fn decode<T>(
+    format: RecordFormat,
+    record: Result<Record, StreamError>,
+) -> Result<T, StreamError>
+where
+    T: Message + DeserializeOwned + Default,
+{
+    let record = record?;
+    format.decode(&record.bytes).map_err(|kind| StreamError {
+        index: record.index,
+        offset: record.offset,
+        kind,
+    })
+}
+
+impl<R, T> Iterator for MessageReader<R, T>
+where
+    R: BufRead,
+    T: Message + DeserializeOwned + Default,
+{
+    type Item = Result<T, StreamError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let format = self.records.format();
+        self.records.next().map(|record| decode(format, record))
+    }
+}
+
+/// Consumes the rest of a line, returning how many bytes it had and whether
+/// it ended with a newline rather than the stream
+fn skip_line(reader: &mut impl BufRead) -> io::Result<(u64, bool)> {
+    let mut skipped = 0;
+    loop {
+        let buf = reader.fill_buf()?;
+        if buf.is_empty() {
+            return Ok((skipped, false));
+        }
+        match buf.iter().position(|&b| b == b'\n') {
+            Some(end) => {
+                reader.consume(end + 1);
+                return Ok((skipped + end as u64 + 1, true));
+            }
+            None => {
+                let len = buf.len();
+                reader.consume(len);
+                skipped += len as u64;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BidRequest;
+
+    fn request(id: &str) -> BidRequest {
+        BidRequest {
+            id: id.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_jsonl_errors_carry_offsets() {
+        let input = "{\"id\":\"a\"}\n\n{\"id\":\nnot json\n{\"id\":\"b\"}";
+        let results: Vec<_> = MessageReader::<_, BidRequest>::jsonl(input.as_bytes()).collect();
+        assert_eq!(results.len(), 4);
+
+        assert_eq!(results[0].as_ref().unwrap().id, "a");
+        let error = results[1].as_ref().unwrap_err();
+        assert_eq!((error.index, error.offset), (1, 12));
+        assert!(matches!(error.kind, StreamErrorKind::Json(_)));
+        let error = results[2].as_ref().unwrap_err();
+        assert_eq!((error.index, error.offset), (2, 19));
+        assert_eq!(
+            error.to_string().split(':').next(),
+            Some("record 2 at byte 19")
+        );
+        // the last line needs no newline
+        assert_eq!(results[3].as_ref().unwrap().id, "b");
+    }
+
+    #[test]
+    fn test_protobuf_frames() {
+        let mut input = Vec::new();
+        request("a").encode_length_delimited(&mut input).unwrap();
+        let second = input.len() as u64;
+        request("b").encode_length_delimited(&mut input).unwrap();
+
+        let records: Vec<Record> = RecordReader::new(input.as_slice(), RecordFormat::Protobuf)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!((records[1].index, records[1].offset), (1, second));
+
+        let ids: Vec<String> = MessageReader::<_, BidRequest>::protobuf(input.as_slice())
+            .map(|r| r.unwrap().id)
+            .collect();
+        assert_eq!(ids, ["a", "b"]);
+
+        // a truncated frame ends the stream
+        let results: Vec<_> =
+            MessageReader::<_, BidRequest>::protobuf(&input[..input.len() - 1]).collect();
+        assert_eq!(results.len(), 2);
+        let error = results[1].as_ref().unwrap_err();
+        assert_eq!(error.offset, second);
+        assert_eq!(
+            error.to_string(),
+            format!("record 1 at byte {second}: truncated record")
+        );
+    }
+
+    #[test]
+    fn test_max_size() {
+        let input = "{\"id\":\"a\"}\n{\"id\":\"abcdefgh\"}\n{\"id\":\"b\"}\n";
+        let reader = RecordReader::new(input.as_bytes(), RecordFormat::Jsonl).with_max_size(12);
+        let results: Vec<_> = MessageReader::<_, BidRequest>::from_records(reader).collect();
+        assert!(matches!(
+            results[1].as_ref().unwrap_err().kind,
+            StreamErrorKind::TooLarge(17)
+        ));
+        assert_eq!(results[2].as_ref().unwrap().id, "b");
+
+        // a long line is skipped through a small buffer, up to its newline
+        let long = io::repeat(b'a').take(1 << 20);
+        let input = long.chain(&b"\n{\"id\":\"b\"}"[..]);
+        let reader =
+            RecordReader::new(io::BufReader::with_capacity(64, input), RecordFormat::Jsonl)
+                .with_max_size(12);
+        let results: Vec<_> = reader.collect();
+        assert!(matches!(
+            results[0].as_ref().unwrap_err().kind,
+            StreamErrorKind::TooLarge(len) if len == 1 << 20
+        ));
+        let record = results[1].as_ref().unwrap();
+        assert_eq!(record.offset, (1 << 20) + 1);
+        assert_eq!(record.bytes, b"{\"id\":\"b\"}");
+
+        let mut input = Vec::new();
+        request("abcdefgh")
+            .encode_length_delimited(&mut input)
+            .unwrap();
+        let reader = RecordReader::new(input.as_slice(), RecordFormat::Protobuf).with_max_size(4);
+        let results: Vec<_> = reader.collect();
+        assert_eq!(results.len(), 1);
+        assert!(matches!(
+            results[0].as_ref().unwrap_err().kind,
+            StreamErrorKind::TooLarge(10)
+        ));
+    }
+}
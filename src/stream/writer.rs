@@ -0,0 +1,109 @@
+use super::reader::RecordFormat;
+use prost::Message;
+use serde::Serialize;
+use std::io::{self, Write};
+
+/// Writes messages as JSON lines or length-delimited protobuf, the
+/// counterpart of [`super::MessageReader`].
+///
+/// # Example
+/// ```no_run
+/// use rtb::BidRequest;
+/// use rtb::stream::{MessageWriter, RecordFormat};
+///
+/// let file = rtb::stream::create("requests.pb.gz").unwrap();
+/// let mut writer = MessageWriter::new(file, RecordFormat::Protobuf);
+/// writer.write(&BidRequest::default()).unwrap();
+/// writer.into_inner().unwrap().finish().unwrap();
+/// ```
+pub struct MessageWriter<W> {
+    writer: W,
+    format: RecordFormat,
+    buf: Vec<u8>,
+    written: u64,
+}
+
+impl<W: Write> MessageWriter<W> {
+    pub fn new(writer: W, format: RecordFormat) -> Self {
+        Self {
+            writer,
+            format,
+            buf: Vec::new(),
+            written: 0,
+        }
+    }
+
+    pub fn write<T: Message + Serialize>(&mut self, message: &T) -> io::Result<()> {
+        self.buf.clear();
+        match self.format {
+            RecordFormat::Jsonl => {
+                serde_json::to_writer(&mut self.buf, message)?;
+                self.buf.push(b'\n');
+            }
+            RecordFormat::Protobuf => message
+                .encode_length_delimited(&mut self.buf)
+                .map_err(io::Error::other)?,
+        }
+
+        self.writer.write_all(&self.buf)?;
+        self.written += 1;
+        Ok(())
+    }
+
+    /// Messages written so far
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    /// Flushes and returns the writer, e.g. to finish an
+    /// [`super::Encoder`]
+    pub fn into_inner(mut self) -> io::Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BidRequest;
+    use crate::stream::{Compression, Encoder, MessageReader, decompress};
+
+    fn requests() -> Vec<BidRequest> {
+        (0..3)
+            .map(|i| BidRequest {
+                id: format!("req-{i}"),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_write_and_read_back() {
+        for format in [RecordFormat::Jsonl, RecordFormat::Protobuf] {
+            for compression in [
+                Compression::None,
+                #[cfg(feature = "gzip")]
+                Compression::Gzip,
+            ] {
+                let encoder = Encoder::new(Vec::new(), compression).unwrap();
+                let mut writer = MessageWriter::new(encoder, format);
+                for request in requests() {
+                    writer.write(&request).unwrap();
+                }
+                assert_eq!(writer.written(), 3);
+                let bytes = writer.into_inner().unwrap().finish().unwrap();
+
+                let read: Vec<BidRequest> =
+                    MessageReader::new(decompress(bytes.as_slice()).unwrap(), format)
+                        .collect::<Result<_, _>>()
+                        .unwrap();
+                assert_eq!(read, requests(), "{format:?} {compression:?}");
+            }
+        }
+    }
+}
@@ -0,0 +1,175 @@
+//! Classification of real-world adm shapes from `test_data/adm/`.
+
+use rtb::openrtb::utils::adm::AdFormat;
+use rtb::openrtb::utils::sniff::{AdmSniff, Confidence, sniff_markup};
+
+struct Case {
+    name: &'static str,
+    adm: &'static str,
+    format: AdFormat,
+    confidence: Confidence,
+    check: fn(&AdmSniff),
+}
+
+fn secure(sniff: &AdmSniff) {
+    assert!(sniff.signals.is_secure);
+}
+
+fn corpus() -> Vec<Case> {
+    vec![
+        Case {
+            name: "banner_html",
+            adm: include_str!("../test_data/adm/banner_html.html"),
+            format: AdFormat::Banner,
+            confidence: Confidence::High,
+            check: |s| {
+                secure(s);
+                assert!(!s.signals.is_mraid);
+                assert!(!s.signals.is_script_only);
+            },
+        },
+        Case {
+            name: "banner_mraid",
+            adm: include_str!("../test_data/adm/banner_mraid.html"),
+            format: AdFormat::Banner,
+            confidence: Confidence::High,
+            check: |s| {
+                assert!(s.signals.is_mraid);
+                assert!(!s.signals.is_script_only);
+            },
+        },
+        Case {
+            name: "banner_script_tag",
+            adm: include_str!("../test_data/adm/banner_script_tag.html"),
+            format: AdFormat::Banner,
+            confidence: Confidence::High,
+            check: |s| {
+                secure(s);
+                assert!(s.signals.is_script_only);
+            },
+        },
+        Case {
+            name: "banner_insecure",
+            adm: include_str!("../test_data/adm/banner_insecure.html"),
+            format: AdFormat::Banner,
+            confidence: Confidence::High,
+            check: |s| assert!(!s.signals.is_secure),
+        },
+        Case {
+            name: "banner_image_url",
+            adm: include_str!("../test_data/adm/banner_image_url.txt"),
+            format: AdFormat::Banner,
+            confidence: Confidence::Medium,
+            check: |s| {
+                secure(s);
+                assert!(s.signals.is_image_url);
+            },
+        },
+        Case {
+            name: "banner_iframe",
+            adm: include_str!("../test_data/adm/banner_iframe.html"),
+            format: AdFormat::Banner,
+            confidence: Confidence::High,
+            check: secure,
+        },
+        Case {
+            name: "vast_inline",
+            adm: include_str!("../test_data/adm/vast_inline.xml"),
+            format: AdFormat::Video,
+            confidence: Confidence::High,
+            check: |s| {
+                // the xmlns:xsi URL is an identifier, not a fetched resource
+                secure(s);
+                assert!(!s.signals.is_vast_wrapper);
+                assert!(!s.signals.is_wrapped);
+            },
+        },
+        Case {
+            name: "vast_wrapper_comment",
+            adm: include_str!("../test_data/adm/vast_wrapper_comment.xml"),
+            format: AdFormat::Video,
+            confidence: Confidence::High,
+            check: |s| {
+                assert!(s.signals.is_vast_wrapper);
+                assert!(!s.signals.is_wrapped);
+            },
+        },
+        Case {
+            name: "vast_cdata_wrapped",
+            adm: include_str!("../test_data/adm/vast_cdata_wrapped.xml"),
+            format: AdFormat::Video,
+            confidence: Confidence::Medium,
+            check: |s| {
+                secure(s);
+                assert!(s.signals.is_wrapped);
+            },
+        },
+        Case {
+            name: "vast_in_html",
+            adm: include_str!("../test_data/adm/vast_in_html.html"),
+            format: AdFormat::Video,
+            confidence: Confidence::Medium,
+            check: |s| {
+                assert!(s.signals.is_wrapped);
+                assert!(s.signals.is_vast_wrapper);
+                assert!(!s.signals.is_script_only);
+            },
+        },
+        Case {
+            name: "vast_bom_lowercase",
+            adm: include_str!("../test_data/adm/vast_bom_lowercase.xml"),
+            format: AdFormat::Video,
+            confidence: Confidence::High,
+            check: |s| assert!(!s.signals.is_secure),
+        },
+        Case {
+            name: "vast_audio",
+            adm: include_str!("../test_data/adm/vast_audio.xml"),
+            format: AdFormat::Audio,
+            confidence: Confidence::High,
+            check: |s| {
+                secure(s);
+                assert!(!s.signals.is_daast);
+            },
+        },
+        Case {
+            name: "daast",
+            adm: include_str!("../test_data/adm/daast.xml"),
+            format: AdFormat::Audio,
+            confidence: Confidence::High,
+            check: |s| assert!(s.signals.is_daast),
+        },
+        Case {
+            name: "native_12",
+            adm: include_str!("../test_data/adm/native_12.json"),
+            format: AdFormat::Native,
+            confidence: Confidence::High,
+            check: secure,
+        },
+        Case {
+            name: "native_wrapped",
+            adm: include_str!("../test_data/adm/native_wrapped.json"),
+            format: AdFormat::Native,
+            confidence: Confidence::High,
+            check: |s| assert!(!s.signals.is_secure),
+        },
+    ]
+}
+
+#[test]
+fn test_adm_corpus() {
+    for case in corpus() {
+        let sniff = sniff_markup(case.adm).unwrap_or_else(|| panic!("{}: no sniff", case.name));
+
+        assert_eq!(sniff.format, case.format, "{}", case.name);
+        assert_eq!(sniff.confidence, case.confidence, "{}", case.name);
+        assert_eq!(sniff.markup_format, Some(case.format), "{}", case.name);
+        (case.check)(&sniff);
+    }
+}
+
+#[test]
+fn test_empty_adm() {
+    assert!(sniff_markup("").is_none());
+    assert!(sniff_markup("\u{feff}  \n").is_none());
+}